@@ -0,0 +1,93 @@
+use common::correlations;
+use common::eventstore::{self, EventRecord};
+use log::{debug, error, info};
+use sqlx::PgPool;
+use sqlx::types::Uuid;
+
+use crate::projector;
+
+const PROJECTION_NAME: &str = "entity_correlator";
+
+/// Indexes the observables of entities the materializer has projected and
+/// correlates them across cases. Never reads past the materializer, so the
+/// documents it indexes are the ones the events produced.
+pub async fn run(pool: PgPool) {
+    info!("Projector '{PROJECTION_NAME}' started");
+    let mut last = match eventstore::get_checkpoint(&pool, PROJECTION_NAME).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("checkpoint load error: {e}");
+            0
+        }
+    };
+
+    loop {
+        let materialized = match eventstore::get_checkpoint(&pool, projector::PROJECTION_NAME).await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!("checkpoint load error: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let events = match eventstore::events_after(&pool, last, 500).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!("events fetch error: {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let events: Vec<&EventRecord> = events.iter().filter(|e| e.seq <= materialized).collect();
+        if events.is_empty() {
+            tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+            continue;
+        }
+        for ev in events {
+            if let Err(err) = apply_event(&pool, ev).await {
+                error!("correlation apply error at seq {}: {}", ev.seq, err);
+                tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+                break;
+            }
+            last = ev.seq;
+        }
+        if let Err(e) = eventstore::set_checkpoint(&pool, PROJECTION_NAME, last).await {
+            error!("checkpoint save error: {e}");
+        }
+    }
+}
+
+async fn apply_event(pool: &PgPool, ev: &EventRecord) -> Result<(), sqlx::Error> {
+    let stream = sqlx::query!(
+        r#"SELECT category, key FROM event_streams WHERE stream_id = $1"#,
+        ev.stream_id
+    )
+    .fetch_one(pool)
+    .await?;
+    if stream.category != "entity" {
+        return Ok(());
+    }
+    let Ok(graph_uuid) = Uuid::parse_str(&stream.key) else {
+        return Ok(());
+    };
+    // the entity itself, one a merge absorbed and one a split created
+    let affected = [
+        &ev.payload["id"],
+        &ev.payload["merged"],
+        &ev.payload["into"]["id"],
+    ];
+    for entity_id in affected
+        .iter()
+        .filter_map(|v| v.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+    {
+        let created = correlations::index_entity(pool, graph_uuid, entity_id).await?;
+        if created > 0 {
+            debug!(
+                "{created} new correlations for entity {entity_id} at seq {}",
+                ev.seq
+            );
+        }
+    }
+    Ok(())
+}
@@ -1,13 +1,17 @@
 use crate::{middleware::auth::AuthMiddleware, schemas::Paginate};
 use actix_web::{
-    HttpResponse, Result, get,
-    web::{Data, Path},
+    HttpResponse, Result, get, post,
+    web::{Data, Json, Path},
 };
 use chrono::{DateTime, Utc};
+use common::correlations;
+use common::cypher::{self, CypherError};
+use common::provenance::{self, ProvenanceFilter};
 use common::utils::to_snake_case;
 use common::{db, errors::AppError};
+use log::error;
 use serde::Serialize;
-use serde_json::Value as JsonValue;
+use serde_json::{Value as JsonValue, json};
 use sqids::Sqids;
 use std::collections::{HashMap, HashSet};
 
@@ -539,3 +543,169 @@ pub async fn get_case_chord_handler(
 
     Ok(HttpResponse::Ok().json(ChordResponse { nodes, links }))
 }
+
+#[derive(Debug, Serialize)]
+struct CaseProvenance {
+    entities: Vec<provenance::Sourced>,
+    edges: Vec<provenance::Sourced>,
+}
+
+// Everything on a case from one source, transform, plugin or job, e.g.
+// `?source_url=https://...` for a chain of custody
+#[get("/cases/{id}/provenance")]
+pub async fn get_case_provenance_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    graph_id: Path<String>,
+    filter: Query<ProvenanceFilter>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let ids = sqids.decode(&graph_id);
+    let decoded_id = ids.first().ok_or(AppError {
+        message: "Invalid graph ID.",
+    })?;
+    let decoded_id = *decoded_id as i64;
+
+    let graph = sqlx::query!(
+        "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+        decoded_id,
+        auth.account_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(|_| AppError {
+        message: "We ran into an error getting this case.",
+    })?;
+    let Some(graph_uuid) = graph.uuid else {
+        return Err(AppError {
+            message: "Case has no UUID.",
+        });
+    };
+
+    let (entities, edges) = provenance::find(pool.as_ref(), graph_uuid, &filter)
+        .await
+        .map_err(|_| AppError {
+            message: "We ran into an error querying this case's provenance.",
+        })?;
+    Ok(HttpResponse::Ok().json(CaseProvenance { entities, edges }))
+}
+
+#[derive(Debug, Serialize)]
+struct CaseCorrelation {
+    other_case_id: String,
+    #[serde(flatten)]
+    correlation: correlations::Correlation,
+}
+
+// Entities of this case whose email, phone or wallet also turned up on
+// other cases the caller can open
+#[get("/cases/{id}/correlations")]
+pub async fn get_case_correlations_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    graph_id: Path<String>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let ids = sqids.decode(&graph_id);
+    let decoded_id = ids.first().ok_or(AppError {
+        message: "Invalid graph ID.",
+    })?;
+    let decoded_id = *decoded_id as i64;
+
+    let graph = sqlx::query!(
+        "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+        decoded_id,
+        auth.account_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(|_| AppError {
+        message: "We ran into an error getting this case.",
+    })?;
+    let Some(graph_uuid) = graph.uuid else {
+        return Err(AppError {
+            message: "Case has no UUID.",
+        });
+    };
+
+    let found = correlations::for_case(pool.as_ref(), graph_uuid, auth.account_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error getting this case's correlations.",
+            }
+        })?;
+    let mut list = Vec::with_capacity(found.len());
+    for correlation in found {
+        let other_case_id = sqids
+            .encode(&[correlation.other_case_id as u64])
+            .map_err(|err| {
+                error!("Error encoding sqid: {err}");
+                AppError {
+                    message: "We ran into an error encoding graph ID.",
+                }
+            })?;
+        list.push(CaseCorrelation {
+            other_case_id,
+            correlation,
+        });
+    }
+    Ok(HttpResponse::Ok().json(list))
+}
+
+// Rows a query returns at most, whatever its own LIMIT
+const QUERY_MAX_ROWS: u64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct CaseQuery {
+    query: String,
+}
+
+// Runs an openCypher subset query, e.g.
+// `MATCH (p:Person)-[*1..3]-(e:Email) RETURN p.name, e.email`, over the
+// case's current entities and edges
+#[post("/cases/{id}/query")]
+pub async fn query_case_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    graph_id: Path<String>,
+    sqids: Data<Sqids>,
+    body: Json<CaseQuery>,
+) -> Result<HttpResponse, AppError> {
+    let ids = sqids.decode(&graph_id);
+    let decoded_id = ids.first().ok_or(AppError {
+        message: "Invalid graph ID.",
+    })?;
+    let decoded_id = *decoded_id as i64;
+
+    let graph = sqlx::query!(
+        "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+        decoded_id,
+        auth.account_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(|_| AppError {
+        message: "We ran into an error getting this case.",
+    })?;
+    let Some(graph_uuid) = graph.uuid else {
+        return Err(AppError {
+            message: "Case has no UUID.",
+        });
+    };
+
+    match cypher::run(pool.as_ref(), graph_uuid, &body.query, QUERY_MAX_ROWS).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result)),
+        Err(CypherError::Sql(err)) => {
+            error!("{err}");
+            Err(AppError {
+                message: "We ran into an error running this query.",
+            })
+        }
+        Err(err) => Ok(HttpResponse::UnprocessableEntity().json(json!({
+            "message": "This query isn't supported.",
+            "detail": err.to_string(),
+        }))),
+    }
+}
@@ -0,0 +1,91 @@
+use actix_web::{
+    HttpResponse, Result, get, post,
+    web::{Data, Path, Query},
+};
+use common::errors::AppError;
+use common::identity;
+use log::error;
+use serde::Deserialize;
+use sqids::Sqids;
+use sqlx::types::Uuid;
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+#[derive(Deserialize)]
+pub struct ListDuplicatesQuery {
+    // pending (default), merged or dismissed
+    pub status: Option<String>,
+}
+
+#[get("/cases/{id}/duplicates")]
+pub async fn list_duplicates_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    graph_id: Path<String>,
+    q: Query<ListDuplicatesQuery>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let ids = sqids.decode(&graph_id);
+    let decoded_id = ids.first().ok_or(AppError {
+        message: "Invalid graph ID.",
+    })?;
+    let decoded_id = *decoded_id as i64;
+
+    let graph = sqlx::query!(
+        "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+        decoded_id,
+        auth.account_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(|_| AppError {
+        message: "We ran into an error getting this case.",
+    })?;
+    let Some(graph_uuid) = graph.uuid else {
+        return Err(AppError {
+            message: "Case has no UUID.",
+        });
+    };
+
+    let status = q.status.as_deref().unwrap_or("pending");
+    if !matches!(status, "pending" | "merged" | "dismissed") {
+        return Err(AppError {
+            message: "Status must be pending, merged or dismissed.",
+        });
+    }
+    let candidates = identity::list_candidates(pool.as_ref(), graph_uuid, status)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error listing duplicate candidates.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().json(candidates))
+}
+
+// The entities stay as they are, the pair just leaves the review queue
+#[post("/duplicates/{candidate_id}/dismiss")]
+pub async fn dismiss_duplicate_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let candidate_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid duplicate candidate id.",
+    })?;
+    let candidate =
+        identity::resolve_candidate(pool.as_ref(), candidate_id, auth.account_id, "dismissed")
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                AppError {
+                    message: "We ran into an error dismissing this duplicate candidate.",
+                }
+            })?
+            .ok_or(AppError {
+                message: "Pending duplicate candidate not found.",
+            })?;
+    Ok(HttpResponse::Ok().json(candidate))
+}
@@ -1,6 +1,7 @@
 use crate::{
-    db,
+    AppData, db,
     middleware::auth::AuthMiddleware,
+    plugin_service::PluginServiceError,
     schemas::{
         Paginate,
         entities::{
@@ -10,8 +11,10 @@ use crate::{
     },
 };
 use common::errors::AppError;
+use common::plugin_cli::PluginCliError;
+use common::plugin_registry;
 use log::error;
-use serde_json::Value;
+use serde_json::{Value, json};
 use sqids::Sqids;
 
 use actix_web::{
@@ -295,200 +298,132 @@ fn to_snake_case(s: &str) -> String {
     result
 }
 
-async fn get_blueprint(label: &str) -> Result<Value, AppError> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&format!(
-            "http://127.0.0.1:42562/blueprint?label={}",
-            to_snake_case(label)
-        ))
-        .send()
-        .await
-        .map_err(|err| {
-            error!("Error fetching blueprint: {}", err);
-            AppError {
-                message: "Failed to fetch blueprint from plugins service.",
-            }
-        })?;
+#[derive(serde::Deserialize)]
+struct TransformQuery {
+    label: String,
+}
 
-    let blueprint: Value = response.json().await.map_err(|err| {
-        error!("Error parsing blueprint response: {}", err);
-        AppError {
-            message: "Failed to parse blueprint response.",
-        }
-    })?;
+// Plugin reads are served from the registry once it has synced, and from
+// the CLI before that
+fn registry_error(err: sqlx::Error) -> AppError {
+    error!("{err}");
+    AppError {
+        message: "We ran into an error loading the plugin registry.",
+    }
+}
 
-    Ok(blueprint)
+fn service_error(err: PluginServiceError) -> AppError {
+    error!("{err}");
+    AppError {
+        message: err.message(),
+    }
 }
 
-#[derive(serde::Deserialize)]
-struct TransformQuery {
-    label: String,
+fn cli_error(err: PluginCliError) -> AppError {
+    error!("{err}");
+    AppError {
+        message: err.message(),
+    }
+}
+
+async fn registry_synced(pool: &db::Database) -> Result<bool, AppError> {
+    let synced = plugin_registry::last_synced(pool.as_ref())
+        .await
+        .map_err(registry_error)?;
+    Ok(synced.is_some())
 }
 
 #[get("/entity/plugins/transform/")]
 async fn get_entity_transforms(
-    _auth: AuthMiddleware,
+    pool: db::Database,
+    app: AppData,
+    auth: AuthMiddleware,
     query: Query<TransformQuery>,
 ) -> Result<HttpResponse, AppError> {
-    use std::process::Command;
-
-    let output = Command::new("ob")
-        .args(&["ls", "transforms", "-L", &query.label])
-        .output()
-        .map_err(|err| {
-            error!("Error running 'ob ls -l {}': {}", query.label, err);
-            AppError {
-                message: "Failed to execute 'ob ls -l' command.",
-            }
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        error!("OB command 'ls -L {}' failed: {}", query.label, stderr);
-        return Err(AppError {
-            message: "Command 'ob ls -L' execution failed.",
-        });
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let transforms: Value = serde_json::from_str(&stdout).map_err(|err| {
-        error!("Error parsing transforms JSON: {}", err);
-        AppError {
-            message: "Failed to parse transforms JSON output.",
+    // labels the registry doesn't know yet may have been installed since
+    if registry_synced(&pool).await? {
+        let plugin = plugin_registry::entity(pool.as_ref(), Some(auth.org_id), &query.label)
+            .await
+            .map_err(registry_error)?;
+        if let Some(plugin) = plugin {
+            return Ok(HttpResponse::Ok().json(plugin.transforms));
         }
-    })?;
+        // another organization's or a disabled plugin offers nothing
+        let enabled = plugin_registry::enabled_for_org(pool.as_ref(), auth.org_id, &query.label)
+            .await
+            .map_err(registry_error)?;
+        if !enabled {
+            return Ok(HttpResponse::Ok().json(Vec::<serde_json::Value>::new()));
+        }
+    }
 
+    let transforms = app
+        .plugins
+        .transforms(&query.label)
+        .await
+        .map_err(cli_error)?;
     Ok(HttpResponse::Ok().json(transforms))
 }
 
 #[get("/entity/details/{hid}")]
 async fn get_entity_details(
+    app: AppData,
     _auth: AuthMiddleware,
     hid: Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&format!("http://127.0.0.1:42562/entities/{}", hid.as_str()))
-        .send()
-        .await
-        .map_err(|err| {
-            error!("Error fetching entity details: {}", err);
-            AppError {
-                message: "Failed to fetch entity details from plugins service.",
-            }
-        })?;
-
-    let mut entity: Value = response.json().await.map_err(|err| {
-        error!("Error parsing entity response: {}", err);
-        AppError {
-            message: "Failed to parse entity response.",
-        }
-    })?;
+    let service = &app.plugin_service;
+    let mut entity = service.entity(hid.as_str()).await.map_err(service_error)?;
 
     if let Some(label) = entity.get("label").and_then(|l| l.as_str()) {
-        let label_str = label.to_string();
-        let blueprint = get_blueprint(&label_str).await?;
-        entity
-            .as_object_mut()
-            .unwrap()
-            .insert("blueprint".to_string(), blueprint);
-
-        let transforms_response = reqwest::Client::new()
-            .get(&format!(
-                "http://127.0.0.1:42562/transforms?label={}",
-                to_snake_case(&label_str)
-            ))
-            .send()
-            .await
-            .map_err(|err| {
-                error!("Error fetching transforms: {}", err);
-                AppError {
-                    message: "Failed to fetch transforms from plugins service.",
-                }
-            })?;
-
-        let transforms: Value = transforms_response.json().await.map_err(|err| {
-            error!("Error parsing transforms response: {}", err);
-            AppError {
-                message: "Failed to parse transforms response.",
-            }
-        })?;
-
-        entity
-            .as_object_mut()
-            .unwrap()
-            .insert("transforms".to_string(), transforms);
+        let label = to_snake_case(label);
+        let blueprint = service.blueprint(&label).await.map_err(service_error)?;
+        let transforms = service.transforms(&label).await.map_err(service_error)?;
+        if let Some(obj) = entity.as_object_mut() {
+            obj.insert("blueprint".to_string(), blueprint);
+            obj.insert("transforms".to_string(), transforms);
+        }
     }
 
     Ok(HttpResponse::Ok().json(entity))
 }
 
 #[get("/entity")]
-async fn get_entities_from_plugins(_auth: AuthMiddleware) -> Result<HttpResponse, AppError> {
-    use std::process::Command;
-    // TODO: Get entities from db when in production environment (app.cfg.environment == "production")
-    // and run the plugin system in firecracker VMs
-    let output = Command::new("ob")
-        .args(&["ls", "entities"])
-        .output()
-        .map_err(|err| {
-            error!("Error running 'ob ls entities': {}", err);
-            AppError {
-                message: "Failed to execute 'ob ls entities' command.",
-            }
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        error!("Command 'ob ls entities' failed: {}", stderr);
-        return Err(AppError {
-            message: "Command 'ob ls entities' execution failed.",
-        });
+async fn get_entities_from_plugins(
+    pool: db::Database,
+    app: AppData,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    if registry_synced(&pool).await? {
+        let plugins = plugin_registry::entities_for_org(pool.as_ref(), auth.org_id)
+            .await
+            .map_err(registry_error)?;
+        let entities: Vec<Value> = plugins.iter().map(|p| p.listing()).collect();
+        return Ok(HttpResponse::Ok().json(entities));
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let entities: Value = serde_json::from_str(&stdout).map_err(|err| {
-        error!("Error parsing entities JSON: {}", err);
-        AppError {
-            message: "Failed to parse entities JSON output.",
-        }
-    })?;
-
+    let entities = app.plugins.entities().await.map_err(cli_error)?;
     Ok(HttpResponse::Ok().json(entities))
 }
 
-/// Return full plugin entity information by invoking the `ob entities json` CLI.
+/// Return full plugin entity information as `ob entities json` reports it.
 /// This includes blueprint and transforms and is intended for richer UI displays.
 #[get("/entity/plugins/all")]
-async fn get_all_plugin_entities_from_cli(_auth: AuthMiddleware) -> Result<HttpResponse, AppError> {
-    use std::process::Command;
-
-    let output = Command::new("ob")
-        .args(&["entities", "json"])
-        .output()
-        .map_err(|err| {
-            error!("Error running 'ob entities json': {}", err);
-            AppError {
-                message: "Failed to execute 'ob entities json' command.",
-            }
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        error!("Command 'ob entities json' failed: {}", stderr);
-        return Err(AppError {
-            message: "Command 'ob entities json' execution failed.",
-        });
+async fn get_all_plugin_entities_from_cli(
+    pool: db::Database,
+    app: AppData,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    if registry_synced(&pool).await? {
+        let plugins = plugin_registry::entities_for_org(pool.as_ref(), auth.org_id)
+            .await
+            .map_err(registry_error)?;
+        let favorites = plugin_registry::favorites(pool.as_ref())
+            .await
+            .map_err(registry_error)?;
+        let entities: Vec<Value> = plugins.iter().map(|p| p.full()).collect();
+        return Ok(HttpResponse::Ok().json(json!({ "entities": entities, "favorites": favorites })));
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let payload: Value = serde_json::from_str(&stdout).map_err(|err| {
-        error!("Error parsing entities json payload: {}", err);
-        AppError {
-            message: "Failed to parse entities json output.",
-        }
-    })?;
-
+    let payload = app.plugins.entities_full().await.map_err(cli_error)?;
     Ok(HttpResponse::Ok().json(payload))
 }
@@ -1,22 +1,32 @@
+use super::jobs::enqueue_org_job;
+use super::notifications::NotificationView;
 use crate::middleware::auth::decode_jwt;
 use actix_web::{HttpRequest, HttpResponse, Result, web};
 use actix_ws::{Message, Session};
 use chrono::Utc;
+use common::artifacts;
 use common::db::Database;
+use common::entity_schema::{EntitySchemas, FieldError};
 use common::errors::AppError;
 use common::eventstore::{self, AppendEvent};
+use common::findings;
+use common::identity::{self, NaturalKeys};
 use common::jobs;
+use common::layout::Layout;
+use common::lineage;
+use common::notifications;
+use common::persist::{self, PersistContext};
+use common::plugin_cli::{PluginCli, PluginCliError};
+use common::plugin_registry;
+use common::provenance::Origin;
+use common::transform_cache::{self, CacheStamp, CacheTtls};
 use futures_util::StreamExt;
-use futures_util::future::BoxFuture;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use sqids::Sqids;
 use sqlx::PgPool;
 use std::collections::HashMap;
-use std::fmt;
-use std::process::Command;
-use tokio::process::Command as TokioCommand;
 use tokio::time::{Duration, sleep};
 use uuid::Uuid;
 
@@ -45,6 +55,14 @@ pub struct WebSocketMessage {
     pub entity: Option<Value>,
     pub edge: Option<Value>,
     pub token: Option<String>,
+    // transforms: serve a cached result if one exists (default), or skip
+    // the cache and re-run, refreshing the entry
+    pub use_cache: Option<bool>,
+    pub force_refresh: Option<bool>,
+    // playbook:run
+    pub playbook_id: Option<String>,
+    // transforms and playbooks: `grid`, `radial` (default) or `force`
+    pub layout: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -73,66 +91,72 @@ pub struct GraphEdge {
 }
 
 // Used by client to map plugin transform results to entity layouts (elements)
-pub async fn get_entity_blueprints() -> Result<HashMap<String, Value>, AppError> {
-    let output = Command::new("ob")
-        .args(&["blueprints"])
-        .output()
-        .map_err(|err| {
-            error!("Error running 'ob blueprints': {}", err);
-            AppError { message: "" }
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        error!("Command 'ob blueprints' failed: {}", stderr);
-        return Err(AppError { message: "" });
-    };
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let blueprints = serde_json::from_str::<HashMap<String, Value>>(&stdout)
-        .map_err(|err| {
-            error!("Error parsing entities JSON: {}", err);
-            err
-        })
-        .unwrap_or(HashMap::new());
-    Ok(blueprints)
+pub async fn get_entity_blueprints(
+    pool: &PgPool,
+    cli: &PluginCli,
+) -> Result<HashMap<String, Value>, PluginCliError> {
+    match plugin_registry::last_synced(pool).await {
+        Ok(Some(_)) => match plugin_registry::blueprints(pool).await {
+            Ok(blueprints) => return Ok(blueprints),
+            Err(err) => error!("{err}"),
+        },
+        Ok(None) => {}
+        Err(err) => error!("{err}"),
+    }
+    cli.blueprints().await
 }
 
-// Used to show available plugins on the entities sidebar
-pub fn get_available_plugins() -> BoxFuture<'static, Vec<Value>> {
-    Box::pin(async move {
-        let Ok(output) = Command::new("ob").args(&["ls", "entities"]).output() else {
-            sleep(Duration::from_secs(3)).await;
-            return get_available_plugins().await;
-        };
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!("Command 'ob ls entities' failed: {}", stderr);
-            sleep(Duration::from_secs(3)).await;
-            return get_available_plugins().await;
-        }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let Ok(plugins) = serde_json::from_str::<Vec<Value>>(&stdout) else {
-            error!(
-                "Error serializing plugins cmd response into JSON: {}",
-                stdout
-            );
-            sleep(Duration::from_secs(3)).await;
-            return get_available_plugins().await;
-        };
-
-        plugins
+// Used to show available plugins on the entities sidebar, from the registry
+// once it has synced (limited to those the org enabled) and the CLI before that
+pub async fn get_available_plugins(
+    pool: &PgPool,
+    cli: &PluginCli,
+    org_id: i64,
+) -> Result<Vec<Value>, PluginCliError> {
+    match plugin_registry::last_synced(pool).await {
+        Ok(Some(_)) => match plugin_registry::entities_for_org(pool, org_id).await {
+            Ok(plugins) => return Ok(plugins.iter().map(|p| p.listing()).collect()),
+            Err(err) => error!("{err}"),
+        },
+        Ok(None) => {}
+        Err(err) => error!("{err}"),
+    }
+    let plugins = cli.entities().await?;
+    serde_json::from_value(plugins).map_err(|err| PluginCliError::Unreadable {
+        command: "ob ls entities".into(),
+        error: err.to_string(),
     })
 }
 
+// Rejects entity data that breaks its blueprint before it's appended
+async fn send_field_errors(
+    session: &mut Session,
+    entity_id: Option<&Value>,
+    errors: &[FieldError],
+) {
+    let message = json!({
+        "action": "error",
+        "notification": {
+            "autoClose": 8000,
+            "message": errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        },
+        "entity": { "id": entity_id },
+        "errors": errors,
+    });
+    let _ = session.text(message.to_string()).await;
+}
+
 pub async fn handle_create_entity(
     pool: &PgPool,
     graph_uuid: Uuid,
     event: WebSocketMessage,
     session: &mut Session,
     actor_id: i64,
+    schemas: &EntitySchemas,
 ) {
     // Event validation and error messages
     let Some(mut entity) = event.entity else {
@@ -181,6 +205,13 @@ pub async fn handle_create_entity(
         }
         _ => (0.0_f64, 0.0_f64, json!({})),
     };
+    if let Some(data) = properties.as_object() {
+        let errors = schemas.validate(label, data);
+        if !errors.is_empty() {
+            send_field_errors(session, None, &errors).await;
+            return;
+        }
+    }
 
     let mut payload = json!({
         "id":  Uuid::new_v4(),
@@ -422,10 +453,32 @@ pub async fn handle_update_entity(
     event: WebSocketMessage,
     session: &mut Session,
     actor_id: i64,
+    schemas: &EntitySchemas,
 ) {
     let Some(mut entity) = event.entity else {
         return;
     };
+    if let Some(data) = entity.get("data").and_then(Value::as_object) {
+        // the entity's own label decides the schema, a relabel has to
+        // satisfy the new one as well
+        let stored = entity_label(pool, graph_uuid, &entity["id"]).await;
+        let relabel = data
+            .get("label")
+            .and_then(Value::as_str)
+            .filter(|label| stored.as_deref() != Some(*label));
+        let mut errors = stored
+            .map(|label| schemas.validate(&label, data))
+            .unwrap_or_default();
+        if errors.is_empty() {
+            if let Some(label) = relabel {
+                errors = schemas.validate(label, data);
+            }
+        }
+        if !errors.is_empty() {
+            send_field_errors(session, entity.get("id"), &errors).await;
+            return;
+        }
+    }
 
     // Normalize x,y -> position if present
     if let Value::Object(ref mut obj) = entity {
@@ -459,6 +512,25 @@ pub async fn handle_update_entity(
     let _ = session.text(message.to_string()).await;
 }
 
+// Label of an entity on the case, the schema its updates are checked against
+async fn entity_label(pool: &PgPool, graph_uuid: Uuid, entity_id: &Value) -> Option<String> {
+    let entity_id = entity_id.as_str().and_then(|id| Uuid::parse_str(id).ok())?;
+    sqlx::query_scalar!(
+        r#"
+        SELECT doc->>'label' FROM entities_current
+         WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+        "#,
+        graph_uuid,
+        entity_id
+    )
+    .fetch_optional(pool)
+    .await
+    .inspect_err(|e| error!("{e}"))
+    .ok()
+    .flatten()
+    .flatten()
+}
+
 pub async fn handle_delete_entity(
     pool: &PgPool,
     graph_uuid: Uuid,
@@ -544,6 +616,203 @@ pub async fn handle_delete_entity(
     let _ = session.text(message.to_string()).await;
 }
 
+async fn send_entity_error(session: &mut Session, message: &str) {
+    let _ = session
+        .text(
+            json!({
+                "action": "error",
+                "notification": {"autoClose": 8000, "message": message},
+            })
+            .to_string(),
+        )
+        .await;
+}
+
+fn entity_ref(entity: &Value, field: &str) -> Option<Uuid> {
+    entity
+        .get(field)
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok())
+}
+
+// Folds `entity.merge` into `entity.id`: its edges move over, its data fills
+// fields the survivor lacks, and the merge is recorded whole so a split can
+// undo it
+pub async fn handle_merge_entity(
+    pool: &PgPool,
+    graph_uuid: Uuid,
+    event: WebSocketMessage,
+    session: &mut Session,
+    actor_id: i64,
+) {
+    let entity = event.entity.unwrap_or_default();
+    let (Some(survivor), Some(absorbed)) =
+        (entity_ref(&entity, "id"), entity_ref(&entity, "merge"))
+    else {
+        send_entity_error(session, "Merging requires the ids of both entities.").await;
+        return;
+    };
+    if survivor == absorbed {
+        send_entity_error(session, "An entity can't be merged into itself.").await;
+        return;
+    }
+    let payload = match lineage::merge_payload(pool, graph_uuid, survivor, absorbed).await {
+        Ok(Some(payload)) => payload,
+        Ok(None) => {
+            send_entity_error(session, "Both entities must be on this case to merge them.").await;
+            return;
+        }
+        Err(e) => {
+            error!("Failed to plan entity merge: {e}");
+            send_entity_error(session, "We ran into an error merging these entities!").await;
+            return;
+        }
+    };
+    let merge_id = payload["merge_id"]
+        .as_str()
+        .and_then(|s| Uuid::parse_str(s).ok());
+    let ev = AppendEvent {
+        category: "entity".to_string(),
+        key: graph_uuid.to_string(),
+        event_type: "merge".to_string(),
+        payload: payload.clone(),
+        valid_from: Utc::now(),
+        valid_to: None,
+        correlation_id: merge_id,
+        causation_id: None,
+        expected_version: None,
+        actor_id: Some(actor_id),
+    };
+    if let Err(e) = eventstore::append_event(pool, ev).await {
+        error!("Failed to append entity:merge event: {e}");
+        send_entity_error(session, "We ran into an error merging these entities!").await;
+        return;
+    }
+    if let Err(e) =
+        identity::mark_merged(pool, graph_uuid, survivor, absorbed, Some(actor_id)).await
+    {
+        error!("Failed to settle duplicate candidates: {e}");
+    }
+
+    let message = json!({
+        "action": "merged",
+        "notification": {"shouldClose": true, "message": "Entities merged."},
+        "entity": { "id": survivor, "data": payload["data"] },
+        "merged": absorbed,
+        "merge_id": merge_id,
+        "edges": payload["edges"],
+        "dropped": payload["dropped"],
+    });
+    let _ = session.text(message.to_string()).await;
+}
+
+// Splits `entity.id` in two. With `entity.unmerge` set to an entity merged
+// into it, that merge is reversed. Otherwise the data `fields` and `edges`
+// listed move onto a new entity, labelled `label` or like the original.
+pub async fn handle_split_entity(
+    pool: &PgPool,
+    graph_uuid: Uuid,
+    event: WebSocketMessage,
+    session: &mut Session,
+    actor_id: i64,
+) {
+    let entity = event.entity.unwrap_or_default();
+    let Some(entity_id) = entity_ref(&entity, "id") else {
+        send_entity_error(session, "Missing entity id for split.").await;
+        return;
+    };
+    let planned = match entity_ref(&entity, "unmerge") {
+        Some(absorbed) => lineage::unmerge_payload(pool, graph_uuid, entity_id, absorbed).await,
+        None => {
+            let fields: Vec<String> = entity["fields"]
+                .as_array()
+                .map(|f| {
+                    f.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let edges: Vec<Uuid> = entity["edges"]
+                .as_array()
+                .map(|e| {
+                    e.iter()
+                        .filter_map(|v| v.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if fields.is_empty() && edges.is_empty() {
+                send_entity_error(session, "Choose the fields or edges to split off.").await;
+                return;
+            }
+            lineage::split_payload(
+                pool,
+                graph_uuid,
+                entity_id,
+                entity["label"].as_str(),
+                &fields,
+                &edges,
+            )
+            .await
+        }
+    };
+    let payload = match planned {
+        Ok(Some(payload)) => payload,
+        Ok(None) => {
+            send_entity_error(
+                session,
+                "Nothing to split, the entity or merge wasn't found.",
+            )
+            .await;
+            return;
+        }
+        Err(e) => {
+            error!("Failed to plan entity split: {e}");
+            send_entity_error(session, "We ran into an error splitting this entity!").await;
+            return;
+        }
+    };
+    let ev = AppendEvent {
+        category: "entity".to_string(),
+        key: graph_uuid.to_string(),
+        event_type: "split".to_string(),
+        payload: payload.clone(),
+        valid_from: Utc::now(),
+        valid_to: None,
+        correlation_id: payload["split_id"]
+            .as_str()
+            .and_then(|s| Uuid::parse_str(s).ok()),
+        // an unmerge is caused by the merge it reverses
+        causation_id: payload["undoes"]
+            .as_str()
+            .and_then(|s| Uuid::parse_str(s).ok()),
+        expected_version: None,
+        actor_id: Some(actor_id),
+    };
+    if let Err(e) = eventstore::append_event(pool, ev).await {
+        error!("Failed to append entity:split event: {e}");
+        send_entity_error(session, "We ran into an error splitting this entity!").await;
+        return;
+    }
+
+    // projected documents keep the label at the top level only
+    let mut into = ui_node(&payload["into"]);
+    if let (Some(label), Some(data)) = (
+        payload["into"]["label"].as_str(),
+        into["data"].as_object_mut(),
+    ) {
+        data.entry("label").or_insert(json!(label));
+    }
+    let message = json!({
+        "action": "split",
+        "notification": {"shouldClose": true, "message": "Entity split."},
+        "entity": { "id": entity_id, "data": payload["data"] },
+        "into": into,
+        "edges": payload["edges"],
+        "restored": payload["restored"],
+    });
+    let _ = session.text(message.to_string()).await;
+}
+
 // Read latest materialized entities and edges for the case graph
 pub async fn handle_materialized_read(pool: &PgPool, graph_uuid: Uuid, session: &mut Session) {
     let nodes: Vec<Value> = match sqlx::query!(
@@ -667,6 +936,7 @@ pub async fn graphing_websocket_handler(
     let mut authenticated = false;
     let mut total_events = 0;
     let mut actor_id_num: Option<i64> = None;
+    let mut org_id_num: Option<i64> = None;
 
     // Extract and decode graph id from path parameters
     let graph_ids = sqids.decode(&graph_id);
@@ -684,13 +954,19 @@ pub async fn graphing_websocket_handler(
     let deauth_msg = json!({"action": "deauth"});
     actix_web::rt::spawn(async move {
         let mut graph_uuid: Option<Uuid> = None;
-        let Ok(blueprints) = get_entity_blueprints().await else {
-            error!(
-                "Error getting blueprints for graph id `{decoded_id}`. Environment: {}",
-                &app.cfg.environment
-            );
-            return;
-        };
+        let mut plugins: Vec<Value> = vec![];
+        let cache_ttls = CacheTtls::from_config(app.cfg);
+        // the canvas still works without plugins, transforms just can't run
+        let mut plugin_error = None;
+        let blueprints = get_entity_blueprints(pool.as_ref(), &app.plugins)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Error getting blueprints for graph id `{decoded_id}`: {err}");
+                plugin_error = Some(err.message());
+                HashMap::new()
+            });
+        let natural_keys = NaturalKeys::from_blueprints(&blueprints, app.cfg);
+        let schemas = EntitySchemas::from_blueprints(&blueprints);
 
         while let Some(Ok(msg)) = msg_stream.next().await {
             match msg {
@@ -738,7 +1014,24 @@ pub async fn graphing_websocket_handler(
                             };
                             authenticated = true;
                             graph_uuid = graph.uuid;
+                            if actor_id_num.is_none() {
+                                actix_web::rt::spawn(push_notifications(
+                                    pool.get_ref().clone(),
+                                    session.clone(),
+                                    user_id_i64,
+                                    sqids.clone(),
+                                ));
+                            }
                             actor_id_num = Some(user_id_i64);
+                            org_id_num = Some(claims.org_id);
+                            plugins =
+                                get_available_plugins(pool.as_ref(), &app.plugins, claims.org_id)
+                                    .await
+                                    .unwrap_or_else(|err| {
+                                        error!("{err}");
+                                        plugin_error = Some(err.message());
+                                        vec![]
+                                    });
                             let _ = session
                                 .text(
                                     json!({
@@ -749,12 +1042,26 @@ pub async fn graphing_websocket_handler(
                                             "toastId": "graph",
                                             "message": "Your graph has loaded!",
                                         },
-                                        "plugins": get_available_plugins().await,
+                                        "plugins": plugins,
                                         "blueprints": blueprints,
                                     })
                                     .to_string(),
                                 )
                                 .await;
+                            if let Some(message) = plugin_error.take() {
+                                let _ = session
+                                    .text(
+                                        json!({
+                                            "action": "error",
+                                            "notification": {
+                                                "autoClose": 8000,
+                                                "message": message,
+                                            },
+                                        })
+                                        .to_string(),
+                                    )
+                                    .await;
+                            }
                         }
                     }
 
@@ -767,7 +1074,7 @@ pub async fn graphing_websocket_handler(
                             .await;
                         return;
                     };
-                    let Some(actor_id) = actor_id_num else {
+                    let (Some(actor_id), Some(org_id)) = (actor_id_num, org_id_num) else {
                         let _ = session.text(deauth_msg.to_string());
                         let _ = session
                             .close(Some(actix_ws::CloseCode::Policy.into()))
@@ -793,25 +1100,73 @@ pub async fn graphing_websocket_handler(
                             handle_materialized_read(&pool, graph_uuid, &mut session).await;
                         }
                         "create:entity" => {
-                            handle_create_entity(&pool, graph_uuid, event, &mut session, actor_id)
-                                .await;
+                            handle_create_entity(
+                                &pool,
+                                graph_uuid,
+                                event,
+                                &mut session,
+                                actor_id,
+                                &schemas,
+                            )
+                            .await;
                         }
                         "update:entity" => {
-                            handle_update_entity(&pool, graph_uuid, event, &mut session, actor_id)
-                                .await;
+                            handle_update_entity(
+                                &pool,
+                                graph_uuid,
+                                event,
+                                &mut session,
+                                actor_id,
+                                &schemas,
+                            )
+                            .await;
                         }
                         "delete:entity" => {
                             handle_delete_entity(&pool, graph_uuid, event, &mut session, actor_id)
                                 .await;
                         }
+                        "merge:entity" => {
+                            handle_merge_entity(&pool, graph_uuid, event, &mut session, actor_id)
+                                .await;
+                        }
+                        "split:entity" => {
+                            handle_split_entity(&pool, graph_uuid, event, &mut session, actor_id)
+                                .await;
+                        }
                         "transform:entity" => {
                             println!("HANDSLING 'transform:entity' CASE");
                             handle_transform_entity(
                                 &pool,
-                                graph_uuid,
                                 event,
                                 &mut session,
-                                actor_id,
+                                org_id,
+                                TransformCache {
+                                    plugins: &plugins,
+                                    ttls: &cache_ttls,
+                                },
+                                PersistContext {
+                                    graph_id: graph_uuid,
+                                    job_id: Uuid::nil(),
+                                    actor_id: Some(actor_id),
+                                    layout: Layout::default(),
+                                    keys: &natural_keys,
+                                },
+                            )
+                            .await;
+                        }
+                        "playbook:run" => {
+                            handle_playbook_run(
+                                &pool,
+                                event,
+                                &mut session,
+                                org_id,
+                                PersistContext {
+                                    graph_id: graph_uuid,
+                                    job_id: Uuid::nil(),
+                                    actor_id: Some(actor_id),
+                                    layout: Layout::default(),
+                                    keys: &natural_keys,
+                                },
                             )
                             .await;
                         }
@@ -832,13 +1187,69 @@ pub async fn graphing_websocket_handler(
     Ok(response)
 }
 
+// What a transform request needs to address the result cache
+struct TransformCache<'a> {
+    plugins: &'a [Value],
+    ttls: &'a CacheTtls,
+}
+
+impl TransformCache<'_> {
+    // Plugin versions come from `ob ls entities`
+    fn plugin_version(&self, entity: &Value) -> Option<String> {
+        let plugin = transform_cache::plugin_label(entity)?;
+        self.plugins
+            .iter()
+            .find(|p| {
+                p["label"]
+                    .as_str()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(plugin))
+            })
+            .and_then(|p| match &p["version"] {
+                Value::String(v) => Some(v.clone()),
+                Value::Number(v) => Some(v.to_string()),
+                _ => None,
+            })
+    }
+
+    // Upgrading a plugin invalidates its cached results, results of
+    // transforms that read secrets are only shared with the same user
+    async fn stamp(
+        &self,
+        pool: &PgPool,
+        org_id: i64,
+        actor_id: Option<i64>,
+        entity: &Value,
+    ) -> Option<CacheStamp> {
+        let plugin = transform_cache::plugin_label(entity)?;
+        let transform = entity["transform"].as_str()?;
+        let version = self
+            .plugin_version(entity)
+            .unwrap_or_else(|| "unversioned".to_string());
+        let ttl_secs = self.ttls.ttl_secs(plugin, transform);
+        if ttl_secs <= 0 {
+            return None;
+        }
+        let actor_id = match transform_cache::reads_secrets(pool, org_id, entity).await {
+            Ok(true) => Some(actor_id?),
+            Ok(false) => None,
+            Err(e) => {
+                error!("transform secrets lookup failed: {e}");
+                return None;
+            }
+        };
+        CacheStamp::new(org_id, actor_id, entity, &version, ttl_secs)
+    }
+}
+
 // Enqueue a transform job for the worker
+// `ctx` carries everything but the job, which is only known once enqueued
 async fn handle_transform_entity(
     pool: &PgPool,
-    graph_uuid: Uuid,
     event: WebSocketMessage,
     session: &mut Session,
-    actor_id: i64,
+    org_id: i64,
+    cache: TransformCache<'_>,
+    ctx: PersistContext<'_>,
 ) {
     println!("handle_transform_entity()");
     let Some(entity) = event.entity else {
@@ -858,22 +1269,103 @@ async fn handle_transform_entity(
         return;
     };
 
+    let ctx = PersistContext {
+        layout: event
+            .layout
+            .as_deref()
+            .and_then(Layout::parse)
+            .unwrap_or_default(),
+        ..ctx
+    };
+    let stamp = if event.use_cache.unwrap_or(true) {
+        cache.stamp(pool, org_id, ctx.actor_id, &entity).await
+    } else {
+        None
+    };
+    if let Some(stamp) = stamp
+        .as_ref()
+        .filter(|_| !event.force_refresh.unwrap_or(false))
+    {
+        match transform_cache::lookup(pool, org_id, &stamp.key).await {
+            Ok(Some(cached)) => {
+                let job_id = cached.job_id.unwrap_or_else(Uuid::new_v4);
+                let _ = session
+                    .text(
+                        json!({
+                            "action": "transform:started",
+                            "job_id": job_id,
+                            "entity": entity,
+                            "cached": true,
+                            "cached_at": cached.created_at,
+                        })
+                        .to_string(),
+                    )
+                    .await;
+                if let Err(e) = persist_transform_outputs(
+                    pool,
+                    PersistContext { job_id, ..ctx },
+                    &entity,
+                    &cached.result,
+                    session,
+                )
+                .await
+                {
+                    error!("persist cached transform outputs failed: {e}");
+                }
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => error!("transform cache lookup failed: {e}"),
+        }
+        // The same lookup is already running on this entity, follow it
+        // instead of spending another upstream request
+        let entity_id = entity["id"].as_str().unwrap_or_default();
+        match transform_cache::active_job(pool, org_id, &stamp.key, ctx.graph_id, entity_id).await {
+            Ok(Some(job_id)) => {
+                let _ = session
+                    .text(
+                        json!({
+                            "action": "transform:started",
+                            "job_id": job_id,
+                            "entity": entity,
+                            "deduplicated": true,
+                        })
+                        .to_string(),
+                    )
+                    .await;
+                stream_job_events(pool, session, job_id, entity).await;
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => error!("transform dedup lookup failed: {e}"),
+        }
+    }
+
     // Build job payload expected by worker dev runner: `ob run -T '<payload>'`
-    let payload = json!({
+    // (graph_id, actor_id and org_id are stamped in by the queue)
+    let mut payload = json!({
         "action": "transform:entity",
-        "graph_id": graph_uuid,
         "entity": entity,
-        "actor_id": actor_id,
+        // recorded in the provenance of the results
+        "plugin_version": cache.plugin_version(&entity),
+        "layout": event.layout,
     });
+    if let Some(stamp) = stamp {
+        payload["cache"] = json!(stamp);
+    }
     println!("enqueye job()");
 
-    match jobs::enqueue_job(
+    match enqueue_org_job(
         pool,
         jobs::NewJob {
             payload,
             priority: None,
             max_attempts: None,
             scheduled_at: None,
+            org_id: Some(org_id),
+            actor_id: ctx.actor_id,
+            graph_id: Some(ctx.graph_id),
+            parent_id: None,
         },
     )
     .await
@@ -890,17 +1382,9 @@ async fn handle_transform_entity(
                     .to_string(),
                 )
                 .await;
-            // Stream events for this job until it completes or times out,
-            // then persist transform outputs as entity/edge events and emit UI updates.
-            stream_job_events(
-                pool,
-                session,
-                job.job_id,
-                graph_uuid,
-                job.payload["entity"].clone(),
-                actor_id,
-            )
-            .await;
+            // Relay events for this job until it completes or times out, the
+            // worker saves its outputs whether or not anyone is listening
+            stream_job_events(pool, session, job.job_id, job.payload["entity"].clone()).await;
         }
         Err(e) => {
             error!("enqueue transform job failed: {}", e);
@@ -911,7 +1395,7 @@ async fn handle_transform_entity(
                         "notification": {
                             "toastId": entity["id"],
                             "autoClose": 8000,
-                            "message": format!("Failed to enqueue transform: {}", e),
+                            "message": e.message,
                         },
                     })
                     .to_string(),
@@ -921,13 +1405,155 @@ async fn handle_transform_entity(
     }
 }
 
+// Start a saved playbook from an entity, the worker enqueues its transforms
+// and persists their outputs, the run's events are streamed back here
+async fn handle_playbook_run(
+    pool: &PgPool,
+    event: WebSocketMessage,
+    session: &mut Session,
+    org_id: i64,
+    ctx: PersistContext<'_>,
+) {
+    let entity_id = event.entity.as_ref().map(|e| e["id"].clone());
+    let result = match (entity_id, event.playbook_id) {
+        (Some(entity_id), Some(playbook_id)) => {
+            enqueue_org_job(
+                pool,
+                jobs::NewJob {
+                    payload: json!({
+                        "kind": "playbook",
+                        "playbook_id": playbook_id,
+                        "entity": { "id": entity_id },
+                        "layout": event.layout,
+                    }),
+                    priority: None,
+                    max_attempts: Some(1),
+                    scheduled_at: None,
+                    org_id: Some(org_id),
+                    actor_id: ctx.actor_id,
+                    graph_id: Some(ctx.graph_id),
+                    parent_id: None,
+                },
+            )
+            .await
+        }
+        _ => Err(AppError {
+            message: "Playbook request missing entity or playbook.",
+        }),
+    };
+    match result {
+        Ok(job) => {
+            let _ = session
+                .text(
+                    json!({
+                        "action": "playbook:started",
+                        "job_id": job.job_id,
+                        "playbook_id": job.payload["playbook_id"],
+                        "entity": job.payload["entity"],
+                    })
+                    .to_string(),
+                )
+                .await;
+            // no source entity, the run's summary result isn't a transform's
+            stream_job_events(pool, session, job.job_id, Value::Null).await;
+        }
+        Err(e) => {
+            let _ = session
+                .text(
+                    json!({
+                        "action": "error",
+                        "notification": {
+                            "autoClose": 8000,
+                            "message": e.message,
+                        },
+                    })
+                    .to_string(),
+                )
+                .await;
+        }
+    }
+}
+
+// Large results are only referenced by artifact id in the result event
+async fn result_data(pool: &PgPool, payload: &Value) -> Option<Value> {
+    if let Some(data) = payload.get("data") {
+        return Some(data.clone());
+    }
+    let artifact_id = payload
+        .get("artifact_id")
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok())?;
+    match artifacts::get_artifact(pool, artifact_id).await {
+        Ok(Some((_, bytes))) => serde_json::from_slice(&bytes).ok(),
+        Ok(None) => None,
+        Err(e) => {
+            error!("load result artifact {artifact_id} failed: {e}");
+            None
+        }
+    }
+}
+
+// Pushes the user's new notifications to an open canvas, until the
+// connection closes
+async fn push_notifications(
+    pool: PgPool,
+    mut session: Session,
+    user_id: i64,
+    sqids: web::Data<Sqids>,
+) {
+    const PAGE: i64 = 100;
+    // what the user had when the canvas opened was already there to list
+    let mut seen = match notifications::latest(&pool, user_id).await {
+        Ok(seen) => seen,
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    };
+    loop {
+        sleep(Duration::from_secs(5)).await;
+        if session.ping(b"").await.is_err() {
+            return;
+        }
+        // a burst larger than a page drains over the following pages
+        loop {
+            let list = match notifications::since(&pool, user_id, seen, PAGE).await {
+                Ok(list) => list,
+                Err(err) => {
+                    error!("{err}");
+                    break;
+                }
+            };
+            let full = list.len() as i64 == PAGE;
+            for n in list {
+                seen = Some((n.created_at, n.notification_id));
+                let message = json!({
+                    "action": "notification",
+                    "notification": {
+                        "autoClose": 8000,
+                        "message": n.message,
+                    },
+                    "data": NotificationView::new(&sqids, n),
+                });
+                if session.text(message.to_string()).await.is_err() {
+                    return;
+                }
+            }
+            if !full {
+                break;
+            }
+        }
+    }
+}
+
+// Relays a job's events to the canvas. Transforms report what the worker
+// saved from their result in a `saved` progress record, sent on with the
+// result once it arrives.
 async fn stream_job_events(
     pool: &PgPool,
     session: &mut Session,
     job_id: Uuid,
-    graph_uuid: Uuid,
     source_entity: Value,
-    actor_id: i64,
 ) {
     println!("stream_job_events()");
 
@@ -939,13 +1565,13 @@ async fn stream_job_events(
     println!("let Ok(stream)");
 
     let mut last_version: i32 = 0;
-    let start = std::time::Instant::now();
-    let timeout = std::time::Duration::from_secs(60);
-    let fallback_delay = std::time::Duration::from_secs(5);
-    let mut fallback_attempted = false;
+    let mut saved = Value::Null;
+    // plugins that keep streaming records keep the socket listening
+    let mut last_activity = std::time::Instant::now();
+    let idle_timeout = std::time::Duration::from_secs(60);
     loop {
         println!("ensure_stream() loop");
-        if start.elapsed() > timeout {
+        if last_activity.elapsed() > idle_timeout {
             println!("ensure_stream() loop BREAK");
 
             break;
@@ -971,44 +1597,10 @@ async fn stream_job_events(
         };
         if rows.is_empty() {
             println!("is empty!");
-            if !fallback_attempted && start.elapsed() > fallback_delay {
-                fallback_attempted = true;
-                match jobs::get_job(pool, job_id).await {
-                    Ok(Some(job)) => {
-                        if job.status == "enqueued" && job.lease_owner.is_none() {
-                            match inline_job_fallback(pool, job_id, &job.payload).await {
-                                Ok(true) => {
-                                    info!("inline fallback executed for job {}", job_id);
-                                }
-                                Ok(false) => {
-                                    info!(
-                                        "inline fallback skipped for job {} (already claimed)",
-                                        job_id
-                                    );
-                                }
-                                Err(err) => {
-                                    error!(
-                                        "inline job fallback failed for job {}: {}",
-                                        job_id, err
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    Ok(None) => {
-                        error!(
-                            "inline fallback unable to load job {} from database",
-                            job_id
-                        );
-                    }
-                    Err(err) => {
-                        error!("inline fallback get_job error for {}: {}", job_id, err);
-                    }
-                }
-            }
             sleep(Duration::from_millis(250)).await;
             continue;
         }
+        last_activity = std::time::Instant::now();
         let mut done = false;
         for r in rows {
             println!("ensure_stream() for r in rows");
@@ -1025,34 +1617,48 @@ async fn stream_job_events(
                 )
                 .await;
 
+            if payload["type"] == "progress" && payload["data"]["saved"] == true {
+                saved = payload["data"].clone();
+                continue;
+            }
+            // Playbook runs report each step's new entities as they land
+            if let Some(entities) = payload["data"]["entities"]
+                .as_array()
+                .filter(|_| payload["type"] == "progress")
+            {
+                let edges = payload["data"]["edges"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                let _ = session
+                    .text(
+                        json!({
+                            "action": "transform:completed",
+                            "notification": {
+                                "toastId": job_id,
+                                "message": payload["data"]["note"],
+                            },
+                            "job": { "job_id": payload["data"]["job_id"], "status": "completed" },
+                            "entities": entities.iter().map(ui_node).collect::<Vec<_>>(),
+                            "edges": edges.iter().map(ui_edge).collect::<Vec<_>>(),
+                        })
+                        .to_string(),
+                    )
+                    .await;
+            }
+
             if payload
                 .get("type")
                 .and_then(|v| v.as_str())
                 .map(|t| t == "result" || t == "error")
                 .unwrap_or(false)
             {
-                // On result, try to persist transform outputs as entity/edge events
-                if payload
-                    .get("type")
-                    .and_then(|v| v.as_str())
-                    .map(|t| t == "result")
-                    .unwrap_or(false)
+                if payload["type"] == "result"
+                    && !source_entity.is_null()
+                    && let Some(data) = result_data(pool, &payload).await
                 {
-                    if let Some(data) = payload.get("data") {
-                        if let Err(e) = persist_transform_outputs(
-                            pool,
-                            graph_uuid,
-                            job_id,
-                            &source_entity,
-                            data,
-                            session,
-                            actor_id,
-                        )
-                        .await
-                        {
-                            error!("persist transform outputs failed: {}", e);
-                        }
-                    }
+                    let job = load_job(pool, job_id).await;
+                    transform_completed(session, job_id, job, &source_entity, &data, &saved).await;
                 }
                 done = true;
                 break;
@@ -1064,178 +1670,113 @@ async fn stream_job_events(
     }
 }
 
-// Persist transform outputs by creating entity and edge events, then notify UI with created entities
+async fn load_job(pool: &PgPool, job_id: Uuid) -> Option<jobs::Job> {
+    jobs::get_job(pool, job_id).await.unwrap_or_else(|err| {
+        error!("failed to load job {job_id} for transform results: {err}");
+        None
+    })
+}
+
+// Cached results have no job of their own to save them, they're persisted
+// here as entity and edge events before the UI is notified
 async fn persist_transform_outputs(
     pool: &PgPool,
-    graph_uuid: Uuid,
-    job_id: Uuid,
+    ctx: PersistContext<'_>,
     source_entity: &Value,
     outputs: &Value,
     session: &mut Session,
-    actor_id: i64,
 ) -> Result<(), sqlx::Error> {
-    println!("persist_transform_outputs()");
-
-    // Extract source entity context
+    let job_id = ctx.job_id;
     let src_id = source_entity
         .get("id")
         .and_then(|v| v.as_str())
         .and_then(|s| Uuid::parse_str(s).ok());
-    let src_pos = source_entity.get("position");
     let transform_label = source_entity
         .get("transform")
         .and_then(|v| v.as_str())
         .unwrap_or("transform");
     let Some(src_id) = src_id else {
-        println!("Some(src_id()");
         return Ok(());
     };
-    println!("persist_transform_outputs()");
 
-    // Normalize outputs to an array of objects
-    let items: Vec<Value> = match outputs {
-        Value::Array(a) => a.clone(),
-        Value::Object(_) => vec![outputs.clone()],
-        _ => vec![],
-    };
-
-    // Compute a simple placement fan-out around the source
-    let (base_x, base_y) = if let Some(p) = src_pos.and_then(|p| p.as_object()) {
-        (
-            p.get("x").and_then(|v| v.as_f64()).unwrap_or(0.0),
-            p.get("y").and_then(|v| v.as_f64()).unwrap_or(0.0),
-        )
-    } else {
-        (0.0_f64, 0.0_f64)
+    let job = load_job(pool, job_id).await;
+    // cached replays are attributed to the job that fetched the results
+    let plugin_version = job.as_ref().and_then(|j| {
+        j.payload["plugin_version"]
+            .as_str()
+            .or_else(|| j.payload["cache"]["plugin_version"].as_str())
+    });
+    let origin = Origin {
+        transform: transform_label,
+        plugin: transform_cache::plugin_label(source_entity),
+        plugin_version,
+        retrieved_at: job
+            .as_ref()
+            .and_then(|j| j.finished_at)
+            .unwrap_or_else(Utc::now),
     };
 
-    let mut ui_entities: Vec<Value> = vec![];
-    let mut ui_edges: Vec<Value> = vec![];
-
-    for (i, mut item) in items.into_iter().enumerate() {
-        // Determine label and copy remaining fields as data
-        let label_s = item
-            .get("label")
-            .and_then(|v| v.as_str())
-            .unwrap_or("unknown")
-            .to_string();
-        println!("{}", label_s);
-        // Build entity document
-        let entity_id = Uuid::new_v4();
-        // Arrange entities in rows of 4: increment X across, then Y down by 200.
-        let step_x = 460.0_f64;
-        let step_y = 260.0_f64;
-        let col = (i % 4) as f64;
-        let row = (i / 4) as f64;
-        let pos = json!({
-            "x": base_x + ((col + 1.0) * step_x),
-            "y": base_y + (row * step_y),
-        });
-
-        // Remove non-data fields
-        if let Some(obj) = item.as_object_mut() {
-            obj.remove("edge_label");
-            obj.remove("label");
-        }
-        let mut entity_doc = json!({
-            "id": entity_id,
-            "position": pos,
-            "label": label_s,
-            "data": {}
-        });
-        println!("entity-doc: {}", entity_doc);
-
-        if let (Some(dst), Value::Object(src)) = (entity_doc.get_mut("data"), &item) {
-            if let Some(dst_obj) = dst.as_object_mut() {
-                for (k, v) in src.iter() {
-                    dst_obj.insert(k.clone(), v.clone());
-                }
-                dst_obj.entry("label".to_string()).or_insert(json!(label_s));
-            }
-        }
-
-        let ev_entity = AppendEvent {
-            category: "entity".into(),
-            key: graph_uuid.to_string(),
-            event_type: "create".into(),
-            payload: entity_doc.clone(),
-            valid_from: Utc::now(),
-            valid_to: None,
-            correlation_id: Some(job_id),
-            causation_id: None,
-            expected_version: None,
-            actor_id: Some(actor_id),
-        };
-        println!("evs aooebd {:?}", ev_entity);
-        let _ = eventstore::append_event(pool, ev_entity).await;
+    let created = persist::create_children(
+        pool,
+        ctx,
+        src_id,
+        &source_entity["position"],
+        origin,
+        &findings::output_items(outputs),
+    )
+    .await?;
+    // the same shape the worker reports for the results it saves
+    let saved = json!({
+        "results_count": created.len(),
+        "entities": created.iter().filter(|c| !c.reused).map(|c| &c.entity).collect::<Vec<_>>(),
+        "edges": created.iter().filter_map(|c| c.edge.as_ref()).collect::<Vec<_>>(),
+    });
+    transform_completed(session, job_id, job, source_entity, outputs, &saved).await;
+    Ok(())
+}
 
-        // Append edge:create event from source -> new entity
-        let edge_id = Uuid::new_v4();
-        let edge_label = transform_label;
-        let edge_doc = json!({
-            "id": edge_id,
-            "source": src_id,
-            "target": entity_id,
-            "label": edge_label,
-            "data": {   }
-        });
+// Tells the canvas a transform finished, with the entities and edges saved
+// from its result
+async fn transform_completed(
+    session: &mut Session,
+    job_id: Uuid,
+    job: Option<jobs::Job>,
+    source_entity: &Value,
+    outputs: &Value,
+    saved: &Value,
+) {
+    // entities already on the canvas only gain an edge
+    let ui_entities: Vec<Value> = saved["entities"]
+        .as_array()
+        .map(|entities| entities.iter().map(ui_node).collect())
+        .unwrap_or_default();
+    let ui_edges: Vec<Value> = saved["edges"]
+        .as_array()
+        .map(|edges| edges.iter().map(ui_edge).collect())
+        .unwrap_or_default();
+    let results_count = saved["results_count"].as_u64().unwrap_or_default() as usize;
 
-        let ev_edge = AppendEvent {
-            category: "edge".into(),
-            key: graph_uuid.to_string(),
-            event_type: "create".into(),
-            payload: edge_doc,
-            valid_from: Utc::now(),
-            valid_to: None,
-            correlation_id: Some(job_id),
-            causation_id: None,
-            expected_version: None,
-            actor_id: Some(actor_id),
-        };
-        println!("edfge append {:?}", ev_edge);
-        let _ = eventstore::append_event(pool, ev_edge).await;
-
-        // Immediately inform UI about the new entity (ReactFlow node)
-        // The projector will handle edges; optionally a later read refresh will include them
-        let mut ui_entity = entity_doc.clone();
-        if let Some(obj) = ui_entity.as_object_mut() {
-            obj.insert("type".to_string(), json!("edit"));
-        }
-        ui_entities.push(ui_entity);
-        ui_edges.push(json!({
-            "id": edge_id,
-            "source": src_id,
-            "target": entity_id,
-            "label": edge_label,
-            "data": {  },
-            "type": "sfloat"
-        }));
-        // Immediately inform UI about the new edge so it appears without a refresh
-    }
     let toast_id = source_entity
         .get("id")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string())
         .unwrap_or_else(|| job_id.to_string());
 
-    let mut job_payload = match jobs::get_job(pool, job_id).await {
-        Ok(Some(mut job)) => {
+    let mut job_payload = match job {
+        Some(mut job) => {
             job.status = "completed".to_string();
             serde_json::to_value(job).unwrap_or_else(|_| json!({"job_id": job_id}))
         }
-        Ok(None) => json!({"job_id": job_id}),
-        Err(err) => {
-            error!(
-                "failed to load job {} for websocket completion payload: {}",
-                job_id, err
-            );
-            json!({"job_id": job_id})
-        }
+        None => json!({"job_id": job_id}),
     };
 
     if let Some(job_obj) = job_payload.as_object_mut() {
         job_obj.insert("status".into(), json!("completed"));
-        job_obj.insert("results_count".into(), json!(ui_entities.len()));
+        job_obj.insert("results_count".into(), json!(results_count));
+        job_obj.insert(
+            "reused_count".into(),
+            json!(results_count.saturating_sub(ui_entities.len())),
+        );
         job_obj.insert("result".into(), outputs.clone());
     }
 
@@ -1253,187 +1794,23 @@ async fn persist_transform_outputs(
     println!("sending message: {}", message);
 
     let _ = session.text(message.to_string()).await;
-    Ok(())
-}
-
-#[derive(Debug)]
-enum InlineFallbackError {
-    Sql(sqlx::Error),
-    Json(serde_json::Error),
-    Io(std::io::Error),
-    Command { code: i32, stderr: String },
-}
-
-impl fmt::Display for InlineFallbackError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            InlineFallbackError::Sql(err) => write!(f, "sql error: {}", err),
-            InlineFallbackError::Json(err) => write!(f, "json error: {}", err),
-            InlineFallbackError::Io(err) => write!(f, "io error: {}", err),
-            InlineFallbackError::Command { code, stderr } => {
-                write!(f, "ob exit code {}: {}", code, stderr)
-            }
-        }
-    }
-}
-
-impl std::error::Error for InlineFallbackError {}
-
-impl From<sqlx::Error> for InlineFallbackError {
-    fn from(value: sqlx::Error) -> Self {
-        InlineFallbackError::Sql(value)
-    }
-}
-
-impl From<serde_json::Error> for InlineFallbackError {
-    fn from(value: serde_json::Error) -> Self {
-        InlineFallbackError::Json(value)
-    }
 }
 
-impl From<std::io::Error> for InlineFallbackError {
-    fn from(value: std::io::Error) -> Self {
-        InlineFallbackError::Io(value)
-    }
+// Created entities and edges as ReactFlow nodes and edges, so they appear
+// without a refresh
+fn ui_node(entity: &Value) -> Value {
+    let mut node = entity.clone();
+    node["type"] = json!("edit");
+    node
 }
 
-async fn execute_job_payload_inline(payload: &Value) -> Result<Option<Value>, InlineFallbackError> {
-    let payload_s = serde_json::to_string(payload)?;
-    let mut cmd = TokioCommand::new("ob");
-    cmd.arg("run").arg("-T").arg(payload_s);
-    let output = cmd.output().await?;
-
-    if !output.status.success() {
-        let code = output.status.code().unwrap_or(-1);
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        return Err(InlineFallbackError::Command { code, stderr });
-    }
-
-    let stdout_bytes = output.stdout;
-    if stdout_bytes.is_empty() {
-        return Ok(None);
-    }
-
-    let stdout_str = String::from_utf8_lossy(&stdout_bytes).to_string();
-
-    let parsed = (|| {
-        if let Ok(v) = serde_json::from_str::<Value>(&stdout_str) {
-            return Some(v);
-        }
-        if let Some(idx) = stdout_str.find('{').or_else(|| stdout_str.find('[')) {
-            let tail = &stdout_str[idx..];
-            if let Ok(v) = serde_json::from_str::<Value>(tail.trim()) {
-                return Some(v);
-            }
-        }
-        stdout_str
-            .lines()
-            .rev()
-            .find(|l| !l.trim().is_empty())
-            .and_then(|line| serde_json::from_str::<Value>(line.trim()).ok())
-    })();
-
-    Ok(parsed)
-}
-
-async fn append_job_event(
-    pool: &PgPool,
-    job_id: Uuid,
-    payload: Value,
-    actor_id: Option<i64>,
-) -> Result<(), InlineFallbackError> {
-    let actor_id = actor_id.unwrap_or(0);
-    eventstore::append_event(
-        pool,
-        AppendEvent {
-            category: "job".into(),
-            key: job_id.to_string(),
-            event_type: "job:event".into(),
-            payload,
-            valid_from: Utc::now(),
-            valid_to: None,
-            correlation_id: None,
-            causation_id: None,
-            expected_version: None,
-            actor_id: Some(actor_id),
-        },
-    )
-    .await?;
-    Ok(())
-}
-
-async fn inline_job_fallback(
-    pool: &PgPool,
-    job_id: Uuid,
-    job_payload: &Value,
-) -> Result<bool, InlineFallbackError> {
-    const OWNER: &str = "inline-fallback";
-
-    let actor_id = Some(
-        job_payload
-            .get("actor_id")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0),
-    );
-
-    if !jobs::try_claim_job(pool, job_id, OWNER).await? {
-        return Ok(false);
-    }
-
-    jobs::start_job(pool, job_id, OWNER).await?;
-
-    append_job_event(
-        pool,
-        job_id,
-        json!({
-            "type": "progress",
-            "data": { "note": "inline fallback executing" }
-        }),
-        actor_id,
-    )
-    .await?;
-
-    match execute_job_payload_inline(job_payload).await {
-        Ok(Some(result)) => {
-            append_job_event(
-                pool,
-                job_id,
-                json!({
-                    "type": "result",
-                    "data": result
-                }),
-                actor_id,
-            )
-            .await?;
-            jobs::complete_job(pool, job_id, OWNER).await?;
-        }
-        Ok(None) => {
-            append_job_event(
-                pool,
-                job_id,
-                json!({
-                    "type": "error",
-                    "data": { "message": "inline fallback produced no data" }
-                }),
-                actor_id,
-            )
-            .await?;
-            jobs::fail_job(pool, job_id, OWNER, 10).await?;
-        }
-        Err(err) => {
-            append_job_event(
-                pool,
-                job_id,
-                json!({
-                    "type": "error",
-                    "data": { "message": err.to_string() }
-                }),
-                actor_id,
-            )
-            .await?;
-            jobs::fail_job(pool, job_id, OWNER, 10).await?;
-        }
-    }
-
-    Ok(true)
+fn ui_edge(edge: &Value) -> Value {
+    json!({
+        "id": edge["id"],
+        "source": edge["source"],
+        "target": edge["target"],
+        "label": edge["label"],
+        "data": {},
+        "type": "sfloat"
+    })
 }
@@ -1,32 +1,185 @@
-use actix_web::{HttpResponse, Responder, post, web};
-use common::jobs::{self, NewJob};
+use actix_web::{
+    HttpResponse, Result, get, post,
+    web::{Data, Json, Path},
+};
+use common::artifacts;
+use common::errors::AppError;
+use common::jobs::{self, EnqueueError, Job, JobKind, NewJob};
+use log::error;
 use serde::Deserialize;
 use serde_json::Value as JsonValue;
+use sqids::Sqids;
+use sqlx::{PgPool, types::Uuid};
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
 
 #[derive(Deserialize)]
 pub struct EnqueueJobBody {
     pub payload: JsonValue,
+    // sqid of the case the job operates on
+    pub case_id: Option<String>,
     pub priority: Option<i32>,
     pub max_attempts: Option<i32>,
     pub scheduled_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+/// `jobs::enqueue_org_job`, with its refusals as the error shown to users.
+pub(crate) async fn enqueue_org_job(pool: &PgPool, job: NewJob) -> Result<Job, AppError> {
+    jobs::enqueue_org_job(pool, job)
+        .await
+        .map_err(|err| match err {
+            EnqueueError::Rejected(message) => AppError { message },
+            EnqueueError::Sql(err) => {
+                error!("{err}");
+                AppError {
+                    message: "We ran into an error enqueueing this job.",
+                }
+            }
+        })
+}
+
 #[post("/jobs")]
 pub async fn enqueue_job_handler(
-    pool: web::Data<sqlx::PgPool>,
-    body: web::Json<EnqueueJobBody>,
-) -> impl Responder {
-    let b = body.into_inner();
-    let req = NewJob {
-        payload: b.payload,
-        priority: b.priority,
-        max_attempts: b.max_attempts,
-        scheduled_at: b.scheduled_at,
-    };
-    match jobs::enqueue_job(&pool, req).await {
-        Ok(job) => HttpResponse::Ok().json(job),
-        Err(e) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
-            "message": format!("enqueue failed: {}", e)
-        })),
+    pool: db::Database,
+    auth: AuthMiddleware,
+    body: Json<EnqueueJobBody>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let mut b = body.into_inner();
+    // set by the server when it enqueues these jobs itself, from a client
+    // they could address another org's cache entries or schedules
+    if let Some(obj) = b.payload.as_object_mut() {
+        for field in ["cache", "plugin_version", "schedule_id", "playbook"] {
+            obj.remove(field);
+        }
+    }
+
+    let kind = JobKind::from_payload(&b.payload);
+    if kind == Some(JobKind::Purge) && auth.user_type != "owner" {
+        return Err(AppError {
+            message: "Only organization owners can purge jobs.",
+        });
+    }
+    // package jobs are queued by the plugin endpoints, which own their state
+    if matches!(
+        kind,
+        Some(JobKind::PluginInstall | JobKind::PluginUninstall)
+    ) {
+        return Err(AppError {
+            message: "Plugins are installed and removed through the plugins API.",
+        });
+    }
+    if kind == Some(JobKind::ScheduledRerun) {
+        return Err(AppError {
+            message: "Scheduled reruns are queued by their schedules.",
+        });
     }
+    if kind == Some(JobKind::PluginSync) && auth.user_type != "owner" {
+        return Err(AppError {
+            message: "Only organization owners can sync plugins.",
+        });
+    }
+
+    let graph_id = match b.case_id {
+        Some(case_id) => {
+            let ids = sqids.decode(&case_id);
+            let decoded_id = ids.first().ok_or(AppError {
+                message: "Invalid case ID.",
+            })?;
+            let graph = sqlx::query!(
+                "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+                *decoded_id as i64,
+                auth.account_id
+            )
+            .fetch_one(pool.as_ref())
+            .await
+            .map_err(|_| AppError {
+                message: "We ran into an error getting this case.",
+            })?;
+            graph.uuid
+        }
+        None => None,
+    };
+
+    let job = enqueue_org_job(
+        pool.as_ref(),
+        NewJob {
+            payload: b.payload,
+            priority: b.priority,
+            max_attempts: b.max_attempts,
+            scheduled_at: b.scheduled_at,
+            org_id: Some(auth.org_id),
+            actor_id: Some(auth.account_id),
+            graph_id,
+            parent_id: None,
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+#[get("/jobs/{job_id}/artifacts")]
+pub async fn list_job_artifacts_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let job_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid job id.",
+    })?;
+    let job = jobs::get_job(pool.as_ref(), job_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error getting this job.",
+            }
+        })?
+        .filter(|job| job.org_id == Some(auth.org_id))
+        .ok_or(AppError {
+            message: "Job not found.",
+        })?;
+    let items = artifacts::list_job_artifacts(pool.as_ref(), job.job_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error listing this job's artifacts.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[get("/artifacts/{artifact_id}")]
+pub async fn get_artifact_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let artifact_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid artifact id.",
+    })?;
+    let (artifact, bytes) = artifacts::get_artifact(pool.as_ref(), artifact_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error loading this artifact.",
+            }
+        })?
+        .filter(|(artifact, _)| artifact.org_id == Some(auth.org_id))
+        .ok_or(AppError {
+            message: "Artifact not found.",
+        })?;
+    let filename = artifact
+        .filename
+        .unwrap_or_else(|| artifact.artifact_id.to_string());
+    Ok(HttpResponse::Ok()
+        .content_type(artifact.media_type)
+        .append_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename.replace('"', "'")),
+        ))
+        .body(bytes))
 }
@@ -1,20 +1,33 @@
 use actix_web::{HttpResponse, Responder, get, web};
+use serde_json::json;
 
-use crate::schemas::Notification;
+use crate::AppData;
 
 mod attachments;
 mod cases;
+mod duplicates;
 mod entities;
 mod events;
 mod graphing;
 mod graphs;
 mod jobs;
+mod notifications;
+mod observables;
 mod organization;
+mod playbooks;
+mod plugins;
+mod schedules;
+mod search;
+mod secrets;
 mod user;
 
 #[get("/health")]
-pub async fn healthcheck_handler() -> impl Responder {
-    HttpResponse::Ok().json(Notification { message: "pong" })
+pub async fn healthcheck_handler(app: AppData) -> impl Responder {
+    let plugin_service = app.plugin_service.health().await;
+    HttpResponse::Ok().json(json!({
+        "message": "pong",
+        "plugin_service": plugin_service,
+    }))
 }
 
 pub fn config(conf: &mut web::ServiceConfig) {
@@ -27,6 +40,11 @@ pub fn config(conf: &mut web::ServiceConfig) {
         .service(cases::list_case_activity_handler)
         .service(cases::get_case_activity_summary_handler)
         .service(cases::get_case_chord_handler)
+        .service(cases::get_case_provenance_handler)
+        .service(cases::get_case_correlations_handler)
+        .service(cases::query_case_handler)
+        .service(duplicates::list_duplicates_handler)
+        .service(duplicates::dismiss_duplicate_handler)
         .service(events::append_event_handler)
         .service(cases::get_case_stats_handler)
         .service(user::register_user_handler)
@@ -35,6 +53,38 @@ pub fn config(conf: &mut web::ServiceConfig) {
         .service(user::refresh_handler)
         .service(user::get_me_handler)
         .service(jobs::enqueue_job_handler)
+        .service(jobs::list_job_artifacts_handler)
+        .service(jobs::get_artifact_handler)
+        .service(schedules::create_schedule_handler)
+        .service(schedules::list_schedules_handler)
+        .service(schedules::update_schedule_handler)
+        .service(schedules::delete_schedule_handler)
+        .service(schedules::list_schedule_findings_handler)
+        .service(playbooks::create_playbook_handler)
+        .service(playbooks::list_playbooks_handler)
+        .service(playbooks::get_playbook_handler)
+        .service(playbooks::update_playbook_handler)
+        .service(playbooks::delete_playbook_handler)
+        .service(plugins::get_plugin_sync_handler)
+        .service(plugins::sync_plugins_handler)
+        .service(plugins::list_plugin_packages_handler)
+        .service(plugins::install_plugin_package_handler)
+        .service(plugins::upload_plugin_package_handler)
+        .service(plugins::uninstall_plugin_package_handler)
+        .service(plugins::list_plugin_publishers_handler)
+        .service(plugins::trust_plugin_publisher_handler)
+        .service(plugins::untrust_plugin_publisher_handler)
+        .service(plugins::list_plugins_handler)
+        .service(plugins::enable_plugin_handler)
+        .service(plugins::disable_plugin_handler)
+        .service(notifications::list_notifications_handler)
+        .service(notifications::read_notification_handler)
+        .service(notifications::read_all_notifications_handler)
+        .service(observables::extract_observables_handler)
+        .service(search::search_handler)
+        .service(secrets::list_secrets_handler)
+        .service(secrets::put_secret_handler)
+        .service(secrets::delete_secret_handler)
         .service(graphs::create_graph_handler)
         .service(graphs::update_graph_handler)
         .service(graphs::delete_graph_handler)
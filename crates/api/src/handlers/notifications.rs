@@ -0,0 +1,94 @@
+use actix_web::{
+    HttpResponse, Result, get, post,
+    web::{Data, Path, Query},
+};
+use common::errors::AppError;
+use common::notifications::{self, Notification};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqids::Sqids;
+use sqlx::types::Uuid;
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+#[derive(Deserialize)]
+pub struct NotificationsQuery {
+    pub unread: Option<bool>,
+    pub skip: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// A notification with its cases as the ids the API uses elsewhere.
+#[derive(Serialize)]
+pub struct NotificationView {
+    pub case_id: Option<String>,
+    pub related_case_id: Option<String>,
+    #[serde(flatten)]
+    pub notification: Notification,
+}
+
+impl NotificationView {
+    pub fn new(sqids: &Sqids, notification: Notification) -> Self {
+        let encode = |id: Option<i64>| id.and_then(|id| sqids.encode(&[id as u64]).ok());
+        NotificationView {
+            case_id: encode(notification.case_id),
+            related_case_id: encode(notification.related_case_id),
+            notification,
+        }
+    }
+}
+
+fn notifications_error(err: sqlx::Error) -> AppError {
+    error!("{err}");
+    AppError {
+        message: "We ran into an error getting your notifications.",
+    }
+}
+
+#[get("/notifications")]
+pub async fn list_notifications_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    query: Query<NotificationsQuery>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let list = notifications::list(
+        pool.as_ref(),
+        auth.account_id,
+        query.unread.unwrap_or(false),
+        query.skip.unwrap_or(0).max(0),
+        query.limit.unwrap_or(50).clamp(1, 200),
+    )
+    .await
+    .map_err(notifications_error)?;
+    let list: Vec<NotificationView> = list
+        .into_iter()
+        .map(|n| NotificationView::new(&sqids, n))
+        .collect();
+    Ok(HttpResponse::Ok().json(list))
+}
+
+#[post("/notifications/{notification_id}/read")]
+pub async fn read_notification_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    notification_id: Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let read = notifications::mark_read(pool.as_ref(), auth.account_id, Some(*notification_id))
+        .await
+        .map_err(notifications_error)?;
+    Ok(HttpResponse::Ok().json(json!({ "read": read })))
+}
+
+#[post("/notifications/read")]
+pub async fn read_all_notifications_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let read = notifications::mark_read(pool.as_ref(), auth.account_id, None)
+        .await
+        .map_err(notifications_error)?;
+    Ok(HttpResponse::Ok().json(json!({ "read": read })))
+}
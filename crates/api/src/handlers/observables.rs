@@ -0,0 +1,32 @@
+use actix_web::{HttpResponse, Result, post, web::Json};
+use common::errors::AppError;
+use common::observables;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::middleware::auth::AuthMiddleware;
+
+#[derive(Deserialize)]
+pub struct ExtractObservablesBody {
+    pub text: String,
+}
+
+/// Observables in pasted text as entity candidates, each with the label and
+/// fields the canvas would create it with.
+#[post("/observables/extract")]
+pub async fn extract_observables_handler(
+    _auth: AuthMiddleware,
+    body: Json<ExtractObservablesBody>,
+) -> Result<HttpResponse, AppError> {
+    let candidates: Vec<_> = observables::extract(&body.text)
+        .into_iter()
+        .map(|o| {
+            json!({
+                "label": o.kind.label(),
+                "data": o.data(),
+                "observable": o,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "candidates": candidates })))
+}
@@ -0,0 +1,179 @@
+use actix_web::{
+    HttpResponse, Result, delete, get, patch, post,
+    web::{Json, Path},
+};
+use common::errors::AppError;
+use common::playbooks::{self, NewPlaybook, Plan, Playbook, PlaybookUpdate};
+use log::error;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+#[derive(Deserialize)]
+pub struct CreatePlaybookBody {
+    pub name: String,
+    pub description: Option<String>,
+    // see common::playbooks::Step
+    pub steps: JsonValue,
+    pub max_depth: Option<i32>,
+    pub max_jobs: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePlaybookBody {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub steps: Option<JsonValue>,
+    pub max_depth: Option<i32>,
+    pub max_jobs: Option<i32>,
+}
+
+fn check_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 200 {
+        return Err(AppError {
+            message: "Playbook names must be between 1 and 200 characters.",
+        });
+    }
+    Ok(name.to_string())
+}
+
+async fn org_playbook(pool: &PgPool, path: &str, org_id: i64) -> Result<Playbook, AppError> {
+    let playbook_id = Uuid::parse_str(path).map_err(|_| AppError {
+        message: "Invalid playbook id.",
+    })?;
+    playbooks::get_playbook(pool, playbook_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error getting this playbook.",
+            }
+        })?
+        .filter(|p| p.org_id == org_id)
+        .ok_or(AppError {
+            message: "Playbook not found.",
+        })
+}
+
+#[post("/playbooks")]
+pub async fn create_playbook_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    body: Json<CreatePlaybookBody>,
+) -> Result<HttpResponse, AppError> {
+    let b = body.into_inner();
+    let name = check_name(&b.name)?;
+    Plan::parse(&b.steps).map_err(|message| AppError { message })?;
+    playbooks::check_limits(b.max_depth.unwrap_or(3), b.max_jobs.unwrap_or(25))
+        .map_err(|message| AppError { message })?;
+    let playbook = playbooks::create_playbook(
+        pool.as_ref(),
+        NewPlaybook {
+            org_id: auth.org_id,
+            name,
+            description: b.description,
+            steps: b.steps,
+            max_depth: b.max_depth,
+            max_jobs: b.max_jobs,
+            created_by: Some(auth.account_id),
+        },
+    )
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error creating this playbook.",
+        }
+    })?;
+    Ok(HttpResponse::Ok().json(playbook))
+}
+
+#[get("/playbooks")]
+pub async fn list_playbooks_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let items = playbooks::list_playbooks(pool.as_ref(), auth.org_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error listing playbooks.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[get("/playbooks/{playbook_id}")]
+pub async fn get_playbook_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let playbook = org_playbook(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    Ok(HttpResponse::Ok().json(playbook))
+}
+
+#[patch("/playbooks/{playbook_id}")]
+pub async fn update_playbook_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+    body: Json<UpdatePlaybookBody>,
+) -> Result<HttpResponse, AppError> {
+    let playbook = org_playbook(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    let b = body.into_inner();
+    let name = b.name.as_deref().map(check_name).transpose()?;
+    if let Some(steps) = &b.steps {
+        Plan::parse(steps).map_err(|message| AppError { message })?;
+    }
+    playbooks::check_limits(
+        b.max_depth.unwrap_or(playbook.max_depth),
+        b.max_jobs.unwrap_or(playbook.max_jobs),
+    )
+    .map_err(|message| AppError { message })?;
+    let updated = playbooks::update_playbook(
+        pool.as_ref(),
+        playbook.playbook_id,
+        PlaybookUpdate {
+            name,
+            description: b.description,
+            steps: b.steps,
+            max_depth: b.max_depth,
+            max_jobs: b.max_jobs,
+        },
+    )
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error updating this playbook.",
+        }
+    })?
+    .ok_or(AppError {
+        message: "Playbook not found.",
+    })?;
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+#[delete("/playbooks/{playbook_id}")]
+pub async fn delete_playbook_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let playbook = org_playbook(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    playbooks::delete_playbook(pool.as_ref(), playbook.playbook_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error deleting this playbook.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,499 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    HttpResponse, Result, delete, get, post,
+    web::{Json, Path},
+};
+use common::errors::AppError;
+use common::jobs::{JobKind, NewJob};
+use common::plugin_packages::{self, NewPackage, PluginPackage};
+use common::plugin_registry;
+use common::plugin_trust;
+use futures_util::TryStreamExt as _;
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{PgPool, types::Uuid};
+
+use super::jobs::enqueue_org_job;
+use crate::AppData;
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+fn registry_error(err: sqlx::Error) -> AppError {
+    error!("{err}");
+    AppError {
+        message: "We ran into an error loading the plugin registry.",
+    }
+}
+
+#[get("/plugins/sync")]
+pub async fn get_plugin_sync_handler(
+    pool: db::Database,
+    _auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let last_synced = plugin_registry::last_synced(pool.as_ref())
+        .await
+        .map_err(registry_error)?;
+    let syncs = plugin_registry::recent_syncs(pool.as_ref(), 20)
+        .await
+        .map_err(registry_error)?;
+    let pending = plugin_registry::sync_pending(pool.as_ref())
+        .await
+        .map_err(registry_error)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "last_synced": last_synced,
+        "pending": pending,
+        "syncs": syncs,
+    })))
+}
+
+/// Queues a refresh of the plugin registry from the CLI, unless one is
+/// already queued or running.
+#[post("/plugins/sync")]
+pub async fn sync_plugins_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    if auth.user_type != "owner" {
+        return Err(AppError {
+            message: "Only organization owners can sync plugins.",
+        });
+    }
+    let pending = plugin_registry::sync_pending(pool.as_ref())
+        .await
+        .map_err(registry_error)?;
+    if pending {
+        return Err(AppError {
+            message: "A plugin sync is already in progress.",
+        });
+    }
+
+    let job = enqueue_org_job(
+        pool.as_ref(),
+        NewJob {
+            payload: json!({ "kind": "plugin_sync", "trigger": "manual" }),
+            priority: None,
+            max_attempts: Some(1),
+            scheduled_at: None,
+            org_id: Some(auth.org_id),
+            actor_id: Some(auth.account_id),
+            graph_id: None,
+            parent_id: None,
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+fn require_owner(auth: &AuthMiddleware) -> Result<(), AppError> {
+    if auth.user_type != "owner" {
+        return Err(AppError {
+            message: "Only organization owners can manage plugins.",
+        });
+    }
+    Ok(())
+}
+
+fn package_error(err: sqlx::Error) -> AppError {
+    if err
+        .as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
+    {
+        return AppError {
+            message: "A plugin package with this name is already installed.",
+        };
+    }
+    error!("{err}");
+    AppError {
+        message: "We ran into an error managing this plugin package.",
+    }
+}
+
+// Package jobs run once, a failed install is retried by installing again
+async fn enqueue_package_job(
+    pool: &PgPool,
+    auth: &AuthMiddleware,
+    kind: JobKind,
+    package: &PluginPackage,
+) -> Result<serde_json::Value, AppError> {
+    let job = enqueue_org_job(
+        pool,
+        NewJob {
+            payload: json!({ "kind": kind.as_str(), "package_id": package.package_id }),
+            priority: None,
+            max_attempts: Some(1),
+            scheduled_at: None,
+            org_id: Some(auth.org_id),
+            actor_id: Some(auth.account_id),
+            graph_id: None,
+            parent_id: None,
+        },
+    )
+    .await?;
+    plugin_packages::set_job(pool, package.package_id, job.job_id)
+        .await
+        .map_err(package_error)?;
+    Ok(json!({ "package": package, "job": job }))
+}
+
+// Signed packages name the key they were signed with, the signature itself
+// is checked against the revision once the worker has fetched it
+fn package_signature(
+    signature: Option<String>,
+    public_key: Option<String>,
+) -> Result<(Option<String>, Option<String>), AppError> {
+    let signature = signature
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let public_key = public_key
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty());
+    match (&signature, &public_key) {
+        (None, None) => Ok((None, None)),
+        (Some(sig), Some(key)) => {
+            if plugin_trust::decode_key(key).is_none() {
+                return Err(AppError {
+                    message: "public_key must be a base64 ed25519 public key.",
+                });
+            }
+            if plugin_trust::decode_signature(sig).is_none() {
+                return Err(AppError {
+                    message: "signature must be a base64 ed25519 signature.",
+                });
+            }
+            Ok((signature, public_key))
+        }
+        _ => Err(AppError {
+            message: "Signed plugin packages need both a signature and the public_key that made it.",
+        }),
+    }
+}
+
+/// Installed plugins the organization can use, with versions, whether it
+/// has them enabled and how far their publisher is trusted.
+#[get("/plugins")]
+pub async fn list_plugins_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let plugins = plugin_registry::org_plugins(pool.as_ref(), auth.org_id)
+        .await
+        .map_err(registry_error)?;
+    Ok(HttpResponse::Ok().json(plugins))
+}
+
+async fn set_plugin_enabled(
+    pool: &db::Database,
+    auth: &AuthMiddleware,
+    label: &str,
+    enabled: bool,
+) -> Result<HttpResponse, AppError> {
+    require_owner(auth)?;
+    let found =
+        plugin_registry::set_enabled(pool.as_ref(), auth.org_id, label, enabled, auth.account_id)
+            .await
+            .map_err(registry_error)?;
+    if !found {
+        return Err(AppError {
+            message: "Plugin not found.",
+        });
+    }
+    Ok(HttpResponse::Ok().json(json!({ "label": label, "enabled": enabled })))
+}
+
+#[post("/plugins/{label}/enable")]
+pub async fn enable_plugin_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    label: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    set_plugin_enabled(&pool, &auth, &label, true).await
+}
+
+#[post("/plugins/{label}/disable")]
+pub async fn disable_plugin_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    label: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    set_plugin_enabled(&pool, &auth, &label, false).await
+}
+
+#[get("/plugins/packages")]
+pub async fn list_plugin_packages_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let packages = plugin_packages::list(pool.as_ref(), auth.org_id)
+        .await
+        .map_err(package_error)?;
+    Ok(HttpResponse::Ok().json(packages))
+}
+
+#[derive(Deserialize)]
+pub struct InstallGitPackage {
+    pub git_url: String,
+    // defaults to the repository name
+    pub name: Option<String>,
+    // branch or tag, defaults to the repository's default branch
+    pub git_ref: Option<String>,
+    // base64 ed25519 signature of the commit that gets checked out
+    pub signature: Option<String>,
+    pub public_key: Option<String>,
+}
+
+#[post("/plugins/packages")]
+pub async fn install_plugin_package_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    body: Json<InstallGitPackage>,
+) -> Result<HttpResponse, AppError> {
+    require_owner(&auth)?;
+    let git_url = body.git_url.trim();
+    if !plugin_packages::valid_git_url(git_url) {
+        return Err(AppError {
+            message: "Plugins can only be installed from https git URLs.",
+        });
+    }
+    let git_ref = body
+        .git_ref
+        .as_deref()
+        .map(str::trim)
+        .filter(|r| !r.is_empty());
+    if git_ref.is_some_and(|r| r.starts_with('-') || r.chars().any(char::is_whitespace)) {
+        return Err(AppError {
+            message: "Invalid git branch or tag.",
+        });
+    }
+    let name = match body.name.as_deref() {
+        Some(name) => Some(name.trim().to_string()).filter(|n| plugin_packages::valid_name(n)),
+        None => plugin_packages::name_from_source(git_url),
+    }
+    .ok_or(AppError {
+        message: "Plugin package names may only use letters, digits, '-', '_' and '.'.",
+    })?;
+
+    let (signature, signer_key) =
+        package_signature(body.signature.clone(), body.public_key.clone())?;
+
+    let package = plugin_packages::create(
+        pool.as_ref(),
+        NewPackage {
+            org_id: auth.org_id,
+            name: &name,
+            source_kind: "git",
+            source: git_url,
+            git_ref,
+            archive: None,
+            signature: signature.as_deref(),
+            signer_key: signer_key.as_deref(),
+            installed_by: auth.account_id,
+        },
+    )
+    .await
+    .map_err(package_error)?;
+    let queued =
+        enqueue_package_job(pool.as_ref(), &auth, JobKind::PluginInstall, &package).await?;
+    Ok(HttpResponse::Ok().json(queued))
+}
+
+/// Installs a plugin package from an uploaded `.tar`, `.tar.gz` or `.zip`
+/// archive in the `file` field, named by the `name` field or the file name.
+/// Signed archives add `signature` and `public_key` fields, the signature
+/// covering the archive's sha256.
+#[post("/plugins/packages/archive")]
+pub async fn upload_plugin_package_handler(
+    mut payload: Multipart,
+    pool: db::Database,
+    auth: AuthMiddleware,
+    app: AppData,
+) -> Result<HttpResponse, AppError> {
+    require_owner(&auth)?;
+    let max_mb = app.cfg.upload_max_inline_mb.unwrap_or(10);
+    let max_bytes = (max_mb as usize).saturating_mul(1024 * 1024);
+    let mut name: Option<String> = None;
+    let mut signature: Option<String> = None;
+    let mut public_key: Option<String> = None;
+    let mut filename: Option<String> = None;
+    let mut bytes: Vec<u8> = Vec::new();
+
+    while let Some(mut field) = payload.try_next().await.map_err(|_| AppError {
+        message: "Malformed multipart payload.",
+    })? {
+        let field_name = field.name().to_string();
+        let mut buf = Vec::new();
+        if field_name == "file" {
+            filename = field
+                .content_disposition()
+                .get_filename()
+                .map(str::to_string);
+        }
+        while let Some(chunk) = field.try_next().await.map_err(|_| AppError {
+            message: "Error reading upload stream.",
+        })? {
+            buf.extend_from_slice(&chunk);
+            if buf.len() > max_bytes {
+                return Err(AppError {
+                    message: "File too large.",
+                });
+            }
+        }
+        if field_name == "file" {
+            bytes = buf;
+            continue;
+        }
+        let value = String::from_utf8(buf).map_err(|_| AppError {
+            message: "Form fields must be UTF-8.",
+        })?;
+        match field_name.as_str() {
+            "name" => name = Some(value.trim().to_string()),
+            "signature" => signature = Some(value),
+            "public_key" => public_key = Some(value),
+            _ => {}
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(AppError {
+            message: "Plugin archive is required.",
+        });
+    }
+    let source = filename.unwrap_or_else(|| "upload".into());
+    let name = match name.filter(|n| !n.is_empty()) {
+        Some(name) => Some(name).filter(|n| plugin_packages::valid_name(n)),
+        None => plugin_packages::name_from_source(&source),
+    }
+    .ok_or(AppError {
+        message: "Plugin package names may only use letters, digits, '-', '_' and '.'.",
+    })?;
+
+    let (signature, signer_key) = package_signature(signature, public_key)?;
+
+    let package = plugin_packages::create(
+        pool.as_ref(),
+        NewPackage {
+            org_id: auth.org_id,
+            name: &name,
+            source_kind: "archive",
+            source: &source,
+            git_ref: None,
+            archive: Some(&bytes),
+            signature: signature.as_deref(),
+            signer_key: signer_key.as_deref(),
+            installed_by: auth.account_id,
+        },
+    )
+    .await
+    .map_err(package_error)?;
+    let queued =
+        enqueue_package_job(pool.as_ref(), &auth, JobKind::PluginInstall, &package).await?;
+    Ok(HttpResponse::Ok().json(queued))
+}
+
+#[delete("/plugins/packages/{package_id}")]
+pub async fn uninstall_plugin_package_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    require_owner(&auth)?;
+    let package_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid plugin package id.",
+    })?;
+    let package = plugin_packages::begin_removal(pool.as_ref(), package_id, auth.org_id)
+        .await
+        .map_err(package_error)?
+        .ok_or(AppError {
+            message: "Installed plugin package not found.",
+        })?;
+    let queued =
+        enqueue_package_job(pool.as_ref(), &auth, JobKind::PluginUninstall, &package).await?;
+    Ok(HttpResponse::Ok().json(queued))
+}
+
+fn publisher_error(err: sqlx::Error) -> AppError {
+    if err
+        .as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
+    {
+        return AppError {
+            message: "This publisher key is already trusted.",
+        };
+    }
+    error!("{err}");
+    AppError {
+        message: "We ran into an error managing your trusted publishers.",
+    }
+}
+
+/// The organization's allowlist of publishers whose signed plugins run
+/// without extra confinement.
+#[get("/plugins/publishers")]
+pub async fn list_plugin_publishers_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let publishers = plugin_trust::publishers(pool.as_ref(), auth.org_id)
+        .await
+        .map_err(publisher_error)?;
+    Ok(HttpResponse::Ok().json(publishers))
+}
+
+#[derive(Deserialize)]
+pub struct TrustPublisher {
+    pub name: String,
+    // base64 ed25519 public key
+    pub public_key: String,
+}
+
+#[post("/plugins/publishers")]
+pub async fn trust_plugin_publisher_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    body: Json<TrustPublisher>,
+) -> Result<HttpResponse, AppError> {
+    require_owner(&auth)?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError {
+            message: "Publishers need a name.",
+        });
+    }
+    let public_key = body.public_key.trim();
+    if plugin_trust::decode_key(public_key).is_none() {
+        return Err(AppError {
+            message: "public_key must be a base64 ed25519 public key.",
+        });
+    }
+    let publisher = plugin_trust::add_publisher(
+        pool.as_ref(),
+        auth.org_id,
+        name,
+        public_key,
+        auth.account_id,
+    )
+    .await
+    .map_err(publisher_error)?;
+    Ok(HttpResponse::Ok().json(publisher))
+}
+
+#[delete("/plugins/publishers/{publisher_id}")]
+pub async fn untrust_plugin_publisher_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    require_owner(&auth)?;
+    let publisher_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid publisher id.",
+    })?;
+    let removed = plugin_trust::remove_publisher(pool.as_ref(), auth.org_id, publisher_id)
+        .await
+        .map_err(publisher_error)?;
+    if !removed {
+        return Err(AppError {
+            message: "Publisher not found.",
+        });
+    }
+    Ok(HttpResponse::Ok().json(json!({ "publisher_id": publisher_id })))
+}
@@ -0,0 +1,285 @@
+use actix_web::{
+    HttpResponse, Result, delete, get, patch, post,
+    web::{Data, Json, Path, Query},
+};
+use chrono::{Duration, Utc};
+use common::errors::AppError;
+use common::jobs::JobKind;
+use common::schedules::{self, CronSpec, MIN_INTERVAL_MINUTES, NewSchedule, Schedule};
+use log::error;
+use serde::Deserialize;
+use serde_json::{Value as JsonValue, json};
+use sqids::Sqids;
+use sqlx::{PgPool, types::Uuid};
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+#[derive(Deserialize)]
+pub struct CreateScheduleBody {
+    // sqid of the case the entity lives in
+    pub case_id: String,
+    // the entity as sent for a manual transform, including `transform`
+    pub entity: JsonValue,
+    pub cron: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateScheduleBody {
+    pub cron: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ListSchedulesQuery {
+    pub case_id: Option<String>,
+}
+
+fn parse_cron(cron: &str) -> Result<CronSpec, AppError> {
+    let spec = CronSpec::parse(cron).map_err(|message| AppError { message })?;
+    match spec.min_interval(Utc::now()) {
+        Some(gap) if gap < Duration::minutes(MIN_INTERVAL_MINUTES) => Err(AppError {
+            message: "Schedules can run at most every 5 minutes.",
+        }),
+        Some(_) => Ok(spec),
+        None => Err(AppError {
+            message: "This schedule would never run.",
+        }),
+    }
+}
+
+fn next_run(spec: &CronSpec) -> Result<chrono::DateTime<Utc>, AppError> {
+    spec.next_after(Utc::now()).ok_or(AppError {
+        message: "This schedule would never run.",
+    })
+}
+
+async fn owned_case(
+    pool: &PgPool,
+    sqids: &Sqids,
+    case_id: &str,
+    account_id: i64,
+) -> Result<Uuid, AppError> {
+    let ids = sqids.decode(case_id);
+    let decoded_id = ids.first().ok_or(AppError {
+        message: "Invalid case ID.",
+    })?;
+    sqlx::query_scalar!(
+        "SELECT uuid FROM cases WHERE id = $1 AND owner_id = $2",
+        *decoded_id as i64,
+        account_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error getting this case.",
+        }
+    })?
+    .flatten()
+    .ok_or(AppError {
+        message: "Case not found.",
+    })
+}
+
+async fn org_schedule(pool: &PgPool, path: &str, org_id: i64) -> Result<Schedule, AppError> {
+    let schedule_id = Uuid::parse_str(path).map_err(|_| AppError {
+        message: "Invalid schedule id.",
+    })?;
+    schedules::get_schedule(pool, schedule_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error getting this schedule.",
+            }
+        })?
+        .filter(|s| s.org_id == org_id)
+        .ok_or(AppError {
+            message: "Schedule not found.",
+        })
+}
+
+#[post("/schedules")]
+pub async fn create_schedule_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    body: Json<CreateScheduleBody>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let b = body.into_inner();
+    JobKind::ScheduledRerun
+        .validate(&json!({ "entity": b.entity }))
+        .map_err(|message| AppError { message })?;
+    let entity_id = b.entity["id"]
+        .as_str()
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(AppError {
+            message: "Invalid entity id.",
+        })?;
+    let transform = b.entity["transform"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let spec = parse_cron(&b.cron)?;
+    let graph_id = owned_case(pool.as_ref(), &sqids, &b.case_id, auth.account_id).await?;
+
+    let exists = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+          SELECT 1 FROM entities_current
+           WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+        ) AS "exists!"
+        "#,
+        graph_id,
+        entity_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error getting this entity.",
+        }
+    })?;
+    if !exists {
+        return Err(AppError {
+            message: "Entity not found.",
+        });
+    }
+
+    let schedule = schedules::create_schedule(
+        pool.as_ref(),
+        NewSchedule {
+            org_id: auth.org_id,
+            graph_id,
+            entity_id,
+            transform,
+            entity: b.entity,
+            cron: b.cron.trim().to_string(),
+            created_by: Some(auth.account_id),
+        },
+        next_run(&spec)?,
+    )
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error creating this schedule.",
+        }
+    })?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+#[get("/schedules")]
+pub async fn list_schedules_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    q: Query<ListSchedulesQuery>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let graph_id = match &q.case_id {
+        Some(case_id) => Some(owned_case(pool.as_ref(), &sqids, case_id, auth.account_id).await?),
+        None => None,
+    };
+    let items = schedules::list_schedules(pool.as_ref(), auth.org_id, graph_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error listing schedules.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[patch("/schedules/{schedule_id}")]
+pub async fn update_schedule_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+    body: Json<UpdateScheduleBody>,
+) -> Result<HttpResponse, AppError> {
+    let schedule = org_schedule(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    let b = body.into_inner();
+    let cron = b.cron.as_deref().map(str::trim);
+    let spec = parse_cron(cron.unwrap_or(&schedule.cron))?;
+    let updated = schedules::update_schedule(
+        pool.as_ref(),
+        schedule.schedule_id,
+        cron,
+        b.enabled,
+        next_run(&spec)?,
+    )
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error updating this schedule.",
+        }
+    })?
+    .ok_or(AppError {
+        message: "Schedule not found.",
+    })?;
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+#[delete("/schedules/{schedule_id}")]
+pub async fn delete_schedule_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let schedule = org_schedule(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    schedules::delete_schedule(pool.as_ref(), schedule.schedule_id)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error deleting this schedule.",
+            }
+        })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// New and vanished findings reported by the schedule's runs, newest first.
+#[get("/schedules/{schedule_id}/findings")]
+pub async fn list_schedule_findings_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let schedule = org_schedule(pool.as_ref(), path.as_str(), auth.org_id).await?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT e.event_type, e.payload, e.recorded_at
+          FROM events e
+          JOIN event_streams s ON s.stream_id = e.stream_id
+         WHERE s.category = 'schedule' AND s.key = $1
+         ORDER BY e.version DESC
+         LIMIT 500
+        "#,
+        schedule.schedule_id.to_string()
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .map_err(|err| {
+        error!("{err}");
+        AppError {
+            message: "We ran into an error listing this schedule's findings.",
+        }
+    })?;
+    let findings: Vec<JsonValue> = rows
+        .into_iter()
+        .map(|r| {
+            json!({
+                "type": r.event_type.trim_start_matches("finding:"),
+                "job_id": r.payload["job_id"],
+                "finding": r.payload["finding"],
+                "recorded_at": r.recorded_at,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(findings))
+}
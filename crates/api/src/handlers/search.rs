@@ -0,0 +1,89 @@
+use actix_web::{
+    HttpResponse, Result, get,
+    web::{Data, Query},
+};
+use chrono::{DateTime, Utc};
+use common::errors::AppError;
+use common::search::{self, SearchHit, SearchQuery};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqids::Sqids;
+
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub q: Option<String>,
+    #[serde(rename = "type")]
+    pub entity_type: Option<String>,
+    pub case: Option<String>,
+    pub tag: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub skip: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    case_id: String,
+    #[serde(flatten)]
+    hit: SearchHit,
+}
+
+// Entities across every case the caller can open, e.g.
+// `?q=+1 555 123 4567` for the other cases a phone number turned up in
+#[get("/search")]
+pub async fn search_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    params: Query<SearchParams>,
+    sqids: Data<Sqids>,
+) -> Result<HttpResponse, AppError> {
+    let params = params.into_inner();
+    let text = params.q.unwrap_or_default().trim().to_string();
+    if text.is_empty() && params.entity_type.is_none() && params.tag.is_none() {
+        return Err(AppError {
+            message: "Search needs some text, an entity type or a tag.",
+        });
+    }
+    let case_id = match params.case.as_deref() {
+        Some(case) => Some(*sqids.decode(case).first().ok_or(AppError {
+            message: "Invalid graph ID.",
+        })? as i64),
+        None => None,
+    };
+    let query = SearchQuery {
+        text,
+        entity_type: params.entity_type,
+        case_id,
+        tag: params.tag,
+        since: params.since,
+        until: params.until,
+        skip: params.skip.unwrap_or(0).max(0),
+        limit: params.limit.unwrap_or(50).clamp(1, MAX_LIMIT),
+    };
+
+    let hits = search::entities(pool.as_ref(), auth.account_id, &query)
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            AppError {
+                message: "We ran into an error searching your cases.",
+            }
+        })?;
+    let mut results = Vec::with_capacity(hits.len());
+    for hit in hits {
+        let case_id = sqids.encode(&[hit.case_id as u64]).map_err(|err| {
+            error!("Error encoding sqid: {err}");
+            AppError {
+                message: "We ran into an error encoding graph ID.",
+            }
+        })?;
+        results.push(SearchResult { case_id, hit });
+    }
+    Ok(HttpResponse::Ok().json(results))
+}
@@ -0,0 +1,122 @@
+use actix_web::{
+    HttpResponse, Result, delete, get, post,
+    web::{Json, Path},
+};
+use common::errors::AppError;
+use common::secrets;
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::AppData;
+use crate::db;
+use crate::middleware::auth::AuthMiddleware;
+
+// values end up in a process environment, which has its own size limits
+const MAX_VALUE_BYTES: usize = 8 * 1024;
+
+#[derive(Deserialize)]
+pub struct PutSecretBody {
+    pub name: String,
+    pub value: String,
+    // `user` (the default) or `org`, the latter for owners only
+    pub scope: Option<String>,
+}
+
+fn secrets_error(err: sqlx::Error) -> AppError {
+    error!("{err}");
+    AppError {
+        message: "We ran into an error managing your secrets.",
+    }
+}
+
+/// Names of the organization's secrets and the caller's own, values are
+/// never returned.
+#[get("/secrets")]
+pub async fn list_secrets_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+) -> Result<HttpResponse, AppError> {
+    let secrets = secrets::list(pool.as_ref(), auth.org_id, auth.account_id)
+        .await
+        .map_err(secrets_error)?;
+    Ok(HttpResponse::Ok().json(secrets))
+}
+
+/// Stores a secret for transforms to use, replacing any value of the same
+/// name and scope.
+#[post("/secrets")]
+pub async fn put_secret_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    app: AppData,
+    body: Json<PutSecretBody>,
+) -> Result<HttpResponse, AppError> {
+    let vault = app.vault.as_ref().ok_or(AppError {
+        message: "Secrets are not configured on this server.",
+    })?;
+    let name = body.name.trim();
+    if !secrets::valid_name(name) {
+        return Err(AppError {
+            message: "Secret names must be uppercase letters, digits and underscores, and can't shadow system variables.",
+        });
+    }
+    if body.value.is_empty() || body.value.len() > MAX_VALUE_BYTES || body.value.contains('\0') {
+        return Err(AppError {
+            message: "Secret values must be between 1 byte and 8 KiB of text.",
+        });
+    }
+    let user_id = match body.scope.as_deref().unwrap_or("user") {
+        "user" => Some(auth.account_id),
+        "org" if auth.user_type == "owner" => None,
+        "org" => {
+            return Err(AppError {
+                message: "Only organization owners can manage organization secrets.",
+            });
+        }
+        _ => {
+            return Err(AppError {
+                message: "Secret scope must be user or org.",
+            });
+        }
+    };
+    let secret = secrets::put(
+        pool.as_ref(),
+        vault,
+        auth.org_id,
+        user_id,
+        name,
+        &body.value,
+        auth.account_id,
+    )
+    .await
+    .map_err(secrets_error)?;
+    Ok(HttpResponse::Ok().json(secret))
+}
+
+#[delete("/secrets/{secret_id}")]
+pub async fn delete_secret_handler(
+    pool: db::Database,
+    auth: AuthMiddleware,
+    path: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let secret_id = Uuid::parse_str(path.as_str()).map_err(|_| AppError {
+        message: "Invalid secret id.",
+    })?;
+    let deleted = secrets::delete(
+        pool.as_ref(),
+        auth.org_id,
+        secret_id,
+        auth.account_id,
+        auth.user_type == "owner",
+    )
+    .await
+    .map_err(secrets_error)?;
+    if !deleted {
+        return Err(AppError {
+            message: "Secret not found.",
+        });
+    }
+    Ok(HttpResponse::Ok().json(json!({ "secret_id": secret_id })))
+}
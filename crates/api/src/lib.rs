@@ -1,5 +1,7 @@
+mod correlator;
 pub mod handlers;
 pub mod middleware;
+pub mod plugin_service;
 mod projector;
 pub mod schemas;
 
@@ -14,8 +16,11 @@ use actix_web::web::Data;
 use actix_web::{App, HttpServer, http::header, web};
 
 use common::config::AppConfig;
+use common::plugin_cli::PluginCli;
+use common::secrets::Vault;
 use log::info;
 use moka::sync::Cache;
+use plugin_service::PluginService;
 use sqids::Sqids;
 
 use std::io;
@@ -24,6 +29,11 @@ use std::time::Duration;
 pub struct AppState {
     pub blacklist: Cache<String, bool>,
     pub cfg: &'static AppConfig,
+    // shared by all workers so `ob` listings are cached once
+    pub plugins: PluginCli,
+    pub plugin_service: PluginService,
+    // `None` when no secrets master key is configured
+    pub vault: Option<Vault>,
 }
 
 pub type AppData = Data<AppState>;
@@ -39,14 +49,22 @@ pub async fn run(cfg: &'static AppConfig) -> io::Result<()> {
         "OSIB is listening on: http://{}:{}",
         &cfg.backend_addr, &cfg.backend_port
     );
-    // Start projector loop in the background
+    // Start projector loops in the background
     {
         let projector_pool = pool.clone();
         actix_web::rt::spawn(async move {
             crate::projector::run(projector_pool).await;
         });
+        let correlator_pool = pool.clone();
+        actix_web::rt::spawn(async move {
+            crate::correlator::run(correlator_pool).await;
+        });
     }
 
+    let plugins = PluginCli::from_config(cfg);
+    let plugin_service = PluginService::from_config(cfg);
+    let vault = Vault::from_config(cfg);
+
     HttpServer::new(move || {
         let sqids = Sqids::builder()
             .alphabet(cfg.sqids_alphabet.chars().collect())
@@ -55,6 +73,9 @@ pub async fn run(cfg: &'static AppConfig) -> io::Result<()> {
             .expect("Fatal error building Sqids!");
         let app_state = AppState {
             cfg,
+            plugins: plugins.clone(),
+            plugin_service: plugin_service.clone(),
+            vault: vault.clone(),
             blacklist: Cache::builder()
                 .max_capacity(64_000)
                 .time_to_live(Duration::from_secs(cfg.jwt_maxage * 60))
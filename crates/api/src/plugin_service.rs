@@ -0,0 +1,236 @@
+use common::config::AppConfig;
+use log::warn;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_URL: &str = "http://127.0.0.1:42562";
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_RETRIES: u32 = 2;
+// consecutive failures that open the circuit, and how long it stays open
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+// health checks shouldn't hang on a stalled service
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum PluginServiceError {
+    /// Too many recent failures, requests are refused until the cooldown ends.
+    CircuitOpen,
+    Request(reqwest::Error),
+    Status(StatusCode),
+    Decode(reqwest::Error),
+}
+
+impl fmt::Display for PluginServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginServiceError::CircuitOpen => write!(f, "plugin service circuit is open"),
+            PluginServiceError::Request(e) => write!(f, "plugin service request failed: {e}"),
+            PluginServiceError::Status(s) => write!(f, "plugin service responded {s}"),
+            PluginServiceError::Decode(e) => write!(f, "unreadable plugin service response: {e}"),
+        }
+    }
+}
+
+impl PluginServiceError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            PluginServiceError::CircuitOpen | PluginServiceError::Request(_) => {
+                "The plugins service is not available right now."
+            }
+            PluginServiceError::Status(s) if s.is_client_error() => {
+                "The plugins service could not find what you asked for."
+            }
+            PluginServiceError::Status(_) => "The plugins service reported an error.",
+            PluginServiceError::Decode(_) => "The plugins service returned an unreadable response.",
+        }
+    }
+
+    // Client errors are the caller's problem, not a sign the service is down
+    fn is_transient(&self) -> bool {
+        match self {
+            PluginServiceError::Request(_) => true,
+            PluginServiceError::Status(s) => s.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+// Health is public, so it leaves out the service's address and errors
+#[derive(Debug, Serialize)]
+pub struct PluginServiceHealth {
+    /// `up`, `down` or `circuit_open`
+    pub status: &'static str,
+    pub latency_ms: Option<u128>,
+}
+
+/// Typed client for the HTTP plugin service. Transient failures are retried
+/// with a short backoff, and after `BREAKER_THRESHOLD` failed requests in a
+/// row calls fail fast for `BREAKER_COOLDOWN`. Clones share the circuit.
+#[derive(Clone)]
+pub struct PluginService {
+    client: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+    retries: u32,
+    breaker: Arc<Mutex<Breaker>>,
+}
+
+impl PluginService {
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        let timeout = cfg
+            .plugin_service_timeout_secs
+            .unwrap_or(DEFAULT_TIMEOUT_SECS);
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout))
+            .build()
+            .expect("Fatal error building the plugin service client!");
+        PluginService {
+            client,
+            base_url: cfg
+                .plugin_service_url
+                .as_deref()
+                .unwrap_or(DEFAULT_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            token: cfg.plugin_service_token.clone().filter(|t| !t.is_empty()),
+            retries: cfg.plugin_service_retries.unwrap_or(DEFAULT_RETRIES),
+            breaker: Arc::new(Mutex::new(Breaker::default())),
+        }
+    }
+
+    fn circuit_open(&self) -> bool {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.open_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                // half open, the next request decides
+                breaker.open_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn record(&self, ok: bool) {
+        let mut breaker = self.breaker.lock().unwrap();
+        if ok {
+            breaker.failures = 0;
+            return;
+        }
+        breaker.failures += 1;
+        if breaker.failures >= BREAKER_THRESHOLD {
+            warn!(
+                "plugin service at {} failed {} times, pausing requests for {}s",
+                self.base_url,
+                breaker.failures,
+                BREAKER_COOLDOWN.as_secs()
+            );
+            breaker.open_until = Some(Instant::now() + BREAKER_COOLDOWN);
+            breaker.failures = 0;
+        }
+    }
+
+    async fn send(&self, path: &str, query: &[(&str, &str)]) -> Result<Value, PluginServiceError> {
+        let mut request = self
+            .client
+            .get(format!("{}{path}", self.base_url))
+            .query(query);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(PluginServiceError::Request)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PluginServiceError::Status(status));
+        }
+        response.json().await.map_err(PluginServiceError::Decode)
+    }
+
+    /// GETs `path` as JSON.
+    pub async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Value, PluginServiceError> {
+        if self.circuit_open() {
+            return Err(PluginServiceError::CircuitOpen);
+        }
+        let mut attempt = 0;
+        loop {
+            match self.send(path, query).await {
+                Ok(value) => {
+                    self.record(true);
+                    return Ok(value);
+                }
+                Err(e) if e.is_transient() && attempt < self.retries => {
+                    attempt += 1;
+                    warn!("{e}, retrying {path} ({attempt}/{})", self.retries);
+                    tokio::time::sleep(Duration::from_millis(200 * u64::from(attempt))).await;
+                }
+                Err(e) => {
+                    self.record(!e.is_transient());
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    pub async fn entity(&self, hid: &str) -> Result<Value, PluginServiceError> {
+        self.get(&format!("/entities/{hid}"), &[]).await
+    }
+
+    /// Blueprint of a snake_case entity `label`.
+    pub async fn blueprint(&self, label: &str) -> Result<Value, PluginServiceError> {
+        self.get("/blueprint", &[("label", label)]).await
+    }
+
+    /// Transforms of a snake_case entity `label`.
+    pub async fn transforms(&self, label: &str) -> Result<Value, PluginServiceError> {
+        self.get("/transforms", &[("label", label)]).await
+    }
+
+    /// Probes the service once, without retries, for `/api/health`. Any
+    /// response short of a server error counts as up.
+    pub async fn health(&self) -> PluginServiceHealth {
+        if self.circuit_open() {
+            return PluginServiceHealth {
+                status: "circuit_open",
+                latency_ms: None,
+            };
+        }
+        let started = Instant::now();
+        let mut request = self
+            .client
+            .get(format!("{}/health", self.base_url))
+            .timeout(HEALTH_TIMEOUT);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let status = match request.send().await {
+            Ok(r) if r.status().is_server_error() => {
+                warn!("plugin service health check: {}", r.status());
+                "down"
+            }
+            Ok(_) => "up",
+            Err(e) => {
+                warn!("plugin service health check: {e}");
+                "down"
+            }
+        };
+        PluginServiceHealth {
+            status,
+            latency_ms: Some(started.elapsed().as_millis()),
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use common::eventstore;
 use common::eventstore::EventRecord;
 use log::{debug, error, info};
-use serde_json::Value as JsonValue;
+use serde_json::{Value as JsonValue, json};
 use sqlx::PgPool;
 use sqlx::types::Uuid;
 
-const PROJECTION_NAME: &str = "graph_materializer";
+pub(crate) const PROJECTION_NAME: &str = "graph_materializer";
 
 pub async fn run(pool: PgPool) {
     info!("Projector '{}' started", PROJECTION_NAME);
@@ -272,6 +272,8 @@ async fn apply_event(pool: &PgPool, ev: &EventRecord) -> Result<(), sqlx::Error>
                     .execute(pool)
                     .await?;
                 }
+                "merge" => apply_merge(pool, graph_uuid, entity_uuid, &ev.payload).await?,
+                "split" => apply_split(pool, graph_uuid, entity_uuid, &ev.payload).await?,
                 _ => return Ok(()),
             }
         }
@@ -407,3 +409,228 @@ async fn apply_event(pool: &PgPool, ev: &EventRecord) -> Result<(), sqlx::Error>
 
     Ok(())
 }
+
+fn uuid_list(v: &JsonValue) -> Vec<Uuid> {
+    v.as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Moves the `end` ("source", "target" or "both") of each listed edge from
+// `from` to `to`, ends that no longer sit on `from` are left alone
+async fn repoint_edges(
+    tx: &mut sqlx::PgConnection,
+    graph_uuid: Uuid,
+    edges: &JsonValue,
+    from: Uuid,
+    to: Uuid,
+) -> Result<(), sqlx::Error> {
+    for edge in edges.as_array().into_iter().flatten() {
+        let Some(edge_id) = edge["id"].as_str().and_then(|s| Uuid::parse_str(s).ok()) else {
+            continue;
+        };
+        let end = edge["end"].as_str().unwrap_or_default();
+        sqlx::query!(
+            r#"
+            UPDATE edges_current
+               SET src_id = CASE WHEN $3 IN ('source', 'both') AND src_id = $4 THEN $5 ELSE src_id END,
+                   dst_id = CASE WHEN $3 IN ('target', 'both') AND dst_id = $4 THEN $5 ELSE dst_id END,
+                   sys_from = now()
+             WHERE edge_id = $1
+               AND graph_id = $2
+               AND sys_to IS NULL
+            "#,
+            edge_id,
+            graph_uuid,
+            end,
+            from,
+            to
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn apply_merge(
+    pool: &PgPool,
+    graph_uuid: Uuid,
+    survivor: Uuid,
+    payload: &JsonValue,
+) -> Result<(), sqlx::Error> {
+    let Some(absorbed) = payload["merged"]
+        .as_str()
+        .and_then(|s| Uuid::parse_str(s).ok())
+    else {
+        error!("merge event missing/invalid merged id");
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    let current = sqlx::query_scalar!(
+        r#"SELECT doc FROM entities_current WHERE entity_id = $1 AND graph_id = $2 AND sys_to IS NULL"#,
+        survivor,
+        graph_uuid
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(mut doc) = current else {
+        return Ok(());
+    };
+
+    // the survivor remembers what it absorbed, so the merge can be undone
+    doc["data"] = payload["data"].clone();
+    let mut merged_from = doc["merged_from"].as_array().cloned().unwrap_or_default();
+    merged_from.push(json!({
+        "id": absorbed,
+        "merge_id": payload["merge_id"],
+        "label": payload["absorbed"]["label"],
+    }));
+    doc["merged_from"] = JsonValue::Array(merged_from);
+    sqlx::query!(
+        r#"
+        UPDATE entities_current
+           SET doc = $3, sys_from = now()
+         WHERE entity_id = $1 AND graph_id = $2 AND sys_to IS NULL
+        "#,
+        survivor,
+        graph_uuid,
+        doc
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE entities_current
+           SET sys_to = now(), valid_to = COALESCE(valid_to, now())
+         WHERE entity_id = $1 AND graph_id = $2 AND sys_to IS NULL
+        "#,
+        absorbed,
+        graph_uuid
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // the payload lists the edges when the merge was planned, ones drawn to
+    // the absorbed entity since move or drop the same way
+    sqlx::query!(
+        r#"
+        UPDATE edges_current
+           SET sys_to = now(), valid_to = COALESCE(valid_to, now())
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND (edge_id = ANY($2)
+                OR (src_id = $3 AND dst_id = $4)
+                OR (src_id = $4 AND dst_id = $3))
+        "#,
+        graph_uuid,
+        &uuid_list(&payload["dropped"]),
+        absorbed,
+        survivor
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE edges_current
+           SET src_id = CASE WHEN src_id = $2 THEN $3 ELSE src_id END,
+               dst_id = CASE WHEN dst_id = $2 THEN $3 ELSE dst_id END,
+               sys_from = now()
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND (src_id = $2 OR dst_id = $2)
+        "#,
+        graph_uuid,
+        absorbed,
+        survivor
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+async fn apply_split(
+    pool: &PgPool,
+    graph_uuid: Uuid,
+    entity_uuid: Uuid,
+    payload: &JsonValue,
+) -> Result<(), sqlx::Error> {
+    let into = &payload["into"];
+    let Some(into_uuid) = into["id"].as_str().and_then(|s| Uuid::parse_str(s).ok()) else {
+        error!("split event missing/invalid into.id");
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    let current = sqlx::query_scalar!(
+        r#"SELECT doc FROM entities_current WHERE entity_id = $1 AND graph_id = $2 AND sys_to IS NULL"#,
+        entity_uuid,
+        graph_uuid
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(mut doc) = current else {
+        return Ok(());
+    };
+
+    doc["data"] = payload["data"].clone();
+    if let (Some(undoes), Some(merged_from)) = (
+        payload["undoes"].as_str(),
+        doc.get_mut("merged_from").and_then(|m| m.as_array_mut()),
+    ) {
+        merged_from.retain(|m| m["merge_id"].as_str() != Some(undoes));
+    }
+    sqlx::query!(
+        r#"
+        UPDATE entities_current
+           SET doc = $3, sys_from = now()
+         WHERE entity_id = $1 AND graph_id = $2 AND sys_to IS NULL
+        "#,
+        entity_uuid,
+        graph_uuid,
+        doc
+    )
+    .execute(&mut *tx)
+    .await?;
+    // undoing a merge brings the absorbed entity back under its own id
+    sqlx::query!(
+        r#"
+        INSERT INTO entities_current(entity_id, graph_id, doc, valid_from, valid_to, sys_from, sys_to)
+        VALUES ($1, $2, $3, now(), NULL, now(), NULL)
+        ON CONFLICT (graph_id, entity_id) DO UPDATE SET
+          doc = EXCLUDED.doc,
+          valid_to = NULL,
+          sys_from = now(),
+          sys_to = NULL
+        "#,
+        into_uuid,
+        graph_uuid,
+        into
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    repoint_edges(
+        &mut tx,
+        graph_uuid,
+        &payload["edges"],
+        entity_uuid,
+        into_uuid,
+    )
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE edges_current
+           SET sys_to = NULL, valid_to = NULL, sys_from = now()
+         WHERE graph_id = $1 AND edge_id = ANY($2) AND sys_to IS NOT NULL
+        "#,
+        graph_uuid,
+        &uuid_list(&payload["restored"])
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
@@ -18,6 +18,7 @@ pub struct Organization {
     pub max_entities: i32,
     pub can_export: bool,
     pub can_share: bool,
+    pub max_queued_jobs: i32,
     pub ctime: Option<DateTime<Utc>>,
     pub mtime: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+use std::path::PathBuf;
+
+use crate::config::AppConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    pub artifact_id: Uuid,
+    pub job_id: Uuid,
+    pub org_id: Option<i64>,
+    pub media_type: String,
+    pub filename: Option<String>,
+    pub size: i64,
+    pub offloaded: bool,
+    pub meta: JsonValue,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewArtifact {
+    pub job_id: Uuid,
+    pub media_type: String,
+    pub filename: Option<String>,
+    pub bytes: Vec<u8>,
+    pub meta: JsonValue,
+}
+
+/// Artifacts up to `inline_max_bytes` keep their bytes in postgres, larger
+/// ones are written to `<dir>/<job_id>/<artifact_id>` and referenced by uri.
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    pub inline_max_bytes: usize,
+    pub dir: PathBuf,
+}
+
+impl ArtifactStore {
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        ArtifactStore {
+            inline_max_bytes: (cfg.artifact_inline_max_kb.unwrap_or(1024) as usize)
+                .saturating_mul(1024),
+            dir: PathBuf::from(
+                cfg.artifact_dir
+                    .clone()
+                    .unwrap_or_else(|| "/var/lib/osib/artifacts".to_string()),
+            ),
+        }
+    }
+
+    pub async fn put(&self, pool: &PgPool, new: NewArtifact) -> Result<Artifact, sqlx::Error> {
+        let artifact_id = Uuid::new_v4();
+        let size = new.bytes.len() as i64;
+        let (bytes, uri) = if new.bytes.len() <= self.inline_max_bytes {
+            (Some(new.bytes), None)
+        } else {
+            let dir = self.dir.join(new.job_id.to_string());
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(sqlx::Error::Io)?;
+            let path = dir.join(artifact_id.to_string());
+            tokio::fs::write(&path, &new.bytes)
+                .await
+                .map_err(sqlx::Error::Io)?;
+            (None, Some(format!("file://{}", path.display())))
+        };
+        let offloaded = uri.is_some();
+
+        let rec = sqlx::query!(
+            r#"
+            INSERT INTO artifacts(artifact_id, job_id, media_type, filename, size, bytes, uri, meta)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING (SELECT org_id FROM jobs WHERE job_id = $2) AS org_id, created_at
+            "#,
+            artifact_id,
+            new.job_id,
+            new.media_type,
+            new.filename,
+            size,
+            bytes,
+            uri,
+            new.meta
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Artifact {
+            artifact_id,
+            job_id: new.job_id,
+            org_id: rec.org_id,
+            media_type: new.media_type,
+            filename: new.filename,
+            size,
+            offloaded,
+            meta: new.meta,
+            created_at: rec.created_at,
+        })
+    }
+}
+
+pub async fn list_job_artifacts(pool: &PgPool, job_id: Uuid) -> Result<Vec<Artifact>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT a.artifact_id, a.job_id, j.org_id, a.media_type, a.filename, a.size,
+               a.uri IS NOT NULL AS "offloaded!", a.meta, a.created_at
+          FROM artifacts a
+          JOIN jobs j ON j.job_id = a.job_id
+         WHERE a.job_id = $1
+         ORDER BY a.created_at ASC
+        "#,
+        job_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Artifact {
+            artifact_id: r.artifact_id,
+            job_id: r.job_id,
+            org_id: r.org_id,
+            media_type: r.media_type,
+            filename: r.filename,
+            size: r.size,
+            offloaded: r.offloaded,
+            meta: r.meta,
+            created_at: r.created_at,
+        })
+        .collect())
+}
+
+/// Loads an artifact along with its content, reading offloaded files back.
+pub async fn get_artifact(
+    pool: &PgPool,
+    artifact_id: Uuid,
+) -> Result<Option<(Artifact, Vec<u8>)>, sqlx::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT a.artifact_id, a.job_id, j.org_id, a.media_type, a.filename, a.size,
+               a.bytes, a.uri, a.meta, a.created_at
+          FROM artifacts a
+          JOIN jobs j ON j.job_id = a.job_id
+         WHERE a.artifact_id = $1
+        "#,
+        artifact_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let bytes = match (r.bytes, &r.uri) {
+        (Some(bytes), _) => bytes,
+        (None, Some(uri)) => read_uri(uri).await?,
+        (None, None) => Vec::new(),
+    };
+    let artifact = Artifact {
+        artifact_id: r.artifact_id,
+        job_id: r.job_id,
+        org_id: r.org_id,
+        media_type: r.media_type,
+        filename: r.filename,
+        size: r.size,
+        offloaded: r.uri.is_some(),
+        meta: r.meta,
+        created_at: r.created_at,
+    };
+    Ok(Some((artifact, bytes)))
+}
+
+/// A finished job's result, read from its `result.json` artifact or, when
+/// none was stored, from the `result` event itself.
+pub async fn job_result(pool: &PgPool, job_id: Uuid) -> Result<Option<JsonValue>, sqlx::Error> {
+    let artifact_id = sqlx::query_scalar!(
+        r#"
+        SELECT artifact_id FROM artifacts
+         WHERE job_id = $1 AND meta->>'kind' = 'result'
+         ORDER BY created_at DESC
+         LIMIT 1
+        "#,
+        job_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(artifact_id) = artifact_id {
+        if let Some((_, bytes)) = get_artifact(pool, artifact_id).await? {
+            return Ok(serde_json::from_slice(&bytes).ok());
+        }
+    }
+    let data = sqlx::query_scalar!(
+        r#"
+        SELECT e.payload->'data' AS data
+          FROM events e
+          JOIN event_streams s ON s.stream_id = e.stream_id
+         WHERE s.category = 'job' AND s.key = $1 AND e.payload->>'type' = 'result'
+         ORDER BY e.version DESC
+         LIMIT 1
+        "#,
+        job_id.to_string()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(data.flatten())
+}
+
+async fn read_uri(uri: &str) -> Result<Vec<u8>, sqlx::Error> {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return Err(sqlx::Error::Protocol(format!(
+            "unsupported artifact uri: {uri}"
+        )));
+    };
+    tokio::fs::read(path).await.map_err(sqlx::Error::Io)
+}
+
+/// Deletes offloaded files, used once their rows are gone.
+pub async fn remove_offloaded(uris: &[String]) {
+    for uri in uris {
+        let Some(path) = uri.strip_prefix("file://") else {
+            continue;
+        };
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            warn!("failed to remove artifact file {path}: {e}");
+        }
+    }
+}
@@ -22,14 +22,62 @@ pub struct AppConfig {
     pub serve_build: Option<bool>,
     // uploads
     pub upload_max_inline_mb: Option<u64>,
+    // job artifacts larger than this are written under `artifact_dir`
+    pub artifact_inline_max_kb: Option<u64>,
+    pub artifact_dir: Option<String>,
+    // transform results are reused for this long, per transform overrides
+    // are a JSON object of `{"<transform>" | "<plugin>/<transform>": secs}`
+    pub transform_cache_ttl_secs: Option<i64>,
+    pub transform_cache_ttls: Option<String>,
+    // natural key fields per entity label, `{"<label>": "<field>" | ["<field>", ...]}`,
+    // overriding the `natural_key` plugin blueprints declare
+    pub entity_natural_keys: Option<String>,
+    // `ob` calls are killed after this long, listings are cached for
+    // `plugin_cli_cache_secs`
+    pub plugin_cli_timeout_secs: Option<u64>,
+    pub plugin_cli_cache_secs: Option<u64>,
+    // HTTP plugin service for entity details, blueprints and transforms
+    pub plugin_service_url: Option<String>,
+    pub plugin_service_timeout_secs: Option<u64>,
+    pub plugin_service_retries: Option<u32>,
+    #[confik(secret)]
+    pub plugin_service_token: Option<String>,
+    // base64 AES-256 key the secrets vault is encrypted with
+    #[confik(secret)]
+    pub secrets_master_key: Option<String>,
 
     // worker + firecracker tuning
     pub worker_owner: Option<String>,
     pub worker_lease_seconds: Option<i32>,
     pub worker_batch: Option<i64>,
     pub worker_tick_ms: Option<u64>,
+    // how often the worker looks for due schedules
+    pub scheduler_tick_secs: Option<u64>,
+    // how often the plugin registry is re-synced from the CLI, 0 disables
+    pub plugin_sync_interval_secs: Option<u64>,
+    // packages are installed under it, each with its own `plugins/`
+    pub plugin_install_dir: Option<String>,
+    // JSON overrides of per job kind and per plugin limits
+    pub worker_kind_limits: Option<String>,
+    pub worker_plugin_limits: Option<String>,
     pub firecracker_bin: Option<String>,
     pub firecracker_vmroot: Option<String>,
+    pub firecracker_kernel: Option<String>,
+    pub firecracker_rootfs: Option<String>,
+    pub firecracker_vcpus: Option<u8>,
+    pub firecracker_mem_mib: Option<u32>,
+    pub firecracker_timeout_secs: Option<u64>,
+    // tap devices microVMs reach the network through, comma separated
+    // `<device>:<guest ip>:<host ip>`, each a /30 link. Unset VMs have none
+    pub firecracker_taps: Option<String>,
+    // local, sandbox or firecracker
+    pub worker_executor: Option<String>,
+    pub sandbox_memory_mib: Option<u64>,
+    pub sandbox_cpus: Option<u32>,
+    pub sandbox_pids: Option<u32>,
+    pub sandbox_timeout_secs: Option<u64>,
+    pub sandbox_network: Option<bool>,
+    pub sandbox_cgroup_root: Option<String>,
 }
 
 pub static CFG: OnceCell<AppConfig> = OnceCell::const_new();
@@ -55,12 +103,42 @@ pub async fn cfg() -> AppConfig {
                     "03d2394fc289b30660772ea8d444540ff64z066631063d823b41444e1bdef086",
                 ),
                 upload_max_inline_mb: Some(100),
+                artifact_inline_max_kb: Some(1024),
+                artifact_dir: Some(String::from("/var/lib/osib/artifacts")),
+                transform_cache_ttl_secs: Some(3600),
+                transform_cache_ttls: None,
+                entity_natural_keys: None,
+                plugin_cli_timeout_secs: Some(30),
+                plugin_cli_cache_secs: Some(60),
+                plugin_service_url: Some(String::from("http://127.0.0.1:42562")),
+                plugin_service_timeout_secs: Some(10),
+                plugin_service_retries: Some(2),
+                plugin_service_token: None,
+                secrets_master_key: None,
                 worker_owner: None,
                 worker_lease_seconds: Some(300),
                 worker_batch: Some(8),
                 worker_tick_ms: Some(500),
+                scheduler_tick_secs: Some(30),
+                plugin_sync_interval_secs: Some(3600),
+                plugin_install_dir: Some(String::from("plugin-packages")),
+                worker_kind_limits: None,
+                worker_plugin_limits: None,
                 firecracker_bin: Some(String::from("/usr/local/bin/firecracker")),
                 firecracker_vmroot: Some(String::from("/var/lib/osib/vms")),
+                firecracker_kernel: None,
+                firecracker_rootfs: None,
+                firecracker_vcpus: Some(1),
+                firecracker_mem_mib: Some(512),
+                firecracker_timeout_secs: Some(120),
+                firecracker_taps: None,
+                worker_executor: None,
+                sandbox_memory_mib: Some(1024),
+                sandbox_cpus: Some(1),
+                sandbox_pids: Some(128),
+                sandbox_timeout_secs: Some(120),
+                sandbox_network: Some(true),
+                sandbox_cgroup_root: Some(String::from("/sys/fs/cgroup/osib")),
             };
             error!(
                 "No `.env` file found, using default configuration: {:?}\nError loading env: {}",
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashSet;
+
+use crate::observables::{self, ObservableKind};
+
+// Observables specific enough to one person or account that seeing one on
+// two cases links them, unlike a domain or an IP address
+const CORRELATED: &[ObservableKind] = &[
+    ObservableKind::Email,
+    ObservableKind::Phone,
+    ObservableKind::CryptoWallet,
+];
+
+/// The same observable on another case, seen from one of `graph_id`'s entities.
+#[derive(Debug, Clone, Serialize)]
+pub struct Correlation {
+    pub correlation_id: Uuid,
+    pub entity_id: Uuid,
+    #[serde(skip_serializing)]
+    pub other_case_id: i64,
+    pub other_case_label: String,
+    pub other_entity_id: Uuid,
+    pub kind: String,
+    pub value: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Correlated observables among the data values of an entity document.
+pub fn keys(doc: &JsonValue) -> Vec<(ObservableKind, String)> {
+    let Some(data) = doc["data"].as_object() else {
+        return vec![];
+    };
+    let mut seen = HashSet::new();
+    data.iter()
+        .filter(|(field, _)| *field != "label")
+        .filter_map(|(_, v)| v.as_str().and_then(observables::detect))
+        .filter(|o| CORRELATED.contains(&o.kind))
+        .map(|o| (o.kind, o.value))
+        .filter(|key| seen.insert(key.clone()))
+        .collect()
+}
+
+/// Re-reads an entity's keys from its open document, then records a
+/// correlation both ways for each one another case of the organization has
+/// and notifies the owners of both cases, if they can open the other one.
+/// Correlations through keys the entity no longer has are dropped, or moved
+/// to another entity of the case that still has the key. Returns how many
+/// correlations were new.
+pub async fn index_entity(
+    pool: &PgPool,
+    graph_id: Uuid,
+    entity_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM entity_keys WHERE graph_id = $1 AND entity_id = $2",
+        graph_id,
+        entity_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    let doc = sqlx::query_scalar!(
+        r#"
+        SELECT doc FROM entities_current
+         WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+        "#,
+        graph_id,
+        entity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let keys = doc.as_ref().map(keys).unwrap_or_default();
+    let (kinds, values): (Vec<String>, Vec<String>) = keys
+        .into_iter()
+        .map(|(kind, value)| (kind.as_str().to_string(), value))
+        .unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO entity_keys (graph_id, entity_id, kind, value)
+        SELECT $1, $2, kind, value FROM UNNEST($3::text[], $4::text[]) AS k(kind, value)
+        ON CONFLICT DO NOTHING
+        "#,
+        graph_id,
+        entity_id,
+        &kinds,
+        &values
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // either side of a correlation can be this entity
+    let dropped = sqlx::query!(
+        r#"
+        DELETE FROM correlations c
+         WHERE ((c.graph_id = $1 AND c.entity_id = $2)
+                OR (c.other_graph_id = $1 AND c.other_entity_id = $2))
+           AND NOT EXISTS (
+               SELECT 1 FROM entity_keys k
+                WHERE k.graph_id = $1 AND k.entity_id = $2
+                  AND k.kind = c.kind AND k.value = c.value)
+        RETURNING c.kind, c.value
+        "#,
+        graph_id,
+        entity_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let dropped: HashSet<(String, String)> =
+        dropped.into_iter().map(|r| (r.kind, r.value)).collect();
+    if kinds.is_empty() && dropped.is_empty() {
+        tx.commit().await?;
+        return Ok(0);
+    }
+    let (dropped_kinds, dropped_values): (Vec<String>, Vec<String>) =
+        dropped.iter().cloned().unzip();
+
+    // a dropped key other entities of the case still have correlates through them
+    let recorded = sqlx::query!(
+        r#"
+        WITH matches AS (
+            SELECT k.graph_id, k.entity_id, o.graph_id AS other_graph_id,
+                   o.entity_id AS other_entity_id, k.kind, k.value
+              FROM entity_keys k
+              JOIN entity_keys o ON o.kind = k.kind AND o.value = k.value
+                                AND o.graph_id <> k.graph_id
+              JOIN cases a ON a.uuid = k.graph_id
+              JOIN cases b ON b.uuid = o.graph_id
+             WHERE k.graph_id = $1 AND a.org_id = b.org_id
+               AND (k.entity_id = $2
+                    OR (k.kind, k.value) IN (SELECT * FROM UNNEST($3::text[], $4::text[])))
+        )
+        INSERT INTO correlations (graph_id, entity_id, other_graph_id, other_entity_id, kind, value)
+        SELECT graph_id, entity_id, other_graph_id, other_entity_id, kind, value FROM matches
+         UNION ALL
+        SELECT other_graph_id, other_entity_id, graph_id, entity_id, kind, value FROM matches
+        ON CONFLICT DO NOTHING
+        RETURNING correlation_id, kind, value
+        "#,
+        graph_id,
+        entity_id,
+        &dropped_kinds,
+        &dropped_values
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    // moved correlations were already notified
+    let created: Vec<Uuid> = recorded
+        .into_iter()
+        .filter(|r| !dropped.contains(&(r.kind.clone(), r.value.clone())))
+        .map(|r| r.correlation_id)
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO notifications (user_id, kind, message, case_id, related_case_id, payload)
+        SELECT a.owner_id, 'correlation',
+               format('%s also appears in the case %s', c.value, b.label),
+               a.id, b.id,
+               jsonb_build_object(
+                   'correlation_id', c.correlation_id,
+                   'entity_id', c.entity_id,
+                   'other_entity_id', c.other_entity_id,
+                   'kind', c.kind,
+                   'value', c.value
+               )
+          FROM correlations c
+          JOIN cases a ON a.uuid = c.graph_id
+          JOIN cases b ON b.uuid = c.other_graph_id
+         WHERE c.correlation_id = ANY($1) AND case_visible_to(b.id, a.owner_id)
+        "#,
+        &created
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(created.len() as u64)
+}
+
+/// Correlations of `graph_id`'s entities with cases `user_id` can open.
+pub async fn for_case(
+    pool: &PgPool,
+    graph_id: Uuid,
+    user_id: i64,
+) -> Result<Vec<Correlation>, sqlx::Error> {
+    sqlx::query_as!(
+        Correlation,
+        r#"
+        SELECT c.correlation_id, c.entity_id, b.id AS other_case_id,
+               b.label AS other_case_label, c.other_entity_id, c.kind, c.value, c.created_at
+          FROM correlations c
+          JOIN cases b ON b.uuid = c.other_graph_id
+         WHERE c.graph_id = $1 AND case_visible_to(b.id, $2)
+         ORDER BY c.created_at DESC
+        "#,
+        graph_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
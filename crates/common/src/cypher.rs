@@ -0,0 +1,1194 @@
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::identity::label_key;
+
+/// Longest path a variable length relationship walks, every hop further
+/// fans out over more of the case.
+pub const MAX_HOPS: u32 = 6;
+// queries are read only but can still be slow, e.g. long paths in dense cases
+const STATEMENT_TIMEOUT: &str = "5s";
+
+#[derive(Debug)]
+pub enum CypherError {
+    /// The query isn't in the supported subset, `at` is a byte offset into it.
+    Syntax {
+        at: usize,
+        message: String,
+    },
+    /// The query parses but can't run, e.g. it returns an unbound variable.
+    Invalid(String),
+    Sql(sqlx::Error),
+}
+
+impl fmt::Display for CypherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CypherError::Syntax { at, message } => write!(f, "{message} at offset {at}"),
+            CypherError::Invalid(message) => f.write_str(message),
+            CypherError::Sql(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for CypherError {
+    fn from(e: sqlx::Error) -> Self {
+        CypherError::Sql(e)
+    }
+}
+
+fn invalid<T>(message: impl Into<String>) -> Result<T, CypherError> {
+    Err(CypherError::Invalid(message.into()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    /// A `backticked` name, never a keyword.
+    Quoted(String),
+    Str(String),
+    Num(String),
+    Sym(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    at: usize,
+    end: usize,
+}
+
+const SYMBOLS: &[&str] = &[
+    "<>", "<=", ">=", "=~", "..", "(", ")", "[", "]", "{", "}", ":", ",", ".", "|", "*", "-", "<",
+    ">", "=", ";",
+];
+
+fn syntax<T>(at: usize, message: impl Into<String>) -> Result<T, CypherError> {
+    Err(CypherError::Syntax {
+        at,
+        message: message.into(),
+    })
+}
+
+fn lex(text: &str) -> Result<Vec<Token>, CypherError> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let tok = if c.is_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_alphanumeric() || *c == '_')
+            {
+                name.push(c);
+                chars.next();
+            }
+            Tok::Ident(name)
+        } else if c.is_ascii_digit() {
+            let mut num = String::new();
+            while let Some(&(i, c)) = chars.peek() {
+                // `1..3` is a range, not a number
+                let fraction = c == '.'
+                    && !num.contains('.')
+                    && text[i + 1..].starts_with(|n: char| n.is_ascii_digit());
+                if !(c.is_ascii_digit() || fraction) {
+                    break;
+                }
+                num.push(c);
+                chars.next();
+            }
+            Tok::Num(num)
+        } else if c == '\'' || c == '"' || c == '`' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) if c != '`' => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, e)) => value.push(e),
+                        None => return syntax(at, "unterminated string"),
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, ch)) => value.push(ch),
+                    None => return syntax(at, "unterminated string"),
+                }
+            }
+            if c == '`' {
+                Tok::Quoted(value)
+            } else {
+                Tok::Str(value)
+            }
+        } else if let Some(sym) = SYMBOLS.iter().find(|s| text[at..].starts_with(**s)) {
+            for _ in 0..sym.len() {
+                chars.next();
+            }
+            Tok::Sym(sym)
+        } else {
+            return syntax(at, format!("unexpected `{c}`"));
+        };
+        let end = chars.peek().map(|(i, _)| *i).unwrap_or(text.len());
+        tokens.push(Token { tok, at, end });
+    }
+    tokens.push(Token {
+        tok: Tok::End,
+        at: text.len(),
+        end: text.len(),
+    });
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Str(String),
+    Num(String),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone)]
+struct NodePattern {
+    var: Option<String>,
+    label: Option<String>,
+    props: Vec<(String, Literal)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Out,
+    In,
+    Both,
+}
+
+#[derive(Debug, Clone)]
+struct RelPattern {
+    var: Option<String>,
+    types: Vec<String>,
+    direction: Direction,
+    /// Minimum and maximum hops of a variable length relationship.
+    hops: Option<(u32, u32)>,
+    props: Vec<(String, Literal)>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    start: NodePattern,
+    steps: Vec<(RelPattern, NodePattern)>,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Var(String),
+    Prop(String, String),
+    Id(String),
+    Type(String),
+    Lit(Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Matches,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Operand, CmpOp, Operand),
+    IsNull(Operand, bool),
+    In(Operand, Vec<Literal>),
+}
+
+#[derive(Debug, Clone)]
+enum ReturnExpr {
+    Value(Operand),
+    CountAll,
+    Count(Operand),
+}
+
+#[derive(Debug, Clone)]
+struct ReturnItem {
+    expr: ReturnExpr,
+    alias: String,
+}
+
+#[derive(Debug, Clone)]
+struct Query {
+    patterns: Vec<Pattern>,
+    filter: Option<Expr>,
+    distinct: bool,
+    items: Vec<ReturnItem>,
+    limit: Option<u64>,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn at(&self) -> usize {
+        self.tokens[self.pos].at
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.tok != Tok::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_kw(&self, kw: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(name) if name.eq_ignore_ascii_case(kw))
+    }
+
+    fn eat_kw(&mut self, kw: &str) -> bool {
+        let found = self.is_kw(kw);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_kw(&mut self, kw: &str) -> Result<(), CypherError> {
+        if self.eat_kw(kw) {
+            return Ok(());
+        }
+        syntax(self.at(), format!("expected {kw}"))
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        let found = matches!(self.peek(), Tok::Sym(s) if *s == sym);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), CypherError> {
+        if self.eat(sym) {
+            return Ok(());
+        }
+        syntax(self.at(), format!("expected `{sym}`"))
+    }
+
+    fn name(&mut self) -> Result<String, CypherError> {
+        match self.peek().clone() {
+            Tok::Ident(name) | Tok::Quoted(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => syntax(self.at(), "expected a name"),
+        }
+    }
+
+    fn optional_var(&mut self) -> Option<String> {
+        match self.peek().clone() {
+            Tok::Ident(name) | Tok::Quoted(name) => {
+                self.advance();
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, CypherError> {
+        let at = self.at();
+        match self.advance().tok {
+            Tok::Num(n) => n.parse().or_else(|_| syntax(at, "expected a whole number")),
+            _ => syntax(at, "expected a number"),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, CypherError> {
+        let mut patterns = vec![];
+        self.expect_kw("MATCH")?;
+        loop {
+            patterns.push(self.pattern()?);
+            if self.eat(",") || self.eat_kw("MATCH") {
+                continue;
+            }
+            break;
+        }
+        let filter = if self.eat_kw("WHERE") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        self.expect_kw("RETURN")?;
+        let distinct = self.eat_kw("DISTINCT");
+        let mut items = vec![self.return_item()?];
+        while self.eat(",") {
+            items.push(self.return_item()?);
+        }
+        let limit = if self.eat_kw("LIMIT") {
+            Some(self.number()?)
+        } else {
+            None
+        };
+        self.eat(";");
+        if *self.peek() != Tok::End {
+            return syntax(self.at(), "unexpected input after the query");
+        }
+        Ok(Query {
+            patterns,
+            filter,
+            distinct,
+            items,
+            limit,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, CypherError> {
+        let start = self.node()?;
+        let mut steps = vec![];
+        while matches!(self.peek(), Tok::Sym("-" | "<")) {
+            let rel = self.rel()?;
+            steps.push((rel, self.node()?));
+        }
+        Ok(Pattern { start, steps })
+    }
+
+    fn node(&mut self) -> Result<NodePattern, CypherError> {
+        self.expect("(")?;
+        let var = self.optional_var();
+        let label = if self.eat(":") {
+            Some(self.name()?)
+        } else {
+            None
+        };
+        let props = self.props()?;
+        self.expect(")")?;
+        Ok(NodePattern { var, label, props })
+    }
+
+    fn rel(&mut self) -> Result<RelPattern, CypherError> {
+        let at = self.at();
+        let left = self.eat("<");
+        self.expect("-")?;
+        let mut rel = RelPattern {
+            var: None,
+            types: vec![],
+            direction: Direction::Both,
+            hops: None,
+            props: vec![],
+        };
+        if self.eat("[") {
+            rel.var = self.optional_var();
+            if self.eat(":") {
+                rel.types.push(self.name()?);
+                while self.eat("|") {
+                    self.eat(":");
+                    rel.types.push(self.name()?);
+                }
+            }
+            if self.eat("*") {
+                rel.hops = Some(self.hops()?);
+            }
+            rel.props = self.props()?;
+            self.expect("]")?;
+        }
+        self.expect("-")?;
+        let right = self.eat(">");
+        rel.direction = match (left, right) {
+            (false, true) => Direction::Out,
+            (true, false) => Direction::In,
+            (false, false) => Direction::Both,
+            (true, true) => return syntax(at, "a relationship can't point both ways"),
+        };
+        Ok(rel)
+    }
+
+    fn hops(&mut self) -> Result<(u32, u32), CypherError> {
+        let at = self.at();
+        let min = match self.peek() {
+            Tok::Num(_) => Some(self.number()?),
+            _ => None,
+        };
+        let max = if self.eat("..") {
+            match self.peek() {
+                Tok::Num(_) => Some(self.number()?),
+                _ => None,
+            }
+        } else {
+            // `*2` is exactly two hops
+            min
+        };
+        let (min, max) = (min.unwrap_or(1), max.unwrap_or(MAX_HOPS as u64));
+        if max > MAX_HOPS as u64 {
+            return syntax(at, format!("paths can be at most {MAX_HOPS} hops"));
+        }
+        if min > max {
+            return syntax(at, "the minimum hops exceed the maximum");
+        }
+        Ok((min as u32, max as u32))
+    }
+
+    fn props(&mut self) -> Result<Vec<(String, Literal)>, CypherError> {
+        let mut props = vec![];
+        if !self.eat("{") {
+            return Ok(props);
+        }
+        if self.eat("}") {
+            return Ok(props);
+        }
+        loop {
+            let key = self.name()?;
+            self.expect(":")?;
+            props.push((key, self.literal()?));
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect("}")?;
+        Ok(props)
+    }
+
+    fn literal(&mut self) -> Result<Literal, CypherError> {
+        let at = self.at();
+        let negative = self.eat("-");
+        let literal = match self.advance().tok {
+            Tok::Num(n) if negative => Literal::Num(format!("-{n}")),
+            Tok::Num(n) => Literal::Num(n),
+            _ if negative => return syntax(at, "expected a number"),
+            Tok::Str(s) => Literal::Str(s),
+            Tok::Ident(kw) if kw.eq_ignore_ascii_case("true") => Literal::Bool(true),
+            Tok::Ident(kw) if kw.eq_ignore_ascii_case("false") => Literal::Bool(false),
+            Tok::Ident(kw) if kw.eq_ignore_ascii_case("null") => Literal::Null,
+            _ => return syntax(at, "expected a value"),
+        };
+        Ok(literal)
+    }
+
+    fn expr(&mut self) -> Result<Expr, CypherError> {
+        let mut left = self.and_expr()?;
+        while self.eat_kw("OR") {
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, CypherError> {
+        let mut left = self.not_expr()?;
+        while self.eat_kw("AND") {
+            left = Expr::And(Box::new(left), Box::new(self.not_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, CypherError> {
+        if self.eat_kw("NOT") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, CypherError> {
+        let left = self.operand()?;
+        if self.eat_kw("IS") {
+            let negated = self.eat_kw("NOT");
+            self.expect_kw("NULL")?;
+            return Ok(Expr::IsNull(left, !negated));
+        }
+        if self.eat_kw("IN") {
+            self.expect("[")?;
+            let mut values = vec![];
+            if !self.eat("]") {
+                loop {
+                    values.push(self.literal()?);
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect("]")?;
+            }
+            return Ok(Expr::In(left, values));
+        }
+        let op = if self.eat_kw("CONTAINS") {
+            CmpOp::Contains
+        } else if self.eat_kw("STARTS") {
+            self.expect_kw("WITH")?;
+            CmpOp::StartsWith
+        } else if self.eat_kw("ENDS") {
+            self.expect_kw("WITH")?;
+            CmpOp::EndsWith
+        } else {
+            let at = self.at();
+            match self.advance().tok {
+                Tok::Sym("=") => CmpOp::Eq,
+                Tok::Sym("<>") => CmpOp::Ne,
+                Tok::Sym("<") => CmpOp::Lt,
+                Tok::Sym("<=") => CmpOp::Le,
+                Tok::Sym(">") => CmpOp::Gt,
+                Tok::Sym(">=") => CmpOp::Ge,
+                Tok::Sym("=~") => CmpOp::Matches,
+                _ => return syntax(at, "expected a comparison"),
+            }
+        };
+        Ok(Expr::Cmp(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, CypherError> {
+        let at = self.at();
+        match self.peek().clone() {
+            Tok::Ident(name) | Tok::Quoted(name)
+                if !["true", "false", "null"]
+                    .iter()
+                    .any(|kw| name.eq_ignore_ascii_case(kw))
+                    || matches!(self.peek(), Tok::Quoted(_)) =>
+            {
+                self.advance();
+                if self.eat("(") {
+                    let var = self.name()?;
+                    self.expect(")")?;
+                    return match name.to_lowercase().as_str() {
+                        "id" => Ok(Operand::Id(var)),
+                        "type" => Ok(Operand::Type(var)),
+                        _ => syntax(at, format!("unsupported function `{name}`")),
+                    };
+                }
+                if self.eat(".") {
+                    return Ok(Operand::Prop(name, self.name()?));
+                }
+                Ok(Operand::Var(name))
+            }
+            _ => Ok(Operand::Lit(self.literal()?)),
+        }
+    }
+
+    fn return_item(&mut self) -> Result<ReturnItem, CypherError> {
+        let start = self.at();
+        let expr = if self.is_kw("count") && matches!(self.tokens[self.pos + 1].tok, Tok::Sym("("))
+        {
+            self.advance();
+            self.advance();
+            let expr = if self.eat("*") {
+                ReturnExpr::CountAll
+            } else {
+                ReturnExpr::Count(self.operand()?)
+            };
+            self.expect(")")?;
+            expr
+        } else {
+            ReturnExpr::Value(self.operand()?)
+        };
+        let end = self.tokens[self.pos.saturating_sub(1)].end;
+        let alias = if self.eat_kw("AS") {
+            self.name()?
+        } else {
+            self.text[start..end].trim().to_string()
+        };
+        Ok(ReturnItem { expr, alias })
+    }
+}
+
+fn parse(text: &str) -> Result<Query, CypherError> {
+    let mut parser = Parser {
+        text,
+        tokens: lex(text)?,
+        pos: 0,
+    };
+    parser.query()
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    Node(String),
+    Edge(String),
+    Path(String),
+}
+
+#[derive(Default)]
+struct Compiler {
+    // `$1` is the case, every other value is bound as text
+    binds: Vec<String>,
+    ctes: Vec<String>,
+    from: Vec<String>,
+    conds: Vec<String>,
+    vars: HashMap<String, Binding>,
+    hops: Vec<String>,
+    aliases: usize,
+}
+
+// The kind of an entity, compared the way natural keys compare labels so
+// `:ip_address` matches `IP Address`
+const NODE_LABEL: &str = "trim(both '_' from lower(regexp_replace(COALESCE({a}.doc->>'entity_type', {a}.doc->>'label'), '[\\s_-]+', '_', 'g')))";
+const EDGE_TYPE: &str = "COALESCE({a}.props->>'label', {a}.props->>'transform')";
+
+fn numeric(text: &str) -> String {
+    format!("(CASE WHEN ({text}) ~ '^-?[0-9]+(\\.[0-9]+)?$' THEN ({text})::numeric END)")
+}
+
+impl Compiler {
+    fn bind(&mut self, value: impl Into<String>) -> String {
+        self.binds.push(value.into());
+        format!("${}::text", self.binds.len())
+    }
+
+    fn alias(&mut self, prefix: &str) -> String {
+        self.aliases += 1;
+        format!("{prefix}{}", self.aliases)
+    }
+
+    fn binding(&self, var: &str) -> Result<Binding, CypherError> {
+        match self.vars.get(var) {
+            Some(b) => Ok(b.clone()),
+            None => invalid(format!("variable `{var}` is not defined")),
+        }
+    }
+
+    /// A literal as SQL text, or as a number when `number` is set.
+    fn literal(&mut self, literal: &Literal, number: bool) -> String {
+        match literal {
+            Literal::Str(s) => self.bind(s.clone()),
+            Literal::Num(n) if number => format!("{}::numeric", self.bind(n.clone())),
+            Literal::Num(n) => self.bind(n.clone()),
+            Literal::Bool(b) => self.bind(b.to_string()),
+            Literal::Null => "NULL::text".to_string(),
+        }
+    }
+
+    fn prop_text(&mut self, binding: &Binding, key: &str) -> Result<String, CypherError> {
+        Ok(match (binding, key) {
+            (Binding::Node(a), "id") => format!("{a}.entity_id::text"),
+            (Binding::Node(a), "label") => format!("{a}.doc->>'label'"),
+            (Binding::Node(a), _) => format!("{a}.doc->'data'->>{}", self.bind(key)),
+            (Binding::Edge(a), "id") => format!("{a}.edge_id::text"),
+            (Binding::Edge(a), _) => format!("{a}.props->>{}", self.bind(key)),
+            (Binding::Path(_), _) => return invalid("paths have no properties"),
+        })
+    }
+
+    fn text(&mut self, operand: &Operand) -> Result<String, CypherError> {
+        match operand {
+            Operand::Var(var) => invalid(format!("compare a property of `{var}`, not `{var}`")),
+            Operand::Prop(var, key) => {
+                let binding = self.binding(var)?;
+                self.prop_text(&binding, key)
+            }
+            Operand::Id(var) => match self.binding(var)? {
+                Binding::Node(a) => Ok(format!("{a}.entity_id::text")),
+                Binding::Edge(a) => Ok(format!("{a}.edge_id::text")),
+                Binding::Path(_) => invalid("paths have no id"),
+            },
+            Operand::Type(var) => match self.binding(var)? {
+                Binding::Edge(a) => Ok(EDGE_TYPE.replace("{a}", &a)),
+                _ => invalid(format!("`{var}` is not a relationship")),
+            },
+            Operand::Lit(literal) => Ok(self.literal(literal, false)),
+        }
+    }
+
+    fn json(&mut self, operand: &Operand) -> Result<String, CypherError> {
+        Ok(match operand {
+            Operand::Var(var) => match self.binding(var)? {
+                Binding::Node(a) => format!("({a}.doc || jsonb_build_object('id', {a}.entity_id))"),
+                Binding::Edge(a) => format!(
+                    "jsonb_build_object('id', {a}.edge_id, 'source', {a}.source, 'target', {a}.target, 'props', {a}.props)"
+                ),
+                Binding::Path(a) => format!(
+                    "jsonb_build_object('nodes', to_jsonb({a}.path), 'edges', to_jsonb({a}.edges), 'length', {a}.depth)"
+                ),
+            },
+            Operand::Prop(var, key) => match (self.binding(var)?, key.as_str()) {
+                (Binding::Node(a), key) if key != "id" && key != "label" => {
+                    format!("{a}.doc->'data'->{}", self.bind(key))
+                }
+                (Binding::Edge(a), key) if key != "id" => {
+                    format!("{a}.props->{}", self.bind(key))
+                }
+                _ => format!("to_jsonb({})", self.text(operand)?),
+            },
+            Operand::Lit(Literal::Num(n)) => format!("to_jsonb({}::numeric)", self.bind(n.clone())),
+            Operand::Lit(Literal::Bool(b)) => {
+                format!("to_jsonb({}::boolean)", self.bind(b.to_string()))
+            }
+            Operand::Lit(Literal::Null) => "'null'::jsonb".to_string(),
+            _ => format!("to_jsonb({})", self.text(operand)?),
+        })
+    }
+
+    fn compare(&mut self, left: &str, op: CmpOp, right: &str) -> String {
+        match op {
+            CmpOp::Eq => format!("{left} = {right}"),
+            CmpOp::Ne => format!("{left} <> {right}"),
+            CmpOp::Lt => format!("{left} < {right}"),
+            CmpOp::Le => format!("{left} <= {right}"),
+            CmpOp::Gt => format!("{left} > {right}"),
+            CmpOp::Ge => format!("{left} >= {right}"),
+            CmpOp::Contains => format!("strpos({left}, {right}) > 0"),
+            CmpOp::StartsWith => format!("starts_with({left}, {right})"),
+            CmpOp::EndsWith => format!("right({left}, length({right})) = {right}"),
+            CmpOp::Matches => format!("{left} ~ ('^(?:' || {right} || ')$')"),
+        }
+    }
+
+    // Numbers compare as numbers, a value that isn't one never matches
+    fn cmp(&mut self, left: &Operand, op: CmpOp, right: &Operand) -> Result<String, CypherError> {
+        let is_num = |o: &Operand| matches!(o, Operand::Lit(Literal::Num(_)));
+        let ordered = !matches!(
+            op,
+            CmpOp::Contains | CmpOp::StartsWith | CmpOp::EndsWith | CmpOp::Matches
+        );
+        let as_number = ordered && (is_num(left) || is_num(right));
+        let side = |c: &mut Self, o: &Operand| -> Result<String, CypherError> {
+            match o {
+                Operand::Lit(l) => Ok(c.literal(l, as_number)),
+                _ if as_number => Ok(numeric(&c.text(o)?)),
+                _ => c.text(o),
+            }
+        };
+        let (l, r) = (side(self, left)?, side(self, right)?);
+        Ok(self.compare(&l, op, &r))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<String, CypherError> {
+        Ok(match expr {
+            Expr::And(a, b) => format!("({} AND {})", self.expr(a)?, self.expr(b)?),
+            Expr::Or(a, b) => format!("({} OR {})", self.expr(a)?, self.expr(b)?),
+            Expr::Not(a) => format!("(NOT {})", self.expr(a)?),
+            Expr::Cmp(l, op, r) => self.cmp(l, *op, r)?,
+            Expr::IsNull(o, true) => format!("{} IS NULL", self.text(o)?),
+            Expr::IsNull(o, false) => format!("{} IS NOT NULL", self.text(o)?),
+            Expr::In(_, values) if values.is_empty() => "false".to_string(),
+            Expr::In(o, values) => {
+                let as_number = values.iter().all(|v| matches!(v, Literal::Num(_)));
+                let text = self.text(o)?;
+                let left = if as_number { numeric(&text) } else { text };
+                let values: Vec<String> =
+                    values.iter().map(|v| self.literal(v, as_number)).collect();
+                format!("{left} IN ({})", values.join(", "))
+            }
+        })
+    }
+
+    fn props(
+        &mut self,
+        binding: &Binding,
+        props: &[(String, Literal)],
+    ) -> Result<Vec<String>, CypherError> {
+        let mut conds = vec![];
+        for (key, value) in props {
+            let as_number = matches!(value, Literal::Num(_));
+            let text = self.prop_text(binding, key)?;
+            let left = if as_number { numeric(&text) } else { text };
+            let right = self.literal(value, as_number);
+            conds.push(self.compare(&left, CmpOp::Eq, &right));
+        }
+        Ok(conds)
+    }
+
+    fn node(&mut self, node: &NodePattern) -> Result<String, CypherError> {
+        let alias = match node.var.as_ref().and_then(|v| self.vars.get(v)) {
+            Some(Binding::Node(alias)) => alias.clone(),
+            Some(_) => {
+                return invalid(format!(
+                    "`{}` is already a relationship",
+                    node.var.as_deref().unwrap_or_default()
+                ));
+            }
+            None => {
+                let alias = self.alias("n");
+                self.from.push(format!("entities_current {alias}"));
+                self.conds.push(format!(
+                    "{alias}.graph_id = $1::uuid AND {alias}.sys_to IS NULL"
+                ));
+                if let Some(var) = &node.var {
+                    self.vars.insert(var.clone(), Binding::Node(alias.clone()));
+                }
+                alias
+            }
+        };
+        if let Some(label) = &node.label {
+            let label = self.bind(label_key(label));
+            self.conds
+                .push(format!("{} = {label}", NODE_LABEL.replace("{a}", &alias)));
+        }
+        let props = self.props(&Binding::Node(alias.clone()), &node.props)?;
+        self.conds.extend(props);
+        Ok(alias)
+    }
+
+    /// A CTE of the case's open edges a relationship pattern can follow,
+    /// oriented so `src` to `dst` is the direction it's walked in.
+    fn edges(&mut self, rel: &RelPattern) -> Result<String, CypherError> {
+        let name = self.alias("rel");
+        let mut filters = vec!["graph_id = $1::uuid".to_string(), "sys_to IS NULL".into()];
+        if !rel.types.is_empty() {
+            let types: Vec<String> = rel.types.iter().map(|t| self.bind(t.clone())).collect();
+            filters.push(format!(
+                "{} IN ({})",
+                EDGE_TYPE.replace("{a}.", ""),
+                types.join(", ")
+            ));
+        }
+        let props = self.props(&Binding::Edge("edges_current".into()), &rel.props)?;
+        filters.extend(props);
+        let filters = filters.join(" AND ");
+        let select = |src: &str, dst: &str| {
+            format!(
+                "SELECT edge_id, {src} AS src, {dst} AS dst, src_id AS source, dst_id AS target, props FROM edges_current WHERE {filters}"
+            )
+        };
+        let body = match rel.direction {
+            Direction::Out => select("src_id", "dst_id"),
+            Direction::In => select("dst_id", "src_id"),
+            Direction::Both => format!(
+                "{} UNION ALL {}",
+                select("src_id", "dst_id"),
+                select("dst_id", "src_id")
+            ),
+        };
+        self.ctes.push(format!("{name} AS ({body})"));
+        Ok(name)
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Result<(), CypherError> {
+        let mut left = self.node(&pattern.start)?;
+        for (rel, node) in &pattern.steps {
+            if let Some(var) = &rel.var {
+                if self.vars.contains_key(var) {
+                    return invalid(format!("`{var}` is already defined"));
+                }
+            }
+            let edges = self.edges(rel)?;
+            let right = self.node(node)?;
+            let binding = match rel.hops {
+                None => {
+                    let alias = self.alias("r");
+                    self.from.push(format!("{edges} {alias}"));
+                    self.conds.push(format!(
+                        "{alias}.src = {left}.entity_id AND {alias}.dst = {right}.entity_id"
+                    ));
+                    // a match uses each relationship once
+                    for other in &self.hops {
+                        self.conds
+                            .push(format!("{alias}.edge_id <> {other}.edge_id"));
+                    }
+                    self.hops.push(alias.clone());
+                    Binding::Edge(alias)
+                }
+                Some((min, max)) => {
+                    // paths don't revisit an entity, which also keeps the walk finite
+                    let alias = self.alias("p");
+                    self.from.push(format!(
+                        "LATERAL (WITH RECURSIVE walk(node, depth, path, edges) AS ( \
+                         SELECT {left}.entity_id, 0, ARRAY[{left}.entity_id], ARRAY[]::uuid[] \
+                         UNION ALL \
+                         SELECT h.dst, w.depth + 1, w.path || h.dst, w.edges || h.edge_id \
+                           FROM walk w JOIN {edges} h ON h.src = w.node \
+                          WHERE w.depth < {max} AND h.dst <> ALL(w.path)) \
+                         SELECT node, depth, path, edges FROM walk WHERE depth >= {min}) {alias}"
+                    ));
+                    self.conds.push(format!("{alias}.node = {right}.entity_id"));
+                    Binding::Path(alias)
+                }
+            };
+            if let Some(var) = &rel.var {
+                self.vars.insert(var.clone(), binding);
+            }
+            left = right;
+        }
+        Ok(())
+    }
+}
+
+/// A query compiled to SQL over `entities_current` and `edges_current`,
+/// returning one JSON array per row.
+struct Compiled {
+    columns: Vec<String>,
+    sql: String,
+    binds: Vec<String>,
+}
+
+fn compile(query: &Query, graph_id: Uuid, limit: u64) -> Result<Compiled, CypherError> {
+    let mut c = Compiler {
+        binds: vec![graph_id.to_string()],
+        ..Default::default()
+    };
+    for pattern in &query.patterns {
+        c.pattern(pattern)?;
+    }
+    if let Some(filter) = &query.filter {
+        let filter = c.expr(filter)?;
+        c.conds.push(filter);
+    }
+
+    let mut columns = vec![];
+    let mut values = vec![];
+    let mut groups = vec![];
+    let aggregate = query
+        .items
+        .iter()
+        .any(|i| !matches!(i.expr, ReturnExpr::Value(_)));
+    for item in &query.items {
+        if columns.contains(&item.alias) {
+            return invalid(format!("column `{}` is returned twice", item.alias));
+        }
+        columns.push(item.alias.clone());
+        let value = match &item.expr {
+            ReturnExpr::Value(operand) => {
+                let value = c.json(operand)?;
+                groups.push(value.clone());
+                value
+            }
+            ReturnExpr::CountAll => "count(*)".to_string(),
+            ReturnExpr::Count(operand) => format!("count({})", c.json(operand)?),
+        };
+        values.push(value);
+    }
+
+    let mut sql = String::new();
+    if !c.ctes.is_empty() {
+        sql.push_str(&format!("WITH {} ", c.ctes.join(", ")));
+    }
+    sql.push_str(&format!(
+        "SELECT {}jsonb_build_array({}) FROM {} WHERE {}",
+        if query.distinct { "DISTINCT " } else { "" },
+        values.join(", "),
+        c.from.join(", "),
+        c.conds.join(" AND ")
+    ));
+    if aggregate && !groups.is_empty() {
+        sql.push_str(&format!(" GROUP BY {}", groups.join(", ")));
+    }
+    // one row past the limit tells whether the result was cut short
+    let limit = query.limit.unwrap_or(limit).min(limit);
+    sql.push_str(&format!(" LIMIT {}", limit + 1));
+    Ok(Compiled {
+        columns,
+        sql,
+        binds: c.binds,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<JsonValue>,
+    /// More rows matched than were returned.
+    pub truncated: bool,
+}
+
+/// Runs a query in the openCypher subset on one case, returning at most
+/// `max_rows` rows. Supported are one or more `MATCH` patterns of nodes
+/// `(n:Label {field: value})` and relationships `-[r:TYPE*1..3]->`, an
+/// optional `WHERE` of comparisons joined by `AND`, `OR` and `NOT`, and
+/// `RETURN [DISTINCT]` of variables, properties, `id()`, `type()` and
+/// `count()`, with an optional `LIMIT`. Entity properties are the fields
+/// of their data besides `id` and `label`, a node label matches the kind of
+/// entity and a relationship's type is its label or transform.
+pub async fn run(
+    pool: &PgPool,
+    graph_id: Uuid,
+    text: &str,
+    max_rows: u64,
+) -> Result<QueryResult, CypherError> {
+    let query = parse(text)?;
+    let compiled = compile(&query, graph_id, max_rows)?;
+    let limit = query.limit.unwrap_or(max_rows).min(max_rows) as usize;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!(
+        "SET LOCAL statement_timeout = '{STATEMENT_TIMEOUT}'"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    let mut select = sqlx::query_scalar::<_, JsonValue>(&compiled.sql);
+    for value in compiled.binds {
+        select = select.bind(value);
+    }
+    let mut rows = select.fetch_all(&mut *tx).await?;
+    tx.rollback().await?;
+
+    let truncated = rows.len() > limit;
+    rows.truncate(limit);
+    Ok(QueryResult {
+        columns: compiled.columns,
+        rows,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_ROWS: u64 = 100;
+
+    fn compiled(text: &str) -> Compiled {
+        let query = parse(text).unwrap_or_else(|e| panic!("{text}: {e}"));
+        compile(&query, Uuid::nil(), MAX_ROWS).unwrap_or_else(|e| panic!("{text}: {e}"))
+    }
+
+    fn parse_error(text: &str) -> String {
+        match parse(text) {
+            Err(CypherError::Syntax { message, .. }) => message,
+            other => panic!("{text}: expected a syntax error, got {other:?}"),
+        }
+    }
+
+    /// Every read of the projections must stay inside the case in `$1`.
+    fn assert_scoped(sql: &str) {
+        for (i, _) in sql.match_indices("entities_current ") {
+            let alias: String = sql[i + "entities_current ".len()..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric())
+                .collect();
+            assert!(
+                sql.contains(&format!("{alias}.graph_id = $1::uuid")),
+                "{alias} is not scoped to the case: {sql}"
+            );
+        }
+        let reads = sql.matches("FROM edges_current").count();
+        let scoped = sql
+            .matches("FROM edges_current WHERE graph_id = $1::uuid")
+            .count();
+        assert_eq!(reads, scoped, "edges read outside the case: {sql}");
+    }
+
+    #[test]
+    fn every_alias_is_scoped_to_the_case() {
+        for text in [
+            "MATCH (n) RETURN n",
+            "MATCH (n:ip_address {ip: '1.2.3.4'}) RETURN n.ip",
+            "MATCH (a)-[r:resolves_to]->(b) RETURN a, r, b",
+            "MATCH (a)<-[r]-(b)-[s]-(c) RETURN id(a), type(r), type(s)",
+            "MATCH (a)-[p*1..3]->(b), (c:domain) RETURN p, c",
+            "MATCH (a) MATCH (b)-[*]-(a) WHERE b.port > 80 RETURN count(*)",
+        ] {
+            let c = compiled(text);
+            assert_eq!(c.binds[0], Uuid::nil().to_string());
+            assert!(c.sql.contains("entities_current"), "{text}");
+            assert_scoped(&c.sql);
+        }
+    }
+
+    #[test]
+    fn values_are_bound_not_inlined() {
+        let c = compiled("MATCH (n {name: 'x\\' OR 1=1 --'}) RETURN n");
+        assert!(!c.sql.contains("OR 1=1"));
+        assert!(c.binds.contains(&"x' OR 1=1 --".to_string()));
+    }
+
+    #[test]
+    fn unsupported_queries_are_rejected() {
+        for text in [
+            "CREATE (n) RETURN n",
+            "MATCH (n) DETACH DELETE n",
+            "MATCH (n) SET n.x = 1 RETURN n",
+            "MATCH (n) RETURN n SKIP 1",
+            "MATCH (n) RETURN n; MATCH (m) RETURN m",
+            "MATCH (a)<-[r]->(b) RETURN r",
+            "MATCH (n) RETURN upper(n.name)",
+            "MATCH (n) WHERE n.name = 'open RETURN n",
+        ] {
+            parse_error(text);
+        }
+        for text in [
+            "MATCH (n) RETURN m",
+            "MATCH (n) RETURN n, n",
+            "MATCH (a)-[p*2]->(b) RETURN p.name",
+            "MATCH (a)-[r]->(b) WHERE a = b RETURN a",
+        ] {
+            let query = parse(text).unwrap();
+            assert!(
+                matches!(
+                    compile(&query, Uuid::nil(), MAX_ROWS),
+                    Err(CypherError::Invalid(_))
+                ),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn variable_length_paths_are_bounded() {
+        let hops = |text: &str| {
+            let query = parse(text).unwrap();
+            query.patterns[0].steps[0].0.hops
+        };
+        assert_eq!(hops("MATCH (a)-[*]->(b) RETURN b"), Some((1, MAX_HOPS)));
+        assert_eq!(hops("MATCH (a)-[*2]->(b) RETURN b"), Some((2, 2)));
+        assert_eq!(hops("MATCH (a)-[*..3]->(b) RETURN b"), Some((1, 3)));
+        assert_eq!(hops("MATCH (a)-[*2..]->(b) RETURN b"), Some((2, MAX_HOPS)));
+        assert_eq!(hops("MATCH (a)-[*0..6]->(b) RETURN b"), Some((0, 6)));
+        assert_eq!(
+            parse_error("MATCH (a)-[*1..7]->(b) RETURN b"),
+            "paths can be at most 6 hops"
+        );
+        assert_eq!(
+            parse_error("MATCH (a)-[*9]->(b) RETURN b"),
+            "paths can be at most 6 hops"
+        );
+        assert_eq!(
+            parse_error("MATCH (a)-[*4..2]->(b) RETURN b"),
+            "the minimum hops exceed the maximum"
+        );
+        let c = compiled("MATCH (a)-[*2..4]->(b) RETURN b");
+        assert!(c.sql.contains("w.depth < 4"));
+        assert!(c.sql.contains("WHERE depth >= 2"));
+    }
+
+    #[test]
+    fn counts_group_by_the_other_columns() {
+        let c = compiled("MATCH (a)-[r]->(b) RETURN a.label, count(b) AS links");
+        assert_eq!(c.columns, ["a.label", "links"]);
+        assert!(c.sql.contains(" GROUP BY to_jsonb(n1.doc->>'label')"));
+
+        let c = compiled("MATCH (n) RETURN count(*)");
+        assert!(c.sql.contains("count(*)"));
+        assert!(!c.sql.contains("GROUP BY"));
+
+        let c = compiled("MATCH (n) RETURN DISTINCT n.label");
+        assert!(c.sql.starts_with("SELECT DISTINCT "));
+        assert!(!c.sql.contains("GROUP BY"));
+    }
+
+    #[test]
+    fn limit_fetches_one_extra_row_and_never_exceeds_the_max() {
+        assert!(compiled("MATCH (n) RETURN n").sql.ends_with(" LIMIT 101"));
+        assert!(
+            compiled("MATCH (n) RETURN n LIMIT 10")
+                .sql
+                .ends_with(" LIMIT 11")
+        );
+        assert!(
+            compiled("MATCH (n) RETURN n LIMIT 5000")
+                .sql
+                .ends_with(" LIMIT 101")
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use log::warn;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::HashMap;
+
+use crate::identity::label_key;
+
+/// Field rules per entity label, compiled from the `elements` of plugin
+/// blueprints. An element with a `label` becomes the field named by its
+/// snake_cased label, the same key the canvas stores it under, and may
+/// declare `"required": true` and a `"pattern"` regex its value must match
+/// in full. Dropdown values must be one of the element's `options`.
+#[derive(Debug, Clone, Default)]
+pub struct EntitySchemas {
+    fields: HashMap<String, Vec<FieldRule>>,
+}
+
+#[derive(Debug, Clone)]
+struct FieldRule {
+    key: String,
+    label: String,
+    kind: FieldKind,
+    required: bool,
+    pattern: Option<Regex>,
+}
+
+#[derive(Debug, Clone)]
+enum FieldKind {
+    Text,
+    Dropdown(Vec<String>),
+    Upload,
+    /// An element type this server doesn't know, its value isn't type checked.
+    Other,
+}
+
+/// A field whose value breaks its blueprint element's rules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    /// One of `required`, `type`, `option` or `format`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(rule: &FieldRule, code: &'static str, message: String) -> Self {
+        FieldError {
+            field: rule.key.clone(),
+            code,
+            message,
+        }
+    }
+}
+
+// Same as the canvas' `toSnakeCase`, element values are stored under it
+fn field_key(label: &str) -> String {
+    let mut key = String::with_capacity(label.len());
+    let mut in_gap = false;
+    for c in label.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            key.push(c.to_ascii_lowercase());
+            in_gap = false;
+        } else if !in_gap {
+            key.push('_');
+            in_gap = true;
+        }
+    }
+    key
+}
+
+fn option_values(options: &JsonValue) -> Vec<String> {
+    let Some(options) = options.as_array() else {
+        return vec![];
+    };
+    let mut values = vec![];
+    for option in options {
+        match option {
+            JsonValue::String(s) => values.push(s.clone()),
+            JsonValue::Object(o) => {
+                // the canvas stores `value`, falling back to `label` when it's empty
+                for k in ["label", "value"] {
+                    if let Some(v) = o.get(k).and_then(JsonValue::as_str) {
+                        if !v.is_empty() {
+                            values.push(v.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    values
+}
+
+fn rule(entity: &str, element: &JsonValue) -> Option<FieldRule> {
+    let kind = match element["type"].as_str()? {
+        // display only, nothing is stored for these
+        "title" | "section" | "empty" => return None,
+        "text" | "textarea" | "copy-text" => FieldKind::Text,
+        "dropdown" => FieldKind::Dropdown(option_values(&element["options"])),
+        "upload" => FieldKind::Upload,
+        _ => FieldKind::Other,
+    };
+    let label = element["label"].as_str()?;
+    let key = field_key(label);
+    if key.is_empty() {
+        return None;
+    }
+    let pattern = element["pattern"].as_str().and_then(|p| {
+        Regex::new(&format!("^(?:{p})$"))
+            .inspect_err(|e| warn!("ignoring pattern of {entity} field `{label}`: {e}"))
+            .ok()
+    });
+    Some(FieldRule {
+        key,
+        label: label.to_string(),
+        kind,
+        required: element["required"].as_bool().unwrap_or(false),
+        pattern,
+    })
+}
+
+fn blank(value: &JsonValue) -> bool {
+    match value {
+        JsonValue::Null => true,
+        JsonValue::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+impl EntitySchemas {
+    pub fn from_blueprints(blueprints: &HashMap<String, JsonValue>) -> Self {
+        let mut fields = HashMap::new();
+        for (label, blueprint) in blueprints {
+            let Some(elements) = blueprint["elements"].as_array() else {
+                continue;
+            };
+            // elements laid out side by side are nested one level
+            let rules: Vec<FieldRule> = elements
+                .iter()
+                .flat_map(|e| match e {
+                    JsonValue::Array(row) => row.iter().collect(),
+                    element => vec![element],
+                })
+                .filter_map(|e| rule(label, e))
+                .collect();
+            if rules.is_empty() {
+                continue;
+            }
+            if let Some(display) = blueprint["label"].as_str() {
+                fields.insert(label_key(display), rules.clone());
+            }
+            fields.insert(label_key(label), rules);
+        }
+        EntitySchemas { fields }
+    }
+
+    /// Errors in the fields `data` sets on an entity labelled `label`, empty
+    /// when it's valid or the label has no blueprint. Entities are created
+    /// empty and filled in a field at a time, so a required field may be
+    /// absent but can't be set blank. Fields no element describes are kept
+    /// as they are.
+    pub fn validate(&self, label: &str, data: &Map<String, JsonValue>) -> Vec<FieldError> {
+        let Some(rules) = self.fields.get(&label_key(label)) else {
+            return vec![];
+        };
+        let mut errors = vec![];
+        for rule in rules {
+            let Some(value) = data.get(&rule.key) else {
+                continue;
+            };
+            if blank(value) {
+                if rule.required {
+                    let message = format!("{} is required", rule.label);
+                    errors.push(FieldError::new(rule, "required", message));
+                }
+                continue;
+            }
+            let text = match (&rule.kind, value) {
+                (FieldKind::Upload | FieldKind::Other, JsonValue::String(s)) => s,
+                (FieldKind::Upload | FieldKind::Other, _) => continue,
+                (_, JsonValue::String(s)) => s,
+                _ => {
+                    let message = format!("{} must be text", rule.label);
+                    errors.push(FieldError::new(rule, "type", message));
+                    continue;
+                }
+            };
+            if let FieldKind::Dropdown(options) = &rule.kind {
+                if !options.is_empty() && !options.contains(text) {
+                    let message = format!("{text} is not an option of {}", rule.label);
+                    errors.push(FieldError::new(rule, "option", message));
+                    continue;
+                }
+            }
+            if rule.pattern.as_ref().is_some_and(|p| !p.is_match(text)) {
+                let message = format!("{} is not in the expected format", rule.label);
+                errors.push(FieldError::new(rule, "format", message));
+            }
+        }
+        errors
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashSet;
+
+/// An entity linked from a source entity, along with the transform that
+/// produced it when the edge recorded one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Child {
+    pub entity_id: Uuid,
+    pub edge_id: Uuid,
+    pub label: String,
+    pub data: JsonValue,
+    pub transform: Option<String>,
+}
+
+/// Transform outputs compared to what a source entity already links to.
+#[derive(Debug, Default, Serialize)]
+pub struct FindingsDiff {
+    /// Output items with no matching child, in the shape the plugin emitted.
+    pub new: Vec<JsonValue>,
+    /// Children this transform produced before that it no longer returns.
+    pub vanished: Vec<Child>,
+    pub unchanged: usize,
+}
+
+pub async fn children(
+    pool: &PgPool,
+    graph_id: Uuid,
+    src_id: Uuid,
+) -> Result<Vec<Child>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT e.entity_id AS "entity_id!", ed.edge_id, e.doc, ed.props->>'transform' AS transform
+          FROM edges_current ed
+          JOIN entities_current e
+            ON e.graph_id = ed.graph_id AND e.entity_id = ed.dst_id AND e.sys_to IS NULL
+         WHERE ed.graph_id = $1
+           AND ed.src_id = $2
+           AND ed.sys_to IS NULL
+        "#,
+        graph_id,
+        src_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Child {
+            entity_id: r.entity_id,
+            edge_id: r.edge_id,
+            label: r.doc["label"].as_str().unwrap_or("unknown").to_string(),
+            data: r.doc.get("data").cloned().unwrap_or_else(|| json!({})),
+            transform: r.transform,
+        })
+        .collect())
+}
+
+/// Plugin results are a single object or an array of them.
+pub fn output_items(outputs: &JsonValue) -> Vec<JsonValue> {
+    match outputs {
+        JsonValue::Array(items) => items.iter().filter(|i| i.is_object()).cloned().collect(),
+        JsonValue::Object(_) => vec![outputs.clone()],
+        _ => vec![],
+    }
+}
+
+// Identity of a finding: its label plus its data fields. Object keys are
+// sorted, so the serialized form is stable.
+fn finding_key(label: &str, fields: &JsonValue) -> String {
+    let mut fields = fields.clone();
+    if let Some(obj) = fields.as_object_mut() {
+        for key in ["label", "edge_label", "entity_type"] {
+            obj.remove(key);
+        }
+    }
+    format!("{label}\u{0}{fields}")
+}
+
+/// Splits `outputs` of `transform` into findings that are new to the source
+/// entity and children that vanished since it last ran. Outputs matching any
+/// child count as known, only children whose edge names this transform can
+/// vanish.
+pub fn diff(children: &[Child], outputs: &[JsonValue], transform: &str) -> FindingsDiff {
+    let output_keys: HashSet<String> = outputs
+        .iter()
+        .map(|item| finding_key(item["label"].as_str().unwrap_or("unknown"), item))
+        .collect();
+    let child_keys: HashSet<String> = children
+        .iter()
+        .map(|c| finding_key(&c.label, &c.data))
+        .collect();
+
+    let mut result = FindingsDiff::default();
+    let mut seen = HashSet::new();
+    for item in outputs {
+        let key = finding_key(item["label"].as_str().unwrap_or("unknown"), item);
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        if child_keys.contains(&key) {
+            result.unchanged += 1;
+        } else {
+            result.new.push(item.clone());
+        }
+    }
+    result.vanished = children
+        .iter()
+        .filter(|c| c.transform.as_deref() == Some(transform))
+        .filter(|c| !output_keys.contains(&finding_key(&c.label, &c.data)))
+        .cloned()
+        .collect();
+    result
+}
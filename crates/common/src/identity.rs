@@ -0,0 +1,334 @@
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashMap;
+
+use crate::config::AppConfig;
+use crate::plugin_cli::PluginCli;
+
+/// Natural key fields per entity label: two entities of a label with equal
+/// values in these fields are the same real-world thing. Plugin blueprints
+/// declare them as `"natural_key": "<field>" | ["<field>", ...]`, and
+/// `ENTITY_NATURAL_KEYS` adds to or overrides them.
+#[derive(Debug, Clone, Default)]
+pub struct NaturalKeys {
+    fields: HashMap<String, Vec<String>>,
+}
+
+/// A natural key value, normalized for exact matching, and a looser form
+/// with only letters and digits that catches near-duplicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityKey {
+    pub strict: String,
+    pub loose: String,
+}
+
+// Blueprints are keyed by snake_case label while entities carry the display
+// label, e.g. `IP Address` and `ip_address`
+pub(crate) fn label_key(label: &str) -> String {
+    label
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn key_fields(v: &JsonValue) -> Option<Vec<String>> {
+    let fields: Vec<String> = match v {
+        JsonValue::String(s) => vec![s.clone()],
+        JsonValue::Array(items) => items
+            .iter()
+            .filter_map(|i| i.as_str().map(str::to_string))
+            .collect(),
+        _ => return None,
+    };
+    (!fields.is_empty()).then_some(fields)
+}
+
+fn normalize(v: &JsonValue) -> Option<String> {
+    let raw = match v {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    let value = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    // `example.com.` and `example.com` are the same domain
+    let value = value.trim_end_matches('.').to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+impl NaturalKeys {
+    pub fn from_blueprints(blueprints: &HashMap<String, JsonValue>, cfg: &AppConfig) -> Self {
+        let mut fields = HashMap::new();
+        for (label, blueprint) in blueprints {
+            if let Some(f) = key_fields(&blueprint["natural_key"]) {
+                if let Some(display) = blueprint["label"].as_str() {
+                    fields.insert(label_key(display), f.clone());
+                }
+                fields.insert(label_key(label), f);
+            }
+        }
+        if let Some(raw) = cfg
+            .entity_natural_keys
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+        {
+            match serde_json::from_str::<HashMap<String, JsonValue>>(raw) {
+                Ok(overrides) => {
+                    for (label, v) in overrides {
+                        match key_fields(&v) {
+                            Some(f) => fields.insert(label_key(&label), f),
+                            None => fields.remove(&label_key(&label)),
+                        };
+                    }
+                }
+                Err(e) => error!("ignoring invalid ENTITY_NATURAL_KEYS: {e}"),
+            }
+        }
+        NaturalKeys { fields }
+    }
+
+    /// Reads blueprints from `ob blueprints`, for processes that don't load
+    /// them for the canvas already. Without `ob`, only config keys apply.
+    pub async fn load(cfg: &AppConfig) -> Self {
+        let blueprints = PluginCli::from_config(cfg)
+            .blueprints()
+            .await
+            .unwrap_or_else(|e| {
+                warn!("natural keys from config only: {e}");
+                HashMap::new()
+            });
+        Self::from_blueprints(&blueprints, cfg)
+    }
+
+    /// Key of an entity labelled `label` with fields `data`, `None` when the
+    /// label has no natural key or a key field is empty.
+    pub fn key(&self, label: &str, data: &JsonValue) -> Option<EntityKey> {
+        let fields = self.fields.get(&label_key(label))?;
+        let parts = fields
+            .iter()
+            .map(|f| normalize(&data[f]))
+            .collect::<Option<Vec<_>>>()?;
+        let strict = parts.join("\u{0}");
+        let loose = strict.chars().filter(|c| c.is_alphanumeric()).collect();
+        Some(EntityKey { strict, loose })
+    }
+}
+
+/// How a transform output relates to the entities already on the case.
+#[derive(Debug, Clone)]
+pub enum Resolution {
+    /// Same natural key as this entity document.
+    Existing(JsonValue),
+    /// A new entity, possibly resembling another one closely enough to review.
+    New(Option<NearDuplicate>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NearDuplicate {
+    pub entity_id: Uuid,
+    pub key: String,
+    pub existing_key: String,
+}
+
+/// The case's keyed entities, looked up by strict and loose key.
+#[derive(Debug, Default)]
+pub struct CaseIndex {
+    strict: HashMap<(String, String), JsonValue>,
+    loose: HashMap<(String, String), (Uuid, String)>,
+}
+
+impl CaseIndex {
+    /// Indexes the case's entities with one of `labels`.
+    pub async fn load(
+        pool: &PgPool,
+        graph_id: Uuid,
+        keys: &NaturalKeys,
+        labels: &[String],
+    ) -> Result<Self, sqlx::Error> {
+        let mut index = CaseIndex::default();
+        let lowered: Vec<String> = labels.iter().map(|l| l.to_lowercase()).collect();
+        if keys.fields.is_empty() || lowered.is_empty() {
+            return Ok(index);
+        }
+        let rows = sqlx::query!(
+            r#"
+            SELECT entity_id AS "entity_id!", doc
+              FROM entities_current
+             WHERE graph_id = $1
+               AND sys_to IS NULL
+               AND lower(doc->>'label') = ANY($2)
+             ORDER BY sys_from ASC
+            "#,
+            graph_id,
+            &lowered
+        )
+        .fetch_all(pool)
+        .await?;
+        for r in rows {
+            let label = r.doc["label"].as_str().unwrap_or_default().to_string();
+            if let Some(key) = keys.key(&label, &r.doc["data"]) {
+                index.insert(&label, &key, r.entity_id, r.doc);
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn resolve(&self, label: &str, key: &EntityKey) -> Resolution {
+        let label = label_key(label);
+        if let Some(doc) = self.strict.get(&(label.clone(), key.strict.clone())) {
+            return Resolution::Existing(doc.clone());
+        }
+        let near = self
+            .loose
+            .get(&(label, key.loose.clone()))
+            .map(|(entity_id, existing_key)| NearDuplicate {
+                entity_id: *entity_id,
+                key: key.strict.clone(),
+                existing_key: existing_key.clone(),
+            });
+        Resolution::New(near)
+    }
+
+    // Earliest entity wins, later lookups resolve to it
+    fn insert(&mut self, label: &str, key: &EntityKey, entity_id: Uuid, doc: JsonValue) {
+        let label = label_key(label);
+        self.strict
+            .entry((label.clone(), key.strict.clone()))
+            .or_insert(doc);
+        if !key.loose.is_empty() {
+            self.loose
+                .entry((label, key.loose.clone()))
+                .or_insert((entity_id, key.strict.clone()));
+        }
+    }
+}
+
+/// Queues a near-duplicate for review.
+pub async fn flag_duplicate(
+    pool: &PgPool,
+    graph_id: Uuid,
+    entity_id: Uuid,
+    label: &str,
+    near: &NearDuplicate,
+    job_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO duplicate_candidates (graph_id, entity_id, duplicate_of, label, natural_key, existing_key, job_id)
+        -- cached results replay under a job id that may be gone
+        VALUES ($1, $2, $3, $4, $5, $6, (SELECT job_id FROM jobs WHERE job_id = $7))
+        "#,
+        graph_id,
+        entity_id,
+        near.entity_id,
+        label,
+        near.key,
+        near.existing_key,
+        job_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCandidate {
+    pub candidate_id: Uuid,
+    pub graph_id: Uuid,
+    pub entity_id: Uuid,
+    pub duplicate_of: Uuid,
+    pub label: String,
+    pub natural_key: String,
+    pub existing_key: String,
+    pub job_id: Option<Uuid>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<i64>,
+}
+
+pub async fn list_candidates(
+    pool: &PgPool,
+    graph_id: Uuid,
+    status: &str,
+) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    sqlx::query_as!(
+        DuplicateCandidate,
+        r#"
+        SELECT candidate_id, graph_id, entity_id, duplicate_of, label, natural_key, existing_key,
+               job_id, status, created_at, resolved_at, resolved_by
+          FROM duplicate_candidates
+         WHERE graph_id = $1 AND status = $2
+         ORDER BY created_at DESC
+         LIMIT 500
+        "#,
+        graph_id,
+        status
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Marks a pending candidate on one of `owner_id`'s cases as resolved,
+/// `None` when there is no such candidate.
+pub async fn resolve_candidate(
+    pool: &PgPool,
+    candidate_id: Uuid,
+    owner_id: i64,
+    status: &str,
+) -> Result<Option<DuplicateCandidate>, sqlx::Error> {
+    sqlx::query_as!(
+        DuplicateCandidate,
+        r#"
+        UPDATE duplicate_candidates d
+           SET status = $3, resolved_at = now(), resolved_by = $2
+          FROM cases c
+         WHERE d.candidate_id = $1
+           AND d.status = 'pending'
+           AND c.uuid = d.graph_id
+           AND c.owner_id = $2
+        RETURNING d.candidate_id, d.graph_id, d.entity_id, d.duplicate_of, d.label, d.natural_key,
+                  d.existing_key, d.job_id, d.status, d.created_at, d.resolved_at, d.resolved_by
+        "#,
+        candidate_id,
+        owner_id,
+        status
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Settles pending candidates pairing the two entities once they are merged.
+pub async fn mark_merged(
+    pool: &PgPool,
+    graph_id: Uuid,
+    a: Uuid,
+    b: Uuid,
+    actor_id: Option<i64>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE duplicate_candidates
+           SET status = 'merged', resolved_at = now(), resolved_by = $4
+         WHERE graph_id = $1
+           AND status = 'pending'
+           AND ((entity_id = $2 AND duplicate_of = $3) OR (entity_id = $3 AND duplicate_of = $2))
+        "#,
+        graph_id,
+        a,
+        b,
+        actor_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use serde_json::{Value as JsonValue, json};
+
+/// One line of plugin stdout under the line-delimited JSON protocol, e.g.
+/// `{"type": "progress", "data": {"pct": 40, "note": "resolving"}}`. Fields
+/// other than `type` are used as `data` when it is missing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Progress(JsonValue),
+    Partial(JsonValue),
+    Log(JsonValue),
+    // `{"media_type", "filename", "content", "encoding": "base64"}`, stored
+    // as an artifact by the worker
+    Artifact(JsonValue),
+    Result(JsonValue),
+}
+
+impl Record {
+    pub fn parse_line(line: &str) -> Option<Record> {
+        let line = line.trim();
+        if !line.starts_with('{') {
+            return None;
+        }
+        let JsonValue::Object(mut obj) = serde_json::from_str::<JsonValue>(line).ok()? else {
+            return None;
+        };
+        let kind = obj.get("type")?.as_str()?.to_string();
+        obj.remove("type");
+        let data = obj.remove("data").unwrap_or(JsonValue::Object(obj));
+        match kind.as_str() {
+            "progress" => Some(Record::Progress(data)),
+            "partial" => Some(Record::Partial(data)),
+            "log" => Some(Record::Log(data)),
+            "artifact" => Some(Record::Artifact(data)),
+            "result" => Some(Record::Result(data)),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Record::Progress(_) => "progress",
+            Record::Partial(_) => "partial",
+            Record::Log(_) => "log",
+            Record::Artifact(_) => "artifact",
+            Record::Result(_) => "result",
+        }
+    }
+
+    /// Payload of the `job:event` this record is stored as.
+    pub fn to_event(&self) -> JsonValue {
+        let data = match self {
+            Record::Progress(d)
+            | Record::Partial(d)
+            | Record::Log(d)
+            | Record::Artifact(d)
+            | Record::Result(d) => d,
+        };
+        json!({ "type": self.kind(), "data": data })
+    }
+}
+
+/// Accumulates plugin stdout line by line. The final result is the explicit
+/// `result` record, else every `partial` record merged, else whatever JSON
+/// the legacy heuristic finds in the non-protocol output.
+#[derive(Debug, Default)]
+pub struct OutputCollector {
+    result: Option<JsonValue>,
+    partials: Vec<JsonValue>,
+    raw: String,
+}
+
+impl OutputCollector {
+    /// Feeds one stdout line, returning the record to stream to listeners.
+    /// The result record is held back for `finish`.
+    pub fn push_line(&mut self, line: &str) -> Option<Record> {
+        match Record::parse_line(line) {
+            Some(Record::Result(data)) => {
+                self.result = Some(data);
+                None
+            }
+            Some(record) => {
+                if let Record::Partial(data) = &record {
+                    self.partials.push(data.clone());
+                }
+                Some(record)
+            }
+            None => {
+                self.raw.push_str(line);
+                self.raw.push('\n');
+                None
+            }
+        }
+    }
+
+    pub fn finish(self) -> Option<JsonValue> {
+        if self.result.is_some() {
+            return self.result;
+        }
+        if !self.partials.is_empty() {
+            let mut merged = Vec::new();
+            for partial in self.partials {
+                match partial {
+                    JsonValue::Array(items) => merged.extend(items),
+                    other => merged.push(other),
+                }
+            }
+            return Some(JsonValue::Array(merged));
+        }
+        parse_output(&self.raw)
+    }
+}
+
+// Try to parse a JSON result from stdout (entire body or last JSON-looking chunk)
+pub fn parse_output(s: &str) -> Option<JsonValue> {
+    // Try entire stdout first
+    if let Ok(v) = serde_json::from_str::<JsonValue>(s) {
+        return Some(v);
+    }
+    // Heuristic: find first '{' or '[' and parse from there
+    if let Some(idx) = s.find('{').or_else(|| s.find('[')) {
+        let tail = &s[idx..];
+        if let Ok(v) = serde_json::from_str::<JsonValue>(tail.trim()) {
+            return Some(v);
+        }
+    }
+    // Fallback: last non-empty line
+    s.lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .and_then(|last| serde_json::from_str::<JsonValue>(last.trim()).ok())
+}
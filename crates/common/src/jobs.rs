@@ -2,6 +2,9 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use sqlx::{PgPool, Row, types::Uuid};
+use std::fmt;
+
+use crate::{plugin_registry, transform_cache};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
@@ -18,6 +21,11 @@ pub struct Job {
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
     pub backoff_until: Option<DateTime<Utc>>,
+    pub org_id: Option<i64>,
+    pub actor_id: Option<i64>,
+    pub graph_id: Option<Uuid>,
+    pub failure_reason: Option<String>,
+    pub parent_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,22 +34,276 @@ pub struct NewJob {
     pub priority: Option<i32>,
     pub max_attempts: Option<i32>,
     pub scheduled_at: Option<DateTime<Utc>>,
+    pub org_id: Option<i64>,
+    pub actor_id: Option<i64>,
+    pub graph_id: Option<Uuid>,
+    /// Set on the transforms a playbook run enqueues.
+    pub parent_id: Option<Uuid>,
+}
+
+/// Kinds of work the worker can execute off the job queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Transform,
+    BulkImport,
+    Export,
+    Report,
+    ScheduledRerun,
+    Purge,
+    Playbook,
+    PluginSync,
+    PluginInstall,
+    PluginUninstall,
+}
+
+impl JobKind {
+    pub const ALL: [JobKind; 10] = [
+        JobKind::Transform,
+        JobKind::BulkImport,
+        JobKind::Export,
+        JobKind::Report,
+        JobKind::ScheduledRerun,
+        JobKind::Purge,
+        JobKind::Playbook,
+        JobKind::PluginSync,
+        JobKind::PluginInstall,
+        JobKind::PluginUninstall,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::Transform => "transform",
+            JobKind::BulkImport => "bulk_import",
+            JobKind::Export => "export",
+            JobKind::Report => "report",
+            JobKind::ScheduledRerun => "scheduled_rerun",
+            JobKind::Purge => "purge",
+            JobKind::Playbook => "playbook",
+            JobKind::PluginSync => "plugin_sync",
+            JobKind::PluginInstall => "plugin_install",
+            JobKind::PluginUninstall => "plugin_uninstall",
+        }
+    }
+
+    /// Resolve the kind of a job payload from its `kind` field, falling back to
+    /// the `action` field used by transform payloads sent to `ob run -T`.
+    pub fn from_payload(payload: &JsonValue) -> Option<JobKind> {
+        if let Some(kind) = payload.get("kind").and_then(|v| v.as_str()) {
+            return JobKind::ALL.into_iter().find(|k| k.as_str() == kind);
+        }
+        match payload.get("action").and_then(|v| v.as_str()) {
+            Some("transform:entity") => Some(JobKind::Transform),
+            _ => None,
+        }
+    }
+
+    /// Whether jobs of this kind operate on a case and need a `graph_id`.
+    pub fn requires_case(&self) -> bool {
+        !matches!(
+            self,
+            JobKind::Purge
+                | JobKind::PluginSync
+                | JobKind::PluginInstall
+                | JobKind::PluginUninstall
+        )
+    }
+
+    /// Check a payload against the shape expected for this kind.
+    pub fn validate(&self, payload: &JsonValue) -> Result<(), &'static str> {
+        let Some(obj) = payload.as_object() else {
+            return Err("Job payload must be a JSON object.");
+        };
+        match self {
+            JobKind::Transform | JobKind::ScheduledRerun => {
+                let Some(entity) = obj.get("entity").and_then(|v| v.as_object()) else {
+                    return Err("Transform jobs require an entity object.");
+                };
+                if entity.get("id").and_then(|v| v.as_str()).is_none() {
+                    return Err("Transform jobs require an entity id.");
+                }
+                if entity.get("transform").and_then(|v| v.as_str()).is_none() {
+                    return Err("Transform jobs require a transform label.");
+                }
+                Ok(())
+            }
+            JobKind::BulkImport => {
+                let Some(entities) = obj.get("entities").and_then(|v| v.as_array()) else {
+                    return Err("Bulk import jobs require an entities array.");
+                };
+                if entities.is_empty() {
+                    return Err("Bulk import jobs require at least one entity.");
+                }
+                let all_labelled = entities
+                    .iter()
+                    .all(|e| e.get("label").and_then(|v| v.as_str()).is_some());
+                if !all_labelled {
+                    return Err("Every imported entity requires a label.");
+                }
+                Ok(())
+            }
+            JobKind::Export => match obj.get("format").and_then(|v| v.as_str()) {
+                None | Some("json") => Ok(()),
+                Some(_) => Err("Unsupported export format."),
+            },
+            JobKind::Report => Ok(()),
+            JobKind::PluginSync => match obj.get("trigger").and_then(|v| v.as_str()) {
+                None | Some("manual" | "scheduled") => Ok(()),
+                Some(_) => Err("Unsupported plugin sync trigger."),
+            },
+            JobKind::PluginInstall | JobKind::PluginUninstall => {
+                match obj.get("package_id").and_then(|v| v.as_str()) {
+                    Some(id) if Uuid::parse_str(id).is_ok() => Ok(()),
+                    _ => Err("Plugin package jobs require a package_id."),
+                }
+            }
+            JobKind::Purge => match obj.get("older_than_days").and_then(|v| v.as_i64()) {
+                Some(days) if days >= 1 => Ok(()),
+                _ => Err("Purge jobs require older_than_days of at least 1."),
+            },
+            JobKind::Playbook => {
+                if obj.get("playbook_id").and_then(|v| v.as_str()).is_none() {
+                    return Err("Playbook jobs require a playbook_id.");
+                }
+                let has_id = obj
+                    .get("entity")
+                    .and_then(|e| e.get("id"))
+                    .and_then(|v| v.as_str())
+                    .is_some();
+                if !has_id {
+                    return Err("Playbook jobs require an entity with an id.");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Resolve and validate the kind of a job payload.
+pub fn validate_payload(payload: &JsonValue) -> Result<JobKind, &'static str> {
+    let Some(kind) = JobKind::from_payload(payload) else {
+        return Err("Unknown job kind.");
+    };
+    kind.validate(payload)?;
+    Ok(kind)
+}
+
+#[derive(Debug)]
+pub enum EnqueueError {
+    /// The job isn't allowed, the message can be shown to users.
+    Rejected(&'static str),
+    Sql(sqlx::Error),
+}
+
+impl fmt::Display for EnqueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnqueueError::Rejected(message) => f.write_str(message),
+            EnqueueError::Sql(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for EnqueueError {
+    fn from(e: sqlx::Error) -> Self {
+        EnqueueError::Sql(e)
+    }
+}
+
+/// Validate a job against its kind, the org's plugin registry and its queue
+/// quota, then enqueue it. `org_id`, `actor_id` and `graph_id` must be set by
+/// the caller from the authenticated session or the job enqueueing it, never
+/// from client input.
+pub async fn enqueue_org_job(pool: &PgPool, job: NewJob) -> Result<Job, EnqueueError> {
+    let kind = validate_payload(&job.payload).map_err(EnqueueError::Rejected)?;
+    if kind.requires_case() && job.graph_id.is_none() {
+        return Err(EnqueueError::Rejected("This job requires a case."));
+    }
+
+    let Some(org_id) = job.org_id else {
+        return Err(EnqueueError::Rejected(
+            "Jobs must belong to an organization.",
+        ));
+    };
+    if matches!(
+        kind,
+        JobKind::Transform | JobKind::ScheduledRerun | JobKind::Playbook
+    ) && let Some(label) = transform_cache::plugin_label(&job.payload["entity"])
+        && !plugin_registry::enabled_for_org(pool, org_id, label).await?
+    {
+        return Err(EnqueueError::Rejected(
+            "This plugin is disabled for your organization.",
+        ));
+    }
+    // the org row stays locked until the job is in, so concurrent enqueues
+    // count each other
+    let mut tx = pool.begin().await?;
+    let max_queued_jobs = sqlx::query_scalar!(
+        "SELECT max_queued_jobs FROM organizations WHERE id = $1 FOR UPDATE",
+        org_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let active = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM jobs
+        WHERE org_id = $1
+          AND status IN ('enqueued'::job_status, 'leased'::job_status, 'running'::job_status)
+        "#,
+        org_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if active >= max_queued_jobs as i64 {
+        return Err(EnqueueError::Rejected(
+            "Your organization has reached its job queue limit, please wait for running jobs to finish.",
+        ));
+    }
+
+    let job = insert_job(&mut tx, job).await?;
+    tx.commit().await?;
+    Ok(job)
 }
 
 pub async fn enqueue_job(pool: &PgPool, j: NewJob) -> Result<Job, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    insert_job(&mut conn, j).await
+}
+
+async fn insert_job(conn: &mut sqlx::PgConnection, j: NewJob) -> Result<Job, sqlx::Error> {
+    // Stamp ownership into the payload, the worker reads `actor_id` from there
+    let mut payload = j.payload;
+    if let Some(obj) = payload.as_object_mut() {
+        if let Some(org_id) = j.org_id {
+            obj.insert("org_id".into(), org_id.into());
+        }
+        if let Some(actor_id) = j.actor_id {
+            obj.insert("actor_id".into(), actor_id.into());
+        }
+        if let Some(graph_id) = j.graph_id {
+            obj.insert("graph_id".into(), graph_id.to_string().into());
+        }
+    }
+
     let rec = sqlx::query!(
         r#"
-        INSERT INTO jobs (job_id, payload, status, priority, max_attempts, scheduled_at)
-        VALUES (uuid_generate_v4(), $1, 'enqueued'::job_status, coalesce($2, 100), coalesce($3, 3), coalesce($4, now()))
+        INSERT INTO jobs (job_id, payload, status, priority, max_attempts, scheduled_at, org_id, actor_id, graph_id, parent_id)
+        VALUES (uuid_generate_v4(), $1, 'enqueued'::job_status, coalesce($2, 100), coalesce($3, 3), coalesce($4, now()), $5, $6, $7, $8)
         RETURNING job_id, payload, status::text as status, priority, attempts, max_attempts, lease_owner, lease_until,
-                  created_at, scheduled_at, started_at, finished_at, backoff_until
+                  created_at, scheduled_at, started_at, finished_at, backoff_until, org_id, actor_id, graph_id,
+                  failure_reason, parent_id
         "#,
-        j.payload,
+        payload,
         j.priority,
         j.max_attempts,
         j.scheduled_at,
+        j.org_id,
+        j.actor_id,
+        j.graph_id,
+        j.parent_id,
     )
-    .fetch_one(pool)
+    .fetch_one(&mut *conn)
     .await?;
 
     Ok(Job {
@@ -59,6 +321,11 @@ pub async fn enqueue_job(pool: &PgPool, j: NewJob) -> Result<Job, sqlx::Error> {
         started_at: rec.started_at,
         finished_at: rec.finished_at,
         backoff_until: rec.backoff_until,
+        org_id: rec.org_id,
+        actor_id: rec.actor_id,
+        graph_id: rec.graph_id,
+        failure_reason: rec.failure_reason,
+        parent_id: rec.parent_id,
     })
 }
 
@@ -77,7 +344,12 @@ pub async fn get_job(pool: &PgPool, job_id: Uuid) -> Result<Option<Job>, sqlx::E
                scheduled_at,
                started_at,
                finished_at,
-               backoff_until
+               backoff_until,
+               org_id,
+               actor_id,
+               graph_id,
+               failure_reason,
+               parent_id
         FROM jobs
         WHERE job_id = $1
         "#,
@@ -100,9 +372,44 @@ pub async fn get_job(pool: &PgPool, job_id: Uuid) -> Result<Option<Job>, sqlx::E
         started_at: rec.started_at,
         finished_at: rec.finished_at,
         backoff_until: rec.backoff_until,
+        org_id: rec.org_id,
+        actor_id: rec.actor_id,
+        graph_id: rec.graph_id,
+        failure_reason: rec.failure_reason,
+        parent_id: rec.parent_id,
     }))
 }
 
+/// Count the org's jobs that are still enqueued or held by a worker.
+pub async fn count_active_org_jobs(pool: &PgPool, org_id: i64) -> Result<i64, sqlx::Error> {
+    let rec = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM jobs
+        WHERE org_id = $1
+          AND status IN ('enqueued'::job_status, 'leased'::job_status, 'running'::job_status)
+        "#,
+        org_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(rec)
+}
+
+/// Status of every job enqueued on behalf of `parent_id`.
+pub async fn child_statuses(
+    pool: &PgPool,
+    parent_id: Uuid,
+) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT job_id, status::text AS "status!" FROM jobs WHERE parent_id = $1"#,
+        parent_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.job_id, r.status)).collect())
+}
+
 pub async fn try_claim_job(pool: &PgPool, job_id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
     let result = sqlx::query!(
         r#"
@@ -145,7 +452,8 @@ pub async fn lease_jobs(
             FOR UPDATE SKIP LOCKED
         )
         RETURNING job_id, payload, status::text as status, priority, attempts, max_attempts, lease_owner, lease_until,
-                  created_at, scheduled_at, started_at, finished_at, backoff_until
+                  created_at, scheduled_at, started_at, finished_at, backoff_until, org_id, actor_id, graph_id,
+                  failure_reason, parent_id
         "#,
     )
     .bind(max)
@@ -170,6 +478,11 @@ pub async fn lease_jobs(
             started_at: row.get("started_at"),
             finished_at: row.get("finished_at"),
             backoff_until: row.get("backoff_until"),
+            org_id: row.get("org_id"),
+            actor_id: row.get("actor_id"),
+            graph_id: row.get("graph_id"),
+            failure_reason: row.get("failure_reason"),
+            parent_id: row.get("parent_id"),
         })
         .collect())
 }
@@ -204,7 +517,7 @@ pub async fn extend_lease(
 
 pub async fn complete_job(pool: &PgPool, job_id: Uuid, owner: &str) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE jobs SET status = 'completed'::job_status, finished_at = now() WHERE job_id = $1 AND lease_owner = $2"#,
+        r#"UPDATE jobs SET status = 'completed'::job_status, finished_at = now(), failure_reason = NULL WHERE job_id = $1 AND lease_owner = $2"#,
         job_id,
         owner
     )
@@ -213,11 +526,14 @@ pub async fn complete_job(pool: &PgPool, job_id: Uuid, owner: &str) -> Result<()
     Ok(())
 }
 
+/// Record a failed attempt. `reason` is a short machine readable cause such
+/// as `timeout`, `oom` or `output_too_large`.
 pub async fn fail_job(
     pool: &PgPool,
     job_id: Uuid,
     owner: &str,
     backoff_seconds: i32,
+    reason: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
@@ -225,14 +541,57 @@ pub async fn fail_job(
             status = case when attempts + 1 >= max_attempts then 'dead'::job_status else 'failed'::job_status end,
             attempts = attempts + 1,
             finished_at = now(),
-            backoff_until = now() + make_interval(secs => $3::double precision)
+            backoff_until = now() + make_interval(secs => $3::double precision),
+            failure_reason = $4
         WHERE job_id = $1 AND lease_owner = $2
         "#,
         job_id,
         owner,
-        backoff_seconds as f64
+        backoff_seconds as f64,
+        reason
     )
     .execute(pool)
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn purge(org_id: i64) -> NewJob {
+        NewJob {
+            payload: json!({ "kind": "purge", "older_than_days": 30 }),
+            priority: None,
+            max_attempts: None,
+            scheduled_at: None,
+            org_id: Some(org_id),
+            actor_id: None,
+            graph_id: None,
+            parent_id: None,
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn concurrent_enqueues_stay_within_the_quota(pool: PgPool) {
+        sqlx::query("INSERT INTO organizations (id, name, max_queued_jobs) VALUES (1, 'org', 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let attempts = (0..12).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { enqueue_org_job(&pool, purge(1)).await })
+        });
+        let mut queued = 0;
+        for attempt in futures_util::future::join_all(attempts).await {
+            match attempt.unwrap() {
+                Ok(_) => queued += 1,
+                Err(EnqueueError::Rejected(_)) => {}
+                Err(e) => panic!("{e}"),
+            }
+        }
+        assert_eq!(queued, 3);
+        assert_eq!(count_active_org_jobs(&pool, 1).await.unwrap(), 3);
+    }
+}
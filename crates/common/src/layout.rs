@@ -0,0 +1,312 @@
+use sqlx::{PgPool, types::Uuid};
+use std::f64::consts::TAU;
+
+// Canvas footprint of an entity node plus a margin, nodes closer than this
+// on both axes overlap
+const NODE_WIDTH: f64 = 420.0;
+const NODE_HEIGHT: f64 = 220.0;
+// The grid the canvas has always used for transform results
+const STEP_X: f64 = 460.0;
+const STEP_Y: f64 = 260.0;
+const FIRST_RING: f64 = 480.0;
+const RING_GAP: f64 = 300.0;
+const FORCE_ITERATIONS: usize = 150;
+const SEARCH_STEP: f64 = 40.0;
+
+/// How the entities created by a transform are placed around their source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Rows of 4 to the right of the source.
+    Grid,
+    /// Rings around the source, nearest ring first.
+    #[default]
+    Radial,
+    /// Radial, then relaxed so results spread away from nearby nodes while
+    /// staying close to the source.
+    Force,
+}
+
+impl Layout {
+    /// `None` for unknown names, callers fall back to the default.
+    pub fn parse(name: &str) -> Option<Layout> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "grid" => Some(Layout::Grid),
+            "radial" => Some(Layout::Radial),
+            "force" => Some(Layout::Force),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn from_json(position: &serde_json::Value) -> Point {
+        Point {
+            x: position["x"].as_f64().unwrap_or(0.0),
+            y: position["y"].as_f64().unwrap_or(0.0),
+        }
+    }
+}
+
+fn collides(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < NODE_WIDTH && (a.y - b.y).abs() < NODE_HEIGHT
+}
+
+fn is_free(p: Point, taken: &[Point]) -> bool {
+    taken.iter().all(|&q| !collides(p, q))
+}
+
+/// Positions for `count` new entities around `source` that overlap neither
+/// each other nor anything in `occupied`.
+pub fn place(layout: Layout, source: Point, occupied: &[Point], count: usize) -> Vec<Point> {
+    let mut taken = occupied.to_vec();
+    taken.push(source);
+    match layout {
+        Layout::Grid => grid(source, &mut taken, count),
+        Layout::Radial => radial(source, &mut taken, count),
+        Layout::Force => force(source, &mut taken, count),
+    }
+}
+
+fn grid(source: Point, taken: &mut Vec<Point>, count: usize) -> Vec<Point> {
+    let mut placed = Vec::with_capacity(count);
+    let mut slot = 0;
+    while placed.len() < count {
+        let (col, row) = ((slot % 4) as f64, (slot / 4) as f64);
+        let p = Point {
+            x: source.x + (col + 1.0) * STEP_X,
+            y: source.y + row * STEP_Y,
+        };
+        if is_free(p, taken) {
+            taken.push(p);
+            placed.push(p);
+        }
+        slot += 1;
+    }
+    placed
+}
+
+fn radial(source: Point, taken: &mut Vec<Point>, count: usize) -> Vec<Point> {
+    let mut placed = Vec::with_capacity(count);
+    let mut ring = 0;
+    while placed.len() < count {
+        let radius = FIRST_RING + ring as f64 * RING_GAP;
+        let capacity = ((TAU * radius) / NODE_WIDTH).floor().max(1.0) as usize;
+        // spread a partly filled ring evenly instead of bunching it at 0°,
+        // spots something already sits on push results out a ring
+        let slots = capacity.min(count - placed.len());
+        let offset = ring as f64 * 0.5;
+        for k in 0..slots {
+            let angle = offset + TAU * k as f64 / slots as f64;
+            let p = Point {
+                x: source.x + radius * angle.cos(),
+                y: source.y + radius * angle.sin(),
+            };
+            if is_free(p, taken) {
+                taken.push(p);
+                placed.push(p);
+            }
+        }
+        ring += 1;
+    }
+    placed
+}
+
+fn force(source: Point, taken: &mut Vec<Point>, count: usize) -> Vec<Point> {
+    let fixed = taken.clone();
+    let mut points = radial(source, &mut taken.clone(), count);
+    let range = NODE_WIDTH.hypot(NODE_HEIGHT);
+
+    for iter in 0..FORCE_ITERATIONS {
+        let cooling = 1.0 - iter as f64 / FORCE_ITERATIONS as f64;
+        for i in 0..points.len() {
+            let p = points[i];
+            let (mut fx, mut fy) = (0.0, 0.0);
+
+            // spring towards a ring around the source
+            let (dx, dy) = (p.x - source.x, p.y - source.y);
+            let dist = dx.hypot(dy).max(1.0);
+            let pull = (dist - FIRST_RING) / dist * 0.1;
+            fx -= dx * pull;
+            fy -= dy * pull;
+
+            // push away from every other node within reach
+            let others = points
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, q)| q)
+                .chain(fixed.iter());
+            for q in others {
+                let (dx, dy) = (p.x - q.x, p.y - q.y);
+                let dist = dx.hypot(dy);
+                if dist >= range {
+                    continue;
+                }
+                let (ux, uy) = if dist < 1.0 {
+                    // stacked exactly, split them apart by index
+                    let angle = TAU * i as f64 / points.len().max(1) as f64;
+                    (angle.cos(), angle.sin())
+                } else {
+                    (dx / dist, dy / dist)
+                };
+                let push = (range - dist) * 0.5;
+                fx += ux * push;
+                fy += uy * push;
+            }
+
+            let step = fx.hypot(fy);
+            let max_step = 60.0 * cooling;
+            if step > max_step {
+                fx *= max_step / step;
+                fy *= max_step / step;
+            }
+            points[i] = Point {
+                x: p.x + fx,
+                y: p.y + fy,
+            };
+        }
+    }
+
+    // relaxation only approximates, settle whatever still overlaps
+    for p in points.iter_mut() {
+        if !is_free(*p, taken) {
+            *p = nearest_free(*p, taken);
+        }
+        taken.push(*p);
+    }
+    points
+}
+
+// Walks outward from `p` on a square spiral until a spot is free
+fn nearest_free(p: Point, taken: &[Point]) -> Point {
+    let mut ring = 1i64;
+    loop {
+        for dx in -ring..=ring {
+            for dy in -ring..=ring {
+                if dx.abs() != ring && dy.abs() != ring {
+                    continue;
+                }
+                let q = Point {
+                    x: p.x + dx as f64 * SEARCH_STEP,
+                    y: p.y + dy as f64 * SEARCH_STEP,
+                };
+                if is_free(q, taken) {
+                    return q;
+                }
+            }
+        }
+        ring += 1;
+    }
+}
+
+/// Positions of the entities currently on the case's canvas.
+pub async fn occupied(pool: &PgPool, graph_id: Uuid) -> Result<Vec<Point>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT (doc->'position'->>'x')::float8 AS "x!", (doc->'position'->>'y')::float8 AS "y!"
+          FROM entities_current
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND jsonb_typeof(doc->'position'->'x') = 'number'
+           AND jsonb_typeof(doc->'position'->'y') = 'number'
+        "#,
+        graph_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| Point { x: r.x, y: r.y }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [Layout; 3] = [Layout::Grid, Layout::Radial, Layout::Force];
+
+    fn canvases() -> Vec<(&'static str, Vec<Point>)> {
+        let at = |x: f64, y: f64| Point { x, y };
+        // where the grid and the first ring would put results
+        let in_the_way = (0..8)
+            .map(|k| at((k % 4 + 1) as f64 * STEP_X, (k / 4) as f64 * STEP_Y))
+            .chain((0..6).map(|k| {
+                let angle = TAU * k as f64 / 6.0;
+                at(FIRST_RING * angle.cos(), FIRST_RING * angle.sin())
+            }))
+            .collect();
+        // a crowded case, nodes packed edge to edge around the source
+        let packed = (-4..=4)
+            .flat_map(|i| (-4..=4).map(move |j| (i, j)))
+            .filter(|&(i, j)| (i, j) != (0, 0))
+            .map(|(i, j)| at(i as f64 * NODE_WIDTH, j as f64 * NODE_HEIGHT))
+            .collect();
+        vec![
+            ("empty", vec![]),
+            ("in the way", in_the_way),
+            ("packed", packed),
+            ("stacked on the source", vec![at(0.0, 0.0); 3]),
+        ]
+    }
+
+    #[test]
+    fn places_every_entity_without_overlap() {
+        let source = Point { x: 0.0, y: 0.0 };
+        for layout in LAYOUTS {
+            for (name, occupied) in canvases() {
+                for count in [0, 1, 4, 9, 40] {
+                    let placed = place(layout, source, &occupied, count);
+                    let case = format!("{layout:?} on {name} canvas, {count} entities");
+                    assert_eq!(placed.len(), count, "{case}");
+                    for (i, &p) in placed.iter().enumerate() {
+                        assert!(p.x.is_finite() && p.y.is_finite(), "{case}");
+                        assert!(!collides(p, source), "{case}: {p:?} on the source");
+                        assert!(is_free(p, &occupied), "{case}: {p:?} on an occupied node");
+                        assert!(
+                            is_free(p, &placed[i + 1..]),
+                            "{case}: {p:?} overlaps another result"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_fills_rows_of_four_to_the_right() {
+        let source = Point { x: 100.0, y: 50.0 };
+        let placed = place(Layout::Grid, source, &[], 5);
+        assert_eq!(
+            placed[0],
+            Point {
+                x: 100.0 + STEP_X,
+                y: 50.0
+            }
+        );
+        assert_eq!(
+            placed[3],
+            Point {
+                x: 100.0 + 4.0 * STEP_X,
+                y: 50.0
+            }
+        );
+        assert_eq!(
+            placed[4],
+            Point {
+                x: 100.0 + STEP_X,
+                y: 50.0 + STEP_Y
+            }
+        );
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(Layout::parse(" Force "), Some(Layout::Force));
+        assert_eq!(Layout::parse("grid"), Some(Layout::Grid));
+        assert_eq!(Layout::parse("spiral"), None);
+    }
+}
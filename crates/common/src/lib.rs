@@ -1,6 +1,28 @@
+pub mod artifacts;
 pub mod config;
+pub mod correlations;
+pub mod cypher;
 pub mod db;
+pub mod entity_schema;
 pub mod errors;
 pub mod eventstore;
+pub mod findings;
+pub mod identity;
+pub mod job_output;
 pub mod jobs;
+pub mod layout;
+pub mod lineage;
+pub mod notifications;
+pub mod observables;
+pub mod persist;
+pub mod playbooks;
+pub mod plugin_cli;
+pub mod plugin_packages;
+pub mod plugin_registry;
+pub mod plugin_trust;
+pub mod provenance;
+pub mod schedules;
+pub mod search;
+pub mod secrets;
+pub mod transform_cache;
 pub mod utils;
@@ -0,0 +1,236 @@
+use serde_json::{Map, Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+
+// Merges and splits are recorded as `merge` and `split` events on the case's
+// entity stream. Their payloads carry everything needed to project them and
+// to undo them later:
+//
+// merge: { id, merged, merge_id, data, previous_data, absorbed, edges, dropped }
+//   `id` survives with the combined `data`, `merged` is retired. `edges` are
+//   re-pointed from `merged` to `id`, `dropped` edges linked the two and are
+//   closed. `absorbed` is the retired entity's document.
+//
+// split: { id, split_id, into, data, edges, restored, undoes? }
+//   `into` is created (or brought back, when undoing a merge), `id` keeps
+//   `data`. `edges` are re-pointed from `id` to `into`, `restored` edges are
+//   reopened. `undoes` names the merge being reversed.
+
+/// The end of an edge that sits on the entity being merged or split.
+fn edge_end(src_id: Uuid, dst_id: Uuid, entity_id: Uuid) -> &'static str {
+    match (src_id == entity_id, dst_id == entity_id) {
+        (true, true) => "both",
+        (true, false) => "source",
+        _ => "target",
+    }
+}
+
+async fn open_doc(
+    pool: &PgPool,
+    graph_id: Uuid,
+    entity_id: Uuid,
+) -> Result<Option<JsonValue>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT doc FROM entities_current
+         WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+        "#,
+        graph_id,
+        entity_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+fn data_of(doc: &JsonValue) -> Map<String, JsonValue> {
+    doc["data"].as_object().cloned().unwrap_or_default()
+}
+
+/// Payload of a `merge` event folding `absorbed` into `survivor`, `None`
+/// when either entity isn't on the case. Fields both entities have keep the
+/// survivor's value, the absorbed document is kept whole in the event.
+pub async fn merge_payload(
+    pool: &PgPool,
+    graph_id: Uuid,
+    survivor: Uuid,
+    absorbed: Uuid,
+) -> Result<Option<JsonValue>, sqlx::Error> {
+    let (Some(kept), Some(retired)) = (
+        open_doc(pool, graph_id, survivor).await?,
+        open_doc(pool, graph_id, absorbed).await?,
+    ) else {
+        return Ok(None);
+    };
+
+    let previous = data_of(&kept);
+    let mut data = data_of(&retired);
+    data.extend(previous.clone());
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT edge_id, src_id, dst_id
+          FROM edges_current
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND (src_id = $2 OR dst_id = $2)
+        "#,
+        graph_id,
+        absorbed
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut edges = Vec::new();
+    let mut dropped = Vec::new();
+    for r in rows {
+        // an edge between the two would become a self-loop on the survivor
+        if r.src_id == survivor || r.dst_id == survivor {
+            dropped.push(json!(r.edge_id));
+        } else {
+            edges.push(json!({ "id": r.edge_id, "end": edge_end(r.src_id, r.dst_id, absorbed) }));
+        }
+    }
+
+    Ok(Some(json!({
+        "id": survivor,
+        "merged": absorbed,
+        "merge_id": Uuid::new_v4(),
+        "data": data,
+        "previous_data": previous,
+        "absorbed": retired,
+        "edges": edges,
+        "dropped": dropped,
+    })))
+}
+
+/// Payload of a `split` event moving the `fields` of `entity_id`'s data and
+/// the `edge_ids` attached to it onto a new entity. `None` when the entity
+/// isn't on the case.
+pub async fn split_payload(
+    pool: &PgPool,
+    graph_id: Uuid,
+    entity_id: Uuid,
+    label: Option<&str>,
+    fields: &[String],
+    edge_ids: &[Uuid],
+) -> Result<Option<JsonValue>, sqlx::Error> {
+    let Some(doc) = open_doc(pool, graph_id, entity_id).await? else {
+        return Ok(None);
+    };
+    let label = label
+        .or_else(|| doc["label"].as_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    // labels live at the top level of projected documents
+    let mut data = data_of(&doc);
+    let mut moved = Map::new();
+    for field in fields {
+        if field == "label" {
+            continue;
+        }
+        if let Some(v) = data.remove(field) {
+            moved.insert(field.clone(), v);
+        }
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT edge_id, src_id, dst_id
+          FROM edges_current
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND (src_id = $2 OR dst_id = $2)
+           AND edge_id = ANY($3)
+        "#,
+        graph_id,
+        entity_id,
+        edge_ids
+    )
+    .fetch_all(pool)
+    .await?;
+    let edges: Vec<JsonValue> = rows
+        .into_iter()
+        .map(|r| json!({ "id": r.edge_id, "end": edge_end(r.src_id, r.dst_id, entity_id) }))
+        .collect();
+
+    let x = doc["position"]["x"].as_f64().unwrap_or(0.0);
+    let y = doc["position"]["y"].as_f64().unwrap_or(0.0);
+    let split_id = Uuid::new_v4();
+    Ok(Some(json!({
+        "id": entity_id,
+        "split_id": split_id,
+        "into": {
+            "id": Uuid::new_v4(),
+            "label": label,
+            "position": { "x": x + 460.0, "y": y },
+            "data": moved,
+            "split_from": { "id": entity_id, "split_id": split_id },
+        },
+        "data": data,
+        "edges": edges,
+        "restored": [],
+    })))
+}
+
+/// Payload of a `split` event reversing the latest merge of `absorbed` into
+/// `survivor`, `None` when there is no such merge or it was already undone.
+/// The absorbed entity comes back under its own id with the edges the merge
+/// moved, fields it contributed leave the survivor unless edited since.
+pub async fn unmerge_payload(
+    pool: &PgPool,
+    graph_id: Uuid,
+    survivor: Uuid,
+    absorbed: Uuid,
+) -> Result<Option<JsonValue>, sqlx::Error> {
+    let merge = sqlx::query_scalar!(
+        r#"
+        SELECT e.payload
+          FROM events e
+          JOIN event_streams s ON s.stream_id = e.stream_id
+         WHERE s.category = 'entity'
+           AND s.key = $1
+           AND e.event_type = 'merge'
+           AND e.payload->>'id' = $2
+           AND e.payload->>'merged' = $3
+           AND NOT EXISTS (
+                 SELECT 1 FROM events u
+                  WHERE u.stream_id = e.stream_id
+                    AND u.event_type = 'split'
+                    AND u.payload->>'undoes' = e.payload->>'merge_id'
+               )
+         ORDER BY e.seq DESC
+         LIMIT 1
+        "#,
+        graph_id.to_string(),
+        survivor.to_string(),
+        absorbed.to_string()
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(merge) = merge else {
+        return Ok(None);
+    };
+    let Some(doc) = open_doc(pool, graph_id, survivor).await? else {
+        return Ok(None);
+    };
+
+    let previous = merge["previous_data"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    let contributed = data_of(&merge["absorbed"]);
+    let mut data = data_of(&doc);
+    data.retain(|k, v| previous.contains_key(k) || contributed.get(k) != Some(v));
+
+    // the merge recorded ends relative to the absorbed entity, which the
+    // survivor stands in for until the split
+    let edges = merge["edges"].clone();
+    Ok(Some(json!({
+        "id": survivor,
+        "split_id": Uuid::new_v4(),
+        "undoes": merge["merge_id"],
+        "into": merge["absorbed"],
+        "data": data,
+        "edges": edges,
+        "restored": merge["dropped"],
+    })))
+}
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+
+/// Something a user should know about, e.g. a correlation between cases.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub notification_id: Uuid,
+    pub kind: String,
+    pub message: String,
+    #[serde(skip_serializing)]
+    pub case_id: Option<i64>,
+    #[serde(skip_serializing)]
+    pub related_case_id: Option<i64>,
+    pub payload: JsonValue,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+/// `user_id`'s notifications, newest first.
+pub async fn list(
+    pool: &PgPool,
+    user_id: i64,
+    unread_only: bool,
+    skip: i64,
+    limit: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    sqlx::query_as!(
+        Notification,
+        r#"
+        SELECT notification_id, kind, message, case_id, related_case_id, payload, created_at,
+               read_at
+          FROM notifications
+         WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+         ORDER BY created_at DESC
+         OFFSET $3 LIMIT $4
+        "#,
+        user_id,
+        unread_only,
+        skip,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Where a user's notifications were read up to, ordered by creation and
+/// then id so notifications created at the same instant all come through.
+pub type Cursor = (DateTime<Utc>, Uuid);
+
+/// The newest of `user_id`'s notifications, `None` when they have none.
+pub async fn latest(pool: &PgPool, user_id: i64) -> Result<Option<Cursor>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT created_at, notification_id
+          FROM notifications
+         WHERE user_id = $1
+         ORDER BY created_at DESC, notification_id DESC
+         LIMIT 1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| (r.created_at, r.notification_id)))
+}
+
+/// Up to `limit` notifications `user_id` received after `after`, or from
+/// the first without one, oldest first, for pushing to an open connection.
+pub async fn since(
+    pool: &PgPool,
+    user_id: i64,
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let (created_at, notification_id) = after.unzip();
+    sqlx::query_as!(
+        Notification,
+        r#"
+        SELECT notification_id, kind, message, case_id, related_case_id, payload, created_at,
+               read_at
+          FROM notifications
+         WHERE user_id = $1
+           AND ($2::timestamptz IS NULL OR (created_at, notification_id) > ($2, $3::uuid))
+         ORDER BY created_at, notification_id
+         LIMIT $4
+        "#,
+        user_id,
+        created_at,
+        notification_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Marks one of `user_id`'s notifications read, or all of them without a
+/// `notification_id`. Returns how many were unread.
+pub async fn mark_read(
+    pool: &PgPool,
+    user_id: i64,
+    notification_id: Option<Uuid>,
+) -> Result<u64, sqlx::Error> {
+    let read = sqlx::query!(
+        r#"
+        UPDATE notifications
+           SET read_at = now()
+         WHERE user_id = $1 AND read_at IS NULL
+           AND ($2::uuid IS NULL OR notification_id = $2)
+        "#,
+        user_id,
+        notification_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(read)
+}
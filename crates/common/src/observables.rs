@@ -0,0 +1,459 @@
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::LazyLock;
+
+/// What kind of indicator a piece of text is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservableKind {
+    Url,
+    Email,
+    Cidr,
+    Ipv6,
+    Ipv4,
+    CryptoWallet,
+    Hash,
+    Phone,
+    Domain,
+    Username,
+}
+
+impl ObservableKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObservableKind::Url => "url",
+            ObservableKind::Email => "email",
+            ObservableKind::Cidr => "cidr",
+            ObservableKind::Ipv6 => "ipv6",
+            ObservableKind::Ipv4 => "ipv4",
+            ObservableKind::CryptoWallet => "crypto_wallet",
+            ObservableKind::Hash => "hash",
+            ObservableKind::Phone => "phone",
+            ObservableKind::Domain => "domain",
+            ObservableKind::Username => "username",
+        }
+    }
+
+    /// Entity label of the kind on the canvas.
+    pub fn label(self) -> &'static str {
+        match self {
+            ObservableKind::Url => "URL",
+            ObservableKind::Email => "Email",
+            ObservableKind::Cidr => "CIDR",
+            ObservableKind::Ipv6 => "IPv6",
+            ObservableKind::Ipv4 => "IPv4",
+            ObservableKind::CryptoWallet => "Crypto Wallet",
+            ObservableKind::Hash => "Hash",
+            ObservableKind::Phone => "Phone",
+            ObservableKind::Domain => "Domain",
+            ObservableKind::Username => "Username",
+        }
+    }
+}
+
+/// An observable found in text, `value` normalized so the same indicator
+/// written differently compares equal. `start` and `end` are byte offsets
+/// of `raw` in the text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Observable {
+    pub kind: ObservableKind,
+    /// The hash algorithm or wallet chain.
+    pub subtype: Option<&'static str>,
+    pub value: String,
+    pub raw: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Observable {
+    /// Entity fields for the observable, the value stored under the kind.
+    pub fn data(&self) -> JsonValue {
+        let mut data = json!({ self.kind.as_str(): self.value });
+        if let Some(subtype) = self.subtype {
+            data["type"] = json!(subtype);
+        }
+        data
+    }
+}
+
+// Matchers run in this order and a match can't overlap an earlier one, so
+// the domain of an email or URL isn't reported again on its own
+static MATCHERS: LazyLock<Vec<(ObservableKind, Regex)>> = LazyLock::new(|| {
+    let re = |p: &str| Regex::new(p).expect("observable pattern");
+    vec![
+        (
+            ObservableKind::Url,
+            re(r#"(?i)\b(?:https?|ftp)://[^\s<>"'`]+"#),
+        ),
+        (
+            ObservableKind::Email,
+            re(r"\b[A-Za-z0-9._%+-]+@(?:[A-Za-z0-9-]+\.)+[A-Za-z]{2,63}\b"),
+        ),
+        (
+            ObservableKind::Cidr,
+            re(r"(?i)[0-9a-f:.]*[0-9a-f:]/\d{1,3}\b"),
+        ),
+        (ObservableKind::Ipv6, re(r"(?i)[0-9a-f:.]*:[0-9a-f:.]*")),
+        (ObservableKind::Ipv4, re(r"\b\d{1,3}(?:\.\d{1,3}){3}\b")),
+        (
+            ObservableKind::CryptoWallet,
+            re(
+                r"(?i)\b(?:0x[0-9a-f]{40}|bc1[ac-hj-np-z02-9]{11,71}|[13][1-9A-HJ-NP-Za-km-z]{25,34})\b",
+            ),
+        ),
+        (
+            ObservableKind::Hash,
+            re(r"(?i)\b(?:[0-9a-f]{128}|[0-9a-f]{64}|[0-9a-f]{40}|[0-9a-f]{32})\b"),
+        ),
+        (ObservableKind::Phone, re(r"\+\d[\d\s().-]{6,20}\d")),
+        (
+            ObservableKind::Domain,
+            re(r"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,63}\b"),
+        ),
+        (ObservableKind::Username, re(r"(?:^|[^\w@.])@(\w{1,30})\b")),
+    ]
+});
+
+// File names look like domains, these extensions are never read as a TLD
+const FILE_EXTENSIONS: &[&str] = &[
+    "bak", "bin", "csv", "dll", "docx", "exe", "gif", "htm", "html", "ini", "jpeg", "jpg", "js",
+    "json", "log", "pdf", "php", "png", "tmp", "txt", "xlsx", "xml", "yaml", "yml",
+];
+
+fn base58_decode(s: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    let mut bytes: Vec<u8> = vec![];
+    for c in s.bytes() {
+        let mut carry = ALPHABET.iter().position(|&a| a == c)? as u32;
+        for b in bytes.iter_mut().rev() {
+            carry += *b as u32 * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    let mut out = vec![0; zeros];
+    out.extend(bytes);
+    Some(out)
+}
+
+// Legacy bitcoin addresses carry a checksum, which rules out random words
+fn base58check(s: &str) -> bool {
+    let Some(bytes) = base58_decode(s) else {
+        return false;
+    };
+    if bytes.len() != 25 {
+        return false;
+    }
+    let (payload, checksum) = bytes.split_at(21);
+    Sha256::digest(Sha256::digest(payload))[..4] == *checksum
+}
+
+fn trim_trailing(raw: &str) -> &str {
+    raw.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}', '\'', '"'])
+}
+
+/// Normalized value and subtype of `raw`, `None` when it only looks like a
+/// `kind`. `raw` may be shortened, e.g. by trailing punctuation.
+fn normalize(kind: ObservableKind, raw: &str) -> Option<(String, Option<&'static str>, usize)> {
+    let value = match kind {
+        ObservableKind::Url => {
+            let raw = trim_trailing(raw);
+            let (scheme, rest) = raw.split_once("://")?;
+            let split = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+            let (host, path) = rest.split_at(split);
+            if host.is_empty() {
+                return None;
+            }
+            let value = format!("{}://{}{path}", scheme.to_lowercase(), host.to_lowercase());
+            return Some((value, None, raw.len()));
+        }
+        ObservableKind::Email => raw.to_lowercase(),
+        ObservableKind::Cidr => {
+            let (ip, prefix) = raw.split_once('/')?;
+            let prefix: u8 = prefix.parse().ok()?;
+            // the network the block covers, not the address it was written with
+            match ip.parse::<IpAddr>().ok()? {
+                IpAddr::V4(ip) if prefix <= 32 => {
+                    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                    format!("{}/{prefix}", Ipv4Addr::from(u32::from(ip) & mask))
+                }
+                IpAddr::V6(ip) if prefix <= 128 => {
+                    let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                    format!("{}/{prefix}", Ipv6Addr::from(u128::from(ip) & mask))
+                }
+                _ => return None,
+            }
+        }
+        ObservableKind::Ipv6 => {
+            let mut raw = raw.trim_end_matches('.');
+            if raw.ends_with(':') && !raw.ends_with("::") {
+                raw = &raw[..raw.len() - 1];
+            }
+            if raw.matches(':').count() < 2 {
+                return None;
+            }
+            let value = raw.parse::<Ipv6Addr>().ok()?.to_string();
+            return Some((value, None, raw.len()));
+        }
+        ObservableKind::Ipv4 => raw.parse::<Ipv4Addr>().ok()?.to_string(),
+        ObservableKind::CryptoWallet => {
+            let lower = raw.to_lowercase();
+            if lower.starts_with("0x") {
+                return Some((lower, Some("ethereum"), raw.len()));
+            }
+            if lower.starts_with("bc1") {
+                // bech32 is all one case
+                if raw != lower && raw != raw.to_uppercase() {
+                    return None;
+                }
+                return Some((lower, Some("bitcoin"), raw.len()));
+            }
+            if !base58check(raw) {
+                return None;
+            }
+            return Some((raw.to_string(), Some("bitcoin"), raw.len()));
+        }
+        ObservableKind::Hash => {
+            let algorithm = match raw.len() {
+                32 => "md5",
+                40 => "sha1",
+                64 => "sha256",
+                _ => "sha512",
+            };
+            return Some((raw.to_lowercase(), Some(algorithm), raw.len()));
+        }
+        ObservableKind::Phone => {
+            let digits: String = raw.chars().filter(char::is_ascii_digit).collect();
+            // E.164 numbers have at most 15 digits
+            if !(8..=15).contains(&digits.len()) {
+                return None;
+            }
+            format!("+{digits}")
+        }
+        ObservableKind::Domain => {
+            let value = raw.to_lowercase();
+            let tld = value.rsplit('.').next().unwrap_or_default();
+            if FILE_EXTENSIONS.contains(&tld) {
+                return None;
+            }
+            value
+        }
+        ObservableKind::Username => raw.trim_start_matches('@').to_lowercase(),
+    };
+    Some((value, None, raw.len()))
+}
+
+fn overlaps(taken: &[(usize, usize)], start: usize, end: usize) -> bool {
+    taken.iter().any(|&(s, e)| start < e && s < end)
+}
+
+// IPv4 matches that are part of a longer dotted run, like a version number
+fn dotted_run(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let mut after = text[end..].chars();
+    let dot_digit = matches!(
+        (after.next(), after.next()),
+        (Some('.'), Some(c)) if c.is_ascii_digit()
+    );
+    dot_digit || before.is_some_and(|c| c == '.' || c.is_alphanumeric())
+}
+
+/// Every observable in `text`, in the order they appear, each value once.
+pub fn extract(text: &str) -> Vec<Observable> {
+    let mut taken: Vec<(usize, usize)> = vec![];
+    let mut found: Vec<Observable> = vec![];
+    for (kind, re) in MATCHERS.iter() {
+        for caps in re.captures_iter(text) {
+            // usernames capture the handle after what precedes the `@`
+            let m = match kind {
+                ObservableKind::Username => caps.get(1).map(|h| (h.start() - 1, h.end())),
+                _ => caps.get(0).map(|m| (m.start(), m.end())),
+            };
+            let Some((start, end)) = m else {
+                continue;
+            };
+            if *kind == ObservableKind::Ipv4 && dotted_run(text, start, end) {
+                continue;
+            }
+            let Some((value, subtype, len)) = normalize(*kind, &text[start..end]) else {
+                continue;
+            };
+            let end = start + len;
+            if overlaps(&taken, start, end) {
+                continue;
+            }
+            taken.push((start, end));
+            found.push(Observable {
+                kind: *kind,
+                subtype,
+                value,
+                raw: text[start..end].to_string(),
+                start,
+                end,
+            });
+        }
+    }
+    found.sort_by_key(|o| o.start);
+    let mut seen = HashSet::new();
+    found.retain(|o| seen.insert((o.kind, o.value.clone())));
+    found
+}
+
+/// The observable `value` is as a whole, `None` when it's anything more or
+/// less than one.
+pub fn detect(value: &str) -> Option<Observable> {
+    let value = value.trim();
+    match extract(value).as_slice() {
+        [one] if one.start == 0 && one.end == value.len() => Some(one.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ObservableKind::*;
+
+    // what `normalize` makes of a raw match: the value and subtype, if any
+    type Normalized = Option<(&'static str, Option<&'static str>)>;
+
+    #[test]
+    fn normalize_cases() {
+        let cases: &[(ObservableKind, &str, Normalized)] = &[
+            // base58check rules out look-alike words
+            (
+                CryptoWallet,
+                "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+                Some(("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", Some("bitcoin"))),
+            ),
+            (CryptoWallet, "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb", None),
+            (
+                CryptoWallet,
+                "BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ",
+                Some((
+                    "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                    Some("bitcoin"),
+                )),
+            ),
+            (
+                CryptoWallet,
+                "bc1QAR0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                None,
+            ),
+            (
+                CryptoWallet,
+                "0xDE0B295669a9FD93d5F28D9Ec85E40f4cb697BAe",
+                Some((
+                    "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+                    Some("ethereum"),
+                )),
+            ),
+            // a block is the network it covers
+            (Cidr, "10.1.2.3/8", Some(("10.0.0.0/8", None))),
+            (Cidr, "192.168.1.77/32", Some(("192.168.1.77/32", None))),
+            (Cidr, "1.2.3.4/0", Some(("0.0.0.0/0", None))),
+            (Cidr, "10.0.0.0/33", None),
+            (Cidr, "2001:DB8:ffff::1/32", Some(("2001:db8::/32", None))),
+            (Cidr, "2001:db8::/129", None),
+            (
+                Ipv6,
+                "2001:0DB8:0000:0000:0000:0000:0000:0001",
+                Some(("2001:db8::1", None)),
+            ),
+            (Ipv6, "fe80::1:", Some(("fe80::1", None))),
+            (Ipv6, "::", Some(("::", None))),
+            (Ipv6, "12:30", None),
+            (Ipv4, "8.8.8.8", Some(("8.8.8.8", None))),
+            (Ipv4, "300.1.1.1", None),
+            (
+                Hash,
+                "D41D8CD98F00B204E9800998ECF8427E",
+                Some(("d41d8cd98f00b204e9800998ecf8427e", Some("md5"))),
+            ),
+            (Phone, "+1 (555) 010-9999", Some(("+15550109999", None))),
+            (Phone, "+1 234 56", None),
+            (Email, "Bob@Example.COM", Some(("bob@example.com", None))),
+            (Domain, "Example.COM", Some(("example.com", None))),
+            (Domain, "report.pdf", None),
+            (
+                Url,
+                "HTTPS://Example.COM/Path?q=1).",
+                Some(("https://example.com/Path?q=1", None)),
+            ),
+            (Username, "@Alice_1", Some(("alice_1", None))),
+        ];
+        for (kind, raw, expected) in cases {
+            let got = normalize(*kind, raw);
+            let got = got
+                .as_ref()
+                .map(|(value, subtype, _)| (value.as_str(), *subtype));
+            assert_eq!(got, *expected, "{kind:?} {raw}");
+        }
+    }
+
+    #[test]
+    fn extract_cases() {
+        let cases: &[(&str, &[(ObservableKind, &str)])] = &[
+            // dotted runs are versions, not addresses
+            ("upgrade to 1.2.3.4.5 now", &[]),
+            ("built v10.0.0.1", &[]),
+            ("beacon to 10.0.0.1, twice", &[(Ipv4, "10.0.0.1")]),
+            // the domain of an email or URL isn't reported again
+            ("mail Bob@Example.com", &[(Email, "bob@example.com")]),
+            (
+                "see https://evil.example/login and evil.example",
+                &[
+                    (Url, "https://evil.example/login"),
+                    (Domain, "evil.example"),
+                ],
+            ),
+            // nor the address of a block
+            (
+                "block 10.1.2.3/8 and 10.1.2.3",
+                &[(Cidr, "10.0.0.0/8"), (Ipv4, "10.1.2.3")],
+            ),
+            (
+                "host 2001:DB8::1 answered from fe80::1.",
+                &[(Ipv6, "2001:db8::1"), (Ipv6, "fe80::1")],
+            ),
+            ("8.8.8.8 then 8.8.8.8 again", &[(Ipv4, "8.8.8.8")]),
+            ("ping @Alice_1 now", &[(Username, "alice_1")]),
+            (
+                "paid 1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+                &[(CryptoWallet, "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")],
+            ),
+            ("paid 1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb", &[]),
+            ("opened invoice.pdf", &[]),
+        ];
+        for (text, expected) in cases {
+            let got: Vec<(ObservableKind, &str)> = extract(text)
+                .iter()
+                .map(|o| (o.kind, &text[o.start..o.end]))
+                .collect();
+            let values: Vec<(ObservableKind, String)> = extract(text)
+                .into_iter()
+                .map(|o| (o.kind, o.value))
+                .collect();
+            let expected: Vec<(ObservableKind, String)> =
+                expected.iter().map(|(k, v)| (*k, v.to_string())).collect();
+            assert_eq!(values, expected, "{text}: matched {got:?}");
+        }
+    }
+
+    #[test]
+    fn raw_span_drops_trailing_punctuation() {
+        let text = "go to http://a.example/x).";
+        let [url] = extract(text).try_into().unwrap();
+        assert_eq!(url.raw, "http://a.example/x");
+        assert_eq!(&text[url.start..url.end], url.raw);
+    }
+}
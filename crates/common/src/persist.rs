@@ -0,0 +1,262 @@
+use chrono::Utc;
+use serde_json::{Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+use std::collections::{HashMap, hash_map::Entry};
+
+use crate::eventstore::{self, AppendEvent};
+use crate::findings::{self, Child};
+use crate::identity::{CaseIndex, NaturalKeys, NearDuplicate, Resolution, flag_duplicate};
+use crate::layout::{self, Layout, Point};
+use crate::observables::{self, Observable};
+use crate::provenance::Origin;
+
+/// Who the entity and edge events of a transform are recorded for, and how
+/// its outputs are matched to and placed among the case's entities.
+#[derive(Debug, Clone, Copy)]
+pub struct PersistContext<'a> {
+    pub graph_id: Uuid,
+    pub job_id: Uuid,
+    pub actor_id: Option<i64>,
+    pub layout: Layout,
+    pub keys: &'a NaturalKeys,
+}
+
+/// Documents of the entity one transform output resolved to and the edge
+/// linking it from its source, as appended to the event store. `reused`
+/// entities were already on the case, and `edge` is `None` when the source
+/// already linked to them.
+#[derive(Debug, Clone)]
+pub struct Created {
+    pub entity: JsonValue,
+    pub edge: Option<JsonValue>,
+    pub reused: bool,
+}
+
+impl Created {
+    pub fn child(&self) -> Child {
+        let mut data = self.entity["data"].clone();
+        if let Some(obj) = data.as_object_mut() {
+            obj.remove("label");
+        }
+        Child {
+            entity_id: uuid_field(&self.entity["id"]),
+            edge_id: self
+                .edge
+                .as_ref()
+                .map(|e| uuid_field(&e["id"]))
+                .unwrap_or_default(),
+            label: self.entity["label"]
+                .as_str()
+                .unwrap_or("unknown")
+                .to_string(),
+            data,
+            transform: self
+                .edge
+                .as_ref()
+                .and_then(|e| e["data"]["transform"].as_str())
+                .map(str::to_string),
+        }
+    }
+}
+
+fn uuid_field(v: &JsonValue) -> Uuid {
+    v.as_str()
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .unwrap_or_default()
+}
+
+async fn append(
+    pool: &PgPool,
+    ctx: PersistContext<'_>,
+    category: &str,
+    payload: &JsonValue,
+) -> Result<(), sqlx::Error> {
+    eventstore::append_event(
+        pool,
+        AppendEvent {
+            category: category.into(),
+            key: ctx.graph_id.to_string(),
+            event_type: "create".into(),
+            payload: payload.clone(),
+            valid_from: Utc::now(),
+            valid_to: None,
+            correlation_id: Some(ctx.job_id),
+            causation_id: None,
+            expected_version: None,
+            actor_id: ctx.actor_id,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+// Outputs a plugin didn't label, a bare value or an item with only a
+// `value`, are typed by the observable they are. The projector mirrors
+// `entity_type` into the label, so it's only set on these.
+fn observed(item: &JsonValue) -> Option<Observable> {
+    match item {
+        JsonValue::String(value) => observables::detect(value),
+        _ if item["label"].is_string() => None,
+        _ => item["value"].as_str().and_then(observables::detect),
+    }
+}
+
+enum Target {
+    Source,
+    Existing(JsonValue),
+    // index of the batch item that creates the entity
+    Batch(usize),
+    New(Option<NearDuplicate>),
+}
+
+/// Appends `create` events for an entity per output item, each linked from
+/// `src_id` by an edge named after the transform, both carrying the item's
+/// provenance. Items whose natural key
+/// matches an entity on the case link to that entity instead, and ones that
+/// only nearly match are created and flagged as duplicate candidates. New
+/// entities are placed around the source by `ctx.layout`, clear of
+/// everything already on the canvas.
+pub async fn create_children(
+    pool: &PgPool,
+    ctx: PersistContext<'_>,
+    src_id: Uuid,
+    src_position: &JsonValue,
+    origin: Origin<'_>,
+    items: &[JsonValue],
+) -> Result<Vec<Created>, sqlx::Error> {
+    let transform = origin.transform;
+    let entries: Vec<(String, JsonValue)> = items
+        .iter()
+        .map(|item| {
+            let observed = observed(item);
+            let label = match &observed {
+                Some(o) => o.kind.label(),
+                None => item["label"].as_str().unwrap_or("unknown"),
+            }
+            .to_string();
+            let mut data = match (&observed, item) {
+                (Some(o), JsonValue::String(_)) => o.data(),
+                _ => item.clone(),
+            };
+            if let Some(obj) = data.as_object_mut() {
+                obj.remove("edge_label");
+                obj.remove("provenance");
+                obj.insert("label".into(), json!(label));
+                if let Some(JsonValue::Object(fields)) = observed.as_ref().map(Observable::data) {
+                    obj.extend(fields);
+                    obj.insert("entity_type".into(), json!(label));
+                }
+            }
+            (label, data)
+        })
+        .collect();
+
+    let mut labels: Vec<String> = entries.iter().map(|(l, _)| l.clone()).collect();
+    labels.sort();
+    labels.dedup();
+    let index = CaseIndex::load(pool, ctx.graph_id, ctx.keys, &labels).await?;
+
+    // resolve before placing anything so only new entities take up space
+    let mut batch = HashMap::new();
+    let mut targets = Vec::with_capacity(entries.len());
+    for (i, (label, data)) in entries.iter().enumerate() {
+        let target = match ctx.keys.key(label, data) {
+            None => Target::New(None),
+            Some(key) => match index.resolve(label, &key) {
+                // a transform finding its own input adds nothing
+                Resolution::Existing(doc) if uuid_field(&doc["id"]) == src_id => Target::Source,
+                Resolution::Existing(doc) => Target::Existing(doc),
+                Resolution::New(near) => match batch.entry((label.to_lowercase(), key.strict)) {
+                    Entry::Occupied(e) => Target::Batch(*e.get()),
+                    Entry::Vacant(e) => {
+                        e.insert(i);
+                        Target::New(near)
+                    }
+                },
+            },
+        };
+        targets.push(target);
+    }
+
+    let new_count = targets
+        .iter()
+        .filter(|t| matches!(t, Target::New(_)))
+        .count();
+    let occupied = layout::occupied(pool, ctx.graph_id).await?;
+    let mut positions = layout::place(
+        ctx.layout,
+        Point::from_json(src_position),
+        &occupied,
+        new_count,
+    )
+    .into_iter();
+
+    let mut linked: Vec<Uuid> = if targets.iter().any(|t| matches!(t, Target::Existing(_))) {
+        findings::children(pool, ctx.graph_id, src_id)
+            .await?
+            .into_iter()
+            .map(|c| c.entity_id)
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let mut created: Vec<Created> = Vec::with_capacity(entries.len());
+    let mut batch_docs: HashMap<usize, JsonValue> = HashMap::new();
+    for (i, ((label, data), target)) in entries.into_iter().zip(targets).enumerate() {
+        let provenance = origin.record(ctx.job_id, &items[i]);
+        let (entity, reused) = match target {
+            Target::Source => continue,
+            Target::Existing(doc) => (doc, true),
+            Target::Batch(first) => (batch_docs[&first].clone(), true),
+            Target::New(near) => {
+                let position = positions.next().unwrap_or(Point { x: 0.0, y: 0.0 });
+                let entity_id = Uuid::new_v4();
+                let entity = json!({
+                    "id": entity_id,
+                    "position": { "x": position.x, "y": position.y },
+                    "label": label,
+                    "data": data,
+                    "provenance": provenance,
+                });
+                append(pool, ctx, "entity", &entity).await?;
+                if let Some(near) = near {
+                    flag_duplicate(
+                        pool,
+                        ctx.graph_id,
+                        entity_id,
+                        &label,
+                        &near,
+                        Some(ctx.job_id),
+                    )
+                    .await?;
+                }
+                batch_docs.insert(i, entity.clone());
+                (entity, false)
+            }
+        };
+
+        let entity_id = uuid_field(&entity["id"]);
+        let edge = if linked.contains(&entity_id) {
+            None
+        } else {
+            let edge = json!({
+                "id": Uuid::new_v4(),
+                "source": src_id,
+                "target": entity_id,
+                "label": transform,
+                // lets scheduled reruns tell which transform produced the child
+                "data": { "transform": transform, "provenance": provenance },
+            });
+            append(pool, ctx, "edge", &edge).await?;
+            linked.push(entity_id);
+            Some(edge)
+        };
+        created.push(Created {
+            entity,
+            edge,
+            reused,
+        });
+    }
+    Ok(created)
+}
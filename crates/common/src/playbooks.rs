@@ -0,0 +1,277 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::transform_cache::plugin_label;
+
+pub const MAX_DEPTH: i32 = 5;
+pub const MAX_JOBS: i32 = 100;
+const MAX_STEPS: usize = 32;
+
+/// A saved chain of transforms, see `Step` for the shape of `steps`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playbook {
+    pub playbook_id: Uuid,
+    pub org_id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: JsonValue,
+    pub max_depth: i32,
+    pub max_jobs: i32,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewPlaybook {
+    pub org_id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: JsonValue,
+    pub max_depth: Option<i32>,
+    pub max_jobs: Option<i32>,
+    pub created_by: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlaybookUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub steps: Option<JsonValue>,
+    pub max_depth: Option<i32>,
+    pub max_jobs: Option<i32>,
+}
+
+/// One transform of a playbook. It runs on every entity labelled `input`
+/// whose data fields match the `filter` regexes, and the entities it
+/// creates feed the steps named in `then`. Steps nothing points at run on
+/// the entity the playbook is started from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    pub id: String,
+    pub input: String,
+    pub transform: String,
+    #[serde(default)]
+    pub filter: BTreeMap<String, String>,
+    #[serde(default)]
+    pub then: Vec<String>,
+}
+
+/// Validated steps, with filters compiled and `then` resolved to indices.
+#[derive(Debug)]
+pub struct Plan {
+    steps: Vec<Step>,
+    filters: Vec<Vec<(String, Regex)>>,
+    next: Vec<Vec<usize>>,
+    roots: Vec<usize>,
+}
+
+impl Plan {
+    pub fn parse(steps: &JsonValue) -> Result<Plan, &'static str> {
+        let steps: Vec<Step> = serde_json::from_value(steps.clone())
+            .map_err(|_| "Playbook steps must be a list of steps.")?;
+        if steps.is_empty() {
+            return Err("Playbooks require at least one step.");
+        }
+        if steps.len() > MAX_STEPS {
+            return Err("Playbooks can have at most 32 steps.");
+        }
+
+        let mut index = HashMap::new();
+        for (i, step) in steps.iter().enumerate() {
+            if step.id.trim().is_empty() {
+                return Err("Every playbook step requires an id.");
+            }
+            if step.input.trim().is_empty() || step.transform.trim().is_empty() {
+                return Err("Every playbook step requires an input label and a transform.");
+            }
+            if index.insert(step.id.as_str(), i).is_some() {
+                return Err("Playbook step ids must be unique.");
+            }
+        }
+
+        let mut next = Vec::with_capacity(steps.len());
+        let mut incoming = vec![0usize; steps.len()];
+        for step in &steps {
+            let mut targets = Vec::with_capacity(step.then.len());
+            for id in &step.then {
+                let &target = index
+                    .get(id.as_str())
+                    .ok_or("A playbook step follows a step that doesn't exist.")?;
+                if !targets.contains(&target) {
+                    targets.push(target);
+                    incoming[target] += 1;
+                }
+            }
+            next.push(targets);
+        }
+        let roots: Vec<usize> = (0..steps.len()).filter(|&i| incoming[i] == 0).collect();
+
+        // Kahn's algorithm, anything left unvisited sits on a cycle
+        let mut remaining = incoming.clone();
+        let mut queue = roots.clone();
+        let mut visited = 0;
+        while let Some(i) = queue.pop() {
+            visited += 1;
+            for &t in &next[i] {
+                remaining[t] -= 1;
+                if remaining[t] == 0 {
+                    queue.push(t);
+                }
+            }
+        }
+        if visited != steps.len() {
+            return Err("Playbook steps can't loop back on themselves.");
+        }
+
+        let mut filters = Vec::with_capacity(steps.len());
+        for step in &steps {
+            let mut compiled = Vec::with_capacity(step.filter.len());
+            for (field, pattern) in &step.filter {
+                let re = Regex::new(pattern).map_err(|_| "Invalid playbook filter pattern.")?;
+                compiled.push((field.clone(), re));
+            }
+            filters.push(compiled);
+        }
+
+        Ok(Plan {
+            steps,
+            filters,
+            next,
+            roots,
+        })
+    }
+
+    pub fn step(&self, i: usize) -> &Step {
+        &self.steps[i]
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    /// Steps fed by the entities step `i` creates.
+    pub fn next(&self, i: usize) -> &[usize] {
+        &self.next[i]
+    }
+
+    /// Whether step `i` runs on `entity`, by label and filters.
+    pub fn accepts(&self, i: usize, entity: &JsonValue) -> bool {
+        let label_matches = plugin_label(entity)
+            .is_some_and(|label| label.eq_ignore_ascii_case(&self.steps[i].input));
+        label_matches
+            && self.filters[i]
+                .iter()
+                .all(|(field, re)| match &entity["data"][field] {
+                    JsonValue::String(s) => re.is_match(s),
+                    JsonValue::Null => false,
+                    v => re.is_match(&v.to_string()),
+                })
+    }
+}
+
+pub fn check_limits(max_depth: i32, max_jobs: i32) -> Result<(), &'static str> {
+    if !(1..=MAX_DEPTH).contains(&max_depth) {
+        return Err("Playbook depth must be between 1 and 5.");
+    }
+    if !(1..=MAX_JOBS).contains(&max_jobs) {
+        return Err("Playbooks can run between 1 and 100 transforms.");
+    }
+    Ok(())
+}
+
+pub async fn create_playbook(pool: &PgPool, new: NewPlaybook) -> Result<Playbook, sqlx::Error> {
+    sqlx::query_as!(
+        Playbook,
+        r#"
+        INSERT INTO playbooks (org_id, name, description, steps, max_depth, max_jobs, created_by)
+        VALUES ($1, $2, $3, $4, coalesce($5, 3), coalesce($6, 25), $7)
+        RETURNING playbook_id, org_id, name, description, steps, max_depth, max_jobs,
+                  created_by, created_at, updated_at
+        "#,
+        new.org_id,
+        new.name,
+        new.description,
+        new.steps,
+        new.max_depth,
+        new.max_jobs,
+        new.created_by
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_playbook(
+    pool: &PgPool,
+    playbook_id: Uuid,
+) -> Result<Option<Playbook>, sqlx::Error> {
+    sqlx::query_as!(
+        Playbook,
+        r#"
+        SELECT playbook_id, org_id, name, description, steps, max_depth, max_jobs,
+               created_by, created_at, updated_at
+          FROM playbooks
+         WHERE playbook_id = $1
+        "#,
+        playbook_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_playbooks(pool: &PgPool, org_id: i64) -> Result<Vec<Playbook>, sqlx::Error> {
+    sqlx::query_as!(
+        Playbook,
+        r#"
+        SELECT playbook_id, org_id, name, description, steps, max_depth, max_jobs,
+               created_by, created_at, updated_at
+          FROM playbooks
+         WHERE org_id = $1
+         ORDER BY name ASC
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn update_playbook(
+    pool: &PgPool,
+    playbook_id: Uuid,
+    update: PlaybookUpdate,
+) -> Result<Option<Playbook>, sqlx::Error> {
+    sqlx::query_as!(
+        Playbook,
+        r#"
+        UPDATE playbooks
+           SET name = coalesce($2, name),
+               description = coalesce($3, description),
+               steps = coalesce($4, steps),
+               max_depth = coalesce($5, max_depth),
+               max_jobs = coalesce($6, max_jobs),
+               updated_at = now()
+         WHERE playbook_id = $1
+        RETURNING playbook_id, org_id, name, description, steps, max_depth, max_jobs,
+                  created_by, created_at, updated_at
+        "#,
+        playbook_id,
+        update.name,
+        update.description,
+        update.steps,
+        update.max_depth,
+        update.max_jobs
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_playbook(pool: &PgPool, playbook_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM playbooks WHERE playbook_id = $1", playbook_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
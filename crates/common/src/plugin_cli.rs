@@ -0,0 +1,161 @@
+use moka::sync::Cache;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CACHE_SECS: u64 = 60;
+
+// The only variables `ob` inherits, the rest of the process environment
+// holds `.env` credentials such as `SECRETS_MASTER_KEY` and `DATABASE_URL`
+const INHERITED_ENV: &[&str] = &["PATH", "HOME", "LANG", "TMPDIR", "VIRTUAL_ENV"];
+
+/// The variables of this process a plugin run may see, for spawning `ob`
+/// after `env_clear()`.
+pub fn inherited_env() -> impl Iterator<Item = (&'static str, String)> {
+    INHERITED_ENV
+        .iter()
+        .filter_map(|name| Some((*name, std::env::var(name).ok()?)))
+}
+
+#[derive(Debug)]
+pub enum PluginCliError {
+    /// `ob` could not be started, usually because it isn't installed.
+    Unavailable(String),
+    Failed {
+        command: String,
+        stderr: String,
+    },
+    Timeout {
+        command: String,
+        after: Duration,
+    },
+    Unreadable {
+        command: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for PluginCliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginCliError::Unavailable(e) => write!(f, "`ob` unavailable: {e}"),
+            PluginCliError::Failed { command, stderr } => write!(f, "`{command}` failed: {stderr}"),
+            PluginCliError::Timeout { command, after } => {
+                write!(f, "`{command}` timed out after {}s", after.as_secs())
+            }
+            PluginCliError::Unreadable { command, error } => {
+                write!(f, "unreadable `{command}` output: {error}")
+            }
+        }
+    }
+}
+
+impl PluginCliError {
+    /// Shown to users in place of the details, which are only logged.
+    pub fn message(&self) -> &'static str {
+        match self {
+            PluginCliError::Unavailable(_) => "The plugin system is not available.",
+            PluginCliError::Failed { .. } => "The plugin system reported an error.",
+            PluginCliError::Timeout { .. } => "The plugin system took too long to respond.",
+            PluginCliError::Unreadable { .. } => {
+                "The plugin system returned an unreadable response."
+            }
+        }
+    }
+}
+
+/// Runs `ob` subcommands that print JSON without blocking the runtime. Each
+/// call is killed after `timeout`, and listing calls are cached for a while
+/// since plugins rarely change between requests. Clones share the cache.
+#[derive(Clone)]
+pub struct PluginCli {
+    timeout: Duration,
+    cache: Cache<Vec<String>, JsonValue>,
+}
+
+impl PluginCli {
+    pub fn new(timeout: Duration, cache_ttl: Duration) -> Self {
+        PluginCli {
+            timeout,
+            cache: Cache::builder()
+                .max_capacity(1024)
+                .time_to_live(cache_ttl)
+                .build(),
+        }
+    }
+
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        Self::new(
+            Duration::from_secs(cfg.plugin_cli_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            Duration::from_secs(cfg.plugin_cli_cache_secs.unwrap_or(DEFAULT_CACHE_SECS)),
+        )
+    }
+
+    /// Output of `ob <args>`, bypassing the cache.
+    pub async fn run(&self, args: &[&str]) -> Result<JsonValue, PluginCliError> {
+        let command = format!("ob {}", args.join(" "));
+        let child = tokio::process::Command::new("ob")
+            .args(args)
+            .env_clear()
+            .envs(inherited_env())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, child)
+            .await
+            .map_err(|_| PluginCliError::Timeout {
+                command: command.clone(),
+                after: self.timeout,
+            })?
+            .map_err(|e| PluginCliError::Unavailable(e.to_string()))?;
+        if !output.status.success() {
+            return Err(PluginCliError::Failed {
+                command,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| PluginCliError::Unreadable {
+            command,
+            error: e.to_string(),
+        })
+    }
+
+    /// Output of `ob <args>`, from the cache when a recent call succeeded.
+    /// Failures aren't cached.
+    pub async fn cached(&self, args: &[&str]) -> Result<JsonValue, PluginCliError> {
+        let key: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        if let Some(hit) = self.cache.get(&key) {
+            return Ok(hit);
+        }
+        let value = self.run(args).await?;
+        self.cache.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// `ob ls entities`
+    pub async fn entities(&self) -> Result<JsonValue, PluginCliError> {
+        self.cached(&["ls", "entities"]).await
+    }
+
+    /// `ob entities json`, entities with their blueprint and transforms.
+    pub async fn entities_full(&self) -> Result<JsonValue, PluginCliError> {
+        self.cached(&["entities", "json"]).await
+    }
+
+    /// `ob ls transforms -L <label>`
+    pub async fn transforms(&self, label: &str) -> Result<JsonValue, PluginCliError> {
+        self.cached(&["ls", "transforms", "-L", label]).await
+    }
+
+    /// `ob blueprints`, keyed by snake_case entity label.
+    pub async fn blueprints(&self) -> Result<HashMap<String, JsonValue>, PluginCliError> {
+        let value = self.cached(&["blueprints"]).await?;
+        serde_json::from_value(value).map_err(|e| PluginCliError::Unreadable {
+            command: "ob blueprints".into(),
+            error: e.to_string(),
+        })
+    }
+}
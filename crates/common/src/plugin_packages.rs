@@ -0,0 +1,286 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, types::Uuid};
+
+use crate::identity::label_key;
+
+/// A plugin package an organization installed through the API.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginPackage {
+    pub package_id: Uuid,
+    pub org_id: i64,
+    pub name: String,
+    pub source_kind: String,
+    pub source: String,
+    pub git_ref: Option<String>,
+    pub revision: Option<String>,
+    /// ed25519 signature of `revision` by `signer_key`, both base64.
+    pub signature: Option<String>,
+    pub signer_key: Option<String>,
+    pub path: Option<String>,
+    pub status: String,
+    pub error: Option<String>,
+    pub job_id: Option<Uuid>,
+    pub installed_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub installed_at: Option<DateTime<Utc>>,
+    pub removed_at: Option<DateTime<Utc>>,
+}
+
+pub struct NewPackage<'a> {
+    pub org_id: i64,
+    pub name: &'a str,
+    pub source_kind: &'a str,
+    pub source: &'a str,
+    pub git_ref: Option<&'a str>,
+    pub archive: Option<&'a [u8]>,
+    pub signature: Option<&'a str>,
+    pub signer_key: Option<&'a str>,
+    pub installed_by: i64,
+}
+
+/// Package names become directories under the plugin root.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Only public https repositories are cloned, other git transports can run
+/// commands or read the host's files.
+pub fn valid_git_url(url: &str) -> bool {
+    url.starts_with("https://") && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// `osintbuddy-entities` for `https://github.com/org/osintbuddy-entities.git`.
+pub fn name_from_source(source: &str) -> Option<String> {
+    let last = source.trim_end_matches('/').rsplit('/').next()?;
+    let name = last
+        .trim_end_matches(".git")
+        .trim_end_matches(".tar.gz")
+        .trim_end_matches(".tgz")
+        .trim_end_matches(".tar")
+        .trim_end_matches(".zip");
+    valid_name(name).then(|| name.to_string())
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+pub async fn create(pool: &PgPool, p: NewPackage<'_>) -> Result<PluginPackage, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        INSERT INTO plugin_packages
+               (org_id, name, source_kind, source, git_ref, archive, signature, signer_key, installed_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING package_id, org_id, name, source_kind, source, git_ref, revision, signature,
+                  signer_key, path, status, error, job_id, installed_by, created_at, installed_at,
+                  removed_at
+        "#,
+        p.org_id,
+        p.name,
+        p.source_kind,
+        p.source,
+        p.git_ref,
+        p.archive,
+        p.signature,
+        p.signer_key,
+        p.installed_by
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn set_job(pool: &PgPool, package_id: Uuid, job_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE plugin_packages SET job_id = $2 WHERE package_id = $1",
+        package_id,
+        job_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get(pool: &PgPool, package_id: Uuid) -> Result<Option<PluginPackage>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        SELECT package_id, org_id, name, source_kind, source, git_ref, revision, signature,
+               signer_key, path, status, error, job_id, installed_by, created_at, installed_at,
+               removed_at
+          FROM plugin_packages
+         WHERE package_id = $1
+        "#,
+        package_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The organization's packages that weren't removed, newest first.
+pub async fn list(pool: &PgPool, org_id: i64) -> Result<Vec<PluginPackage>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        SELECT package_id, org_id, name, source_kind, source, git_ref, revision, signature,
+               signer_key, path, status, error, job_id, installed_by, created_at, installed_at,
+               removed_at
+          FROM plugin_packages
+         WHERE org_id = $1 AND status <> 'removed'
+         ORDER BY created_at DESC
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn archive(pool: &PgPool, package_id: Uuid) -> Result<Option<Vec<u8>>, sqlx::Error> {
+    let archive = sqlx::query_scalar!(
+        "SELECT archive FROM plugin_packages WHERE package_id = $1",
+        package_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(archive.flatten())
+}
+
+pub async fn mark_installed(
+    pool: &PgPool,
+    package_id: Uuid,
+    path: &str,
+    revision: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE plugin_packages
+           SET status = 'installed', path = $2, revision = $3, archive = NULL, error = NULL,
+               installed_at = now()
+         WHERE package_id = $1
+        "#,
+        package_id,
+        path,
+        revision
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn mark_failed(pool: &PgPool, package_id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE plugin_packages SET status = 'failed', error = $2, archive = NULL WHERE package_id = $1",
+        package_id,
+        error
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Flags one of `org_id`'s installed or failed packages for removal, `None`
+/// when there is no such package.
+pub async fn begin_removal(
+    pool: &PgPool,
+    package_id: Uuid,
+    org_id: i64,
+) -> Result<Option<PluginPackage>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        UPDATE plugin_packages
+           SET status = 'removing'
+         WHERE package_id = $1 AND org_id = $2 AND status IN ('installed', 'failed')
+        RETURNING package_id, org_id, name, source_kind, source, git_ref, revision, signature,
+                  signer_key, path, status, error, job_id, installed_by, created_at, installed_at,
+                  removed_at
+        "#,
+        package_id,
+        org_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn mark_removed(pool: &PgPool, package_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE plugin_packages
+           SET status = 'removed', removed_at = now()
+         WHERE package_id = $1
+        "#,
+        package_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Installed packages with a directory to list plugins from, oldest first
+/// so earlier installs keep their labels when packages collide.
+pub async fn installed(pool: &PgPool) -> Result<Vec<PluginPackage>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        SELECT package_id, org_id, name, source_kind, source, git_ref, revision, signature,
+               signer_key, path, status, error, job_id, installed_by, created_at, installed_at,
+               removed_at
+          FROM plugin_packages
+         WHERE status = 'installed' AND path IS NOT NULL
+         ORDER BY installed_at, created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// `org_id`'s installed package that provides the plugin for entity `label`,
+/// `None` for the host's plugins and labels the registry doesn't know.
+pub async fn for_label(
+    pool: &PgPool,
+    org_id: i64,
+    label: &str,
+) -> Result<Option<PluginPackage>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginPackage,
+        r#"
+        SELECT k.package_id, k.org_id, k.name, k.source_kind, k.source, k.git_ref, k.revision,
+               k.signature, k.signer_key, k.path, k.status, k.error, k.job_id, k.installed_by,
+               k.created_at, k.installed_at, k.removed_at
+          FROM plugin_entities p
+          JOIN plugin_packages k ON k.package_id = p.package_id
+         WHERE p.label = $2
+           AND p.removed_at IS NULL
+           AND k.org_id = $1
+           AND k.status = 'installed'
+        "#,
+        org_id,
+        label_key(label)
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Labels of the registry entities a package provides.
+pub async fn labels(pool: &PgPool, package_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT label FROM plugin_entities
+         WHERE package_id = $1 AND removed_at IS NULL
+         ORDER BY label
+        "#,
+        package_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,734 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use log::warn;
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashMap;
+
+use crate::identity::label_key;
+use crate::plugin_cli::PluginCli;
+use crate::plugin_trust::{self, Trust};
+
+/// One run of `sync`, as recorded in `plugin_syncs`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginSync {
+    pub sync_id: Uuid,
+    pub trigger: String,
+    pub status: String,
+    pub job_id: Option<Uuid>,
+    pub added: i32,
+    pub updated: i32,
+    pub removed: i32,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// An installed entity plugin as of the last sync.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginEntity {
+    pub label: String,
+    pub display_label: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub version: Option<String>,
+    pub revision: i32,
+    pub meta: JsonValue,
+    pub blueprint: Option<JsonValue>,
+    pub transforms: JsonValue,
+    /// Set for plugins installed through the API, see `plugin_packages`.
+    pub package_id: Option<Uuid>,
+    pub synced_at: DateTime<Utc>,
+}
+
+impl PluginEntity {
+    /// The entry as `ob ls entities` lists it.
+    pub fn listing(&self) -> JsonValue {
+        self.meta.clone()
+    }
+
+    /// The entry as `ob entities json` reports it, with blueprint and transforms.
+    pub fn full(&self) -> JsonValue {
+        let mut entry = self.meta.clone();
+        if let Some(obj) = entry.as_object_mut() {
+            obj.insert("blueprint".into(), self.blueprint.clone().into());
+            obj.insert("transforms".into(), self.transforms.clone());
+        }
+        entry
+    }
+}
+
+fn text(v: &JsonValue) -> Option<String> {
+    v.as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Runs `ob` listing subcommands such as `entities json` for `sync`. The
+/// host's plugins are listed by the `PluginCli`, the worker lists each
+/// package from its own directory in the confined executor.
+pub trait PluginLister: Send + Sync {
+    fn list<'a>(&'a self, args: &'a [&'a str]) -> BoxFuture<'a, Result<JsonValue, String>>;
+}
+
+impl PluginLister for PluginCli {
+    fn list<'a>(&'a self, args: &'a [&'a str]) -> BoxFuture<'a, Result<JsonValue, String>> {
+        Box::pin(async move { self.run(args).await.map_err(|e| e.to_string()) })
+    }
+}
+
+struct Fetched {
+    label: String,
+    display_label: String,
+    meta: JsonValue,
+    blueprint: Option<JsonValue>,
+    transforms: JsonValue,
+}
+
+/// Reads every entity plugin `lister` sees. `ob entities json` carries
+/// blueprints and transforms for newer plugins, older ones are filled in
+/// from `ob blueprints` and `ob ls transforms`.
+async fn fetch(lister: &dyn PluginLister) -> Result<(Vec<Fetched>, JsonValue), String> {
+    let payload = lister.list(&["entities", "json"]).await?;
+    let (entries, favorites) = match payload {
+        JsonValue::Array(entries) => (entries, json!([])),
+        JsonValue::Object(mut obj) => match obj.remove("entities") {
+            Some(JsonValue::Array(entries)) => {
+                (entries, obj.remove("favorites").unwrap_or(json!([])))
+            }
+            _ => return Err("`ob entities json` has no entities".into()),
+        },
+        _ => return Err("`ob entities json` has no entities".into()),
+    };
+
+    let mut blueprints: Option<HashMap<String, JsonValue>> = None;
+    let mut fetched = Vec::with_capacity(entries.len());
+    for mut meta in entries {
+        let Some(display_label) = text(&meta["label"]) else {
+            continue;
+        };
+        let label = label_key(&display_label);
+        let Some(obj) = meta.as_object_mut() else {
+            continue;
+        };
+        let mut blueprint = obj.remove("blueprint").filter(|b| !b.is_null());
+        let mut transforms = obj.remove("transforms").filter(|t| t.is_array());
+
+        if blueprint.is_none() {
+            if blueprints.is_none() {
+                let all: HashMap<String, JsonValue> = lister
+                    .list(&["blueprints"])
+                    .await
+                    .and_then(|v| serde_json::from_value(v).map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        warn!("plugin sync continues without blueprints: {e}");
+                        HashMap::new()
+                    });
+                blueprints = Some(all.into_iter().map(|(k, v)| (label_key(&k), v)).collect());
+            }
+            blueprint = blueprints.as_ref().and_then(|b| b.get(&label).cloned());
+        }
+        if transforms.is_none() {
+            match lister
+                .list(&["ls", "transforms", "-L", &display_label])
+                .await
+            {
+                Ok(t) if t.is_array() => transforms = Some(t),
+                Ok(_) => warn!("`ob ls transforms -L {display_label}` is not a list"),
+                Err(e) => warn!("{e}"),
+            }
+        }
+
+        fetched.push(Fetched {
+            label,
+            display_label,
+            meta,
+            blueprint,
+            transforms: transforms.unwrap_or(json!([])),
+        });
+    }
+    Ok((fetched, favorites))
+}
+
+/// Syncs plugin metadata, blueprints and transforms into the registry from
+/// the host's CLI, bypassing its cache, and from each installed package's
+/// lister. Changed plugins get a new revision, plugins no longer listed are
+/// marked removed. Host CLI failures are recorded on the returned sync
+/// rather than returned as errors, a package that fails to list keeps its
+/// plugins as of the last sync.
+pub async fn sync(
+    pool: &PgPool,
+    host: &dyn PluginLister,
+    packages: &[(Uuid, &dyn PluginLister)],
+    trigger: &str,
+    job_id: Option<Uuid>,
+) -> Result<PluginSync, sqlx::Error> {
+    let sync_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO plugin_syncs (trigger, job_id)
+        VALUES ($1, (SELECT job_id FROM jobs WHERE job_id = $2))
+        RETURNING sync_id
+        "#,
+        trigger,
+        job_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let (host_fetched, favorites) = match fetch(host).await {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("plugin sync {sync_id} failed: {e}");
+            return finish(pool, sync_id, "failed", Some(e), &json!([]), (0, 0, 0)).await;
+        }
+    };
+    let mut fetched: Vec<(Option<Uuid>, Fetched)> =
+        host_fetched.into_iter().map(|f| (None, f)).collect();
+    let mut unlisted = vec![];
+    for (package_id, lister) in packages {
+        match fetch(*lister).await {
+            Ok((listed, _)) => {
+                for f in listed {
+                    // labels are global, the host's plugins and earlier packages keep theirs
+                    if fetched.iter().any(|(_, seen)| seen.label == f.label) {
+                        warn!(
+                            "plugin sync {sync_id}: package {package_id} reuses label {}",
+                            f.label
+                        );
+                        continue;
+                    }
+                    fetched.push((Some(*package_id), f));
+                }
+            }
+            Err(e) => {
+                warn!("plugin sync {sync_id}: package {package_id} failed to list: {e}");
+                unlisted.push(*package_id);
+            }
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let (mut added, mut updated) = (0, 0);
+    for (package_id, f) in &fetched {
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO plugin_entities AS p
+                   (label, display_label, description, author, version, meta, blueprint, transforms,
+                    package_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (label) DO UPDATE
+               SET display_label = EXCLUDED.display_label,
+                   description = EXCLUDED.description,
+                   author = EXCLUDED.author,
+                   version = EXCLUDED.version,
+                   meta = EXCLUDED.meta,
+                   blueprint = EXCLUDED.blueprint,
+                   transforms = EXCLUDED.transforms,
+                   package_id = EXCLUDED.package_id,
+                   revision = p.revision
+                            + CASE WHEN (p.meta, p.blueprint, p.transforms, p.removed_at)
+                                        IS DISTINCT FROM
+                                        (EXCLUDED.meta, EXCLUDED.blueprint, EXCLUDED.transforms, NULL::timestamptz)
+                                   THEN 1 ELSE 0 END,
+                   synced_at = now(),
+                   removed_at = NULL
+            RETURNING revision, (xmax = 0) AS "inserted!"
+            "#,
+            f.label,
+            f.display_label,
+            text(&f.meta["description"]),
+            text(&f.meta["author"]),
+            text(&f.meta["version"]),
+            f.meta,
+            f.blueprint,
+            f.transforms,
+            *package_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // keep every revision a sync has seen
+        let recorded = sqlx::query!(
+            r#"
+            INSERT INTO plugin_entity_revisions (label, revision, version, meta, blueprint, transforms)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (label, revision) DO NOTHING
+            "#,
+            f.label,
+            row.revision,
+            text(&f.meta["version"]),
+            f.meta,
+            f.blueprint,
+            f.transforms
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if row.inserted {
+            added += 1;
+        } else if recorded > 0 {
+            updated += 1;
+        }
+    }
+
+    let labels: Vec<String> = fetched.iter().map(|(_, f)| f.label.clone()).collect();
+    let removed = sqlx::query!(
+        r#"
+        UPDATE plugin_entities
+           SET removed_at = now()
+         WHERE removed_at IS NULL AND label <> ALL($1)
+           AND (package_id IS NULL OR package_id <> ALL($2))
+        "#,
+        &labels,
+        &unlisted
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() as i32;
+    tx.commit().await?;
+
+    finish(
+        pool,
+        sync_id,
+        "completed",
+        None,
+        &favorites,
+        (added, updated, removed),
+    )
+    .await
+}
+
+async fn finish(
+    pool: &PgPool,
+    sync_id: Uuid,
+    status: &str,
+    error: Option<String>,
+    favorites: &JsonValue,
+    (added, updated, removed): (i32, i32, i32),
+) -> Result<PluginSync, sqlx::Error> {
+    sqlx::query_as!(
+        PluginSync,
+        r#"
+        UPDATE plugin_syncs
+           SET status = $2, error = $3, favorites = $4, added = $5, updated = $6, removed = $7,
+               finished_at = now()
+         WHERE sync_id = $1
+        RETURNING sync_id, trigger, status, job_id, added, updated, removed, error, started_at, finished_at
+        "#,
+        sync_id,
+        status,
+        error,
+        favorites,
+        added,
+        updated,
+        removed
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn recent_syncs(pool: &PgPool, limit: i64) -> Result<Vec<PluginSync>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginSync,
+        r#"
+        SELECT sync_id, trigger, status, job_id, added, updated, removed, error, started_at, finished_at
+          FROM plugin_syncs
+         ORDER BY started_at DESC
+         LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// When the registry was last synced successfully, `None` before the first
+/// sync completes and reads should fall back to the CLI.
+pub async fn last_synced(pool: &PgPool) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT max(finished_at) FROM plugin_syncs WHERE status = 'completed'
+        "#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// When the last sync started, whatever its outcome.
+pub async fn last_attempt(pool: &PgPool) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT max(started_at) FROM plugin_syncs"#)
+        .fetch_one(pool)
+        .await
+}
+
+/// Whether a sync is queued or running, so schedules don't stack them.
+pub async fn sync_pending(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let pending = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM jobs
+             WHERE payload->>'kind' = 'plugin_sync'
+               AND status IN ('enqueued'::job_status, 'leased'::job_status, 'running'::job_status)
+        ) AS "pending!"
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(pending)
+}
+
+/// Installed entity plugins, by display label.
+pub async fn entities(pool: &PgPool) -> Result<Vec<PluginEntity>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginEntity,
+        r#"
+        SELECT label, display_label, description, author, version, revision, meta, blueprint,
+               transforms, package_id, synced_at
+          FROM plugin_entities
+         WHERE removed_at IS NULL
+         ORDER BY display_label
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The installed plugin for an entity `label`, in display or snake_case form,
+/// among the host's plugins and the packages of `org_id` that it enabled.
+/// Without an organization only the host's plugins are considered.
+pub async fn entity(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    label: &str,
+) -> Result<Option<PluginEntity>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginEntity,
+        r#"
+        SELECT p.label, p.display_label, p.description, p.author, p.version, p.revision, p.meta,
+               p.blueprint, p.transforms, p.package_id, p.synced_at
+          FROM plugin_entities p
+          LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+          LEFT JOIN org_plugin_settings s ON s.org_id = $1 AND s.label = p.label
+         WHERE p.label = $2
+           AND p.removed_at IS NULL
+           AND (k.org_id IS NULL OR k.org_id = $1)
+           AND coalesce(s.enabled, true)
+        "#,
+        org_id,
+        label_key(label)
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Secrets a plugin's `transform` declares it needs, as
+/// `{"label": "<transform>", "secrets": ["<NAME>", ...]}` in its transforms.
+/// Plugins are looked up as in `entity`.
+pub async fn transform_secrets(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    label: &str,
+    transform: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT s.name AS "name!"
+          FROM plugin_entities p
+          LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+          LEFT JOIN org_plugin_settings o ON o.org_id = $1 AND o.label = p.label
+         CROSS JOIN jsonb_array_elements(p.transforms) t
+         CROSS JOIN jsonb_array_elements_text(
+                 CASE jsonb_typeof(t->'secrets') WHEN 'array' THEN t->'secrets' ELSE '[]'::jsonb END
+               ) AS s(name)
+         WHERE p.label = $2
+           AND p.removed_at IS NULL
+           AND (k.org_id IS NULL OR k.org_id = $1)
+           AND coalesce(o.enabled, true)
+           AND t->>'label' = $3
+        "#,
+        org_id,
+        label_key(label),
+        transform
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Organizations see the host's plugins and the packages they installed
+// themselves, each unless they disabled it.
+
+/// An installed plugin as an organization manages it.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrgPlugin {
+    pub label: String,
+    pub display_label: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub version: Option<String>,
+    pub revision: i32,
+    pub package_id: Option<Uuid>,
+    pub package: Option<String>,
+    pub enabled: bool,
+    /// `host`, `trusted`, `untrusted`, `unsigned` or `invalid`, see
+    /// `plugin_trust::Trust`.
+    pub trust: String,
+    /// Name of the trusted publisher that signed the package.
+    pub publisher: Option<String>,
+    pub synced_at: DateTime<Utc>,
+}
+
+pub async fn org_plugins(pool: &PgPool, org_id: i64) -> Result<Vec<OrgPlugin>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT p.label, p.display_label, p.description, p.author, p.version, p.revision,
+               p.package_id, k.name AS "package?", coalesce(s.enabled, true) AS "enabled!",
+               k.revision AS "package_revision?", k.signature AS "signature?",
+               k.signer_key AS "signer_key?", pub.name AS "publisher?", p.synced_at
+          FROM plugin_entities p
+          LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+          LEFT JOIN plugin_publishers pub ON pub.org_id = $1 AND pub.public_key = k.signer_key
+          LEFT JOIN org_plugin_settings s ON s.org_id = $1 AND s.label = p.label
+         WHERE p.removed_at IS NULL
+           AND (k.org_id IS NULL OR k.org_id = $1)
+         ORDER BY p.display_label
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            let trust = match r.package {
+                None => Trust::Host,
+                Some(_) => plugin_trust::assess(
+                    r.package_revision.as_deref(),
+                    r.signature.as_deref(),
+                    r.signer_key.as_deref(),
+                    r.publisher.is_some(),
+                ),
+            };
+            OrgPlugin {
+                label: r.label,
+                display_label: r.display_label,
+                description: r.description,
+                author: r.author,
+                version: r.version,
+                revision: r.revision,
+                package_id: r.package_id,
+                package: r.package,
+                enabled: r.enabled,
+                trust: trust.as_str().to_string(),
+                publisher: r.publisher.filter(|_| trust == Trust::Trusted),
+                synced_at: r.synced_at,
+            }
+        })
+        .collect())
+}
+
+/// The installed plugins `org_id` has enabled.
+pub async fn entities_for_org(
+    pool: &PgPool,
+    org_id: i64,
+) -> Result<Vec<PluginEntity>, sqlx::Error> {
+    sqlx::query_as!(
+        PluginEntity,
+        r#"
+        SELECT p.label, p.display_label, p.description, p.author, p.version, p.revision, p.meta,
+               p.blueprint, p.transforms, p.package_id, p.synced_at
+          FROM plugin_entities p
+          LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+          LEFT JOIN org_plugin_settings s ON s.org_id = $1 AND s.label = p.label
+         WHERE p.removed_at IS NULL
+           AND (k.org_id IS NULL OR k.org_id = $1)
+           AND coalesce(s.enabled, true)
+         ORDER BY p.display_label
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Whether `org_id` may run the plugin for entity `label`. Labels the
+/// registry doesn't know are let through, the CLI decides about those.
+pub async fn enabled_for_org(pool: &PgPool, org_id: i64, label: &str) -> Result<bool, sqlx::Error> {
+    let blocked = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+              FROM plugin_entities p
+              LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+              LEFT JOIN org_plugin_settings s ON s.org_id = $1 AND s.label = p.label
+             WHERE p.label = $2
+               AND p.removed_at IS NULL
+               AND ((k.org_id IS NOT NULL AND k.org_id <> $1) OR s.enabled = false)
+        ) AS "blocked!"
+        "#,
+        org_id,
+        label_key(label)
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(!blocked)
+}
+
+/// Enables or disables a plugin `org_id` can see, `false` when it can't.
+pub async fn set_enabled(
+    pool: &PgPool,
+    org_id: i64,
+    label: &str,
+    enabled: bool,
+    actor_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        INSERT INTO org_plugin_settings (org_id, label, enabled, updated_by)
+        SELECT $1, p.label, $3, $4
+          FROM plugin_entities p
+          LEFT JOIN plugin_packages k ON k.package_id = p.package_id
+         WHERE p.label = $2
+           AND p.removed_at IS NULL
+           AND (k.org_id IS NULL OR k.org_id = $1)
+        ON CONFLICT (org_id, label) DO UPDATE
+           SET enabled = EXCLUDED.enabled, updated_by = EXCLUDED.updated_by, updated_at = now()
+        "#,
+        org_id,
+        label_key(label),
+        enabled,
+        actor_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(updated > 0)
+}
+
+/// Labels of the plugins currently installed.
+pub async fn active_labels(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT label FROM plugin_entities WHERE removed_at IS NULL")
+        .fetch_all(pool)
+        .await
+}
+
+/// Plugin level favorites reported by the last completed sync.
+pub async fn favorites(pool: &PgPool) -> Result<JsonValue, sqlx::Error> {
+    let favorites = sqlx::query_scalar!(
+        r#"
+        SELECT favorites FROM plugin_syncs
+         WHERE status = 'completed'
+         ORDER BY finished_at DESC
+         LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(favorites.unwrap_or(json!([])))
+}
+
+/// Blueprints of the installed plugins, keyed by snake_case label as
+/// `ob blueprints` reports them.
+pub async fn blueprints(pool: &PgPool) -> Result<HashMap<String, JsonValue>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT label, blueprint AS "blueprint!"
+          FROM plugin_entities
+         WHERE removed_at IS NULL AND blueprint IS NOT NULL
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.label, r.blueprint)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers `ob entities json` with canned entries, or fails to list
+    struct Listed(Option<JsonValue>);
+
+    impl PluginLister for Listed {
+        fn list<'a>(&'a self, _args: &'a [&'a str]) -> BoxFuture<'a, Result<JsonValue, String>> {
+            Box::pin(async move { self.0.clone().ok_or_else(|| "ob exit code 1".to_string()) })
+        }
+    }
+
+    fn listing(labels: &[&str]) -> Listed {
+        let entries: Vec<JsonValue> = labels
+            .iter()
+            .map(|label| json!({ "label": label, "blueprint": {}, "transforms": [] }))
+            .collect();
+        Listed(Some(json!(entries)))
+    }
+
+    async fn package(pool: &PgPool, name: &str) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO plugin_packages (org_id, name, source_kind, source, status, path)
+            VALUES (1, $1, 'git', 'https://example.com/p.git', 'installed', $1)
+            RETURNING package_id
+            "#,
+        )
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn owner(pool: &PgPool, label: &str) -> Option<(Option<Uuid>, bool)> {
+        sqlx::query_as(
+            "SELECT package_id, removed_at IS NOT NULL FROM plugin_entities WHERE label = $1",
+        )
+        .bind(label)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn packages_are_synced_from_their_own_listing(pool: PgPool) {
+        sqlx::query("INSERT INTO organizations (id, name) VALUES (1, 'org')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let shodan = package(&pool, "shodan").await;
+        let broken = package(&pool, "broken").await;
+
+        let host = listing(&["IP Address"]);
+        let listed = listing(&["Shodan Host", "IP Address"]);
+        let failed = Listed(None);
+        let packages: [(Uuid, &dyn PluginLister); 2] = [(shodan, &listed), (broken, &failed)];
+        let synced = sync(&pool, &host, &packages, "manual", None).await.unwrap();
+        assert_eq!(synced.status, "completed");
+        assert_eq!(synced.added, 2);
+        // the host keeps a label a package also lists
+        assert_eq!(owner(&pool, "ip_address").await, Some((None, false)));
+        assert_eq!(
+            owner(&pool, "shodan_host").await,
+            Some((Some(shodan), false))
+        );
+
+        // a package that fails to list keeps what it had, the host's
+        // plugins follow the host's listing
+        let host = listing(&[]);
+        let packages: [(Uuid, &dyn PluginLister); 1] = [(shodan, &failed)];
+        let synced = sync(&pool, &host, &packages, "manual", None).await.unwrap();
+        assert_eq!(synced.removed, 1);
+        assert_eq!(owner(&pool, "ip_address").await, Some((None, true)));
+        assert_eq!(
+            owner(&pool, "shodan_host").await,
+            Some((Some(shodan), false))
+        );
+
+        // a package no longer installed takes its plugins with it
+        let synced = sync(&pool, &host, &[], "manual", None).await.unwrap();
+        assert_eq!(synced.removed, 1);
+        assert_eq!(
+            owner(&pool, "shodan_host").await,
+            Some((Some(shodan), true))
+        );
+    }
+}
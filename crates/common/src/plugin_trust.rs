@@ -0,0 +1,377 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::Serialize;
+use sqlx::{PgPool, types::Uuid};
+use std::fmt;
+
+use crate::identity::label_key;
+
+/// How far a plugin is trusted to run code in the worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trust {
+    /// Installed on the host by its operator, not through the API.
+    Host,
+    /// Signed by a publisher the organization trusts.
+    Trusted,
+    /// Signed by a publisher the organization doesn't trust.
+    Untrusted,
+    Unsigned,
+    /// The recorded signature doesn't verify or the package is another
+    /// organization's, the plugin never runs.
+    Invalid,
+}
+
+impl Trust {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Trust::Host => "host",
+            Trust::Trusted => "trusted",
+            Trust::Untrusted => "untrusted",
+            Trust::Unsigned => "unsigned",
+            Trust::Invalid => "invalid",
+        }
+    }
+
+    /// Plugins nobody the organization trusts vouches for only run on the
+    /// most restrictive executor.
+    pub fn confined(self) -> bool {
+        matches!(self, Trust::Untrusted | Trust::Unsigned)
+    }
+}
+
+impl fmt::Display for Trust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A base64 ed25519 public key.
+pub fn decode_key(key: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = STANDARD.decode(key.trim()).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+/// A base64 ed25519 signature.
+pub fn decode_signature(signature: &str) -> Option<Signature> {
+    let bytes = STANDARD.decode(signature.trim()).ok()?;
+    Signature::from_slice(&bytes).ok()
+}
+
+/// Whether `signature` is `key`'s signature of a package `revision`: the
+/// commit a git install checked out or the sha256 of an uploaded archive,
+/// as lowercase hex.
+pub fn verify(key: &str, signature: &str, revision: &str) -> bool {
+    let (Some(key), Some(signature)) = (decode_key(key), decode_signature(signature)) else {
+        return false;
+    };
+    key.verify_strict(revision.as_bytes(), &signature).is_ok()
+}
+
+/// Trust of a package from what was recorded at install: its `revision`,
+/// `signature` and `signer_key`, and whether the organization `trusts` that
+/// key.
+pub fn assess(
+    revision: Option<&str>,
+    signature: Option<&str>,
+    signer_key: Option<&str>,
+    trusts: bool,
+) -> Trust {
+    let (Some(revision), Some(signature), Some(key)) = (revision, signature, signer_key) else {
+        return Trust::Unsigned;
+    };
+    if !verify(key, signature, revision) {
+        Trust::Invalid
+    } else if trusts {
+        Trust::Trusted
+    } else {
+        Trust::Untrusted
+    }
+}
+
+/// Trust of the plugin providing entity `label` to `org_id`'s jobs. Labels
+/// the registry doesn't attribute to a package come from the host, another
+/// organization's package never runs for `org_id`.
+pub async fn for_label(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    label: &str,
+) -> Result<Trust, sqlx::Error> {
+    let package = sqlx::query!(
+        r#"
+        SELECT k.org_id, k.revision, k.signature, k.signer_key,
+               EXISTS (
+                   SELECT 1 FROM plugin_publishers pub
+                    WHERE pub.org_id = $1 AND pub.public_key = k.signer_key
+               ) AS "trusted!"
+          FROM plugin_entities p
+          JOIN plugin_packages k ON k.package_id = p.package_id
+         WHERE p.label = $2 AND p.removed_at IS NULL
+        "#,
+        org_id,
+        label_key(label)
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(package) = package else {
+        return Ok(Trust::Host);
+    };
+    if Some(package.org_id) != org_id {
+        return Ok(Trust::Invalid);
+    }
+    Ok(assess(
+        package.revision.as_deref(),
+        package.signature.as_deref(),
+        package.signer_key.as_deref(),
+        package.trusted,
+    ))
+}
+
+/// A publisher on an organization's allowlist.
+#[derive(Debug, Clone, Serialize)]
+pub struct Publisher {
+    pub publisher_id: Uuid,
+    pub name: String,
+    pub public_key: String,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub async fn publishers(pool: &PgPool, org_id: i64) -> Result<Vec<Publisher>, sqlx::Error> {
+    sqlx::query_as!(
+        Publisher,
+        r#"
+        SELECT publisher_id, name, public_key, created_by, created_at
+          FROM plugin_publishers
+         WHERE org_id = $1
+         ORDER BY name
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn add_publisher(
+    pool: &PgPool,
+    org_id: i64,
+    name: &str,
+    public_key: &str,
+    created_by: i64,
+) -> Result<Publisher, sqlx::Error> {
+    sqlx::query_as!(
+        Publisher,
+        r#"
+        INSERT INTO plugin_publishers (org_id, name, public_key, created_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING publisher_id, name, public_key, created_by, created_at
+        "#,
+        org_id,
+        name,
+        public_key,
+        created_by
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Takes a publisher off `org_id`'s allowlist, `false` when it wasn't on it.
+pub async fn remove_publisher(
+    pool: &PgPool,
+    org_id: i64,
+    publisher_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM plugin_publishers WHERE org_id = $1 AND publisher_id = $2",
+        org_id,
+        publisher_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(removed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const REVISION: &str = "3f2a9c0e5b7d4e1f8a6b2c9d0e1f2a3b4c5d6e7f";
+
+    fn signer(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn public_key(seed: u8) -> String {
+        STANDARD.encode(signer(seed).verifying_key().as_bytes())
+    }
+
+    fn sign(seed: u8, revision: &str) -> String {
+        STANDARD.encode(signer(seed).sign(revision.as_bytes()).to_bytes())
+    }
+
+    #[test]
+    fn good_signature_verifies() {
+        assert!(verify(&public_key(1), &sign(1, REVISION), REVISION));
+    }
+
+    #[test]
+    fn tampered_revision_or_other_key_fails() {
+        let signature = sign(1, REVISION);
+        assert!(!verify(
+            &public_key(1),
+            &signature,
+            &REVISION.replace('3', "4")
+        ));
+        assert!(!verify(&public_key(2), &signature, REVISION));
+        assert!(!verify(&public_key(1), "not base64!", REVISION));
+        assert!(!verify("c2hvcnQ=", &signature, REVISION));
+    }
+
+    async fn org(pool: &PgPool, id: i64) {
+        sqlx::query("INSERT INTO organizations (id, name) VALUES ($1, 'org')")
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    // An installed package of `org_id` providing entity `label`
+    async fn package(
+        pool: &PgPool,
+        org_id: i64,
+        label: &str,
+        revision: &str,
+        signed: Option<(String, String)>,
+    ) {
+        let (signature, signer_key) = signed.unzip();
+        let package_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO plugin_packages
+                   (org_id, name, source_kind, source, revision, status, signature, signer_key)
+            VALUES ($1, $2, 'git', 'https://example.com/p.git', $3, 'installed', $4, $5)
+            RETURNING package_id
+            "#,
+        )
+        .bind(org_id)
+        .bind(format!("pkg-{label}"))
+        .bind(revision)
+        .bind(signature)
+        .bind(signer_key)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO plugin_entities (label, display_label, package_id) VALUES ($1, $1, $2)",
+        )
+        .bind(label)
+        .bind(package_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn trust_of_each_label(pool: PgPool) {
+        org(&pool, 1).await;
+        sqlx::query(
+            "INSERT INTO plugin_publishers (org_id, name, public_key) VALUES (1, 'Acme', $1)",
+        )
+        .bind(public_key(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        package(
+            &pool,
+            1,
+            "trusted",
+            REVISION,
+            Some((sign(1, REVISION), public_key(1))),
+        )
+        .await;
+        package(
+            &pool,
+            1,
+            "stranger",
+            REVISION,
+            Some((sign(2, REVISION), public_key(2))),
+        )
+        .await;
+        let other = REVISION.replace('3', "4");
+        package(
+            &pool,
+            1,
+            "tampered",
+            &other,
+            Some((sign(1, REVISION), public_key(1))),
+        )
+        .await;
+        package(&pool, 1, "unsigned", REVISION, None).await;
+
+        let trust = |label: &'static str| for_label(&pool, Some(1), label);
+        assert_eq!(trust("trusted").await.unwrap(), Trust::Trusted);
+        assert_eq!(trust("stranger").await.unwrap(), Trust::Untrusted);
+        assert_eq!(trust("tampered").await.unwrap(), Trust::Invalid);
+        assert_eq!(trust("unsigned").await.unwrap(), Trust::Unsigned);
+        // nothing attributes the label to a package
+        assert_eq!(trust("ip_address").await.unwrap(), Trust::Host);
+
+        let listed = crate::plugin_registry::org_plugins(&pool, 1).await.unwrap();
+        let listed = |label: &str| {
+            let p = listed.iter().find(|p| p.label == label).unwrap();
+            (p.trust.clone(), p.publisher.clone())
+        };
+        assert_eq!(listed("trusted"), ("trusted".into(), Some("Acme".into())));
+        assert_eq!(listed("stranger"), ("untrusted".into(), None));
+        assert_eq!(listed("tampered"), ("invalid".into(), None));
+        assert_eq!(listed("unsigned"), ("unsigned".into(), None));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn other_orgs_packages_are_out_of_scope(pool: PgPool) {
+        org(&pool, 1).await;
+        org(&pool, 2).await;
+        package(&pool, 1, "mine", REVISION, None).await;
+        sqlx::query(
+            r#"UPDATE plugin_entities SET transforms = '[{"label": "lookup", "secrets": ["API_KEY"]}]'"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        use crate::plugin_registry::{entity, transform_secrets};
+        assert!(entity(&pool, Some(1), "mine").await.unwrap().is_some());
+        assert_eq!(
+            transform_secrets(&pool, Some(1), "mine", "lookup")
+                .await
+                .unwrap(),
+            vec!["API_KEY".to_string()]
+        );
+        assert_eq!(
+            for_label(&pool, Some(1), "mine").await.unwrap(),
+            Trust::Unsigned
+        );
+
+        for org_id in [Some(2), None] {
+            assert!(entity(&pool, org_id, "mine").await.unwrap().is_none());
+            let secrets = transform_secrets(&pool, org_id, "mine", "lookup");
+            assert!(secrets.await.unwrap().is_empty());
+            assert_eq!(
+                for_label(&pool, org_id, "mine").await.unwrap(),
+                Trust::Invalid
+            );
+        }
+
+        sqlx::query(
+            "INSERT INTO org_plugin_settings (org_id, label, enabled) VALUES (1, 'mine', false)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(entity(&pool, Some(1), "mine").await.unwrap().is_none());
+    }
+}
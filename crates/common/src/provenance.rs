@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+
+/// What produced a batch of transform outputs. Every entity and edge created
+/// from them records this as its `provenance`, along with the `source_url`
+/// and `confidence` the plugin reported for the item, if any.
+#[derive(Debug, Clone, Copy)]
+pub struct Origin<'a> {
+    pub transform: &'a str,
+    pub plugin: Option<&'a str>,
+    /// Unknown when the plugin list wasn't consulted, e.g. for playbook steps.
+    pub plugin_version: Option<&'a str>,
+    /// When the plugin returned the results, cached replays keep the
+    /// original time.
+    pub retrieved_at: DateTime<Utc>,
+}
+
+impl Origin<'_> {
+    /// Provenance of one output `item`. Plugins attribute items either with
+    /// a `provenance` object or with top-level `source_url` and `confidence`
+    /// fields, confidence is clamped to 0..=1.
+    pub fn record(&self, job_id: Uuid, item: &JsonValue) -> JsonValue {
+        let reported = match item.get("provenance") {
+            Some(p) if p.is_object() => p,
+            _ => item,
+        };
+        let source_url = reported["source_url"]
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+        let confidence = reported["confidence"].as_f64().map(|c| c.clamp(0.0, 1.0));
+        json!({
+            "transform": self.transform,
+            "plugin": self.plugin,
+            "plugin_version": self.plugin_version,
+            "source_url": source_url,
+            "retrieved_at": self.retrieved_at,
+            "confidence": confidence,
+            "job_id": job_id,
+        })
+    }
+}
+
+/// Narrows a provenance query, unset fields match anything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProvenanceFilter {
+    pub source_url: Option<String>,
+    pub transform: Option<String>,
+    pub plugin: Option<String>,
+    pub job_id: Option<Uuid>,
+    pub min_confidence: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sourced {
+    pub id: Uuid,
+    pub doc: JsonValue,
+    pub provenance: JsonValue,
+}
+
+/// Open entities and edges of a case whose provenance matches `filter`.
+pub async fn find(
+    pool: &PgPool,
+    graph_id: Uuid,
+    filter: &ProvenanceFilter,
+) -> Result<(Vec<Sourced>, Vec<Sourced>), sqlx::Error> {
+    let job_id = filter.job_id.map(|j| j.to_string());
+    let entities = sqlx::query!(
+        r#"
+        SELECT entity_id AS "entity_id!", doc, provenance AS "provenance!"
+          FROM entities_current
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND provenance IS NOT NULL
+           AND ($2::text IS NULL OR provenance->>'source_url' = $2)
+           AND ($3::text IS NULL OR provenance->>'transform' = $3)
+           AND ($4::text IS NULL OR lower(provenance->>'plugin') = lower($4))
+           AND ($5::text IS NULL OR provenance->>'job_id' = $5)
+           AND ($6::float8 IS NULL OR (provenance->>'confidence')::float8 >= $6)
+         ORDER BY sys_from ASC
+         LIMIT 1000
+        "#,
+        graph_id,
+        filter.source_url,
+        filter.transform,
+        filter.plugin,
+        job_id,
+        filter.min_confidence
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Sourced {
+        id: r.entity_id,
+        doc: r.doc,
+        provenance: r.provenance,
+    })
+    .collect();
+
+    let edges = sqlx::query!(
+        r#"
+        SELECT edge_id, src_id, dst_id, props, provenance AS "provenance!"
+          FROM edges_current
+         WHERE graph_id = $1
+           AND sys_to IS NULL
+           AND provenance IS NOT NULL
+           AND ($2::text IS NULL OR provenance->>'source_url' = $2)
+           AND ($3::text IS NULL OR provenance->>'transform' = $3)
+           AND ($4::text IS NULL OR lower(provenance->>'plugin') = lower($4))
+           AND ($5::text IS NULL OR provenance->>'job_id' = $5)
+           AND ($6::float8 IS NULL OR (provenance->>'confidence')::float8 >= $6)
+         ORDER BY sys_from ASC
+         LIMIT 1000
+        "#,
+        graph_id,
+        filter.source_url,
+        filter.transform,
+        filter.plugin,
+        job_id,
+        filter.min_confidence
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Sourced {
+        id: r.edge_id,
+        doc: json!({ "id": r.edge_id, "source": r.src_id, "target": r.dst_id, "data": r.props }),
+        provenance: r.provenance,
+    })
+    .collect();
+
+    Ok((entities, edges))
+}
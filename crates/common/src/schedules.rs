@@ -0,0 +1,404 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlx::{PgPool, types::Uuid};
+
+/// A recurring transform on one entity, see `CronSpec` for the `cron` syntax.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub schedule_id: Uuid,
+    pub org_id: i64,
+    pub graph_id: Uuid,
+    pub entity_id: Uuid,
+    pub transform: String,
+    pub entity: JsonValue,
+    pub cron: String,
+    pub enabled: bool,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_job_id: Option<Uuid>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewSchedule {
+    pub org_id: i64,
+    pub graph_id: Uuid,
+    pub entity_id: Uuid,
+    pub transform: String,
+    pub entity: JsonValue,
+    pub cron: String,
+    pub created_by: Option<i64>,
+}
+
+// Anything more frequent would just hammer the plugin's upstream
+pub const MIN_INTERVAL_MINUTES: i64 = 5;
+
+/// Standard five field cron (`minute hour day-of-month month day-of-week`,
+/// UTC) with `*`, lists, ranges and steps, the `@hourly`, `@daily`,
+/// `@weekly` and `@monthly` shorthands, or `@every <n>m|h|d`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CronSpec {
+    Fields {
+        minutes: u64,
+        hours: u32,
+        days: u32,
+        months: u16,
+        weekdays: u8,
+        // Cron ORs day-of-month and day-of-week when both are restricted
+        any_day: bool,
+        any_weekday: bool,
+    },
+    Every(Duration),
+}
+
+impl CronSpec {
+    pub fn parse(spec: &str) -> Result<CronSpec, &'static str> {
+        let spec = spec.trim();
+        let expanded = match spec {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            _ => spec,
+        };
+        if let Some(every) = expanded.strip_prefix("@every ") {
+            return parse_every(every.trim());
+        }
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err("Schedules need five cron fields or an @every interval.");
+        };
+        let minutes = parse_field(minute, 0, 59)?;
+        let hours = parse_field(hour, 0, 23)? as u32;
+        let days = parse_field(day, 1, 31)? as u32;
+        let months = parse_field(month, 1, 12)? as u16;
+        // 7 is accepted as another name for Sunday
+        let weekdays = parse_field(weekday, 0, 7)?;
+        let weekdays = ((weekdays | (weekdays >> 7)) & 0x7f) as u8;
+        Ok(CronSpec::Fields {
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// First run strictly after `after`, `None` if the spec can never match
+    /// (e.g. February 30th).
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (minutes, hours, days, months, weekdays, any_day, any_weekday) = match *self {
+            CronSpec::Every(interval) => return after.checked_add_signed(interval),
+            CronSpec::Fields {
+                minutes,
+                hours,
+                days,
+                months,
+                weekdays,
+                any_day,
+                any_weekday,
+            } => (minutes, hours, days, months, weekdays, any_day, any_weekday),
+        };
+
+        let mut t = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        // Every candidate skips at least a minute, a few years bounds the search
+        let limit = t + Duration::days(366 * 5);
+        while t < limit {
+            if months & (1 << t.month()) == 0 {
+                t = first_of_next_month(t)?;
+                continue;
+            }
+            let day_ok = days & (1 << t.day()) != 0;
+            let weekday_ok = weekdays & (1 << t.weekday().num_days_from_sunday()) != 0;
+            let matches_day = match (any_day, any_weekday) {
+                (true, true) => true,
+                (false, true) => day_ok,
+                (true, false) => weekday_ok,
+                (false, false) => day_ok || weekday_ok,
+            };
+            if !matches_day {
+                t = t.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+                continue;
+            }
+            if hours & (1 << t.hour()) == 0 {
+                t = t.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+                continue;
+            }
+            if minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+
+    /// Shortest gap between the next few runs, used to reject specs that
+    /// would run more often than `MIN_INTERVAL_MINUTES`.
+    pub fn min_interval(&self, from: DateTime<Utc>) -> Option<Duration> {
+        if let CronSpec::Every(interval) = self {
+            return Some(*interval);
+        }
+        let mut prev = self.next_after(from)?;
+        let mut shortest: Option<Duration> = None;
+        for _ in 0..8 {
+            let Some(next) = self.next_after(prev) else {
+                break;
+            };
+            let gap = next - prev;
+            shortest = Some(shortest.map_or(gap, |s| s.min(gap)));
+            prev = next;
+        }
+        shortest
+    }
+}
+
+fn parse_every(every: &str) -> Result<CronSpec, &'static str> {
+    let invalid = "Intervals look like @every 30m, @every 6h or @every 1d.";
+    let count = |n: &str| n.parse::<i64>().ok().filter(|n| *n >= 1).ok_or(invalid);
+    let interval = if let Some(n) = every.strip_suffix('m') {
+        Duration::try_minutes(count(n)?).ok_or(invalid)?
+    } else if let Some(n) = every.strip_suffix('h') {
+        Duration::try_hours(count(n)?).ok_or(invalid)?
+    } else if let Some(n) = every.strip_suffix('d') {
+        Duration::try_days(count(n)?).ok_or(invalid)?
+    } else {
+        return Err(invalid);
+    };
+    Ok(CronSpec::Every(interval))
+}
+
+// Bitmask of the values one cron field allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, &'static str> {
+    let invalid = "Invalid cron field.";
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid);
+        }
+        let (lo, hi) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((lo, hi)) => (
+                    lo.parse().map_err(|_| invalid)?,
+                    hi.parse().map_err(|_| invalid)?,
+                ),
+                None => {
+                    let v: u32 = range.parse().map_err(|_| invalid)?;
+                    // `5/15` means from 5 to the end in steps of 15
+                    (v, if part.contains('/') { max } else { v })
+                }
+            },
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err("Cron field value out of range.");
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+fn first_of_next_month(t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let (year, month) = if t.month() == 12 {
+        (t.year() + 1, 1)
+    } else {
+        (t.year(), t.month() + 1)
+    };
+    t.with_day(1)?
+        .with_month(month)?
+        .with_year(year)?
+        .duration_trunc(Duration::days(1))
+        .ok()
+}
+
+pub async fn create_schedule(
+    pool: &PgPool,
+    new: NewSchedule,
+    next_run_at: DateTime<Utc>,
+) -> Result<Schedule, sqlx::Error> {
+    sqlx::query_as!(
+        Schedule,
+        r#"
+        INSERT INTO schedules (org_id, graph_id, entity_id, transform, entity, cron, next_run_at, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING schedule_id, org_id, graph_id, entity_id, transform, entity, cron, enabled,
+                  next_run_at, last_run_at, last_job_id, created_by, created_at, updated_at
+        "#,
+        new.org_id,
+        new.graph_id,
+        new.entity_id,
+        new.transform,
+        new.entity,
+        new.cron,
+        next_run_at,
+        new.created_by
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_schedule(
+    pool: &PgPool,
+    schedule_id: Uuid,
+) -> Result<Option<Schedule>, sqlx::Error> {
+    sqlx::query_as!(
+        Schedule,
+        r#"
+        SELECT schedule_id, org_id, graph_id, entity_id, transform, entity, cron, enabled,
+               next_run_at, last_run_at, last_job_id, created_by, created_at, updated_at
+          FROM schedules
+         WHERE schedule_id = $1
+        "#,
+        schedule_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The org's schedules, optionally only those on one case.
+pub async fn list_schedules(
+    pool: &PgPool,
+    org_id: i64,
+    graph_id: Option<Uuid>,
+) -> Result<Vec<Schedule>, sqlx::Error> {
+    sqlx::query_as!(
+        Schedule,
+        r#"
+        SELECT schedule_id, org_id, graph_id, entity_id, transform, entity, cron, enabled,
+               next_run_at, last_run_at, last_job_id, created_by, created_at, updated_at
+          FROM schedules
+         WHERE org_id = $1
+           AND ($2::uuid IS NULL OR graph_id = $2)
+         ORDER BY created_at ASC
+        "#,
+        org_id,
+        graph_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Changes the spec and/or toggles a schedule, `next_run_at` must be
+/// recomputed by the caller whenever either changes.
+pub async fn update_schedule(
+    pool: &PgPool,
+    schedule_id: Uuid,
+    cron: Option<&str>,
+    enabled: Option<bool>,
+    next_run_at: DateTime<Utc>,
+) -> Result<Option<Schedule>, sqlx::Error> {
+    sqlx::query_as!(
+        Schedule,
+        r#"
+        UPDATE schedules
+           SET cron = coalesce($2, cron),
+               enabled = coalesce($3, enabled),
+               next_run_at = $4,
+               updated_at = now()
+         WHERE schedule_id = $1
+        RETURNING schedule_id, org_id, graph_id, entity_id, transform, entity, cron, enabled,
+                  next_run_at, last_run_at, last_job_id, created_by, created_at, updated_at
+        "#,
+        schedule_id,
+        cron,
+        enabled,
+        next_run_at
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_schedule(pool: &PgPool, schedule_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM schedules WHERE schedule_id = $1", schedule_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn disable_schedule(
+    pool: &PgPool,
+    org_id: i64,
+    schedule_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE schedules SET enabled = false, updated_at = now()
+         WHERE schedule_id = $1 AND org_id = $2
+        "#,
+        schedule_id,
+        org_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn due_schedules(pool: &PgPool, max: i64) -> Result<Vec<Schedule>, sqlx::Error> {
+    sqlx::query_as!(
+        Schedule,
+        r#"
+        SELECT schedule_id, org_id, graph_id, entity_id, transform, entity, cron, enabled,
+               next_run_at, last_run_at, last_job_id, created_by, created_at, updated_at
+          FROM schedules
+         WHERE enabled AND next_run_at <= now()
+         ORDER BY next_run_at ASC
+         LIMIT $1
+        "#,
+        max
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Moves a due schedule on to its next run. Only one scheduler wins the
+/// claim for a given `due_at`, the others see it already advanced.
+pub async fn claim_run(
+    pool: &PgPool,
+    schedule_id: Uuid,
+    due_at: DateTime<Utc>,
+    next_run_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE schedules
+           SET next_run_at = $3,
+               last_run_at = now()
+         WHERE schedule_id = $1
+           AND next_run_at = $2
+           AND enabled
+        "#,
+        schedule_id,
+        due_at,
+        next_run_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn set_last_job(
+    pool: &PgPool,
+    schedule_id: Uuid,
+    job_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE schedules SET last_job_id = $2 WHERE schedule_id = $1",
+        schedule_id,
+        job_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
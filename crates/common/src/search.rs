@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sqlx::{PgPool, types::Uuid};
+
+use crate::observables;
+
+/// Entities to look for across cases, unset fields match anything.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// Every word must appear in the entity's label or data. An observable
+    /// matches however it was written, e.g. any formatting of a phone number.
+    pub text: String,
+    pub entity_type: Option<String>,
+    pub case_id: Option<i64>,
+    /// A value of the entity's `tags` field.
+    pub tag: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub skip: i64,
+    pub limit: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub entity_id: Uuid,
+    #[serde(skip_serializing)]
+    pub case_id: i64,
+    pub case_label: String,
+    pub doc: JsonValue,
+    pub valid_from: DateTime<Utc>,
+}
+
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn like(term: &str) -> String {
+    format!("%{}%", escape_like(&term.to_lowercase()))
+}
+
+/// `LIKE` patterns for `text` against `entities_current.search_text`: one
+/// per word, all of which must match, and for an observable its normalized
+/// value, which matches on its own, e.g. a CIDR stored as written.
+fn patterns(text: &str) -> (Vec<String>, Option<String>) {
+    let words = text.split_whitespace().map(like).collect();
+    let observable = observables::detect(text).map(|o| like(&o.value));
+    (words, observable)
+}
+
+/// Open entities matching `query` on cases `user_id` can open: their own,
+/// ones visible to the whole organization, and ones shared with them or
+/// their organization. Newest first.
+pub async fn entities(
+    pool: &PgPool,
+    user_id: i64,
+    query: &SearchQuery,
+) -> Result<Vec<SearchHit>, sqlx::Error> {
+    let tagged = query
+        .tag
+        .as_ref()
+        .map(|t| json!({ "data": { "tags": [t] } }));
+    let (words, observable) = patterns(&query.text);
+    sqlx::query_as!(
+        SearchHit,
+        r#"
+        SELECT e.entity_id AS "entity_id!", c.id AS case_id, c.label AS case_label, e.doc,
+               e.valid_from
+          FROM entities_current e
+          JOIN cases c ON c.uuid = e.graph_id
+         WHERE e.sys_to IS NULL
+           AND (e.search_text LIKE ALL($2) OR e.search_text LIKE $10)
+           AND ($3::text IS NULL
+                OR lower(COALESCE(e.doc->>'entity_type', e.doc->>'label')) = lower($3))
+           AND ($4::bigint IS NULL OR c.id = $4)
+           AND ($5::jsonb IS NULL OR e.doc @> $5)
+           AND ($6::timestamptz IS NULL OR e.valid_from >= $6)
+           AND ($7::timestamptz IS NULL OR e.valid_from < $7)
+           AND case_visible_to(c.id, $1)
+         ORDER BY e.valid_from DESC, e.entity_id
+         OFFSET $8 LIMIT $9
+        "#,
+        user_id,
+        &words,
+        query.entity_type,
+        query.case_id,
+        tagged,
+        query.since,
+        query.until,
+        query.skip,
+        query.limit,
+        observable
+    )
+    .fetch_all(pool)
+    .await
+}
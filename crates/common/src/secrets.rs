@@ -0,0 +1,459 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Serialize;
+use sqlx::{PgPool, types::Uuid};
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::config::AppConfig;
+
+// Variables `ob` and the interpreter it runs under read themselves, a secret
+// by one of these names could change what runs
+const RESERVED_NAMES: &[&str] = &["PATH", "HOME", "TMPDIR", "LANG", "SHELL", "IFS"];
+const RESERVED_PREFIXES: &[&str] = &["LD_", "PYTHON", "LC_", "OB_"];
+// shorter values would redact ordinary words out of plugin output
+const MIN_REDACTED_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum SecretsError {
+    /// No `secrets_master_key` is configured.
+    Unconfigured,
+    Sql(sqlx::Error),
+    /// Secrets a transform declared that neither the org nor the user set.
+    Missing(Vec<String>),
+    /// A stored value doesn't decrypt, usually because the master key changed.
+    Unreadable(String),
+}
+
+impl fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretsError::Unconfigured => write!(f, "no secrets master key is configured"),
+            SecretsError::Sql(e) => write!(f, "{e}"),
+            SecretsError::Missing(names) => write!(f, "missing secrets: {}", names.join(", ")),
+            SecretsError::Unreadable(name) => write!(f, "secret {name} could not be decrypted"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for SecretsError {
+    fn from(e: sqlx::Error) -> Self {
+        SecretsError::Sql(e)
+    }
+}
+
+/// Encrypts secret values with AES-256-GCM under the master key. Each value
+/// is bound to the org, user and name it was stored under, so a ciphertext
+/// copied to another row won't decrypt.
+#[derive(Clone)]
+pub struct Vault {
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Vault(..)")
+    }
+}
+
+impl Vault {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Vault {
+            cipher: Aes256Gcm::new(key.into()),
+        }
+    }
+
+    /// `None` when no master key is configured, or it isn't 32 base64 bytes.
+    pub fn from_config(cfg: &AppConfig) -> Option<Self> {
+        let key = cfg
+            .secrets_master_key
+            .as_deref()
+            .filter(|k| !k.is_empty())?;
+        let key: Option<[u8; 32]> = STANDARD
+            .decode(key.trim())
+            .ok()
+            .and_then(|k| k.try_into().ok());
+        if key.is_none() {
+            error!("SECRETS_MASTER_KEY must be 32 base64 encoded bytes, secrets are disabled");
+        }
+        key.map(|k| Self::new(&k))
+    }
+
+    fn aad(org_id: i64, user_id: Option<i64>, name: &str) -> String {
+        let user = user_id.map(|u| u.to_string()).unwrap_or_default();
+        format!("{org_id}:{user}:{name}")
+    }
+
+    /// Encrypts `value`, returning the nonce and ciphertext.
+    pub fn seal(
+        &self,
+        org_id: i64,
+        user_id: Option<i64>,
+        name: &str,
+        value: &str,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = Self::aad(org_id, user_id, name);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encryption of a bounded value can't fail");
+        (nonce.to_vec(), ciphertext)
+    }
+
+    pub fn open(
+        &self,
+        org_id: i64,
+        user_id: Option<i64>,
+        name: &str,
+        nonce: &[u8],
+        ciphertext: &[u8],
+    ) -> Option<String> {
+        if nonce.len() != 12 {
+            return None;
+        }
+        let aad = Self::aad(org_id, user_id, name);
+        let plain = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .ok()?;
+        String::from_utf8(plain).ok()
+    }
+}
+
+/// Secret names are environment variable names, minus the ones that could
+/// change how `ob` itself runs.
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_uppercase() || c == '_');
+    starts_well
+        && name.len() <= 128
+        && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED_NAMES.contains(&name)
+        && !RESERVED_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+/// Decrypted secrets for one job, exported into the environment `ob` runs
+/// in. Debug output only names them.
+#[derive(Clone, Default)]
+pub struct SecretEnv {
+    vars: Vec<(String, String)>,
+}
+
+impl fmt::Debug for SecretEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.vars.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl SecretEnv {
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// `text` with every secret value replaced, for anything that gets
+    /// logged or stored.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (_, value) in &self.vars {
+            if value.len() >= MIN_REDACTED_LEN && text.contains(value.as_str()) {
+                text = Cow::Owned(text.replace(value.as_str(), "[redacted]"));
+            }
+        }
+        text
+    }
+}
+
+impl FromIterator<(String, String)> for SecretEnv {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        SecretEnv {
+            vars: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// A stored secret, without its value.
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretInfo {
+    pub secret_id: Uuid,
+    pub name: String,
+    /// Set on secrets scoped to one member.
+    pub user_id: Option<i64>,
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The organization's secrets and `user_id`'s own.
+pub async fn list(
+    pool: &PgPool,
+    org_id: i64,
+    user_id: i64,
+) -> Result<Vec<SecretInfo>, sqlx::Error> {
+    sqlx::query_as!(
+        SecretInfo,
+        r#"
+        SELECT secret_id, name, user_id, created_by, created_at, updated_at
+          FROM secrets
+         WHERE org_id = $1 AND (user_id IS NULL OR user_id = $2)
+         ORDER BY name, user_id NULLS FIRST
+        "#,
+        org_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores a secret, replacing the value of one by the same name and scope.
+pub async fn put(
+    pool: &PgPool,
+    vault: &Vault,
+    org_id: i64,
+    user_id: Option<i64>,
+    name: &str,
+    value: &str,
+    actor_id: i64,
+) -> Result<SecretInfo, sqlx::Error> {
+    let (nonce, ciphertext) = vault.seal(org_id, user_id, name, value);
+    match user_id {
+        Some(user_id) => {
+            sqlx::query_as!(
+                SecretInfo,
+                r#"
+                INSERT INTO secrets (org_id, user_id, name, nonce, ciphertext, created_by)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (org_id, user_id, name) WHERE user_id IS NOT NULL DO UPDATE
+                   SET nonce = EXCLUDED.nonce, ciphertext = EXCLUDED.ciphertext, updated_at = now()
+                RETURNING secret_id, name, user_id, created_by, created_at, updated_at
+                "#,
+                org_id,
+                user_id,
+                name,
+                nonce,
+                ciphertext,
+                actor_id
+            )
+            .fetch_one(pool)
+            .await
+        }
+        None => {
+            sqlx::query_as!(
+                SecretInfo,
+                r#"
+                INSERT INTO secrets (org_id, name, nonce, ciphertext, created_by)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (org_id, name) WHERE user_id IS NULL DO UPDATE
+                   SET nonce = EXCLUDED.nonce, ciphertext = EXCLUDED.ciphertext, updated_at = now()
+                RETURNING secret_id, name, user_id, created_by, created_at, updated_at
+                "#,
+                org_id,
+                name,
+                nonce,
+                ciphertext,
+                actor_id
+            )
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+/// Deletes one of `user_id`'s secrets, or an organization wide one when
+/// `org_wide` is allowed. `false` when there was no such secret.
+pub async fn delete(
+    pool: &PgPool,
+    org_id: i64,
+    secret_id: Uuid,
+    user_id: i64,
+    org_wide: bool,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM secrets
+         WHERE org_id = $1 AND secret_id = $2
+           AND (user_id = $3 OR (user_id IS NULL AND $4))
+        "#,
+        org_id,
+        secret_id,
+        user_id,
+        org_wide
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Decrypts the secrets `names` for a job `user_id` runs in `org_id`, the
+/// user's own taking precedence. Every name must be set.
+pub async fn resolve(
+    pool: &PgPool,
+    vault: Option<&Vault>,
+    org_id: i64,
+    user_id: Option<i64>,
+    names: &[String],
+) -> Result<SecretEnv, SecretsError> {
+    if names.is_empty() {
+        return Ok(SecretEnv::default());
+    }
+    let vault = vault.ok_or(SecretsError::Unconfigured)?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (name) name, user_id, nonce, ciphertext
+          FROM secrets
+         WHERE org_id = $1 AND name = ANY($2) AND (user_id IS NULL OR user_id = $3)
+         ORDER BY name, user_id NULLS LAST
+        "#,
+        org_id,
+        names,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let missing: Vec<String> = names
+        .iter()
+        .filter(|n| !rows.iter().any(|r| &r.name == *n))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(SecretsError::Missing(missing));
+    }
+    let mut vars = Vec::with_capacity(rows.len());
+    for row in rows {
+        let value = vault
+            .open(org_id, row.user_id, &row.name, &row.nonce, &row.ciphertext)
+            .ok_or_else(|| SecretsError::Unreadable(row.name.clone()))?;
+        vars.push((row.name, value));
+    }
+    Ok(SecretEnv { vars })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault(byte: u8) -> Vault {
+        Vault::new(&[byte; 32])
+    }
+
+    #[test]
+    fn round_trip() {
+        let v = vault(7);
+        let (nonce, ciphertext) = v.seal(1, Some(2), "SHODAN_API_KEY", "k-123456");
+        assert_ne!(ciphertext, b"k-123456");
+        assert_eq!(
+            v.open(1, Some(2), "SHODAN_API_KEY", &nonce, &ciphertext)
+                .as_deref(),
+            Some("k-123456")
+        );
+        let (nonce, ciphertext) = v.seal(1, None, "SHODAN_API_KEY", "org-wide");
+        assert_eq!(
+            v.open(1, None, "SHODAN_API_KEY", &nonce, &ciphertext)
+                .as_deref(),
+            Some("org-wide")
+        );
+    }
+
+    #[test]
+    fn ciphertext_is_bound_to_its_row() {
+        let v = vault(7);
+        let (nonce, ciphertext) = v.seal(1, Some(2), "SHODAN_API_KEY", "k-123456");
+        // another org, another user, the org's own scope or another name
+        assert_eq!(
+            v.open(3, Some(2), "SHODAN_API_KEY", &nonce, &ciphertext),
+            None
+        );
+        assert_eq!(
+            v.open(1, Some(4), "SHODAN_API_KEY", &nonce, &ciphertext),
+            None
+        );
+        assert_eq!(v.open(1, None, "SHODAN_API_KEY", &nonce, &ciphertext), None);
+        assert_eq!(v.open(1, Some(2), "VT_API_KEY", &nonce, &ciphertext), None);
+        // org 12 user 3 doesn't read as org 1 user 23
+        let (nonce, ciphertext) = v.seal(1, Some(23), "KEY", "value");
+        assert_eq!(v.open(12, Some(3), "KEY", &nonce, &ciphertext), None);
+    }
+
+    #[test]
+    fn wrong_master_key_or_nonce_fails() {
+        let (nonce, ciphertext) = vault(7).seal(1, None, "KEY", "value");
+        assert_eq!(vault(8).open(1, None, "KEY", &nonce, &ciphertext), None);
+        assert_eq!(
+            vault(7).open(1, None, "KEY", &nonce[..11], &ciphertext),
+            None
+        );
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(vault(7).open(1, None, "KEY", &nonce, &tampered), None);
+    }
+
+    #[test]
+    fn nonces_are_fresh() {
+        let v = vault(7);
+        let (a, _) = v.seal(1, None, "KEY", "value");
+        let (b, _) = v.seal(1, None, "KEY", "value");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn redact_skips_short_values() {
+        let env: SecretEnv = [
+            ("SHORT".to_string(), "abc".to_string()),
+            ("EXACT".to_string(), "abcd".to_string()),
+            ("TOKEN".to_string(), "tok-99999".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            env.redact("abc and abcd sent tok-99999 twice: tok-99999"),
+            "abc and [redacted] sent [redacted] twice: [redacted]"
+        );
+        assert!(matches!(env.redact("nothing secret"), Cow::Borrowed(_)));
+        assert_eq!(format!("{env:?}"), r#"["SHORT", "EXACT", "TOKEN"]"#);
+    }
+
+    #[test]
+    fn names_are_env_vars_that_cant_steer_ob() {
+        for name in ["SHODAN_API_KEY", "_TOKEN", "K2"] {
+            assert!(valid_name(name), "{name}");
+        }
+        for name in [
+            "",
+            "lower",
+            "2FA",
+            "A-B",
+            "PATH",
+            "LD_PRELOAD",
+            "PYTHONPATH",
+            "OB_HOME",
+        ] {
+            assert!(!valid_name(name), "{name}");
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, types::Uuid};
+use std::collections::HashMap;
+
+use crate::config::AppConfig;
+use crate::plugin_registry;
+
+/// Where a transform job stores its result, stamped into the job payload as
+/// `cache` when the job is enqueued.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStamp {
+    pub key: String,
+    pub plugin: String,
+    pub transform: String,
+    pub plugin_version: String,
+    pub input: JsonValue,
+    pub ttl_secs: i64,
+    /// The user a result is kept for, set when the transform reads secrets
+    /// since a user's own secret can stand in for the organization's.
+    #[serde(default)]
+    pub actor_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResult {
+    pub result: JsonValue,
+    pub job_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CacheStamp {
+    /// `None` when the entity names no plugin or transform. With an
+    /// `actor_id` the entry is only that user's.
+    pub fn new(
+        org_id: i64,
+        actor_id: Option<i64>,
+        entity: &JsonValue,
+        plugin_version: &str,
+        ttl_secs: i64,
+    ) -> Option<Self> {
+        let plugin = plugin_label(entity)?.to_string();
+        let transform = entity["transform"].as_str()?.to_string();
+        let input = normalize_input(entity);
+        // Objects serialize with sorted keys, so equal inputs hash equally
+        let mut hasher = Sha256::new();
+        let org = org_id.to_string();
+        let input_text = input.to_string();
+        let actor = actor_id.map(|a| a.to_string());
+        let parts = [&org, &plugin, &transform, plugin_version, &input_text];
+        for part in parts.into_iter().chain(actor.as_deref()) {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Some(CacheStamp {
+            key,
+            plugin,
+            transform,
+            plugin_version: plugin_version.to_string(),
+            input,
+            ttl_secs,
+            actor_id,
+        })
+    }
+
+    pub fn from_payload(payload: &JsonValue) -> Option<Self> {
+        serde_json::from_value(payload.get("cache")?.clone()).ok()
+    }
+
+    /// The stamp of a job, re-derived from the job's org, actor and entity
+    /// so a payload can't address another org's or user's entries or file a
+    /// result under an input it wasn't run on. `None` when the stamped key
+    /// doesn't match.
+    pub fn verified(org_id: i64, actor_id: Option<i64>, payload: &JsonValue) -> Option<Self> {
+        let stamp = Self::from_payload(payload)?;
+        if stamp.actor_id.is_some() && stamp.actor_id != actor_id {
+            return None;
+        }
+        let expected = CacheStamp::new(
+            org_id,
+            stamp.actor_id,
+            &payload["entity"],
+            &stamp.plugin_version,
+            stamp.ttl_secs,
+        )?;
+        (expected.key == stamp.key).then_some(expected)
+    }
+}
+
+/// Whether the transform `entity` asks `org_id` for reads secrets, its
+/// results are then cached per user.
+pub async fn reads_secrets(
+    pool: &PgPool,
+    org_id: i64,
+    entity: &JsonValue,
+) -> Result<bool, sqlx::Error> {
+    let (Some(label), Some(transform)) = (plugin_label(entity), entity["transform"].as_str())
+    else {
+        return Ok(false);
+    };
+    let names = plugin_registry::transform_secrets(pool, Some(org_id), label, transform).await?;
+    Ok(!names.is_empty())
+}
+
+pub fn plugin_label(entity: &JsonValue) -> Option<&str> {
+    entity["data"]["label"]
+        .as_str()
+        .or_else(|| entity["label"].as_str())
+}
+
+/// The part of an entity a transform actually reads: its data fields with
+/// surrounding whitespace trimmed and empty values dropped. Ids, positions
+/// and other canvas state never affect the result.
+pub fn normalize_input(entity: &JsonValue) -> JsonValue {
+    let mut input = Map::new();
+    if let Some(data) = entity["data"].as_object() {
+        for (k, v) in data {
+            let v = match v {
+                JsonValue::String(s) => JsonValue::String(s.trim().to_string()),
+                v => v.clone(),
+            };
+            let empty = match &v {
+                JsonValue::Null => true,
+                JsonValue::String(s) => s.is_empty(),
+                _ => false,
+            };
+            if !empty {
+                input.insert(k.clone(), v);
+            }
+        }
+    }
+    JsonValue::Object(input)
+}
+
+/// Cache lifetime per transform: `TRANSFORM_CACHE_TTL_SECS` by default,
+/// overridden by `TRANSFORM_CACHE_TTLS`, a JSON object keyed by transform or
+/// `<plugin>/<transform>`. A TTL of 0 disables caching.
+#[derive(Debug, Clone, Default)]
+pub struct CacheTtls {
+    default_secs: i64,
+    overrides: HashMap<String, i64>,
+}
+
+impl CacheTtls {
+    pub fn from_config(cfg: &AppConfig) -> Self {
+        let overrides = match cfg.transform_cache_ttls.as_deref() {
+            Some(raw) if !raw.trim().is_empty() => serde_json::from_str(raw).unwrap_or_else(|e| {
+                error!("ignoring invalid TRANSFORM_CACHE_TTLS: {e}");
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+        CacheTtls {
+            default_secs: cfg.transform_cache_ttl_secs.unwrap_or(3600),
+            overrides,
+        }
+    }
+
+    pub fn ttl_secs(&self, plugin: &str, transform: &str) -> i64 {
+        self.overrides
+            .get(&format!("{plugin}/{transform}"))
+            .or_else(|| self.overrides.get(transform))
+            .copied()
+            .unwrap_or(self.default_secs)
+    }
+}
+
+/// A live entry for `key`, counting the hit.
+pub async fn lookup(
+    pool: &PgPool,
+    org_id: i64,
+    key: &str,
+) -> Result<Option<CachedResult>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        UPDATE transform_cache
+           SET hits = hits + 1
+         WHERE cache_key = $1
+           AND org_id = $2
+           AND expires_at > now()
+        RETURNING result, job_id, created_at
+        "#,
+        key,
+        org_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(rec.map(|r| CachedResult {
+        result: r.result,
+        job_id: r.job_id,
+        created_at: r.created_at,
+    }))
+}
+
+pub async fn store(
+    pool: &PgPool,
+    org_id: i64,
+    job_id: Uuid,
+    stamp: &CacheStamp,
+    result: &JsonValue,
+) -> Result<(), sqlx::Error> {
+    if stamp.ttl_secs <= 0 {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO transform_cache (cache_key, org_id, plugin, transform, plugin_version, input, result, job_id, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now() + make_interval(secs => $9::bigint))
+        ON CONFLICT (org_id, cache_key) DO UPDATE SET
+          result = EXCLUDED.result,
+          job_id = EXCLUDED.job_id,
+          hits = 0,
+          created_at = now(),
+          expires_at = EXCLUDED.expires_at
+        "#,
+        stamp.key,
+        org_id,
+        stamp.plugin,
+        stamp.transform,
+        stamp.plugin_version,
+        stamp.input,
+        result,
+        job_id,
+        stamp.ttl_secs
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// An enqueued or running job that will fill `key` for entity `entity_id`
+/// of `graph_id`, where the worker saves its results.
+pub async fn active_job(
+    pool: &PgPool,
+    org_id: i64,
+    key: &str,
+    graph_id: Uuid,
+    entity_id: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT job_id FROM jobs
+         WHERE payload->'cache'->>'key' = $1
+           AND org_id = $2
+           AND graph_id = $3
+           AND payload->'entity'->>'id' = $4
+           AND parent_id IS NULL
+           AND status IN ('enqueued'::job_status, 'leased'::job_status, 'running'::job_status)
+         ORDER BY created_at ASC
+         LIMIT 1
+        "#,
+        key,
+        org_id,
+        graph_id,
+        entity_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn purge_expired(pool: &PgPool, org_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM transform_cache WHERE org_id = $1 AND expires_at <= now()",
+        org_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn payload(stamp: &CacheStamp) -> JsonValue {
+        json!({ "entity": entity(), "cache": stamp })
+    }
+
+    fn entity() -> JsonValue {
+        json!({ "transform": "to_ip", "data": { "label": "domain", "domain": "example.com" } })
+    }
+
+    #[test]
+    fn user_scoped_entries_are_keyed_per_user() {
+        let shared = CacheStamp::new(1, None, &entity(), "1.0", 60).unwrap();
+        let alice = CacheStamp::new(1, Some(10), &entity(), "1.0", 60).unwrap();
+        let bob = CacheStamp::new(1, Some(11), &entity(), "1.0", 60).unwrap();
+        assert_ne!(alice.key, bob.key);
+        assert_ne!(alice.key, shared.key);
+        assert_eq!(
+            alice.key,
+            CacheStamp::new(1, Some(10), &entity(), "1.0", 60)
+                .unwrap()
+                .key
+        );
+    }
+
+    #[test]
+    fn verified_checks_the_actor() {
+        let alice = CacheStamp::new(1, Some(10), &entity(), "1.0", 60).unwrap();
+        assert!(CacheStamp::verified(1, Some(10), &payload(&alice)).is_some());
+        assert!(CacheStamp::verified(1, Some(11), &payload(&alice)).is_none());
+        assert!(CacheStamp::verified(1, None, &payload(&alice)).is_none());
+        assert!(CacheStamp::verified(2, Some(10), &payload(&alice)).is_none());
+
+        let shared = CacheStamp::new(1, None, &entity(), "1.0", 60).unwrap();
+        assert!(CacheStamp::verified(1, Some(11), &payload(&shared)).is_some());
+        let mut forged = alice.clone();
+        forged.actor_id = None;
+        assert!(CacheStamp::verified(1, None, &payload(&forged)).is_none());
+    }
+}
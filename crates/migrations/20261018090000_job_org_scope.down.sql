@@ -0,0 +1,7 @@
+ALTER TABLE organizations DROP COLUMN IF EXISTS max_queued_jobs;
+
+DROP INDEX IF EXISTS jobs_org_active_idx;
+ALTER TABLE jobs
+  DROP COLUMN IF EXISTS graph_id,
+  DROP COLUMN IF EXISTS actor_id,
+  DROP COLUMN IF EXISTS org_id;
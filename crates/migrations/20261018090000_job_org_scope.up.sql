@@ -0,0 +1,13 @@
+-- Scope jobs to the organization, user and case that enqueued them
+ALTER TABLE jobs
+  ADD COLUMN IF NOT EXISTS org_id   BIGINT REFERENCES organizations(id) ON DELETE CASCADE,
+  ADD COLUMN IF NOT EXISTS actor_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  ADD COLUMN IF NOT EXISTS graph_id UUID REFERENCES cases(uuid) ON DELETE CASCADE;
+
+-- Quota checks count the org's jobs that are still waiting on or held by a worker
+CREATE INDEX IF NOT EXISTS jobs_org_active_idx
+  ON jobs (org_id) WHERE status IN ('enqueued', 'leased', 'running');
+
+-- Per-org cap on jobs that are enqueued, leased or running at once
+ALTER TABLE organizations
+  ADD COLUMN IF NOT EXISTS max_queued_jobs INTEGER NOT NULL DEFAULT 25;
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS artifacts_job_idx;
+
+ALTER TABLE artifacts ALTER COLUMN artifact_id DROP DEFAULT;
+
+ALTER TABLE artifacts
+  DROP COLUMN IF EXISTS size,
+  DROP COLUMN IF EXISTS filename;
@@ -0,0 +1,10 @@
+-- Artifacts hold job results and plugin files, inline in `bytes` when small
+-- or offloaded to `uri` past the configured size
+ALTER TABLE artifacts
+  ADD COLUMN IF NOT EXISTS filename TEXT,
+  ADD COLUMN IF NOT EXISTS size     BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE artifacts ALTER COLUMN artifact_id SET DEFAULT uuid_generate_v4();
+
+CREATE INDEX IF NOT EXISTS artifacts_job_idx
+  ON artifacts (job_id, created_at);
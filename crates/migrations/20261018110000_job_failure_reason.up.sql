@@ -0,0 +1,2 @@
+-- Why the last attempt failed, e.g. timeout, oom or output_too_large
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS failure_reason TEXT;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS schedules_graph_idx;
+DROP INDEX IF EXISTS schedules_due_idx;
+
+DROP TABLE IF EXISTS schedules;
@@ -0,0 +1,25 @@
+-- Recurring transforms: re-run `transform` on an entity whenever `cron`
+-- comes due, the worker diffs the results against the entity's children
+CREATE TABLE IF NOT EXISTS schedules (
+  schedule_id  UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  org_id       BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  graph_id     UUID NOT NULL REFERENCES cases(uuid) ON DELETE CASCADE,
+  entity_id    UUID NOT NULL,
+  transform    TEXT NOT NULL,
+  entity       JSONB NOT NULL,                -- transform payload entity as scheduled
+  cron         TEXT NOT NULL,
+  enabled      BOOLEAN NOT NULL DEFAULT true,
+  next_run_at  TIMESTAMPTZ NOT NULL,
+  last_run_at  TIMESTAMPTZ,
+  last_job_id  UUID REFERENCES jobs(job_id) ON DELETE SET NULL,
+  created_by   BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The scheduler only ever scans enabled schedules that are due
+CREATE INDEX IF NOT EXISTS schedules_due_idx
+  ON schedules (next_run_at) WHERE enabled;
+
+CREATE INDEX IF NOT EXISTS schedules_graph_idx
+  ON schedules (graph_id, entity_id);
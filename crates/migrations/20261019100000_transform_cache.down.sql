@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS jobs_cache_key_active_idx;
+DROP INDEX IF EXISTS transform_cache_expires_idx;
+
+DROP TABLE IF EXISTS transform_cache;
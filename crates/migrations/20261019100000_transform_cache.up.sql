@@ -0,0 +1,24 @@
+-- Transform results keyed by a hash of (org, plugin, transform, plugin
+-- version, normalized input), served instead of re-running `ob` until expiry
+CREATE TABLE IF NOT EXISTS transform_cache (
+  cache_key       TEXT NOT NULL,
+  org_id          BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  plugin          TEXT NOT NULL,
+  transform       TEXT NOT NULL,
+  plugin_version  TEXT NOT NULL,
+  input           JSONB NOT NULL,
+  result          JSONB NOT NULL,
+  job_id          UUID REFERENCES jobs(job_id) ON DELETE SET NULL,
+  hits            INTEGER NOT NULL DEFAULT 0,
+  created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+  expires_at      TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (org_id, cache_key)
+);
+
+CREATE INDEX IF NOT EXISTS transform_cache_expires_idx
+  ON transform_cache (expires_at);
+
+-- Identical transforms already in flight are joined rather than re-enqueued
+CREATE INDEX IF NOT EXISTS jobs_cache_key_active_idx
+  ON jobs ((payload->'cache'->>'key'))
+  WHERE status IN ('enqueued', 'leased', 'running');
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS jobs_parent_idx;
+ALTER TABLE jobs DROP COLUMN IF EXISTS parent_id;
+
+DROP INDEX IF EXISTS playbooks_org_idx;
+DROP TABLE IF EXISTS playbooks;
@@ -0,0 +1,21 @@
+-- Saved transform chains: `steps` is a DAG of transforms where a step's
+-- outputs feed the steps listed in its `then`, see common::playbooks
+CREATE TABLE IF NOT EXISTS playbooks (
+  playbook_id  UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  org_id       BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  name         TEXT NOT NULL,
+  description  TEXT,
+  steps        JSONB NOT NULL,
+  max_depth    INT NOT NULL DEFAULT 3,
+  max_jobs     INT NOT NULL DEFAULT 25,
+  created_by   BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS playbooks_org_idx ON playbooks (org_id);
+
+-- Transforms a playbook run fans out into point back at the run's job
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS parent_id UUID REFERENCES jobs(job_id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS jobs_parent_idx ON jobs (parent_id) WHERE parent_id IS NOT NULL;
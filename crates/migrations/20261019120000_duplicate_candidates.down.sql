@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS entities_current_label_open_idx;
+
+DROP INDEX IF EXISTS duplicate_candidates_pending_idx;
+DROP TABLE IF EXISTS duplicate_candidates;
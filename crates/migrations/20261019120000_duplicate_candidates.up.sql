@@ -0,0 +1,23 @@
+-- Transform results that look like an entity already on the case without
+-- sharing its natural key exactly, kept for an investigator to merge or dismiss
+CREATE TABLE IF NOT EXISTS duplicate_candidates (
+  candidate_id  UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  graph_id      UUID NOT NULL REFERENCES cases(uuid) ON DELETE CASCADE,
+  entity_id     UUID NOT NULL,                 -- the entity the transform created
+  duplicate_of  UUID NOT NULL,                 -- the entity it resembles
+  label         TEXT NOT NULL,
+  natural_key   TEXT NOT NULL,
+  existing_key  TEXT NOT NULL,
+  job_id        UUID REFERENCES jobs(job_id) ON DELETE SET NULL,
+  status        TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'merged', 'dismissed')),
+  created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+  resolved_at   TIMESTAMPTZ,
+  resolved_by   BIGINT REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS duplicate_candidates_pending_idx
+  ON duplicate_candidates (graph_id) WHERE status = 'pending';
+
+-- Identity resolution looks entities up by label within a case
+CREATE INDEX IF NOT EXISTS entities_current_label_open_idx
+  ON entities_current (graph_id, lower(doc->>'label')) WHERE sys_to IS NULL;
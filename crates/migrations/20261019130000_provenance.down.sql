@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS edges_current_provenance_gin_idx;
+DROP INDEX IF EXISTS entities_current_source_url_idx;
+DROP INDEX IF EXISTS entities_current_provenance_gin_idx;
+
+ALTER TABLE edges_current DROP COLUMN IF EXISTS provenance;
+ALTER TABLE entities_current DROP COLUMN IF EXISTS provenance;
@@ -0,0 +1,13 @@
+-- Where transform results came from, lifted out of the documents so cases
+-- can be searched by source
+ALTER TABLE entities_current
+  ADD COLUMN IF NOT EXISTS provenance JSONB GENERATED ALWAYS AS (doc->'provenance') STORED;
+ALTER TABLE edges_current
+  ADD COLUMN IF NOT EXISTS provenance JSONB GENERATED ALWAYS AS (props->'provenance') STORED;
+
+CREATE INDEX IF NOT EXISTS entities_current_provenance_gin_idx
+  ON entities_current USING gin (provenance jsonb_path_ops);
+CREATE INDEX IF NOT EXISTS entities_current_source_url_idx
+  ON entities_current (graph_id, (provenance->>'source_url')) WHERE sys_to IS NULL;
+CREATE INDEX IF NOT EXISTS edges_current_provenance_gin_idx
+  ON edges_current USING gin (provenance jsonb_path_ops);
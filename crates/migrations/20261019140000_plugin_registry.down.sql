@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS plugin_syncs_started_idx;
+DROP TABLE IF EXISTS plugin_syncs;
+DROP TABLE IF EXISTS plugin_entity_revisions;
+DROP TABLE IF EXISTS plugin_entities;
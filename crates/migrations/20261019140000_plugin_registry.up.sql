@@ -0,0 +1,45 @@
+-- Entity plugins as last synced from the plugin CLI, so reads don't shell out
+-- to `ob` per request. `revision` goes up whenever a sync sees the plugin's
+-- metadata, blueprint or transforms change, earlier revisions are kept in
+-- plugin_entity_revisions.
+CREATE TABLE IF NOT EXISTS plugin_entities (
+  label         TEXT PRIMARY KEY,              -- snake_case, as blueprints are keyed
+  display_label TEXT NOT NULL,
+  description   TEXT,
+  author        TEXT,
+  version       TEXT,                          -- as reported by the plugin
+  revision      INT NOT NULL DEFAULT 1,
+  meta          JSONB NOT NULL DEFAULT '{}'::jsonb,
+  blueprint     JSONB,
+  transforms    JSONB NOT NULL DEFAULT '[]'::jsonb,
+  synced_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+  removed_at    TIMESTAMPTZ                    -- no longer installed
+);
+
+CREATE TABLE IF NOT EXISTS plugin_entity_revisions (
+  label       TEXT NOT NULL REFERENCES plugin_entities(label) ON DELETE CASCADE,
+  revision    INT NOT NULL,
+  version     TEXT,
+  meta        JSONB NOT NULL,
+  blueprint   JSONB,
+  transforms  JSONB NOT NULL,
+  synced_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (label, revision)
+);
+
+-- One row per sync run, the latest completed one backs the registry reads
+CREATE TABLE IF NOT EXISTS plugin_syncs (
+  sync_id      UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  trigger      TEXT NOT NULL CHECK (trigger IN ('manual', 'scheduled')),
+  status       TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed', 'failed')),
+  job_id       UUID REFERENCES jobs(job_id) ON DELETE SET NULL,
+  favorites    JSONB NOT NULL DEFAULT '[]'::jsonb,
+  added        INT NOT NULL DEFAULT 0,
+  updated      INT NOT NULL DEFAULT 0,
+  removed      INT NOT NULL DEFAULT 0,
+  error        TEXT,
+  started_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+  finished_at  TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS plugin_syncs_started_idx ON plugin_syncs (started_at DESC);
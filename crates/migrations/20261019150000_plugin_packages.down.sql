@@ -0,0 +1,9 @@
+DELETE FROM plugin_syncs WHERE trigger = 'install';
+ALTER TABLE plugin_syncs DROP CONSTRAINT IF EXISTS plugin_syncs_trigger_check;
+ALTER TABLE plugin_syncs
+  ADD CONSTRAINT plugin_syncs_trigger_check CHECK (trigger IN ('manual', 'scheduled'));
+
+DROP TABLE IF EXISTS org_plugin_settings;
+ALTER TABLE plugin_entities DROP COLUMN IF EXISTS package_id;
+DROP INDEX IF EXISTS plugin_packages_live_name_idx;
+DROP TABLE IF EXISTS plugin_packages;
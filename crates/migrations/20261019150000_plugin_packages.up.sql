@@ -0,0 +1,44 @@
+-- Plugin packages installed through the API, from a git repository or an
+-- uploaded archive, into the directory `ob` loads plugins from. Packages
+-- belong to the installing organization, entities the host provides
+-- otherwise have no package and are shared by all organizations.
+CREATE TABLE IF NOT EXISTS plugin_packages (
+  package_id    UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  org_id        BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  name          TEXT NOT NULL,
+  source_kind   TEXT NOT NULL CHECK (source_kind IN ('git', 'archive')),
+  source        TEXT NOT NULL,                 -- git URL or archive file name
+  git_ref       TEXT,
+  archive       BYTEA,                         -- uploaded archive, dropped once installed
+  revision      TEXT,                          -- installed commit or archive sha256
+  path          TEXT,
+  status        TEXT NOT NULL DEFAULT 'pending'
+                CHECK (status IN ('pending', 'installed', 'failed', 'removing', 'removed')),
+  error         TEXT,
+  job_id        UUID REFERENCES jobs(job_id) ON DELETE SET NULL,
+  installed_by  BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+  installed_at  TIMESTAMPTZ,
+  removed_at    TIMESTAMPTZ
+);
+
+-- Names are directories under the plugin root, one live package per name
+CREATE UNIQUE INDEX IF NOT EXISTS plugin_packages_live_name_idx
+  ON plugin_packages (name) WHERE status <> 'removed';
+
+ALTER TABLE plugin_entities
+  ADD COLUMN IF NOT EXISTS package_id UUID REFERENCES plugin_packages(package_id) ON DELETE SET NULL;
+
+-- Per organization switches, plugins without a row are enabled
+CREATE TABLE IF NOT EXISTS org_plugin_settings (
+  org_id      BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  label       TEXT NOT NULL REFERENCES plugin_entities(label) ON DELETE CASCADE,
+  enabled     BOOLEAN NOT NULL,
+  updated_by  BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  updated_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (org_id, label)
+);
+
+ALTER TABLE plugin_syncs DROP CONSTRAINT IF EXISTS plugin_syncs_trigger_check;
+ALTER TABLE plugin_syncs
+  ADD CONSTRAINT plugin_syncs_trigger_check CHECK (trigger IN ('manual', 'scheduled', 'install'));
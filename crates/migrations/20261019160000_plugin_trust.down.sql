@@ -0,0 +1,5 @@
+ALTER TABLE plugin_packages
+  DROP COLUMN IF EXISTS signer_key,
+  DROP COLUMN IF EXISTS signature;
+
+DROP TABLE IF EXISTS plugin_publishers;
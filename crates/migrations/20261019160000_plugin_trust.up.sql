@@ -0,0 +1,18 @@
+-- Publishers an organization trusts to sign plugin packages, by ed25519
+-- public key (base64)
+CREATE TABLE IF NOT EXISTS plugin_publishers (
+  publisher_id  UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  org_id        BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  name          TEXT NOT NULL,
+  public_key    TEXT NOT NULL,
+  created_by    BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+  UNIQUE (org_id, public_key)
+);
+
+-- A package's signature covers its revision, `signer_key` is the key it
+-- claims to be signed with, as submitted. The install checks the fetched
+-- revision against both and fails the package if they don't verify
+ALTER TABLE plugin_packages
+  ADD COLUMN IF NOT EXISTS signature TEXT,
+  ADD COLUMN IF NOT EXISTS signer_key TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS secrets_user_name_idx;
+DROP INDEX IF EXISTS secrets_org_name_idx;
+DROP TABLE IF EXISTS secrets;
@@ -0,0 +1,19 @@
+-- Secrets transforms need, such as third party API keys. Values are
+-- encrypted with the configured master key, `user_id` scopes a secret to
+-- one member and overrides an organization wide one of the same name.
+CREATE TABLE IF NOT EXISTS secrets (
+  secret_id   UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  org_id      BIGINT NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  user_id     BIGINT REFERENCES users(id) ON DELETE CASCADE,
+  name        TEXT NOT NULL,
+  nonce       BYTEA NOT NULL,
+  ciphertext  BYTEA NOT NULL,
+  created_by  BIGINT REFERENCES users(id) ON DELETE SET NULL,
+  created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS secrets_org_name_idx
+  ON secrets (org_id, name) WHERE user_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS secrets_user_name_idx
+  ON secrets (org_id, user_id, name) WHERE user_id IS NOT NULL;
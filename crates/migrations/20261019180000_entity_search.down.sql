@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS entities_current_search_trgm_idx;
+
+ALTER TABLE entities_current DROP COLUMN IF EXISTS search_text;
+DROP FUNCTION IF EXISTS entity_search_text(JSONB);
+DROP EXTENSION IF EXISTS pg_trgm;
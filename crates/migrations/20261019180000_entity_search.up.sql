@@ -0,0 +1,25 @@
+-- Entity search matches substrings of everything an entity says about
+-- itself, which trigrams index where words would split phone numbers,
+-- emails and addresses apart
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Lowercased label and data values, and a `+digits` form of anything that
+-- looks like a phone number so differently formatted numbers match
+CREATE OR REPLACE FUNCTION entity_search_text(doc JSONB) RETURNS TEXT
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+  SELECT lower(concat_ws(' ',
+    doc->>'label',
+    (SELECT string_agg(
+              CASE WHEN v.value ~ '^\+?[0-9][0-9 ().-]{6,}$'
+                   THEN v.value || ' +' || regexp_replace(v.value, '[^0-9]', '', 'g')
+                   ELSE v.value
+              END, ' ')
+       FROM jsonb_each_text(CASE jsonb_typeof(doc->'data') WHEN 'object' THEN doc->'data' END) v)
+  ))
+$$;
+
+ALTER TABLE entities_current
+  ADD COLUMN IF NOT EXISTS search_text TEXT GENERATED ALWAYS AS (entity_search_text(doc)) STORED;
+
+CREATE INDEX IF NOT EXISTS entities_current_search_trgm_idx
+  ON entities_current USING gin (search_text gin_trgm_ops) WHERE sys_to IS NULL;
@@ -0,0 +1,11 @@
+DROP INDEX IF EXISTS notifications_unread_idx;
+DROP INDEX IF EXISTS notifications_user_idx;
+DROP TABLE IF EXISTS notifications;
+
+DROP INDEX IF EXISTS correlations_other_graph_idx;
+DROP TABLE IF EXISTS correlations;
+
+DROP INDEX IF EXISTS entity_keys_value_idx;
+DROP TABLE IF EXISTS entity_keys;
+
+DROP FUNCTION IF EXISTS case_visible_to(BIGINT, BIGINT);
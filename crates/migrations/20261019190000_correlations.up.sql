@@ -0,0 +1,58 @@
+-- Whether `user_id` can open case `case_id`: it's theirs, visible to their
+-- whole organization, or shared with them or their organization
+CREATE OR REPLACE FUNCTION case_visible_to(case_id BIGINT, user_id BIGINT) RETURNS BOOLEAN
+LANGUAGE sql STABLE AS $$
+  SELECT EXISTS (
+    SELECT 1
+      FROM cases c
+      JOIN users u ON u.id = user_id
+     WHERE c.id = case_id
+       AND (c.owner_id = u.id
+            OR (c.org_id = u.org_id AND c.visibility IN ('organization', 'public'))
+            OR EXISTS (
+                SELECT 1 FROM resource_shares s
+                 WHERE s.resource_type = 'case' AND s.resource_id = c.id AND s.org_id = u.org_id
+                   AND (s.shared_with_user_id IS NULL OR s.shared_with_user_id = u.id)
+                   AND (s.expires_at IS NULL OR s.expires_at > now())
+            ))
+  )
+$$;
+
+-- Normalized observables of open entities that cases are correlated by
+CREATE TABLE IF NOT EXISTS entity_keys (
+  graph_id    UUID NOT NULL REFERENCES cases(uuid) ON DELETE CASCADE,
+  entity_id   UUID NOT NULL,
+  kind        TEXT NOT NULL,
+  value       TEXT NOT NULL,
+  PRIMARY KEY (graph_id, entity_id, kind, value)
+);
+CREATE INDEX IF NOT EXISTS entity_keys_value_idx ON entity_keys (kind, value);
+
+-- The same observable on two cases of an organization, one row per
+-- direction so each case lists its own
+CREATE TABLE IF NOT EXISTS correlations (
+  correlation_id   UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  graph_id         UUID NOT NULL REFERENCES cases(uuid) ON DELETE CASCADE,
+  entity_id        UUID NOT NULL,
+  other_graph_id   UUID NOT NULL REFERENCES cases(uuid) ON DELETE CASCADE,
+  other_entity_id  UUID NOT NULL,
+  kind             TEXT NOT NULL,
+  value            TEXT NOT NULL,
+  created_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+  UNIQUE (graph_id, other_graph_id, kind, value)
+);
+CREATE INDEX IF NOT EXISTS correlations_other_graph_idx ON correlations (other_graph_id);
+
+CREATE TABLE IF NOT EXISTS notifications (
+  notification_id  UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  user_id          BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  kind             TEXT NOT NULL,
+  message          TEXT NOT NULL,
+  case_id          BIGINT REFERENCES cases(id) ON DELETE CASCADE,
+  related_case_id  BIGINT REFERENCES cases(id) ON DELETE CASCADE,
+  payload          JSONB NOT NULL DEFAULT '{}'::jsonb,
+  created_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+  read_at          TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS notifications_user_idx ON notifications (user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
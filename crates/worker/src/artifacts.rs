@@ -0,0 +1,118 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use common::artifacts::{ArtifactStore, NewArtifact};
+use log::error;
+use serde_json::{Value as JsonValue, json};
+use sqlx::PgPool;
+use sqlx::types::Uuid;
+
+// Results up to this size are also copied into the `result` event so the
+// websocket can apply them without another round trip
+const RESULT_EVENT_INLINE_BYTES: usize = 64 * 1024;
+
+/// Stores a job's final result as a JSON artifact and returns the payload of
+/// its `result` event. Large results are only referenced by `artifact_id`.
+pub async fn result_event(
+    store: &ArtifactStore,
+    pool: &PgPool,
+    job_id: Uuid,
+    data: JsonValue,
+) -> JsonValue {
+    let bytes = match serde_json::to_vec(&data) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("encode job result failed {job_id}: {e}");
+            return json!({"type": "result", "data": data});
+        }
+    };
+    let size = bytes.len();
+    let stored = store
+        .put(
+            pool,
+            NewArtifact {
+                job_id,
+                media_type: "application/json".into(),
+                filename: Some("result.json".into()),
+                bytes,
+                meta: json!({"kind": "result"}),
+            },
+        )
+        .await;
+    match stored {
+        Ok(artifact) if size <= RESULT_EVENT_INLINE_BYTES => json!({
+            "type": "result",
+            "artifact_id": artifact.artifact_id,
+            "data": data,
+        }),
+        Ok(artifact) => json!({
+            "type": "result",
+            "artifact_id": artifact.artifact_id,
+            "size": size,
+        }),
+        Err(e) => {
+            error!("store job result artifact failed {job_id}: {e}");
+            json!({"type": "result", "data": data})
+        }
+    }
+}
+
+/// Stores a file a plugin emitted as an `artifact` record and returns the
+/// payload of the `job:event` announcing it. Failures are reported as error
+/// logs, an `error` event would end the job's stream.
+pub async fn record_event(
+    store: &ArtifactStore,
+    pool: &PgPool,
+    job_id: Uuid,
+    data: &JsonValue,
+) -> JsonValue {
+    let content = data["content"].as_str().unwrap_or_default();
+    let bytes = match data["encoding"].as_str() {
+        Some("base64") => match STANDARD.decode(content) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return json!({
+                    "type": "log",
+                    "data": {"level": "error", "message": format!("invalid base64 artifact content: {e}")},
+                });
+            }
+        },
+        _ => content.as_bytes().to_vec(),
+    };
+    let media_type = data["media_type"]
+        .as_str()
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let filename = data["filename"].as_str().map(str::to_string);
+    let meta = data.get("meta").cloned().unwrap_or_else(|| json!({}));
+
+    match store
+        .put(
+            pool,
+            NewArtifact {
+                job_id,
+                media_type,
+                filename,
+                bytes,
+                meta,
+            },
+        )
+        .await
+    {
+        Ok(artifact) => json!({
+            "type": "artifact",
+            "data": {
+                "artifact_id": artifact.artifact_id,
+                "media_type": artifact.media_type,
+                "filename": artifact.filename,
+                "size": artifact.size,
+            },
+        }),
+        Err(e) => {
+            error!("store job artifact failed {job_id}: {e}");
+            json!({
+                "type": "log",
+                "data": {"level": "error", "message": "failed to store artifact"},
+            })
+        }
+    }
+}
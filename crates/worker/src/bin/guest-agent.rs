@@ -0,0 +1,137 @@
+//! Guest side of the firecracker executor, installed in the microVM rootfs
+//! and started by its init once the root filesystem is up. Build it static:
+//!
+//! ```text
+//! cargo build --release -p worker --bin guest-agent --target x86_64-unknown-linux-musl
+//! ```
+//!
+//! It dials the host over vsock, reads one `AGENT_PROTOCOL` frame, unpacks
+//! its `package` if it has one, runs `ob <args>` from there with the frame's
+//! `env` exported, copies `ob`'s stdout back and reboots the guest, which
+//! ends the VM. `ob`'s stderr goes
+//! to the serial console, the host only logs it when the run fails.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Value as JsonValue, json};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// must match `firecracker::AGENT_PROTOCOL` and `firecracker::VSOCK_PORT`
+const AGENT_PROTOCOL: u64 = 3;
+const VSOCK_PORT: u32 = 52;
+// `ob` loads `plugins/` from its working directory
+const PACKAGE_DIR: &str = "/tmp/package";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("guest-agent: {e}");
+    }
+    // firecracker exits when the guest reboots, see `reboot=k` in the boot args
+    unsafe {
+        libc::sync();
+        libc::reboot(libc::RB_AUTOBOOT);
+    }
+}
+
+fn run() -> io::Result<()> {
+    let mut host = connect()?;
+    let mut line = String::new();
+    BufReader::new(host.try_clone()?).read_line(&mut line)?;
+    let frame: JsonValue = match serde_json::from_str(&line) {
+        Ok(frame) => frame,
+        Err(e) => return report(&mut host, &format!("unreadable frame: {e}")),
+    };
+    if frame["version"].as_u64() != Some(AGENT_PROTOCOL) {
+        let message = format!("unsupported frame version {}", frame["version"]);
+        return report(&mut host, &message);
+    }
+    let env = frame["env"].as_object().cloned().unwrap_or_default();
+    let args: Vec<&str> = match frame["args"].as_array() {
+        Some(args) => args.iter().filter_map(JsonValue::as_str).collect(),
+        None => return report(&mut host, "frame has no args"),
+    };
+
+    let mut ob = Command::new("ob");
+    if let Some(package) = frame["package"].as_str() {
+        if let Err(e) = unpack(package) {
+            return report(&mut host, &format!("package unpack error: {e}"));
+        }
+        ob.current_dir(PACKAGE_DIR);
+    }
+    let mut child = ob
+        .args(args)
+        .env_clear()
+        .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+        .env("HOME", "/tmp")
+        .env("TMPDIR", "/tmp")
+        .env("LANG", "C.UTF-8")
+        .envs(env.iter().filter_map(|(k, v)| Some((k, v.as_str()?))))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    if let Some(mut stdout) = child.stdout.take() {
+        io::copy(&mut stdout, &mut host)?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        eprintln!("guest-agent: ob exited with {status}");
+    }
+    Ok(())
+}
+
+fn unpack(package: &str) -> io::Result<()> {
+    let tar = STANDARD
+        .decode(package)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    std::fs::create_dir_all(PACKAGE_DIR)?;
+    let mut child = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(Path::new(PACKAGE_DIR))
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&tar)?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("tar exited with {status}")));
+    }
+    Ok(())
+}
+
+// A failure before `ob` ran, as a log record the job's listeners see
+fn report(host: &mut File, message: &str) -> io::Result<()> {
+    eprintln!("guest-agent: {message}");
+    let record = json!({ "type": "log", "data": { "level": "error", "message": message } });
+    writeln!(host, "{record}")
+}
+
+// Firecracker forwards the host CID to the worker's unix socket
+fn connect() -> io::Result<File> {
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut addr: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    addr.svm_cid = libc::VMADDR_CID_HOST;
+    addr.svm_port = VSOCK_PORT;
+    let ret = unsafe {
+        libc::connect(
+            fd.as_raw_fd(),
+            (&addr as *const libc::sockaddr_vm).cast(),
+            std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(File::from(fd))
+}
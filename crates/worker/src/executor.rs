@@ -0,0 +1,304 @@
+use common::job_output::{OutputCollector, Record};
+use common::plugin_cli;
+use common::secrets::SecretEnv;
+use futures_util::future::BoxFuture;
+use log::{debug, error};
+use serde_json::Value as JsonValue;
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::firecracker::{FirecrackerBackend, FirecrackerRunner, VmSpec};
+use crate::limits::Limits;
+use crate::sandbox::{SandboxExecutor, SandboxLimits};
+use crate::vm::VmError;
+
+pub type RecordSender = UnboundedSender<Record>;
+
+/// What an executor runs: `ob <args>`, from the package's directory when
+/// `package` is set so `ob` loads that package's `plugins/` and none of the
+/// host's.
+#[derive(Debug, Clone)]
+pub struct ObCommand {
+    pub args: Vec<String>,
+    pub package: Option<PathBuf>,
+}
+
+impl ObCommand {
+    /// `ob run -T '<payload-json>'`
+    pub fn run(payload: &JsonValue) -> Result<Self, VmError> {
+        Ok(ObCommand {
+            args: vec!["run".into(), "-T".into(), encode_payload(payload)?],
+            package: None,
+        })
+    }
+
+    /// A listing subcommand such as `ob entities json`.
+    pub fn list(args: &[&str]) -> Self {
+        ObCommand {
+            args: args.iter().map(|a| a.to_string()).collect(),
+            package: None,
+        }
+    }
+
+    pub fn in_package(self, package: Option<PathBuf>) -> Self {
+        ObCommand { package, ..self }
+    }
+}
+
+/// Runs an `ob` command somewhere, feeding the plugin's stdout into `out`
+/// as it is produced. Backends enforce `limits.timeout` and
+/// `limits.memory_mib` as tightly as their isolation allows, and export
+/// `secrets` into the plugin's environment.
+pub trait Executor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn execute<'a>(
+        &'a self,
+        command: &'a ObCommand,
+        secrets: &'a SecretEnv,
+        limits: &'a Limits,
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>>;
+}
+
+// `WORKER_EXECUTOR` picks the backend: local, sandbox or firecracker. Without
+// it development runs `ob` directly and everything else boots a microVM.
+pub fn from_config(cfg: &common::config::AppConfig) -> Box<dyn Executor> {
+    let fallback = if cfg.environment.eq_ignore_ascii_case("development") {
+        "local"
+    } else {
+        "firecracker"
+    };
+    let kind = cfg
+        .worker_executor
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_else(|| fallback.to_string());
+    let kind = match kind.as_str() {
+        "local" | "sandbox" | "firecracker" => kind.as_str(),
+        other => {
+            error!("unknown worker executor `{other}`, falling back to {fallback}");
+            fallback
+        }
+    };
+    match kind {
+        "local" => Box::new(LocalExecutor),
+        "sandbox" => Box::new(SandboxExecutor::new(SandboxLimits::from_config(cfg))),
+        _ => confined(cfg),
+    }
+}
+
+/// The most restrictive backend, for plugins that aren't trusted whatever
+/// `WORKER_EXECUTOR` says.
+pub fn confined(cfg: &common::config::AppConfig) -> Box<dyn Executor> {
+    Box::new(FirecrackerBackend::new(
+        FirecrackerRunner::from_config(cfg),
+        VmSpec::from_config(cfg),
+    ))
+}
+
+// Runs `ob` as the worker user, no isolation beyond keeping the worker's own
+// environment from it
+pub struct LocalExecutor;
+
+impl Executor for LocalExecutor {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn execute<'a>(
+        &'a self,
+        command: &'a ObCommand,
+        secrets: &'a SecretEnv,
+        limits: &'a Limits,
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>> {
+        Box::pin(async move {
+            let mut cmd = Command::new("ob");
+            cmd.args(&command.args)
+                .env_clear()
+                .envs(plugin_cli::inherited_env())
+                .envs(secrets.iter());
+            if let Some(package) = &command.package {
+                cmd.current_dir(package);
+            }
+            // RLIMIT_DATA caps what the plugin allocates, the address space
+            // it merely reserves for stacks, libraries and arenas is free
+            let memory_bytes = limits.memory_mib.saturating_mul(1024 * 1024);
+            // SAFETY: only setrlimit runs between fork and exec
+            unsafe {
+                cmd.pre_exec(move || {
+                    let lim = libc::rlimit {
+                        rlim_cur: memory_bytes,
+                        rlim_max: memory_bytes,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_DATA, &lim) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+            run_ob(cmd, Some(limits.timeout), out).await
+        })
+    }
+}
+
+pub fn encode_payload(payload: &JsonValue) -> Result<String, VmError> {
+    let payload_s = serde_json::to_string(payload)
+        .map_err(|e| VmError::Launch(format!("payload encode error: {e}")))?;
+    debug!("payload: {payload_s}");
+    Ok(payload_s)
+}
+
+/// Stdout of a running plugin. Protocol records are forwarded to the job's
+/// event stream as they arrive, the final result is kept for the caller.
+/// At most `max_bytes` of output are accepted, and values of the job's
+/// `secrets` never make it past here.
+pub struct OutputStream {
+    collector: Mutex<OutputCollector>,
+    records: RecordSender,
+    max_bytes: usize,
+    read_bytes: AtomicUsize,
+    secrets: SecretEnv,
+}
+
+impl OutputStream {
+    pub fn new(records: RecordSender, max_bytes: usize, secrets: SecretEnv) -> Self {
+        OutputStream {
+            collector: Mutex::new(OutputCollector::default()),
+            records,
+            max_bytes,
+            read_bytes: AtomicUsize::new(0),
+            secrets,
+        }
+    }
+
+    /// `text` with the job's secret values masked, for logging it.
+    pub fn redact<'t>(&self, text: &'t str) -> std::borrow::Cow<'t, str> {
+        self.secrets.redact(text)
+    }
+
+    fn remaining(&self) -> usize {
+        self.max_bytes
+            .saturating_sub(self.read_bytes.load(Ordering::Relaxed))
+    }
+
+    pub fn line(&self, line: &str) {
+        let line = self.secrets.redact(line);
+        let record = self
+            .collector
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_line(&line);
+        if let Some(record) = record {
+            // the receiver only goes away once the job is finished
+            let _ = self.records.send(record);
+        }
+    }
+
+    pub fn finish(self) -> Option<JsonValue> {
+        self.collector
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .finish()
+    }
+}
+
+// Feeds `reader` into `out` one line at a time until EOF. Reads stop one
+// byte past the output budget so an endless line can't exhaust memory.
+pub async fn pump_lines<R: AsyncRead + Unpin>(
+    reader: R,
+    out: &OutputStream,
+) -> Result<(), VmError> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let budget = out.remaining() as u64 + 1;
+        let n = (&mut reader)
+            .take(budget)
+            .read_until(b'\n', &mut buf)
+            .await
+            .map_err(|e| VmError::Launch(format!("ob output error: {e}")))?;
+        if n == 0 {
+            return Ok(());
+        }
+        if out.read_bytes.fetch_add(n, Ordering::Relaxed) + n > out.max_bytes {
+            return Err(VmError::OutputTooLarge(out.max_bytes));
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        debug!("ob: {}", out.redact(line));
+        out.line(line);
+    }
+}
+
+const STDERR_MAX_BYTES: u64 = 64 * 1024;
+
+// Shared by the process based executors: stream `ob` stdout into `out` while
+// it runs, then check how it exited
+pub async fn run_ob(
+    mut cmd: Command,
+    timeout: Option<Duration>,
+    out: &OutputStream,
+) -> Result<(), VmError> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = cmd.spawn().map_err(|e| VmError::Launch(e.to_string()))?;
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(VmError::Launch("ob stdio unavailable".into()));
+    };
+
+    let run = async {
+        let read_stderr = async {
+            let mut buf = Vec::new();
+            (&mut stderr)
+                .take(STDERR_MAX_BYTES)
+                .read_to_end(&mut buf)
+                .await
+                .map(|_| String::from_utf8_lossy(&buf).into_owned())
+        };
+        let (pumped, stderr) = tokio::join!(pump_lines(stdout, out), read_stderr);
+        pumped?;
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| VmError::Launch(e.to_string()))?;
+        Ok::<_, VmError>((status, stderr.unwrap_or_default()))
+    };
+    let (status, stderr) = match timeout {
+        Some(limit) => tokio::time::timeout(limit, run)
+            .await
+            .map_err(|_| VmError::Timeout(limit))??,
+        None => run.await?,
+    };
+
+    if status.success() {
+        if !stderr.is_empty() {
+            debug!("ob stderr: {}", out.redact(stderr.trim()));
+        }
+        return Ok(());
+    }
+    error!("ob failed ({status}): {}", out.redact(stderr.trim()));
+    if out_of_memory(&stderr) {
+        return Err(VmError::Oom);
+    }
+    let code = status.code().unwrap_or(-1);
+    Err(VmError::Launch(format!("ob exit code {code}")))
+}
+
+// Only what the plugin itself reports: an allocation refused under a memory
+// rlimit. A SIGKILL alone could as well be the CPU limit or an operator, the
+// sandbox reads OOM kills from its cgroup instead.
+fn out_of_memory(stderr: &str) -> bool {
+    stderr.contains("MemoryError") || stderr.contains("Cannot allocate memory")
+}
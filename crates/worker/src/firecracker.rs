@@ -0,0 +1,760 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use common::secrets::SecretEnv;
+use futures_util::future::BoxFuture;
+use log::{error, info, warn};
+use serde_json::{Value as JsonValue, json};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::process::Command;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use uuid::Uuid;
+
+use crate::executor::{Executor, ObCommand, OutputStream, pump_lines};
+use crate::limits::Limits;
+use crate::vm::VmError;
+
+// Guest agent dials the host (CID 2) on this vsock port, Firecracker forwards
+// it to the unix socket `<uds_path>_<port>` on the host
+pub const VSOCK_PORT: u32 = 52;
+const GUEST_CID: u32 = 3;
+/// Version of the frame sent to the guest agent (`src/bin/guest-agent.rs`),
+/// one JSON line `{"version": 3, "env": {...}, "args": [...], "package": ...}`
+/// where `package` is a base64 tar of the package's `plugins/` directory, or
+/// null to run with the plugins baked into the image. The agent refuses
+/// frames of any other version.
+pub const AGENT_PROTOCOL: u32 = 3;
+// The runner stops a VM at its timeout and waits a little for it to power
+// off, past this the backend gives up on a runner that hangs regardless
+const TEARDOWN_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct VmSpec {
+    pub kernel_path: PathBuf,
+    pub rootfs_path: PathBuf,
+    pub boot_args: String,
+    pub vcpus: u8,
+    pub mem_mib: u32,
+    pub timeout: Duration,
+}
+
+impl VmSpec {
+    pub fn from_config(cfg: &common::config::AppConfig) -> Self {
+        let vmroot = PathBuf::from(
+            cfg.firecracker_vmroot
+                .clone()
+                .unwrap_or_else(|| "/var/lib/osib/vms".to_string()),
+        );
+        VmSpec {
+            kernel_path: cfg
+                .firecracker_kernel
+                .clone()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| vmroot.join("vmlinux")),
+            rootfs_path: cfg
+                .firecracker_rootfs
+                .clone()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| vmroot.join("rootfs.ext4")),
+            boot_args: "console=ttyS0 reboot=k panic=1 pci=off quiet".to_string(),
+            vcpus: cfg.firecracker_vcpus.unwrap_or(1),
+            mem_mib: cfg.firecracker_mem_mib.unwrap_or(512),
+            timeout: Duration::from_secs(cfg.firecracker_timeout_secs.unwrap_or(120)),
+        }
+    }
+}
+
+/// Boots a microVM for a single payload and feeds everything the guest agent
+/// writes back into `out` until it closes its connection. `payload` is an
+/// `AGENT_PROTOCOL` frame, the agent exports its `env` into `ob`'s
+/// environment and runs `ob <args>` from the unpacked `package`.
+pub trait VmRunner: Send + Sync {
+    fn run<'a>(
+        &'a self,
+        spec: &'a VmSpec,
+        payload: &'a [u8],
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>>;
+}
+
+pub struct FirecrackerBackend<R: VmRunner> {
+    runner: R,
+    spec: VmSpec,
+}
+
+impl<R: VmRunner> FirecrackerBackend<R> {
+    pub fn new(runner: R, spec: VmSpec) -> Self {
+        FirecrackerBackend { runner, spec }
+    }
+}
+
+impl<R: VmRunner> Executor for FirecrackerBackend<R> {
+    fn name(&self) -> &'static str {
+        "firecracker"
+    }
+
+    fn execute<'a>(
+        &'a self,
+        command: &'a ObCommand,
+        secrets: &'a SecretEnv,
+        limits: &'a Limits,
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>> {
+        Box::pin(async move {
+            let package = match &command.package {
+                Some(dir) => Some(pack(dir).await?),
+                None => None,
+            };
+            let frame = agent_frame(command, package, secrets)?;
+            // Job limits can only shrink the configured VM
+            let spec = VmSpec {
+                mem_mib: self
+                    .spec
+                    .mem_mib
+                    .min(u32::try_from(limits.memory_mib).unwrap_or(u32::MAX)),
+                timeout: self.spec.timeout.min(limits.timeout),
+                ..self.spec.clone()
+            };
+            let run = self.runner.run(&spec, &frame, out);
+            tokio::time::timeout(spec.timeout + TEARDOWN_GRACE, run)
+                .await
+                .unwrap_or(Err(VmError::Timeout(spec.timeout)))
+        })
+    }
+}
+
+fn agent_frame(
+    command: &ObCommand,
+    package: Option<Vec<u8>>,
+    secrets: &SecretEnv,
+) -> Result<Vec<u8>, VmError> {
+    let env: serde_json::Map<String, JsonValue> = secrets
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.into()))
+        .collect();
+    serde_json::to_vec(&json!({
+        "version": AGENT_PROTOCOL,
+        "env": env,
+        "args": command.args,
+        "package": package.map(|tar| STANDARD.encode(tar)),
+    }))
+    .map_err(|e| VmError::Launch(format!("payload encode error: {e}")))
+}
+
+// The package's `plugins/` as a tar, checkout metadata left behind
+async fn pack(dir: &Path) -> Result<Vec<u8>, VmError> {
+    let output = Command::new("tar")
+        .arg("-C")
+        .arg(dir)
+        .args(["--exclude=.git", "-cf", "-", "plugins"])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| VmError::Launch(format!("package pack error: {e}")))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(VmError::Launch(format!(
+            "package pack error: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// A host tap device a microVM reaches the network through, one end of a
+/// point to point /30 link the operator set up with `host_ip` on the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tap {
+    pub device: String,
+    pub guest_ip: Ipv4Addr,
+    pub host_ip: Ipv4Addr,
+}
+
+impl Tap {
+    /// Comma separated `<device>:<guest ip>:<host ip>` entries.
+    pub fn parse_list(raw: &str) -> Result<Vec<Tap>, String> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let parts: Vec<&str> = entry.split(':').collect();
+                let [device, guest_ip, host_ip] = parts[..] else {
+                    return Err(format!("{entry:?} is not <device>:<guest ip>:<host ip>"));
+                };
+                let ip = |s: &str| {
+                    s.parse::<Ipv4Addr>()
+                        .map_err(|e| format!("{entry:?}: {s:?} {e}"))
+                };
+                Ok(Tap {
+                    device: device.to_string(),
+                    guest_ip: ip(guest_ip)?,
+                    host_ip: ip(host_ip)?,
+                })
+            })
+            .collect()
+    }
+
+    // Firecracker's convention, 06:00 followed by the guest address
+    fn guest_mac(&self) -> String {
+        let [a, b, c, d] = self.guest_ip.octets();
+        format!("06:00:{a:02X}:{b:02X}:{c:02X}:{d:02X}")
+    }
+
+    // The guest kernel brings eth0 up itself, the image needs no DHCP client
+    fn boot_arg(&self) -> String {
+        format!(
+            "ip={}::{}:255.255.255.252::eth0:off",
+            self.guest_ip, self.host_ip
+        )
+    }
+}
+
+// A tap device can be attached to one VM at a time, boots wait for a free one
+struct TapPool {
+    size: usize,
+    free: Mutex<Vec<Tap>>,
+    permits: Semaphore,
+}
+
+struct TapLease<'a> {
+    pool: &'a TapPool,
+    tap: Tap,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl TapPool {
+    fn new(taps: Vec<Tap>) -> Self {
+        TapPool {
+            size: taps.len(),
+            permits: Semaphore::new(taps.len()),
+            free: Mutex::new(taps),
+        }
+    }
+
+    // `None` right away when no taps are configured
+    async fn lease(&self) -> Option<TapLease<'_>> {
+        if self.size == 0 {
+            return None;
+        }
+        let permit = self.permits.acquire().await.ok()?;
+        let tap = self.free.lock().unwrap().pop()?;
+        Some(TapLease {
+            pool: self,
+            tap,
+            _permit: permit,
+        })
+    }
+}
+
+impl Drop for TapLease<'_> {
+    fn drop(&mut self) {
+        self.pool.free.lock().unwrap().push(self.tap.clone());
+    }
+}
+
+/// Runs the `firecracker` binary without its API socket, configured from a
+/// per-VM config file under `<workdir>/<vm id>/`. VMs get network only
+/// through the configured `firecracker_taps`, without any they have none.
+pub struct FirecrackerRunner {
+    pub bin: PathBuf,
+    pub workdir: PathBuf,
+    taps: TapPool,
+}
+
+impl FirecrackerRunner {
+    pub fn from_config(cfg: &common::config::AppConfig) -> Self {
+        let vmroot = cfg
+            .firecracker_vmroot
+            .clone()
+            .unwrap_or_else(|| "/var/lib/osib/vms".to_string());
+        let taps = match cfg.firecracker_taps.as_deref().map(Tap::parse_list) {
+            Some(Ok(taps)) => taps,
+            Some(Err(e)) => {
+                error!("ignoring invalid firecracker_taps: {e}");
+                vec![]
+            }
+            None => vec![],
+        };
+        if taps.is_empty() {
+            warn!("no firecracker_taps configured, microVMs run without network");
+        }
+        FirecrackerRunner {
+            bin: PathBuf::from(
+                cfg.firecracker_bin
+                    .clone()
+                    .unwrap_or_else(|| "/usr/local/bin/firecracker".to_string()),
+            ),
+            workdir: Path::new(&vmroot).join("run"),
+            taps: TapPool::new(taps),
+        }
+    }
+
+    fn vm_config(spec: &VmSpec, uds_path: &Path, tap: Option<&Tap>) -> JsonValue {
+        let mut boot_args = spec.boot_args.clone();
+        let mut interfaces = vec![];
+        if let Some(tap) = tap {
+            boot_args = format!("{boot_args} {}", tap.boot_arg());
+            interfaces.push(json!({
+                "iface_id": "eth0",
+                "host_dev_name": tap.device,
+                "guest_mac": tap.guest_mac(),
+            }));
+        }
+        json!({
+            "boot-source": {
+                "kernel_image_path": spec.kernel_path,
+                "boot_args": boot_args,
+            },
+            // shared read-only image, the guest keeps scratch state in tmpfs
+            "drives": [{
+                "drive_id": "rootfs",
+                "path_on_host": spec.rootfs_path,
+                "is_root_device": true,
+                "is_read_only": true,
+            }],
+            "machine-config": {
+                "vcpu_count": spec.vcpus,
+                "mem_size_mib": spec.mem_mib,
+                "smt": false,
+            },
+            "network-interfaces": interfaces,
+            "vsock": {
+                "guest_cid": GUEST_CID,
+                "uds_path": uds_path,
+            },
+        })
+    }
+
+    async fn boot(
+        &self,
+        vm_dir: &Path,
+        spec: &VmSpec,
+        tap: Option<&Tap>,
+        payload: &[u8],
+        out: &OutputStream,
+    ) -> Result<(), VmError> {
+        let uds_path = vm_dir.join("v.sock");
+        let config_path = vm_dir.join("config.json");
+        let config = serde_json::to_vec(&Self::vm_config(spec, &uds_path, tap))
+            .map_err(|e| VmError::Launch(format!("vm config encode error: {e}")))?;
+        tokio::fs::write(&config_path, config)
+            .await
+            .map_err(|e| VmError::Launch(format!("vm config write error: {e}")))?;
+
+        // Must be listening before the guest agent dials out
+        let listener = UnixListener::bind(vm_dir.join(format!("v.sock_{VSOCK_PORT}")))
+            .map_err(|e| VmError::Launch(format!("vsock listen error: {e}")))?;
+
+        let vm_id = vm_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Serial console and firecracker logs, only surfaced when the run fails
+        let log_path = vm_dir.join("firecracker.log");
+        let log = std::fs::File::create(&log_path)
+            .map_err(|e| VmError::Launch(format!("vm log error: {e}")))?;
+        let log_err = log
+            .try_clone()
+            .map_err(|e| VmError::Launch(format!("vm log error: {e}")))?;
+        let mut child = Command::new(&self.bin)
+            .arg("--no-api")
+            .arg("--config-file")
+            .arg(&config_path)
+            .arg("--id")
+            .arg(&vm_id)
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(log))
+            .stderr(Stdio::from(log_err))
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| VmError::Launch(format!("firecracker spawn error: {e}")))?;
+        info!(
+            "booted microVM {vm_id} ({} vcpu, {} MiB)",
+            spec.vcpus, spec.mem_mib
+        );
+
+        let exchange = async {
+            let (mut stream, _) = listener
+                .accept()
+                .await
+                .map_err(|e| VmError::Launch(format!("vsock accept error: {e}")))?;
+            stream
+                .write_all(payload)
+                .await
+                .map_err(|e| VmError::Launch(format!("vsock write error: {e}")))?;
+            stream
+                .write_all(b"\n")
+                .await
+                .map_err(|e| VmError::Launch(format!("vsock write error: {e}")))?;
+            stream
+                .shutdown()
+                .await
+                .map_err(|e| VmError::Launch(format!("vsock shutdown error: {e}")))?;
+            pump_lines(stream, out).await
+        };
+
+        let result = tokio::select! {
+            out = tokio::time::timeout(spec.timeout, exchange) => match out {
+                Ok(out) => out,
+                Err(_) => Err(VmError::Timeout(spec.timeout)),
+            },
+            status = child.wait() => {
+                // VM went away before the agent reported back
+                let code = status.ok().and_then(|s| s.code()).unwrap_or(-1);
+                Err(VmError::Launch(format!("firecracker exited early with code {code}")))
+            }
+        };
+
+        // The agent powers the guest off once it is done, give it a moment
+        match tokio::time::timeout(Duration::from_secs(5), child.wait()).await {
+            Ok(_) => {}
+            Err(_) => {
+                warn!("microVM {vm_id} did not shut down, killing it");
+                let _ = child.kill().await;
+            }
+        }
+        if let Err(e) = result {
+            let log = tokio::fs::read_to_string(&log_path)
+                .await
+                .unwrap_or_default();
+            error!("firecracker output ({vm_id}): {}", out.redact(log.trim()));
+            return Err(console_failure(e, &log));
+        }
+        result
+    }
+}
+
+// The guest kernel logs OOM kills to the serial console, a VM that went
+// away with one in its log ran out of memory
+fn console_failure(err: VmError, console: &str) -> VmError {
+    match err {
+        VmError::Launch(_) if console.contains("Out of memory") => VmError::Oom,
+        err => err,
+    }
+}
+
+impl VmRunner for FirecrackerRunner {
+    fn run<'a>(
+        &'a self,
+        spec: &'a VmSpec,
+        payload: &'a [u8],
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>> {
+        Box::pin(async move {
+            let vm_dir = self.workdir.join(Uuid::new_v4().to_string());
+            tokio::fs::create_dir_all(&vm_dir)
+                .await
+                .map_err(|e| VmError::Launch(format!("vm dir error: {e}")))?;
+            let lease = self.taps.lease().await;
+            let tap = lease.as_ref().map(|lease| &lease.tap);
+            let result = self.boot(&vm_dir, spec, tap, payload, out).await;
+            if let Err(e) = tokio::fs::remove_dir_all(&vm_dir).await {
+                warn!("failed to clean up {}: {e}", vm_dir.display());
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Each boot the fake saw: the spec it was given and the frame sent in
+    type Booted = Arc<Mutex<Vec<(VmSpec, Vec<u8>)>>>;
+
+    // Stands in for firecracker on machines without KVM: records what it was
+    // asked to boot and replays canned guest output
+    #[derive(Default)]
+    struct FakeRunner {
+        output: Vec<u8>,
+        outcome: Mutex<Option<VmError>>,
+        hang: bool,
+        booted: Booted,
+    }
+
+    impl VmRunner for FakeRunner {
+        fn run<'a>(
+            &'a self,
+            spec: &'a VmSpec,
+            payload: &'a [u8],
+            out: &'a OutputStream,
+        ) -> BoxFuture<'a, Result<(), VmError>> {
+            Box::pin(async move {
+                self.booted
+                    .lock()
+                    .unwrap()
+                    .push((spec.clone(), payload.to_vec()));
+                if self.hang {
+                    std::future::pending::<()>().await;
+                }
+                pump_lines(self.output.as_slice(), out).await?;
+                match self.outcome.lock().unwrap().take() {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                }
+            })
+        }
+    }
+
+    fn spec() -> VmSpec {
+        VmSpec {
+            kernel_path: PathBuf::from("vmlinux"),
+            rootfs_path: PathBuf::from("rootfs.ext4"),
+            boot_args: String::new(),
+            vcpus: 1,
+            mem_mib: 512,
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    fn limits(memory_mib: u64, timeout_secs: u64) -> Limits {
+        Limits {
+            timeout: Duration::from_secs(timeout_secs),
+            max_output_bytes: 1024,
+            memory_mib,
+        }
+    }
+
+    fn stream(max_bytes: usize) -> OutputStream {
+        let (records, _) = tokio::sync::mpsc::unbounded_channel();
+        OutputStream::new(records, max_bytes, SecretEnv::default())
+    }
+
+    fn payload() -> JsonValue {
+        json!({ "action": "transform:entity", "entity": { "id": "e1", "transform": "to_ip" } })
+    }
+
+    fn command() -> ObCommand {
+        ObCommand::run(&payload()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn job_limits_only_shrink_the_vm() {
+        let runner = FakeRunner::default();
+        let booted = runner.booted.clone();
+        let backend = FirecrackerBackend::new(runner, spec());
+        let secrets = SecretEnv::default();
+        let out = stream(1024);
+
+        backend
+            .execute(&command(), &secrets, &limits(256, 30), &out)
+            .await
+            .unwrap();
+        backend
+            .execute(&command(), &secrets, &limits(4096, 600), &out)
+            .await
+            .unwrap();
+
+        let booted = booted.lock().unwrap();
+        assert_eq!(booted[0].0.mem_mib, 256);
+        assert_eq!(booted[0].0.timeout, Duration::from_secs(30));
+        assert_eq!(booted[1].0.mem_mib, 512);
+        assert_eq!(booted[1].0.timeout, Duration::from_secs(120));
+        assert_eq!(booted[1].0.vcpus, 1);
+    }
+
+    #[tokio::test]
+    async fn frame_carries_env_next_to_the_payload() {
+        let runner = FakeRunner::default();
+        let booted = runner.booted.clone();
+        let backend = FirecrackerBackend::new(runner, spec());
+        let secrets: SecretEnv = [("SHODAN_API_KEY".to_string(), "k-123456".to_string())]
+            .into_iter()
+            .collect();
+        let out = stream(1024);
+        backend
+            .execute(&command(), &secrets, &limits(512, 60), &out)
+            .await
+            .unwrap();
+        backend
+            .execute(&command(), &SecretEnv::default(), &limits(512, 60), &out)
+            .await
+            .unwrap();
+
+        let booted = booted.lock().unwrap();
+        for (_, frame) in booted.iter() {
+            assert!(!frame.contains(&b'\n'), "frame must be a single line");
+        }
+        let with_env: JsonValue = serde_json::from_slice(&booted[0].1).unwrap();
+        assert_eq!(with_env["version"], AGENT_PROTOCOL);
+        assert_eq!(with_env["env"], json!({ "SHODAN_API_KEY": "k-123456" }));
+        assert_eq!(with_env["args"], json!(command().args));
+        assert_eq!(with_env["package"], JsonValue::Null);
+        let without_env: JsonValue = serde_json::from_slice(&booted[1].1).unwrap();
+        assert_eq!(without_env["env"], json!({}));
+        assert_eq!(without_env["args"], json!(command().args));
+    }
+
+    #[tokio::test]
+    async fn frame_ships_only_the_packages_plugins() {
+        let dir = std::env::temp_dir().join(format!("fc-package-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("plugins/.git")).unwrap();
+        std::fs::write(dir.join("plugins/shodan.py"), "print('hi')").unwrap();
+        std::fs::write(dir.join("plugins/.git/HEAD"), "ref").unwrap();
+        std::fs::write(dir.join("notes.txt"), "outside").unwrap();
+
+        let runner = FakeRunner::default();
+        let booted = runner.booted.clone();
+        let backend = FirecrackerBackend::new(runner, spec());
+        let listing = ObCommand::list(&["entities", "json"]).in_package(Some(dir.clone()));
+        backend
+            .execute(
+                &listing,
+                &SecretEnv::default(),
+                &limits(512, 60),
+                &stream(1024),
+            )
+            .await
+            .unwrap();
+
+        let frame: JsonValue = serde_json::from_slice(&booted.lock().unwrap()[0].1).unwrap();
+        assert_eq!(frame["args"], json!(["entities", "json"]));
+        let tar = STANDARD.decode(frame["package"].as_str().unwrap()).unwrap();
+        let listed = std::process::Command::new("tar")
+            .arg("-t")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                use std::io::Write;
+                child.stdin.take().unwrap().write_all(&tar)?;
+                child.wait_with_output()
+            })
+            .unwrap();
+        let listed = String::from_utf8(listed.stdout).unwrap();
+        assert!(listed.contains("plugins/shodan.py"));
+        assert!(!listed.contains(".git"));
+        assert!(!listed.contains("notes.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_runner_times_out() {
+        let runner = FakeRunner {
+            hang: true,
+            ..Default::default()
+        };
+        let backend = FirecrackerBackend::new(runner, spec());
+        let out = stream(1024);
+        let err = backend
+            .execute(&command(), &SecretEnv::default(), &limits(512, 30), &out)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VmError::Timeout(t) if t == Duration::from_secs(30)));
+        assert_eq!(err.reason(), "timeout");
+    }
+
+    #[tokio::test]
+    async fn runner_failures_pass_through() {
+        let runner = FakeRunner {
+            outcome: Mutex::new(Some(VmError::Oom)),
+            ..Default::default()
+        };
+        let backend = FirecrackerBackend::new(runner, spec());
+        let out = stream(1024);
+        let err = backend
+            .execute(&command(), &SecretEnv::default(), &limits(512, 30), &out)
+            .await
+            .unwrap_err();
+        assert_eq!(err.reason(), "oom");
+    }
+
+    #[test]
+    fn console_oom_is_reported_as_oom() {
+        let console = "[    3.2] Out of memory: Killed process 212 (python3)";
+        let exited = || VmError::Launch("firecracker exited early with code 0".into());
+        assert!(matches!(console_failure(exited(), console), VmError::Oom));
+        assert!(matches!(
+            console_failure(exited(), "[    0.4] Run /sbin/init"),
+            VmError::Launch(_)
+        ));
+        // a timeout stays a timeout whatever the guest logged
+        assert!(matches!(
+            console_failure(VmError::Timeout(Duration::from_secs(1)), console),
+            VmError::Timeout(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn guest_output_is_capped() {
+        let line = format!("{}\n", "x".repeat(99));
+        let runner = FakeRunner {
+            output: line.repeat(20).into_bytes(),
+            ..Default::default()
+        };
+        let backend = FirecrackerBackend::new(runner, spec());
+        let out = stream(1024);
+        let err = backend
+            .execute(&command(), &SecretEnv::default(), &limits(512, 30), &out)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VmError::OutputTooLarge(1024)));
+    }
+
+    #[tokio::test]
+    async fn guest_output_under_the_cap_is_the_result() {
+        let runner = FakeRunner {
+            output: b"[{\"label\": \"IP Address\", \"ip\": \"1.2.3.4\"}]\n".to_vec(),
+            ..Default::default()
+        };
+        let backend = FirecrackerBackend::new(runner, spec());
+        let out = stream(1024);
+        backend
+            .execute(&command(), &SecretEnv::default(), &limits(512, 30), &out)
+            .await
+            .unwrap();
+        assert_eq!(
+            out.finish(),
+            Some(json!([{ "label": "IP Address", "ip": "1.2.3.4" }]))
+        );
+    }
+
+    #[test]
+    fn taps_parse_and_configure_the_guest() {
+        let taps = Tap::parse_list("fc-tap0:172.16.0.2:172.16.0.1, fc-tap1:172.16.0.6:172.16.0.5")
+            .unwrap();
+        assert_eq!(taps.len(), 2);
+        assert_eq!(taps[1].device, "fc-tap1");
+        assert!(Tap::parse_list("fc-tap0:172.16.0.2").is_err());
+        assert!(Tap::parse_list("fc-tap0:172.16.0.300:172.16.0.1").is_err());
+        assert_eq!(Tap::parse_list("").unwrap(), vec![]);
+
+        let uds = Path::new("v.sock");
+        let config = FirecrackerRunner::vm_config(&spec(), uds, Some(&taps[0]));
+        assert_eq!(
+            config["network-interfaces"],
+            json!([{ "iface_id": "eth0", "host_dev_name": "fc-tap0", "guest_mac": "06:00:AC:10:00:02" }])
+        );
+        let boot_args = config["boot-source"]["boot_args"].as_str().unwrap();
+        assert!(boot_args.ends_with("ip=172.16.0.2::172.16.0.1:255.255.255.252::eth0:off"));
+
+        let config = FirecrackerRunner::vm_config(&spec(), uds, None);
+        assert_eq!(config["network-interfaces"], json!([]));
+        assert!(
+            !config["boot-source"]["boot_args"]
+                .as_str()
+                .unwrap()
+                .contains("ip=")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_tap_serves_one_vm_at_a_time() {
+        assert!(TapPool::new(vec![]).lease().await.is_none());
+
+        let taps = Tap::parse_list("fc-tap0:172.16.0.2:172.16.0.1").unwrap();
+        let pool = TapPool::new(taps);
+        let first = pool.lease().await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_secs(1), pool.lease()).await;
+        assert!(waiting.is_err(), "the tap is still attached");
+        drop(first);
+        assert_eq!(pool.lease().await.unwrap().tap.device, "fc-tap0");
+    }
+}
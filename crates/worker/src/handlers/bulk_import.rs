@@ -0,0 +1,98 @@
+use chrono::Utc;
+use common::eventstore::{self, AppendEvent};
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+
+use super::{JobContext, JobError, JobHandler};
+
+// Creates entities (and edges between them, by index) from an uploaded list
+pub struct BulkImportHandler;
+
+impl JobHandler for BulkImportHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let graph_id = ctx.graph_id()?;
+            let items = ctx.job.payload["entities"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+
+            let mut ids: Vec<Uuid> = Vec::with_capacity(items.len());
+            for (i, item) in items.iter().enumerate() {
+                let entity_id = Uuid::new_v4();
+                // Without positions, lay imports out in rows of 8
+                let position = item.get("position").cloned().unwrap_or_else(
+                    || json!({ "x": (i % 8) as f64 * 460.0, "y": (i / 8) as f64 * 260.0 }),
+                );
+                let payload = json!({
+                    "id": entity_id,
+                    "label": item["label"],
+                    "position": position,
+                    "data": item.get("data").cloned().unwrap_or_else(|| json!({})),
+                });
+                eventstore::append_event(
+                    &ctx.pool,
+                    AppendEvent {
+                        category: "entity".into(),
+                        key: graph_id.to_string(),
+                        event_type: "create".into(),
+                        payload,
+                        valid_from: Utc::now(),
+                        valid_to: None,
+                        correlation_id: Some(ctx.job.job_id),
+                        causation_id: None,
+                        expected_version: None,
+                        actor_id: ctx.actor_id,
+                    },
+                )
+                .await?;
+                ids.push(entity_id);
+            }
+
+            let edges = ctx.job.payload["edges"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            let mut edges_created = 0;
+            for edge in edges.iter() {
+                let src = edge["source"].as_u64().and_then(|i| ids.get(i as usize));
+                let dst = edge["target"].as_u64().and_then(|i| ids.get(i as usize));
+                let (Some(src), Some(dst)) = (src, dst) else {
+                    continue;
+                };
+                eventstore::append_event(
+                    &ctx.pool,
+                    AppendEvent {
+                        category: "edge".into(),
+                        key: graph_id.to_string(),
+                        event_type: "create".into(),
+                        payload: json!({
+                            "id": Uuid::new_v4(),
+                            "source": src,
+                            "target": dst,
+                            "data": { "label": edge.get("label").cloned().unwrap_or_else(|| json!("")) },
+                        }),
+                        valid_from: Utc::now(),
+                        valid_to: None,
+                        correlation_id: Some(ctx.job.job_id),
+                        causation_id: None,
+                        expected_version: None,
+                        actor_id: ctx.actor_id,
+                    },
+                )
+                .await?;
+                edges_created += 1;
+            }
+
+            Ok(Some(json!({
+                "entities_created": ids.len(),
+                "edges_created": edges_created,
+                "ids": ids,
+            })))
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+
+use super::{JobContext, JobError, JobHandler};
+
+// Snapshot of a case's current entities and edges
+pub struct ExportHandler;
+
+impl JobHandler for ExportHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let graph_id = ctx.graph_id()?;
+
+            let entities: Vec<JsonValue> = sqlx::query!(
+                r#"
+                SELECT entity_id, doc, provenance, valid_from
+                  FROM entities_current
+                 WHERE graph_id = $1
+                   AND sys_to IS NULL
+                 ORDER BY sys_from ASC
+                "#,
+                graph_id
+            )
+            .fetch_all(&ctx.pool)
+            .await?
+            .into_iter()
+            .map(|r| {
+                json!({
+                    "id": r.entity_id,
+                    "doc": r.doc,
+                    "provenance": r.provenance,
+                    "valid_from": r.valid_from,
+                })
+            })
+            .collect();
+
+            let edges: Vec<JsonValue> = sqlx::query!(
+                r#"
+                SELECT edge_id, src_id, dst_id, props, provenance, valid_from
+                  FROM edges_current
+                 WHERE graph_id = $1
+                   AND sys_to IS NULL
+                 ORDER BY sys_from ASC
+                "#,
+                graph_id
+            )
+            .fetch_all(&ctx.pool)
+            .await?
+            .into_iter()
+            .map(|r| {
+                json!({
+                    "id": r.edge_id,
+                    "source": r.src_id,
+                    "target": r.dst_id,
+                    "data": r.props,
+                    "provenance": r.provenance,
+                    "valid_from": r.valid_from,
+                })
+            })
+            .collect();
+
+            // chain of custody: every source the exported findings came from
+            let sources: Vec<JsonValue> = sqlx::query!(
+                r#"
+                SELECT provenance->>'source_url' AS source_url,
+                       provenance->>'plugin' AS plugin,
+                       provenance->>'plugin_version' AS plugin_version,
+                       provenance->>'transform' AS transform,
+                       MIN(provenance->>'retrieved_at') AS first_retrieved,
+                       MAX(provenance->>'retrieved_at') AS last_retrieved,
+                       COUNT(*)::BIGINT AS "entities!"
+                  FROM entities_current
+                 WHERE graph_id = $1
+                   AND sys_to IS NULL
+                   AND provenance IS NOT NULL
+                 GROUP BY 1, 2, 3, 4
+                 ORDER BY 7 DESC
+                "#,
+                graph_id
+            )
+            .fetch_all(&ctx.pool)
+            .await?
+            .into_iter()
+            .map(|r| {
+                json!({
+                    "source_url": r.source_url,
+                    "plugin": r.plugin,
+                    "plugin_version": r.plugin_version,
+                    "transform": r.transform,
+                    "first_retrieved": r.first_retrieved,
+                    "last_retrieved": r.last_retrieved,
+                    "entities": r.entities,
+                })
+            })
+            .collect();
+
+            Ok(Some(json!({
+                "format": "json",
+                "graph_id": graph_id,
+                "exported_at": Utc::now(),
+                "entities": entities,
+                "edges": edges,
+                "sources": sources,
+            })))
+        })
+    }
+}
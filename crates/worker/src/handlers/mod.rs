@@ -0,0 +1,154 @@
+use common::identity::NaturalKeys;
+use common::jobs::{Job, JobKind};
+use common::plugin_cli::PluginCli;
+use futures_util::future::BoxFuture;
+use serde_json::Value as JsonValue;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::executor::RecordSender;
+use crate::limits::{Limits, LimitsConfig};
+use crate::vm::VmError;
+
+mod bulk_import;
+mod export;
+mod playbook;
+mod plugin_package;
+mod plugin_sync;
+mod purge;
+mod report;
+mod scheduled_rerun;
+mod transform;
+
+#[derive(thiserror::Error, Debug)]
+pub enum JobError {
+    #[error(transparent)]
+    Vm(#[from] VmError),
+    #[error("database error: {0}")]
+    Sql(#[from] sqlx::Error),
+    #[error("invalid job: {0}")]
+    Invalid(String),
+}
+
+impl JobError {
+    /// Recorded as the job's `failure_reason`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            JobError::Vm(e) => e.reason(),
+            JobError::Sql(_) => "database_error",
+            JobError::Invalid(_) => "invalid_job",
+        }
+    }
+}
+
+pub struct JobContext {
+    pub pool: PgPool,
+    pub job: Job,
+    pub actor_id: Option<i64>,
+    /// Streamed into the job's `job:event`s while the handler runs.
+    pub records: RecordSender,
+    pub limits: Limits,
+}
+
+impl JobContext {
+    /// Case the job operates on, validated at enqueue time for kinds that need one.
+    pub fn graph_id(&self) -> Result<sqlx::types::Uuid, JobError> {
+        self.job
+            .graph_id
+            .ok_or_else(|| JobError::Invalid("job has no case".into()))
+    }
+}
+
+/// Executes one kind of job. The returned JSON, if any, is reported as the
+/// job's `result` event.
+pub trait JobHandler: Send + Sync {
+    fn run<'a>(&'a self, ctx: &'a JobContext)
+    -> BoxFuture<'a, Result<Option<JsonValue>, JobError>>;
+}
+
+// Handlers that don't go through an executor are only cut off this long
+// after their timeout, executors enforce it themselves
+const TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+/// Routes leased jobs to the handler registered for their kind.
+pub struct Registry {
+    handlers: HashMap<JobKind, Box<dyn JobHandler>>,
+    limits: LimitsConfig,
+}
+
+impl Registry {
+    pub fn new(
+        limits: LimitsConfig,
+        keys: Arc<NaturalKeys>,
+        cli: PluginCli,
+        plugin_root: PathBuf,
+    ) -> Self {
+        let mut registry = Registry {
+            handlers: HashMap::new(),
+            limits,
+        };
+        registry.register(
+            JobKind::Transform,
+            transform::TransformHandler { keys: keys.clone() },
+        );
+        registry.register(JobKind::BulkImport, bulk_import::BulkImportHandler);
+        registry.register(JobKind::Export, export::ExportHandler);
+        registry.register(JobKind::Report, report::ReportHandler);
+        registry.register(
+            JobKind::ScheduledRerun,
+            scheduled_rerun::ScheduledRerunHandler { keys: keys.clone() },
+        );
+        registry.register(JobKind::Purge, purge::PurgeHandler);
+        registry.register(JobKind::Playbook, playbook::PlaybookHandler { keys });
+        registry.register(
+            JobKind::PluginSync,
+            plugin_sync::PluginSyncHandler { cli: cli.clone() },
+        );
+        registry.register(
+            JobKind::PluginInstall,
+            plugin_package::PluginInstallHandler {
+                cli: cli.clone(),
+                root: plugin_root,
+            },
+        );
+        registry.register(
+            JobKind::PluginUninstall,
+            plugin_package::PluginUninstallHandler { cli },
+        );
+        registry
+    }
+
+    pub fn register<H: JobHandler + 'static>(&mut self, kind: JobKind, handler: H) {
+        self.handlers.insert(kind, Box::new(handler));
+    }
+
+    /// Limits for a leased job, unknown kinds get the transform defaults and
+    /// are rejected by `dispatch`.
+    pub fn limits_for(&self, job: &Job) -> Limits {
+        let kind = JobKind::from_payload(&job.payload).unwrap_or(JobKind::Transform);
+        self.limits.resolve(kind, &job.payload)
+    }
+
+    pub async fn dispatch(&self, ctx: &JobContext) -> Result<Option<JsonValue>, JobError> {
+        let kind = JobKind::from_payload(&ctx.job.payload)
+            .ok_or_else(|| JobError::Invalid("unknown job kind".into()))?;
+        let handler = self
+            .handlers
+            .get(&kind)
+            .ok_or_else(|| JobError::Invalid(format!("no handler for {}", kind.as_str())))?;
+        let limits = ctx.limits;
+        let result = tokio::time::timeout(limits.timeout + TIMEOUT_GRACE, handler.run(ctx))
+            .await
+            .map_err(|_| VmError::Timeout(limits.timeout))??;
+        if let Some(data) = &result {
+            let size = serde_json::to_vec(data).map(|b| b.len()).unwrap_or(0);
+            if size > limits.max_output_bytes {
+                return Err(VmError::OutputTooLarge(limits.max_output_bytes).into());
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,240 @@
+use chrono::Utc;
+use common::artifacts;
+use common::findings;
+use common::identity::NaturalKeys;
+use common::job_output::Record;
+use common::jobs::{self, EnqueueError, NewJob};
+use common::layout::Layout;
+use common::persist::{self, PersistContext};
+use common::playbooks::{self, Plan};
+use common::provenance::Origin;
+use common::transform_cache::plugin_label;
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{JobContext, JobError, JobHandler};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const HEARTBEAT_POLLS: u32 = 15;
+
+// Runs a saved playbook from one entity. Every step becomes a child transform
+// job, the entities a step creates feed the steps that follow it, one depth
+// level at a time. Created entities are reported as `progress` records so the
+// websocket can draw them while the run continues.
+pub struct PlaybookHandler {
+    pub keys: Arc<NaturalKeys>,
+}
+
+impl JobHandler for PlaybookHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let graph_id = ctx.graph_id()?;
+            let playbook_id = ctx.job.payload["playbook_id"]
+                .as_str()
+                .and_then(|s| Uuid::parse_str(s).ok())
+                .ok_or_else(|| JobError::Invalid("playbook_id is not a uuid".into()))?;
+            let playbook = playbooks::get_playbook(&ctx.pool, playbook_id)
+                .await?
+                .filter(|p| Some(p.org_id) == ctx.job.org_id)
+                .ok_or_else(|| JobError::Invalid("playbook not found".into()))?;
+            let plan = Plan::parse(&playbook.steps).map_err(|e| JobError::Invalid(e.into()))?;
+
+            let entity_id = ctx.job.payload["entity"]["id"]
+                .as_str()
+                .and_then(|s| Uuid::parse_str(s).ok())
+                .ok_or_else(|| JobError::Invalid("entity id is not a uuid".into()))?;
+            let start = sqlx::query_scalar!(
+                r#"
+                SELECT doc FROM entities_current
+                 WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+                "#,
+                graph_id,
+                entity_id
+            )
+            .fetch_optional(&ctx.pool)
+            .await?
+            .ok_or_else(|| JobError::Invalid("start entity no longer exists".into()))?;
+
+            let layout = ctx.job.payload["layout"]
+                .as_str()
+                .and_then(Layout::parse)
+                .unwrap_or_default();
+
+            let mut frontier: Vec<(JsonValue, usize)> = plan
+                .roots()
+                .iter()
+                .filter(|&&i| plan.accepts(i, &start))
+                .map(|&i| (start.clone(), i))
+                .collect();
+            // outputs can resolve to entities the run already reached, each
+            // step runs on an entity once
+            let mut visited: HashSet<(String, usize)> = frontier
+                .iter()
+                .map(|(e, i)| (e["id"].as_str().unwrap_or_default().to_string(), *i))
+                .collect();
+            let mut enqueued = 0;
+            let mut created_total = 0;
+            let mut failed = Vec::new();
+            let mut truncated = false;
+
+            for depth in 1..=playbook.max_depth {
+                if frontier.is_empty() {
+                    break;
+                }
+                let mut running = Vec::with_capacity(frontier.len());
+                for (entity, i) in frontier.drain(..) {
+                    if enqueued >= playbook.max_jobs {
+                        truncated = true;
+                        break;
+                    }
+                    let step = plan.step(i);
+                    let mut input = entity.clone();
+                    input["transform"] = json!(step.transform);
+                    // a step is held to the same plugin and quota checks as
+                    // the transform a user would have run
+                    let job = jobs::enqueue_org_job(
+                        &ctx.pool,
+                        NewJob {
+                            payload: json!({
+                                "action": "transform:entity",
+                                "entity": input,
+                                "playbook": { "run": ctx.job.job_id, "step": step.id },
+                            }),
+                            priority: Some(ctx.job.priority),
+                            // a failed step is reported, not retried
+                            max_attempts: Some(1),
+                            scheduled_at: None,
+                            org_id: ctx.job.org_id,
+                            actor_id: ctx.actor_id,
+                            graph_id: Some(graph_id),
+                            parent_id: Some(ctx.job.job_id),
+                        },
+                    )
+                    .await;
+                    let job = match job {
+                        Ok(job) => job,
+                        Err(EnqueueError::Rejected(message)) => {
+                            failed.push(json!({
+                                "step": step.id,
+                                "status": "rejected",
+                                "message": message,
+                            }));
+                            continue;
+                        }
+                        Err(EnqueueError::Sql(e)) => return Err(e.into()),
+                    };
+                    enqueued += 1;
+                    let _ = ctx.records.send(Record::Progress(json!({
+                        "note": format!("step {} started", step.id),
+                        "step": step.id,
+                        "depth": depth,
+                        "job_id": job.job_id,
+                    })));
+                    running.push((job.job_id, entity, i));
+                }
+
+                let statuses = wait_for_children(ctx).await?;
+                for (child_id, entity, i) in running {
+                    let step = plan.step(i);
+                    let status = statuses.get(&child_id).map(String::as_str);
+                    if status != Some("completed") {
+                        failed
+                            .push(json!({ "step": step.id, "job_id": child_id, "status": status }));
+                        continue;
+                    }
+                    let Some(outputs) = artifacts::job_result(&ctx.pool, child_id).await? else {
+                        continue;
+                    };
+                    let Some(src_id) = entity["id"].as_str().and_then(|s| Uuid::parse_str(s).ok())
+                    else {
+                        continue;
+                    };
+                    let created = persist::create_children(
+                        &ctx.pool,
+                        PersistContext {
+                            graph_id,
+                            job_id: child_id,
+                            actor_id: ctx.actor_id,
+                            layout,
+                            keys: &self.keys,
+                        },
+                        src_id,
+                        &entity["position"],
+                        Origin {
+                            transform: &step.transform,
+                            plugin: plugin_label(&entity),
+                            plugin_version: None,
+                            retrieved_at: Utc::now(),
+                        },
+                        &findings::output_items(&outputs),
+                    )
+                    .await?;
+                    let entities: Vec<_> = created
+                        .iter()
+                        .filter(|c| !c.reused)
+                        .map(|c| &c.entity)
+                        .collect();
+                    created_total += entities.len();
+                    let _ = ctx.records.send(Record::Progress(json!({
+                        "note": format!("step {} completed", step.id),
+                        "step": step.id,
+                        "depth": depth,
+                        "job_id": child_id,
+                        "entities": entities,
+                        "edges": created.iter().filter_map(|c| c.edge.as_ref()).collect::<Vec<_>>(),
+                    })));
+                    for c in &created {
+                        let id = c.entity["id"].as_str().unwrap_or_default().to_string();
+                        for &n in plan.next(i) {
+                            if plan.accepts(n, &c.entity) && visited.insert((id.clone(), n)) {
+                                frontier.push((c.entity.clone(), n));
+                            }
+                        }
+                    }
+                }
+            }
+            // steps still waiting on entities were cut off by the depth limit
+            truncated |= !frontier.is_empty();
+
+            Ok(Some(json!({
+                "playbook_id": playbook_id,
+                "jobs": enqueued,
+                "entities": created_total,
+                "failed": failed,
+                "truncated": truncated,
+            })))
+        })
+    }
+}
+
+// Waits until every child enqueued so far has finished. Failed attempts are
+// final, children run with a single attempt. Listeners time out on quiet
+// streams, so slow children are reported every `HEARTBEAT_POLLS`.
+async fn wait_for_children(ctx: &JobContext) -> Result<HashMap<Uuid, String>, JobError> {
+    let mut polls: u32 = 0;
+    loop {
+        polls = polls.wrapping_add(1);
+        let statuses = jobs::child_statuses(&ctx.pool, ctx.job.job_id).await?;
+        let running = statuses
+            .iter()
+            .filter(|(_, s)| matches!(s.as_str(), "enqueued" | "leased" | "running"))
+            .count();
+        if running == 0 {
+            return Ok(statuses.into_iter().collect());
+        }
+        if polls % HEARTBEAT_POLLS == 0 {
+            let _ = ctx.records.send(Record::Progress(json!({
+                "note": format!("waiting on {running} transform(s)"),
+                "running": running,
+            })));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
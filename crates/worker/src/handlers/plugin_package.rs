@@ -0,0 +1,191 @@
+use common::plugin_cli::PluginCli;
+use common::plugin_packages::{self, PluginPackage};
+use common::plugin_trust;
+use futures_util::future::BoxFuture;
+use log::info;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use super::plugin_sync::sync;
+use super::{JobContext, JobError, JobHandler};
+
+// Installs a plugin package into `<root>/<name>/plugins`, the registry sync
+// that follows lists it from there on the confined executor
+pub struct PluginInstallHandler {
+    pub cli: PluginCli,
+    pub root: PathBuf,
+}
+
+// Deletes a package's directory and syncs the registry without it
+pub struct PluginUninstallHandler {
+    pub cli: PluginCli,
+}
+
+async fn load_package(ctx: &JobContext, status: &str) -> Result<PluginPackage, JobError> {
+    let package_id = ctx.job.payload["package_id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| JobError::Invalid("plugin job has no package_id".into()))?;
+    let package = plugin_packages::get(&ctx.pool, package_id)
+        .await?
+        .ok_or_else(|| JobError::Invalid(format!("plugin package {package_id} not found")))?;
+    if Some(package.org_id) != ctx.job.org_id {
+        return Err(JobError::Invalid(
+            "plugin package belongs to another organization".into(),
+        ));
+    }
+    if package.status != status {
+        return Err(JobError::Invalid(format!(
+            "plugin package {package_id} is {}",
+            package.status
+        )));
+    }
+    Ok(package)
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("`{program}` unavailable: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`{program} {}` failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Fetches the package into `dest`, returning the installed revision.
+/// `dest` is the package's `plugins/`, where `ob` run from the package's
+/// directory loads it from.
+async fn fetch(ctx: &JobContext, package: &PluginPackage, dest: &Path) -> Result<String, String> {
+    let dest_str = dest.to_string_lossy();
+    match package.source_kind.as_str() {
+        "git" => {
+            let mut args = vec!["clone", "--depth", "1"];
+            if let Some(git_ref) = package.git_ref.as_deref() {
+                args.extend(["--branch", git_ref]);
+            }
+            args.extend(["--", package.source.as_str(), &dest_str]);
+            run("git", &args).await?;
+            run("git", &["-C", &dest_str, "rev-parse", "HEAD"]).await
+        }
+        "archive" => {
+            let archive = plugin_packages::archive(&ctx.pool, package.package_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("uploaded archive is gone")?;
+            let upload = dest.with_extension("upload");
+            tokio::fs::write(&upload, &archive)
+                .await
+                .map_err(|e| format!("writing archive: {e}"))?;
+            tokio::fs::create_dir_all(dest)
+                .await
+                .map_err(|e| format!("creating {dest_str}: {e}"))?;
+            let upload_str = upload.to_string_lossy();
+            // tar and unzip both refuse members that would land outside `dest`
+            let unpacked = if archive.starts_with(b"PK") {
+                run("unzip", &["-q", &upload_str, "-d", &dest_str]).await
+            } else {
+                run("tar", &["-xf", &upload_str, "-C", &dest_str]).await
+            };
+            let _ = tokio::fs::remove_file(&upload).await;
+            unpacked.map(|_| plugin_packages::sha256_hex(&archive))
+        }
+        other => Err(format!("unknown package source `{other}`")),
+    }
+}
+
+// A signed package only installs if what was fetched is what was signed
+fn check_signature(package: &PluginPackage, revision: String) -> Result<String, String> {
+    match (package.signature.as_deref(), package.signer_key.as_deref()) {
+        (Some(signature), Some(key)) if !plugin_trust::verify(key, signature, &revision) => {
+            Err(format!("signature does not match revision {revision}"))
+        }
+        _ => Ok(revision),
+    }
+}
+
+impl JobHandler for PluginInstallHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let package = load_package(ctx, "pending").await?;
+            tokio::fs::create_dir_all(&self.root)
+                .await
+                .map_err(|e| JobError::Invalid(format!("creating {}: {e}", self.root.display())))?;
+            let dest = self.root.join(&package.name);
+            // never clobber a plugin that was installed by hand
+            if tokio::fs::try_exists(&dest).await.unwrap_or(true) {
+                let e = format!("{} already exists", dest.display());
+                plugin_packages::mark_failed(&ctx.pool, package.package_id, &e).await?;
+                return Err(JobError::Invalid(format!("plugin install failed: {e}")));
+            }
+            tokio::fs::create_dir_all(&dest)
+                .await
+                .map_err(|e| JobError::Invalid(format!("creating {}: {e}", dest.display())))?;
+            let fetched = fetch(ctx, &package, &dest.join("plugins")).await;
+            let revision = match fetched.and_then(|rev| check_signature(&package, rev)) {
+                Ok(revision) => revision,
+                Err(e) => {
+                    let _ = tokio::fs::remove_dir_all(&dest).await;
+                    plugin_packages::mark_failed(&ctx.pool, package.package_id, &e).await?;
+                    return Err(JobError::Invalid(format!("plugin install failed: {e}")));
+                }
+            };
+            let path = dest.to_string_lossy().to_string();
+            plugin_packages::mark_installed(&ctx.pool, package.package_id, &path, &revision)
+                .await?;
+            info!("installed plugin package {} at {revision}", package.name);
+
+            let sync = sync(ctx, &self.cli, "install").await?;
+            let entities = plugin_packages::labels(&ctx.pool, package.package_id).await?;
+
+            Ok(Some(json!({
+                "package_id": package.package_id,
+                "name": package.name,
+                "revision": revision,
+                "entities": entities,
+                "sync_error": sync.error,
+            })))
+        })
+    }
+}
+
+impl JobHandler for PluginUninstallHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let package = load_package(ctx, "removing").await?;
+            if let Some(path) = package.path.as_deref() {
+                match tokio::fs::remove_dir_all(path).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(JobError::Invalid(format!("removing {path}: {e}")));
+                    }
+                }
+            }
+            plugin_packages::mark_removed(&ctx.pool, package.package_id).await?;
+            let sync = sync(ctx, &self.cli, "install").await?;
+            Ok(Some(json!({
+                "package_id": package.package_id,
+                "name": package.name,
+                "removed": sync.removed,
+                "sync_error": sync.error,
+            })))
+        })
+    }
+}
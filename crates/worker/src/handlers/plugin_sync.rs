@@ -0,0 +1,93 @@
+use common::plugin_cli::PluginCli;
+use common::plugin_packages;
+use common::plugin_registry::{self, PluginLister, PluginSync};
+use common::secrets::SecretEnv;
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+use std::path::PathBuf;
+
+use super::{JobContext, JobError, JobHandler};
+use crate::executor::{ObCommand, OutputStream};
+use crate::limits::Limits;
+use crate::vm;
+
+// Refreshes the plugin registry from the CLI installed on this worker
+pub struct PluginSyncHandler {
+    pub cli: PluginCli,
+}
+
+// Lists one package's plugins from its own directory on the confined
+// executor, its code never loads in the worker
+struct PackageLister {
+    dir: PathBuf,
+    limits: Limits,
+}
+
+impl PluginLister for PackageLister {
+    fn list<'a>(&'a self, args: &'a [&'a str]) -> BoxFuture<'a, Result<JsonValue, String>> {
+        Box::pin(async move {
+            let command = ObCommand::list(args).in_package(Some(self.dir.clone()));
+            // listings are read here, not streamed to the job's events
+            let (records, _) = tokio::sync::mpsc::unbounded_channel();
+            let out =
+                OutputStream::new(records, self.limits.max_output_bytes, SecretEnv::default());
+            vm::confined_executor()
+                .await
+                .execute(&command, &SecretEnv::default(), &self.limits, &out)
+                .await
+                .map_err(|e| format!("`ob {}` failed: {e}", args.join(" ")))?;
+            out.finish()
+                .ok_or_else(|| format!("`ob {}` printed no JSON", args.join(" ")))
+        })
+    }
+}
+
+/// Syncs the registry from the host's CLI and every installed package.
+pub(super) async fn sync(
+    ctx: &JobContext,
+    cli: &PluginCli,
+    trigger: &str,
+) -> Result<PluginSync, JobError> {
+    let mut listers: Vec<(Uuid, PackageLister)> = vec![];
+    for package in plugin_packages::installed(&ctx.pool).await? {
+        let Some(path) = package.path else {
+            continue;
+        };
+        let dir = std::path::absolute(&path)
+            .map_err(|e| JobError::Invalid(format!("plugin package path {path}: {e}")))?;
+        listers.push((
+            package.package_id,
+            PackageLister {
+                dir,
+                limits: ctx.limits,
+            },
+        ));
+    }
+    let packages: Vec<(Uuid, &dyn PluginLister)> = listers
+        .iter()
+        .map(|(id, lister)| (*id, lister as &dyn PluginLister))
+        .collect();
+    Ok(plugin_registry::sync(&ctx.pool, cli, &packages, trigger, Some(ctx.job.job_id)).await?)
+}
+
+impl JobHandler for PluginSyncHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let trigger = ctx.job.payload["trigger"].as_str().unwrap_or("manual");
+            let sync = sync(ctx, &self.cli, trigger).await?;
+            if let Some(error) = sync.error {
+                return Err(JobError::Invalid(format!("plugin sync failed: {error}")));
+            }
+            Ok(Some(json!({
+                "sync_id": sync.sync_id,
+                "added": sync.added,
+                "updated": sync.updated,
+                "removed": sync.removed,
+            })))
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use common::{artifacts, transform_cache};
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+
+use super::{JobContext, JobError, JobHandler};
+
+// Deletes the org's finished jobs (and their event streams and artifacts)
+// older than `older_than_days`, along with expired transform cache entries
+pub struct PurgeHandler;
+
+impl JobHandler for PurgeHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let Some(org_id) = ctx.job.org_id else {
+                return Err(JobError::Invalid("purge job has no organization".into()));
+            };
+            let days = ctx.job.payload["older_than_days"].as_i64().unwrap_or(30);
+
+            let mut tx = ctx.pool.begin().await?;
+            // artifact rows cascade with their job, offloaded files are
+            // collected from the pre-delete snapshot and removed after commit
+            let rows = sqlx::query!(
+                r#"
+                WITH purged AS (
+                    DELETE FROM jobs
+                     WHERE org_id = $1
+                       AND job_id <> $2
+                       AND status IN ('completed'::job_status, 'dead'::job_status, 'canceled'::job_status)
+                       AND finished_at < now() - make_interval(days => $3::int)
+                    RETURNING job_id
+                )
+                SELECT p.job_id::text AS "job_id!",
+                       ARRAY(SELECT a.uri FROM artifacts a
+                              WHERE a.job_id = p.job_id AND a.uri IS NOT NULL) AS "uris!: Vec<String>"
+                  FROM purged p
+                "#,
+                org_id,
+                ctx.job.job_id,
+                days as i32
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            let mut uris = Vec::new();
+            let purged: Vec<String> = rows
+                .into_iter()
+                .map(|r| {
+                    uris.extend(r.uris);
+                    r.job_id
+                })
+                .collect();
+
+            // job event streams are keyed by job id, events cascade
+            let streams = sqlx::query!(
+                r#"DELETE FROM event_streams WHERE category = 'job' AND key = ANY($1)"#,
+                &purged
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            artifacts::remove_offloaded(&uris).await;
+            let cache_purged = transform_cache::purge_expired(&ctx.pool, org_id).await?;
+
+            Ok(Some(json!({
+                "jobs_purged": purged.len(),
+                "streams_purged": streams.rows_affected(),
+                "files_purged": uris.len(),
+                "cache_entries_purged": cache_purged,
+                "older_than_days": days,
+            })))
+        })
+    }
+}
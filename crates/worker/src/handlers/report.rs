@@ -0,0 +1,66 @@
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+
+use super::{JobContext, JobError, JobHandler};
+
+// Case summary: entity counts per type, edge and event totals
+pub struct ReportHandler;
+
+impl JobHandler for ReportHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let graph_id = ctx.graph_id()?;
+
+            let entity_types: Vec<JsonValue> = sqlx::query!(
+                r#"
+                SELECT COALESCE(doc->>'entity_type', doc->'data'->>'label', doc->>'label') AS entity_type,
+                       COUNT(*)::BIGINT AS "count!"
+                  FROM entities_current
+                 WHERE graph_id = $1
+                   AND sys_to IS NULL
+                 GROUP BY 1
+                 ORDER BY 2 DESC
+                "#,
+                graph_id
+            )
+            .fetch_all(&ctx.pool)
+            .await?
+            .into_iter()
+            .map(|r| json!({ "entity_type": r.entity_type, "count": r.count }))
+            .collect();
+
+            let edges_count = sqlx::query_scalar!(
+                r#"SELECT COUNT(*)::BIGINT AS "count!" FROM edges_current WHERE graph_id = $1 AND sys_to IS NULL"#,
+                graph_id
+            )
+            .fetch_one(&ctx.pool)
+            .await?;
+
+            let events = sqlx::query!(
+                r#"
+                SELECT COUNT(*)::BIGINT AS "count!",
+                       MAX(e.recorded_at) AS last_activity
+                  FROM events e
+                  JOIN event_streams s ON s.stream_id = e.stream_id
+                 WHERE s.key = $1
+                "#,
+                graph_id.to_string()
+            )
+            .fetch_one(&ctx.pool)
+            .await?;
+
+            Ok(Some(json!({
+                "graph_id": graph_id,
+                "generated_at": Utc::now(),
+                "entity_types": entity_types,
+                "edges_count": edges_count,
+                "events_count": events.count,
+                "last_activity": events.last_activity,
+            })))
+        })
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::Utc;
+use common::eventstore::{self, AppendEvent};
+use common::findings::{self, Child};
+use common::identity::NaturalKeys;
+use common::layout::Layout;
+use common::persist::{self, PersistContext};
+use common::provenance::Origin;
+use common::schedules;
+use common::transform_cache::plugin_label;
+use futures_util::future::BoxFuture;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+use std::sync::Arc;
+
+use super::{JobContext, JobError, JobHandler};
+use crate::vm;
+
+// Re-runs a transform on an entity that has already been transformed once,
+// only findings that differ from the entity's current children are applied
+pub struct ScheduledRerunHandler {
+    pub keys: Arc<NaturalKeys>,
+}
+
+impl JobHandler for ScheduledRerunHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let graph_id = ctx.graph_id()?;
+            let schedule_id = ctx.job.payload["schedule_id"]
+                .as_str()
+                .and_then(|s| Uuid::parse_str(s).ok());
+            if let Some(schedule_id) = schedule_id {
+                let schedule = schedules::get_schedule(&ctx.pool, schedule_id).await?;
+                if !schedule
+                    .is_some_and(|s| Some(s.org_id) == ctx.job.org_id && s.graph_id == graph_id)
+                {
+                    return Err(JobError::Invalid(
+                        "schedule no longer exists on this case".into(),
+                    ));
+                }
+            }
+            let mut entity = ctx.job.payload["entity"].clone();
+            let entity_id = entity["id"]
+                .as_str()
+                .and_then(|s| Uuid::parse_str(s).ok())
+                .ok_or_else(|| JobError::Invalid("entity id is not a uuid".into()))?;
+            let transform = entity["transform"].as_str().unwrap_or_default().to_string();
+
+            // Run against the entity as it is now, not as it was scheduled
+            let current = sqlx::query_scalar!(
+                r#"
+                SELECT doc FROM entities_current
+                 WHERE graph_id = $1 AND entity_id = $2 AND sys_to IS NULL
+                "#,
+                graph_id,
+                entity_id
+            )
+            .fetch_optional(&ctx.pool)
+            .await?;
+            let Some(current) = current else {
+                if let (Some(schedule_id), Some(org_id)) = (schedule_id, ctx.job.org_id) {
+                    schedules::disable_schedule(&ctx.pool, org_id, schedule_id).await?;
+                }
+                return Err(JobError::Invalid(
+                    "scheduled entity no longer exists".into(),
+                ));
+            };
+            match (
+                entity.get_mut("data").and_then(|d| d.as_object_mut()),
+                current["data"].as_object(),
+            ) {
+                (Some(data), Some(fresh)) => {
+                    for (k, v) in fresh {
+                        data.insert(k.clone(), v.clone());
+                    }
+                }
+                (None, Some(_)) => entity["data"] = current["data"].clone(),
+                _ => {}
+            }
+            if let Some(position) = current.get("position") {
+                entity["position"] = position.clone();
+            }
+
+            // Hand `ob` the same payload shape a user triggered transform has
+            let payload = json!({
+                "action": "transform:entity",
+                "graph_id": graph_id,
+                "entity": entity,
+                "actor_id": ctx.actor_id,
+            });
+            let Some(outputs) = vm::execute_job(
+                &ctx.pool,
+                ctx.job.org_id,
+                ctx.actor_id,
+                &payload,
+                &ctx.limits,
+                &ctx.records,
+            )
+            .await?
+            else {
+                return Ok(None);
+            };
+
+            let children = findings::children(&ctx.pool, graph_id, entity_id).await?;
+            let items = findings::output_items(&outputs);
+            let diff = findings::diff(&children, &items, &transform);
+
+            let stream_key = schedule_id.unwrap_or(ctx.job.job_id).to_string();
+            let created = persist::create_children(
+                &ctx.pool,
+                PersistContext {
+                    graph_id,
+                    job_id: ctx.job.job_id,
+                    actor_id: ctx.actor_id,
+                    layout: Layout::default(),
+                    keys: &self.keys,
+                },
+                entity_id,
+                &current["position"],
+                Origin {
+                    transform: &transform,
+                    plugin: plugin_label(&current),
+                    plugin_version: None,
+                    retrieved_at: Utc::now(),
+                },
+                &diff.new,
+            )
+            .await?;
+            // a finding that resolved to an entity the source already links
+            // to isn't new to it
+            let created: Vec<_> = created.into_iter().filter(|c| c.edge.is_some()).collect();
+            for child in created.iter().map(|c| c.child()) {
+                append_finding(ctx, &stream_key, "finding:new", schedule_id, &child).await?;
+            }
+            for child in diff.vanished.iter() {
+                append_finding(ctx, &stream_key, "finding:vanished", schedule_id, child).await?;
+            }
+
+            Ok(Some(json!({
+                "schedule_id": schedule_id,
+                "new": created.iter().map(|c| &c.entity["id"]).collect::<Vec<_>>(),
+                "vanished": diff.vanished.iter().map(|c| c.entity_id).collect::<Vec<_>>(),
+                "unchanged": diff.unchanged,
+            })))
+        })
+    }
+}
+
+async fn append_finding(
+    ctx: &JobContext,
+    stream_key: &str,
+    event_type: &str,
+    schedule_id: Option<Uuid>,
+    child: &Child,
+) -> Result<(), JobError> {
+    eventstore::append_event(
+        &ctx.pool,
+        AppendEvent {
+            category: "schedule".into(),
+            key: stream_key.to_string(),
+            event_type: event_type.into(),
+            payload: json!({
+                "schedule_id": schedule_id,
+                "job_id": ctx.job.job_id,
+                "graph_id": ctx.job.graph_id,
+                "finding": child,
+            }),
+            valid_from: Utc::now(),
+            valid_to: None,
+            correlation_id: Some(ctx.job.job_id),
+            causation_id: None,
+            expected_version: None,
+            actor_id: ctx.actor_id,
+        },
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use chrono::Utc;
+use common::findings;
+use common::identity::NaturalKeys;
+use common::job_output::Record;
+use common::layout::Layout;
+use common::persist::{self, PersistContext};
+use common::provenance::Origin;
+use common::transform_cache::{self, CacheStamp, plugin_label};
+use futures_util::future::BoxFuture;
+use log::warn;
+use serde_json::{Value as JsonValue, json};
+use sqlx::types::Uuid;
+use std::sync::Arc;
+
+use super::{JobContext, JobError, JobHandler};
+use crate::vm;
+
+// Plugin transforms, executed by `ob run -T` inside the configured sandbox.
+// Results of jobs enqueued with a `cache` stamp are kept for identical runs.
+// Findings are saved under the entity the transform ran on and reported as a
+// `saved` progress record, transforms a playbook started are saved by it.
+pub struct TransformHandler {
+    pub keys: Arc<NaturalKeys>,
+}
+
+impl TransformHandler {
+    async fn cache(&self, ctx: &JobContext, data: &JsonValue) {
+        let Some(org_id) = ctx.job.org_id else {
+            return;
+        };
+        let Some(stamp) = CacheStamp::verified(org_id, ctx.actor_id, &ctx.job.payload) else {
+            return;
+        };
+        // results fetched with secrets are only ever kept per user
+        if stamp.actor_id.is_none()
+            && transform_cache::reads_secrets(&ctx.pool, org_id, &ctx.job.payload["entity"])
+                .await
+                .unwrap_or(true)
+        {
+            return;
+        }
+        if let Err(e) =
+            transform_cache::store(&ctx.pool, org_id, ctx.job.job_id, &stamp, data).await
+        {
+            warn!("caching result of job {} failed: {e}", ctx.job.job_id);
+        }
+    }
+
+    async fn save(&self, ctx: &JobContext, outputs: &JsonValue) -> Result<(), JobError> {
+        let (Some(graph_id), None) = (ctx.job.graph_id, ctx.job.parent_id) else {
+            return Ok(());
+        };
+        let payload = &ctx.job.payload;
+        let entity = &payload["entity"];
+        let Some(src_id) = entity["id"].as_str().and_then(|s| Uuid::parse_str(s).ok()) else {
+            return Ok(());
+        };
+        let created = persist::create_children(
+            &ctx.pool,
+            PersistContext {
+                graph_id,
+                job_id: ctx.job.job_id,
+                actor_id: ctx.actor_id,
+                layout: payload["layout"]
+                    .as_str()
+                    .and_then(Layout::parse)
+                    .unwrap_or_default(),
+                keys: &self.keys,
+            },
+            src_id,
+            &entity["position"],
+            Origin {
+                transform: entity["transform"].as_str().unwrap_or("transform"),
+                plugin: plugin_label(entity),
+                plugin_version: payload["plugin_version"].as_str(),
+                retrieved_at: Utc::now(),
+            },
+            &findings::output_items(outputs),
+        )
+        .await?;
+        // entities already on the canvas only gain an edge
+        let _ = ctx.records.send(Record::Progress(json!({
+            "note": "transform results saved",
+            "saved": true,
+            "results_count": created.len(),
+            "entities": created
+                .iter()
+                .filter(|c| !c.reused)
+                .map(|c| &c.entity)
+                .collect::<Vec<_>>(),
+            "edges": created.iter().filter_map(|c| c.edge.as_ref()).collect::<Vec<_>>(),
+        })));
+        Ok(())
+    }
+}
+
+impl JobHandler for TransformHandler {
+    fn run<'a>(
+        &'a self,
+        ctx: &'a JobContext,
+    ) -> BoxFuture<'a, Result<Option<JsonValue>, JobError>> {
+        Box::pin(async move {
+            let result = vm::execute_job(
+                &ctx.pool,
+                ctx.job.org_id,
+                ctx.actor_id,
+                &ctx.job.payload,
+                &ctx.limits,
+                &ctx.records,
+            )
+            .await?;
+            if let Some(data) = &result {
+                self.cache(ctx, data).await;
+                self.save(ctx, data).await?;
+            }
+            Ok(result)
+        })
+    }
+}
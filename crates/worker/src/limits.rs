@@ -0,0 +1,111 @@
+use common::jobs::JobKind;
+use log::error;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Resource limits a single job runs under.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+    pub memory_mib: u64,
+}
+
+impl Limits {
+    fn for_kind(kind: JobKind) -> Self {
+        let (timeout_secs, max_output_mib) = match kind {
+            JobKind::Transform | JobKind::ScheduledRerun => (120, 8),
+            JobKind::BulkImport => (600, 64),
+            JobKind::Export => (300, 256),
+            JobKind::Report => (120, 8),
+            JobKind::Purge => (600, 1),
+            // only waits on the transforms it enqueues, each under its own limits
+            JobKind::Playbook => (1800, 1),
+            // shells out to `ob` once per plugin lacking transforms
+            JobKind::PluginSync => (600, 1),
+            // cloning or unpacking, then a sync
+            JobKind::PluginInstall | JobKind::PluginUninstall => (900, 1),
+        };
+        Limits {
+            timeout: Duration::from_secs(timeout_secs),
+            max_output_bytes: max_output_mib * 1024 * 1024,
+            memory_mib: 1024,
+        }
+    }
+
+    fn apply(mut self, o: &LimitOverride) -> Self {
+        if let Some(secs) = o.timeout_secs {
+            self.timeout = Duration::from_secs(secs);
+        }
+        if let Some(kb) = o.max_output_kb {
+            self.max_output_bytes = (kb as usize).saturating_mul(1024);
+        }
+        if let Some(mib) = o.memory_mib {
+            self.memory_mib = mib;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+struct LimitOverride {
+    timeout_secs: Option<u64>,
+    max_output_kb: Option<u64>,
+    memory_mib: Option<u64>,
+}
+
+/// Per job kind defaults, overridden by `WORKER_KIND_LIMITS` and then by
+/// `WORKER_PLUGIN_LIMITS`, both JSON objects of
+/// `{"<key>": {"timeout_secs", "max_output_kb", "memory_mib"}}`. Plugin keys
+/// are an entity label, or `<label>/<transform>` for a single transform.
+#[derive(Debug, Default)]
+pub struct LimitsConfig {
+    kinds: HashMap<String, LimitOverride>,
+    plugins: HashMap<String, LimitOverride>,
+}
+
+impl LimitsConfig {
+    pub fn from_config(cfg: &common::config::AppConfig) -> Self {
+        LimitsConfig {
+            kinds: parse_overrides("WORKER_KIND_LIMITS", cfg.worker_kind_limits.as_deref()),
+            plugins: parse_overrides("WORKER_PLUGIN_LIMITS", cfg.worker_plugin_limits.as_deref()),
+        }
+    }
+
+    pub fn resolve(&self, kind: JobKind, payload: &JsonValue) -> Limits {
+        let mut limits = Limits::for_kind(kind);
+        if let Some(o) = self.kinds.get(kind.as_str()) {
+            limits = limits.apply(o);
+        }
+        if kind == JobKind::Playbook {
+            return limits;
+        }
+        let entity = &payload["entity"];
+        let label = entity["data"]["label"]
+            .as_str()
+            .or_else(|| entity["label"].as_str());
+        if let Some(label) = label {
+            if let Some(o) = self.plugins.get(label) {
+                limits = limits.apply(o);
+            }
+            if let Some(transform) = entity["transform"].as_str() {
+                if let Some(o) = self.plugins.get(&format!("{label}/{transform}")) {
+                    limits = limits.apply(o);
+                }
+            }
+        }
+        limits
+    }
+}
+
+fn parse_overrides(name: &str, raw: Option<&str>) -> HashMap<String, LimitOverride> {
+    let Some(raw) = raw.filter(|s| !s.trim().is_empty()) else {
+        return HashMap::new();
+    };
+    serde_json::from_str(raw).unwrap_or_else(|e| {
+        error!("ignoring invalid {name}: {e}");
+        HashMap::new()
+    })
+}
@@ -1,7 +1,15 @@
 use env_logger::Env;
 use log::info;
+use std::sync::Arc;
 
+mod artifacts;
+mod executor;
+mod firecracker;
+mod handlers;
+mod limits;
 mod poller;
+mod sandbox;
+mod scheduler;
 mod vm;
 
 #[tokio::main]
@@ -20,11 +28,31 @@ async fn main() {
     let lease_secs = cfg.worker_lease_seconds.unwrap_or(300);
     let batch = cfg.worker_batch.unwrap_or(8);
     let tick = cfg.worker_tick_ms.unwrap_or(500);
+    let scheduler_tick = cfg.scheduler_tick_secs.unwrap_or(30);
+    let plugin_sync = cfg.plugin_sync_interval_secs.unwrap_or(3600);
 
     info!(
         "OSIB worker starting: owner={} lease={}s batch={} tick={}ms",
         owner, lease_secs, batch, tick
     );
 
-    poller::run_loop(pool, owner, lease_secs, batch, tick).await;
+    tokio::spawn(scheduler::run_loop(
+        pool.clone(),
+        scheduler_tick,
+        plugin_sync,
+    ));
+
+    // transform results are matched to existing entities by these
+    let keys = Arc::new(common::identity::NaturalKeys::load(cfg).await);
+    let registry = Arc::new(handlers::Registry::new(
+        limits::LimitsConfig::from_config(cfg),
+        keys,
+        common::plugin_cli::PluginCli::from_config(cfg),
+        cfg.plugin_install_dir
+            .clone()
+            .unwrap_or_else(|| "plugin-packages".into())
+            .into(),
+    ));
+    let store = common::artifacts::ArtifactStore::from_config(cfg);
+    poller::run_loop(pool, registry, store, owner, lease_secs, batch, tick).await;
 }
@@ -1,13 +1,26 @@
 use chrono::Utc;
+use common::artifacts::ArtifactStore;
+use common::job_output::Record;
 use common::{eventstore, jobs};
 use log::{error, info, warn};
 use serde_json::json;
 use sqlx::PgPool;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc;
 
-use crate::vm;
+use crate::artifacts;
+use crate::handlers::{JobContext, Registry};
 
-pub async fn run_loop(pool: PgPool, owner: String, lease_secs: i32, batch: i64, tick_ms: u64) {
+pub async fn run_loop(
+    pool: PgPool,
+    registry: Arc<Registry>,
+    store: ArtifactStore,
+    owner: String,
+    lease_secs: i32,
+    batch: i64,
+    tick_ms: u64,
+) {
     info!(
         "Worker poller started owner={} lease={}s batch={} tick={}ms",
         owner, lease_secs, batch, tick_ms
@@ -22,13 +35,15 @@ pub async fn run_loop(pool: PgPool, owner: String, lease_secs: i32, batch: i64,
                 for job in leased.drain(..) {
                     let pool_clone = pool.clone();
                     let owner_clone = owner.clone();
+                    let registry = registry.clone();
+                    let store = store.clone();
                     tokio::spawn(async move {
-                        let actor_id = Some(
+                        let actor_id = Some(job.actor_id.unwrap_or_else(|| {
                             job.payload
                                 .get("actor_id")
                                 .and_then(|v| v.as_i64())
-                                .unwrap_or(0),
-                        );
+                                .unwrap_or(0)
+                        }));
 
                         if let Err(e) = jobs::start_job(&pool_clone, job.job_id, &owner_clone).await
                         {
@@ -56,17 +71,80 @@ pub async fn run_loop(pool: PgPool, owner: String, lease_secs: i32, batch: i64,
                             error!("append job progress event failed {}: {}", job.job_id, e);
                         }
 
-                        let res = vm::execute_job(&job.payload).await;
+                        let (records, mut rx) = mpsc::unbounded_channel::<Record>();
+                        let forward_pool = pool_clone.clone();
+                        let forward_store = store.clone();
+                        let job_id = job.job_id;
+                        let job_key = job.job_id.to_string();
+                        let forwarder = tokio::spawn(async move {
+                            while let Some(record) = rx.recv().await {
+                                let payload = match &record {
+                                    Record::Artifact(data) => {
+                                        artifacts::record_event(
+                                            &forward_store,
+                                            &forward_pool,
+                                            job_id,
+                                            data,
+                                        )
+                                        .await
+                                    }
+                                    _ => record.to_event(),
+                                };
+                                if let Err(e) = eventstore::append_event(
+                                    &forward_pool,
+                                    eventstore::AppendEvent {
+                                        category: "job".into(),
+                                        key: job_key.clone(),
+                                        event_type: "job:event".into(),
+                                        payload,
+                                        valid_from: Utc::now(),
+                                        valid_to: None,
+                                        correlation_id: None,
+                                        causation_id: None,
+                                        expected_version: None,
+                                        actor_id,
+                                    },
+                                )
+                                .await
+                                {
+                                    error!(
+                                        "append job {} event failed {job_key}: {e}",
+                                        record.kind()
+                                    );
+                                }
+                            }
+                        });
+
+                        let limits = registry.limits_for(&job);
+                        let ctx = JobContext {
+                            pool: pool_clone.clone(),
+                            job,
+                            actor_id,
+                            records,
+                            limits,
+                        };
+                        let res = registry.dispatch(&ctx).await;
+                        let JobContext { job, records, .. } = ctx;
+                        // flush streamed records so they land before the result
+                        drop(records);
+                        let _ = forwarder.await;
                         match res {
                             Ok(maybe_json) => {
                                 if let Some(data) = maybe_json {
+                                    let payload = artifacts::result_event(
+                                        &store,
+                                        &pool_clone,
+                                        job.job_id,
+                                        data,
+                                    )
+                                    .await;
                                     if let Err(e) = eventstore::append_event(
                                         &pool_clone,
                                         eventstore::AppendEvent {
                                             category: "job".into(),
                                             key: job.job_id.to_string(),
                                             event_type: "job:event".into(),
-                                            payload: json!({"type":"result","data": data}),
+                                            payload,
                                             valid_from: Utc::now(),
                                             valid_to: None,
                                             correlation_id: None,
@@ -98,7 +176,7 @@ pub async fn run_loop(pool: PgPool, owner: String, lease_secs: i32, batch: i64,
                                         category: "job".into(),
                                         key: job.job_id.to_string(),
                                         event_type: "job:event".into(),
-                                        payload: json!({"type":"error","data":{"message": e.to_string()}}),
+                                        payload: json!({"type":"error","data":{"message": e.to_string(), "reason": e.reason()}}),
                                         valid_from: Utc::now(),
                                         valid_to: None,
                                         correlation_id: None,
@@ -115,8 +193,14 @@ pub async fn run_loop(pool: PgPool, owner: String, lease_secs: i32, batch: i64,
                                     );
                                 }
 
-                                let _ =
-                                    jobs::fail_job(&pool_clone, job.job_id, &owner_clone, 10).await;
+                                let _ = jobs::fail_job(
+                                    &pool_clone,
+                                    job.job_id,
+                                    &owner_clone,
+                                    10,
+                                    e.reason(),
+                                )
+                                .await;
                             }
                         }
                     });
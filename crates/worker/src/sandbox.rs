@@ -0,0 +1,613 @@
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use common::secrets::SecretEnv;
+
+use crate::executor::{Executor, ObCommand, OutputStream, run_ob};
+use crate::limits::Limits;
+use crate::vm::VmError;
+
+// Syscalls an `ob` transform never needs and that widen the kernel attack
+// surface or let it escape the namespaces, answered with EPERM
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_userfaultfd,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_acct,
+    libc::SYS_quotactl,
+    // the new mount API could undo the read-only root as well as `mount`
+    libc::SYS_mount_setattr,
+    libc::SYS_open_tree,
+    libc::SYS_move_mount,
+    libc::SYS_fsopen,
+    libc::SYS_fsconfig,
+    libc::SYS_fsmount,
+    libc::SYS_fspick,
+];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+#[derive(Debug, Clone)]
+pub struct SandboxLimits {
+    pub memory_mib: u64,
+    pub cpus: u32,
+    pub pids: u32,
+    pub timeout: Duration,
+    pub network: bool,
+    pub cgroup_root: PathBuf,
+}
+
+impl SandboxLimits {
+    pub fn from_config(cfg: &common::config::AppConfig) -> Self {
+        SandboxLimits {
+            memory_mib: cfg.sandbox_memory_mib.unwrap_or(1024),
+            cpus: cfg.sandbox_cpus.unwrap_or(1).max(1),
+            pids: cfg.sandbox_pids.unwrap_or(128),
+            timeout: Duration::from_secs(cfg.sandbox_timeout_secs.unwrap_or(120)),
+            network: cfg.sandbox_network.unwrap_or(true),
+            cgroup_root: PathBuf::from(
+                cfg.sandbox_cgroup_root
+                    .clone()
+                    .unwrap_or_else(|| "/sys/fs/cgroup/osib".to_string()),
+            ),
+        }
+    }
+
+    // The configured sandbox caps are an upper bound, a job may ask for less
+    fn for_job(&self, job: &Limits) -> Self {
+        SandboxLimits {
+            memory_mib: self.memory_mib.min(job.memory_mib),
+            timeout: self.timeout.min(job.timeout),
+            ..self.clone()
+        }
+    }
+}
+
+/// Runs `ob` in fresh user, mount, pid, IPC and UTS (and optionally network)
+/// namespaces with its own `/proc`, a private tmpfs `/tmp`, `no_new_privs`,
+/// a seccomp deny list and a cgroup v2 leaf for memory, CPU and pid limits.
+/// Hosts without a delegated cgroup subtree fall back to rlimits.
+pub struct SandboxExecutor {
+    limits: SandboxLimits,
+}
+
+impl SandboxExecutor {
+    pub fn new(limits: SandboxLimits) -> Self {
+        SandboxExecutor { limits }
+    }
+}
+
+impl Executor for SandboxExecutor {
+    fn name(&self) -> &'static str {
+        "sandbox"
+    }
+
+    fn execute<'a>(
+        &'a self,
+        command: &'a ObCommand,
+        secrets: &'a SecretEnv,
+        job_limits: &'a Limits,
+        out: &'a OutputStream,
+    ) -> BoxFuture<'a, Result<(), VmError>> {
+        Box::pin(async move {
+            let limits = self.limits.for_job(job_limits);
+            let cgroup = Cgroup::create(&limits);
+            let plan = ChildPlan::new(&limits, cgroup.as_ref(), command.package.as_deref())
+                .map_err(|e| VmError::Launch(format!("sandbox setup error: {e}")))?;
+
+            let mut cmd = Command::new("ob");
+            cmd.args(&command.args)
+                .env_clear()
+                .env("PATH", std::env::var("PATH").unwrap_or_default())
+                .env("HOME", "/tmp")
+                .env("TMPDIR", "/tmp")
+                .env("LANG", "C.UTF-8")
+                .envs(secrets.iter());
+            // SAFETY: the closure runs between fork and exec, it only makes
+            // raw syscalls on memory prepared by `ChildPlan` before the fork
+            unsafe {
+                cmd.pre_exec(move || plan.apply());
+            }
+            info!("executing job in sandbox (cgroup: {})", cgroup.is_some());
+            let mut result = run_ob(cmd, Some(limits.timeout), out).await;
+            if let Some(cgroup) = cgroup {
+                if matches!(result, Err(VmError::Launch(_))) && cgroup.oom_killed() {
+                    result = Err(VmError::Oom);
+                }
+                cgroup.remove();
+            }
+            result
+        })
+    }
+}
+
+struct Cgroup {
+    path: PathBuf,
+    procs: File,
+}
+
+impl Cgroup {
+    // One leaf per run under the delegated root, e.g. /sys/fs/cgroup/osib/<uuid>
+    fn create(limits: &SandboxLimits) -> Option<Cgroup> {
+        let path = limits.cgroup_root.join(Uuid::new_v4().to_string());
+        let setup = || -> io::Result<File> {
+            fs::create_dir(&path)?;
+            fs::write(
+                path.join("memory.max"),
+                (limits.memory_mib * 1024 * 1024).to_string(),
+            )?;
+            fs::write(path.join("memory.swap.max"), "0").ok();
+            fs::write(
+                path.join("cpu.max"),
+                format!("{} 100000", limits.cpus as u64 * 100_000),
+            )?;
+            fs::write(path.join("pids.max"), limits.pids.to_string())?;
+            File::options().write(true).open(path.join("cgroup.procs"))
+        };
+        match setup() {
+            Ok(procs) => Some(Cgroup { path, procs }),
+            Err(e) => {
+                warn!(
+                    "cgroup unavailable under {}, using rlimits: {e}",
+                    limits.cgroup_root.display()
+                );
+                fs::remove_dir(&path).ok();
+                None
+            }
+        }
+    }
+
+    // memory.events counts OOM kills inside the leaf
+    fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .map(|events| {
+                events.lines().any(|l| {
+                    l.strip_prefix("oom_kill ")
+                        .and_then(|n| n.trim().parse::<u64>().ok())
+                        .is_some_and(|n| n > 0)
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    fn remove(self) {
+        drop(self.procs);
+        if let Err(e) = fs::remove_dir(&self.path) {
+            warn!("failed to remove cgroup {}: {e}", self.path.display());
+        }
+    }
+}
+
+// Everything the child needs after fork, allocated up front since only
+// async-signal-safe calls are allowed in `pre_exec`
+struct ChildPlan {
+    cgroup_procs: Option<libc::c_int>,
+    memory_bytes: u64,
+    cpu_secs: u64,
+    clone_flags: libc::c_int,
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    setgroups_path: CString,
+    uid_map_path: CString,
+    gid_map_path: CString,
+    root: CString,
+    // flags of `/` a remount has to keep, the kernel locks them in a user
+    // namespace
+    root_flags: libc::c_ulong,
+    dev_null: CString,
+    hidden: Vec<CString>,
+    proc: CString,
+    procfs: CString,
+    tmp: CString,
+    tmpfs: CString,
+    tmpfs_opts: CString,
+    // where `ob` starts, a package's directory or the private `/tmp`
+    workdir: CString,
+    filter: Vec<libc::sock_filter>,
+}
+
+impl ChildPlan {
+    fn new(
+        limits: &SandboxLimits,
+        cgroup: Option<&Cgroup>,
+        package: Option<&Path>,
+    ) -> Result<Self, std::ffi::NulError> {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let mut clone_flags = libc::CLONE_NEWUSER
+            | libc::CLONE_NEWNS
+            | libc::CLONE_NEWPID
+            | libc::CLONE_NEWIPC
+            | libc::CLONE_NEWUTS;
+        if !limits.network {
+            clone_flags |= libc::CLONE_NEWNET;
+        }
+        Ok(ChildPlan {
+            cgroup_procs: cgroup.map(|c| c.procs.as_raw_fd()),
+            memory_bytes: limits.memory_mib * 1024 * 1024,
+            cpu_secs: limits.timeout.as_secs().max(1),
+            clone_flags,
+            // keep our own ids inside the namespace so `ob` execs without caps
+            uid_map: format!("{uid} {uid} 1").into_bytes(),
+            gid_map: format!("{gid} {gid} 1").into_bytes(),
+            setgroups_path: CString::new("/proc/self/setgroups")?,
+            uid_map_path: CString::new("/proc/self/uid_map")?,
+            gid_map_path: CString::new("/proc/self/gid_map")?,
+            root: CString::new("/")?,
+            root_flags: root_flags(),
+            dev_null: CString::new("/dev/null")?,
+            hidden: env_files()
+                .into_iter()
+                .map(|p| CString::new(p.into_os_string().into_encoded_bytes()))
+                .collect::<Result<_, _>>()?,
+            proc: CString::new("/proc")?,
+            procfs: CString::new("proc")?,
+            tmp: CString::new("/tmp")?,
+            tmpfs: CString::new("tmpfs")?,
+            tmpfs_opts: CString::new("mode=1777,size=256m")?,
+            workdir: match package {
+                Some(dir) => CString::new(dir.as_os_str().as_encoded_bytes())?,
+                None => CString::new("/tmp")?,
+            },
+            filter: seccomp_filter(),
+        })
+    }
+
+    fn apply(&self) -> io::Result<()> {
+        // Join the cgroup while we still have our own credentials, the
+        // worker may sit outside the delegated subtree so keep rlimits handy
+        let joined = match self.cgroup_procs {
+            Some(fd) => write_fd(fd, b"0").is_ok(),
+            None => false,
+        };
+        if !joined {
+            set_rlimit(libc::RLIMIT_AS, self.memory_bytes)?;
+        }
+        set_rlimit(libc::RLIMIT_CPU, self.cpu_secs)?;
+        set_rlimit(libc::RLIMIT_CORE, 0)?;
+
+        check(unsafe { libc::unshare(self.clone_flags) })?;
+        write_file(&self.setgroups_path, b"deny")?;
+        write_file(&self.uid_map_path, &self.uid_map)?;
+        write_file(&self.gid_map_path, &self.gid_map)?;
+        enter_pid_namespace()?;
+
+        check(unsafe {
+            libc::mount(
+                std::ptr::null(),
+                self.root.as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            )
+        })?;
+        // The worker's `.env` holds the vault key and database url, the rest
+        // of the host stays readable for `ob` and its plugins but not writable
+        for path in &self.hidden {
+            check(unsafe {
+                libc::mount(
+                    self.dev_null.as_ptr(),
+                    path.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND,
+                    std::ptr::null(),
+                )
+            })?;
+        }
+        // The host's `/proc` lists the worker and its environment, the one of
+        // the new pid namespace only has the sandbox's own processes
+        check(unsafe {
+            libc::mount(
+                self.procfs.as_ptr(),
+                self.proc.as_ptr(),
+                self.procfs.as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                std::ptr::null(),
+            )
+        })?;
+        self.read_only_root()?;
+        check(unsafe {
+            libc::mount(
+                self.tmpfs.as_ptr(),
+                self.tmp.as_ptr(),
+                self.tmpfs.as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                self.tmpfs_opts.as_ptr().cast(),
+            )
+        })?;
+        check(unsafe { libc::chdir(self.workdir.as_ptr()) })?;
+
+        check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+        let prog = libc::sock_fprog {
+            len: self.filter.len() as libc::c_ushort,
+            filter: self.filter.as_ptr() as *mut libc::sock_filter,
+        };
+        check(unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            )
+        })?;
+        Ok(())
+    }
+
+    fn read_only_root(&self) -> io::Result<()> {
+        let attr = libc::mount_attr {
+            attr_set: libc::MOUNT_ATTR_RDONLY,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                self.root.as_ptr(),
+                libc::AT_RECURSIVE,
+                &attr as *const libc::mount_attr,
+                std::mem::size_of::<libc::mount_attr>(),
+            )
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+        // kernels before 5.12 can only remount `/` itself, not the mounts
+        // below it
+        if io::Error::last_os_error().raw_os_error() != Some(libc::ENOSYS) {
+            return Err(io::Error::last_os_error());
+        }
+        check(unsafe {
+            libc::mount(
+                std::ptr::null(),
+                self.root.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | self.root_flags,
+                std::ptr::null(),
+            )
+        })
+    }
+}
+
+// `unshare` only puts the children of the caller in the new pid namespace.
+// The forked child returns to become its init and exec `ob`, the caller
+// stays behind to pass on how it exited and never returns.
+fn enter_pid_namespace() -> io::Result<()> {
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        // killing the process the worker spawned takes the namespace with it
+        return check(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0) });
+    }
+    // std reads the exec error pipe until every copy is closed, only the
+    // child execs and closes its own
+    if unsafe { libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0) } != 0 {
+        for fd in 3..1024 {
+            unsafe { libc::close(fd) };
+        }
+    }
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            unsafe { libc::_exit(1) };
+        }
+    }
+    unsafe {
+        if libc::WIFSIGNALED(status) {
+            let signal = libc::WTERMSIG(status);
+            libc::signal(signal, libc::SIG_DFL);
+            libc::kill(libc::getpid(), signal);
+            libc::_exit(128 + signal);
+        }
+        libc::_exit(libc::WEXITSTATUS(status))
+    }
+}
+
+fn root_flags() -> libc::c_ulong {
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c"/".as_ptr(), &mut st) } != 0 {
+        return 0;
+    }
+    [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ]
+    .into_iter()
+    .filter(|(st_flag, _)| st.f_flag & st_flag != 0)
+    .fold(0, |flags, (_, ms_flag)| flags | ms_flag)
+}
+
+// The `.env` files `config::cfg` may have loaded, dotenvy looks in the
+// working directory and its parents
+fn env_files() -> Vec<PathBuf> {
+    let Ok(cwd) = std::env::current_dir() else {
+        return vec![];
+    };
+    cwd.ancestors()
+        .map(|dir| dir.join(".env"))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let stmt = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    // offsets into `struct seccomp_data`
+    let nr_offset = 0;
+    let arch_offset = 4;
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    let mut checks: Vec<(u32, u32)> = Vec::new();
+    // x32 syscalls share the x86_64 arch tag, refuse them outright
+    #[cfg(target_arch = "x86_64")]
+    checks.push((libc::BPF_JGE, 0x4000_0000));
+    checks.extend(DENIED_SYSCALLS.iter().map(|nr| (libc::BPF_JEQ, *nr as u32)));
+
+    let mut filter = vec![
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, arch_offset),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH,
+            1,
+            0,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, nr_offset),
+    ];
+    // every check jumps to the trailing deny, past the allow
+    let count = checks.len();
+    for (i, (op, k)) in checks.into_iter().enumerate() {
+        let to_deny = (count - i) as u8;
+        filter.push(jump(libc::BPF_JMP | op | libc::BPF_K, k, to_deny, 0));
+    }
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    filter.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+    filter
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn set_rlimit(resource: libc::__rlimit_resource_t, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    check(unsafe { libc::setrlimit(resource, &limit) })
+}
+
+fn write_fd(fd: libc::c_int, data: &[u8]) -> io::Result<()> {
+    let n = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn write_file(path: &CString, data: &[u8]) -> io::Result<()> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let res = write_fd(fd, data);
+    unsafe { libc::close(fd) };
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    // `script` under `sh`, sandboxed the way the executor runs `ob`
+    fn sandboxed(script: &str) -> std::process::Command {
+        let limits = SandboxLimits {
+            memory_mib: 256,
+            cpus: 1,
+            pids: 64,
+            timeout: Duration::from_secs(60),
+            network: true,
+            cgroup_root: PathBuf::from("/nonexistent"),
+        };
+        let plan = ChildPlan::new(&limits, None, None).unwrap();
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c")
+            .arg(script)
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .stdin(Stdio::null());
+        unsafe {
+            cmd.pre_exec(move || plan.apply());
+        }
+        cmd
+    }
+
+    #[test]
+    fn worker_environ_is_out_of_reach() {
+        let worker = std::process::id();
+        let out = sandboxed(&format!(
+            "cat /proc/{worker}/environ || echo unreadable; cat /proc/1/cmdline"
+        ))
+        .output()
+        .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{out:?}");
+        assert!(stdout.starts_with("unreadable\n"), "{stdout}");
+        // the sandbox's shell is init of its own pid namespace
+        assert!(stdout.contains("sh\0-c"), "{stdout}");
+    }
+
+    #[test]
+    fn exit_status_is_passed_on() {
+        let status = sandboxed("exit 3").status().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn killing_the_child_ends_the_sandbox() {
+        let mut child = sandboxed("sleep 30")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let started = std::time::Instant::now();
+        child.kill().unwrap();
+        // stdout closes once nothing in the namespace is left holding it
+        let mut rest = Vec::new();
+        io::Read::read_to_end(&mut child.stdout.take().unwrap(), &mut rest).unwrap();
+        child.wait().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
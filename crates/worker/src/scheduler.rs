@@ -0,0 +1,127 @@
+use chrono::Utc;
+use common::jobs::{self, EnqueueError, NewJob};
+use common::plugin_registry;
+use common::schedules::{self, CronSpec, Schedule};
+use log::{error, info, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+
+// Recurring runs queue behind interactive transforms (default priority 100)
+const SCHEDULED_PRIORITY: i32 = 200;
+
+/// Enqueues a `scheduled_rerun` job for every schedule that comes due, and
+/// a `plugin_sync` job once the plugin registry is `plugin_sync_secs` old
+/// (never when 0). Several workers can run this loop, each run is claimed by
+/// exactly one.
+pub async fn run_loop(pool: PgPool, tick_secs: u64, plugin_sync_secs: u64) {
+    info!("Scheduler started tick={tick_secs}s plugin_sync={plugin_sync_secs}s");
+    loop {
+        if plugin_sync_secs > 0 {
+            if let Err(e) = enqueue_plugin_sync(&pool, plugin_sync_secs).await {
+                error!("plugin sync scheduling failed: {e}");
+            }
+        }
+        match schedules::due_schedules(&pool, 32).await {
+            Ok(due) => {
+                for schedule in due {
+                    if let Err(e) = enqueue_run(&pool, &schedule).await {
+                        error!("schedule {} run failed: {e}", schedule.schedule_id);
+                    }
+                }
+            }
+            Err(e) => error!("due_schedules error: {e}"),
+        }
+        tokio::time::sleep(Duration::from_secs(tick_secs)).await;
+    }
+}
+
+async fn enqueue_run(pool: &PgPool, schedule: &Schedule) -> Result<(), sqlx::Error> {
+    let next = CronSpec::parse(&schedule.cron)
+        .ok()
+        .and_then(|spec| spec.next_after(Utc::now()));
+    let Some(next) = next else {
+        warn!(
+            "disabling schedule {} with unusable spec `{}`",
+            schedule.schedule_id, schedule.cron
+        );
+        return schedules::disable_schedule(pool, schedule.org_id, schedule.schedule_id).await;
+    };
+    if !schedules::claim_run(pool, schedule.schedule_id, schedule.next_run_at, next).await? {
+        return Ok(());
+    }
+
+    // A slow plugin shouldn't pile up runs, skip this one while the last is live
+    if let Some(last_job_id) = schedule.last_job_id {
+        if let Some(job) = jobs::get_job(pool, last_job_id).await? {
+            if matches!(job.status.as_str(), "enqueued" | "leased" | "running") {
+                info!(
+                    "schedule {} skipped, job {last_job_id} still {}",
+                    schedule.schedule_id, job.status
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    let job = jobs::enqueue_org_job(
+        pool,
+        NewJob {
+            payload: json!({
+                "kind": "scheduled_rerun",
+                "schedule_id": schedule.schedule_id,
+                "entity": schedule.entity,
+            }),
+            priority: Some(SCHEDULED_PRIORITY),
+            max_attempts: None,
+            scheduled_at: None,
+            org_id: Some(schedule.org_id),
+            actor_id: schedule.created_by,
+            graph_id: Some(schedule.graph_id),
+            parent_id: None,
+        },
+    )
+    .await;
+    // a disabled plugin or a full queue skips this run, the next one retries
+    let job = match job {
+        Ok(job) => job,
+        Err(EnqueueError::Rejected(message)) => {
+            warn!("schedule {} skipped: {message}", schedule.schedule_id);
+            return Ok(());
+        }
+        Err(EnqueueError::Sql(e)) => return Err(e),
+    };
+    schedules::set_last_job(pool, schedule.schedule_id, job.job_id).await?;
+    info!(
+        "schedule {} enqueued job {}, next run {next}",
+        schedule.schedule_id, job.job_id
+    );
+    Ok(())
+}
+
+async fn enqueue_plugin_sync(pool: &PgPool, interval_secs: u64) -> Result<(), sqlx::Error> {
+    // failed syncs count too, their job already retried them
+    let due = match plugin_registry::last_attempt(pool).await? {
+        Some(at) => (Utc::now() - at).num_seconds() >= interval_secs as i64,
+        None => true,
+    };
+    if !due || plugin_registry::sync_pending(pool).await? {
+        return Ok(());
+    }
+    let job = jobs::enqueue_job(
+        pool,
+        NewJob {
+            payload: json!({ "kind": "plugin_sync", "trigger": "scheduled" }),
+            priority: Some(SCHEDULED_PRIORITY),
+            max_attempts: None,
+            scheduled_at: None,
+            org_id: None,
+            actor_id: None,
+            graph_id: None,
+            parent_id: None,
+        },
+    )
+    .await?;
+    info!("enqueued plugin sync job {}", job.job_id);
+    Ok(())
+}
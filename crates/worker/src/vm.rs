@@ -1,86 +1,174 @@
-use log::{debug, error, info};
+use common::plugin_packages;
+use common::plugin_registry;
+use common::plugin_trust::{self, Trust};
+use common::secrets::{self, SecretEnv, SecretsError, Vault};
+use common::transform_cache;
+use log::info;
 use serde_json::Value as JsonValue;
+use sqlx::PgPool;
+use std::path::PathBuf;
 use std::time::Duration;
-use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use crate::executor::{self, Executor, ObCommand, OutputStream, RecordSender};
+use crate::limits::Limits;
 
 #[derive(thiserror::Error, Debug)]
 pub enum VmError {
     #[error("vm launch error: {0}")]
     Launch(String),
+    #[error("vm timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("vm ran out of memory")]
+    Oom,
+    #[error("output exceeded {0} bytes")]
+    OutputTooLarge(usize),
+    #[error("plugin {0} failed signature verification")]
+    Untrusted(String),
+    #[error("transform secrets unavailable: {0}")]
+    Secrets(String),
 }
 
-// Development: run local `ob` CLI with the job payload.
-// Production: placeholder until Firecracker integration is ready.
-pub async fn execute_job(payload: &JsonValue) -> Result<Option<JsonValue>, VmError> {
-    let cfg = common::config::CFG.get_or_init(common::config::cfg).await;
-    let env = cfg.environment.as_str();
+impl VmError {
+    /// Short machine readable cause, stored as the job's `failure_reason`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            VmError::Launch(_) => "launch_error",
+            VmError::Timeout(_) => "timeout",
+            VmError::Oom => "oom",
+            VmError::OutputTooLarge(_) => "output_too_large",
+            VmError::Untrusted(_) => "untrusted_plugin",
+            VmError::Secrets(_) => "secrets_unavailable",
+        }
+    }
+}
 
-    if env.eq_ignore_ascii_case("development") {
-        // Call: ob run -T '<payload-json>'
-        let payload_s = serde_json::to_string(payload)
-            .map_err(|e| VmError::Launch(format!("payload encode error: {}", e)))?;
+static EXECUTOR: OnceCell<Box<dyn Executor>> = OnceCell::const_new();
+static CONFINED: OnceCell<Box<dyn Executor>> = OnceCell::const_new();
+static VAULT: OnceCell<Option<Vault>> = OnceCell::const_new();
 
-        info!("executing job via ob CLI (dev)");
-        debug!("payload: {}", payload_s);
+// Trust of the plugin the payload runs, for the job's organization. Payloads
+// that don't name an entity label get no benefit of the doubt.
+async fn plugin_trust(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    payload: &JsonValue,
+) -> Result<(String, Trust), VmError> {
+    let Some(label) = transform_cache::plugin_label(&payload["entity"]) else {
+        return Ok((String::new(), Trust::Unsigned));
+    };
+    let trust = plugin_trust::for_label(pool, org_id, label)
+        .await
+        .map_err(|e| VmError::Launch(format!("plugin trust lookup error: {e}")))?;
+    Ok((label.to_string(), trust))
+}
 
-        let mut cmd = Command::new("ob");
-        cmd.arg("run").arg("-T").arg(payload_s);
-        let output = cmd
-            .output()
-            .await
-            .map_err(|e| VmError::Launch(e.to_string()))?;
+/// The executor untrusted code runs on, plugin packages are only ever listed
+/// and run here.
+pub async fn confined_executor() -> &'static dyn Executor {
+    let cfg = common::config::CFG.get_or_init(common::config::cfg).await;
+    CONFINED
+        .get_or_init(|| async { executor::confined(cfg) })
+        .await
+        .as_ref()
+}
 
-        if output.status.success() {
-            // Optionally log stdout for visibility during dev
-            if !output.stdout.is_empty() {
-                if let Ok(s) = String::from_utf8(output.stdout.clone()) {
-                    info!("ob stdout: {}", s.trim());
-                }
-            }
-            if !output.stderr.is_empty() {
-                if let Ok(s) = String::from_utf8(output.stderr.clone()) {
-                    debug!("ob stderr: {}", s.trim());
-                }
-            }
-            // Try to parse JSON result from stdout (entire body or last JSON-looking chunk)
-            let parsed = (|| {
-                let s = String::from_utf8(output.stdout.clone()).ok()?;
-                // Try entire stdout first
-                if let Ok(v) = serde_json::from_str::<JsonValue>(&s) {
-                    return Some(v);
-                }
-                // Heuristic: find first '{' or '[' and parse from there
-                if let Some(idx) = s.find('{').or_else(|| s.find('[')) {
-                    let tail = &s[idx..];
-                    if let Ok(v) = serde_json::from_str::<JsonValue>(tail.trim()) {
-                        return Some(v);
-                    }
-                }
-                // Fallback: last non-empty line
-                if let Some(last) = s.lines().rev().find(|l| !l.trim().is_empty()) {
-                    if let Ok(v) = serde_json::from_str::<JsonValue>(last.trim()) {
-                        return Some(v);
-                    }
-                }
-                None
-            })();
+// Directory of the job's organization's package that provides `label`, its
+// `plugins/` is the only package code the run loads
+async fn package_dir(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    label: &str,
+) -> Result<Option<PathBuf>, VmError> {
+    let Some(org_id) = org_id.filter(|_| !label.is_empty()) else {
+        return Ok(None);
+    };
+    let package = plugin_packages::for_label(pool, org_id, label)
+        .await
+        .map_err(|e| VmError::Launch(format!("plugin package lookup error: {e}")))?;
+    match package.and_then(|p| p.path) {
+        Some(path) => std::path::absolute(&path)
+            .map(Some)
+            .map_err(|e| VmError::Launch(format!("plugin package path error: {e}"))),
+        None => Ok(None),
+    }
+}
 
-            Ok(parsed)
-        } else {
-            let code = output.status.code().unwrap_or(-1);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!(
-                "ob failed (code={}): {} | {}",
-                code,
-                stdout.trim(),
-                stderr.trim()
-            );
-            Err(VmError::Launch(format!("ob exit code {}", code)))
-        }
+// Decrypts the secrets the payload's transform declares, for the org and
+// the user who started the job
+async fn job_secrets(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    actor_id: Option<i64>,
+    label: &str,
+    payload: &JsonValue,
+) -> Result<SecretEnv, VmError> {
+    let (Some(org_id), Some(transform)) = (org_id, payload["entity"]["transform"].as_str()) else {
+        return Ok(SecretEnv::default());
+    };
+    let names = plugin_registry::transform_secrets(pool, Some(org_id), label, transform)
+        .await
+        .map_err(|e| VmError::Launch(format!("transform secrets lookup error: {e}")))?;
+    if names.is_empty() {
+        return Ok(SecretEnv::default());
+    }
+    let vault = VAULT
+        .get_or_init(|| async {
+            let cfg = common::config::CFG.get_or_init(common::config::cfg).await;
+            Vault::from_config(cfg)
+        })
+        .await;
+    secrets::resolve(pool, vault.as_ref(), org_id, actor_id, &names)
+        .await
+        .map_err(|e| match e {
+            SecretsError::Sql(e) => VmError::Launch(format!("transform secrets lookup error: {e}")),
+            e => VmError::Secrets(e.to_string()),
+        })
+}
+
+// Runs the payload on the executor picked by config, see `crate::executor`.
+// Plugins whose signature doesn't verify are refused, package plugins run in
+// a microVM from their package's directory, the host's plugins never see
+// package code.
+// Secrets the transform declares are exported into its environment, never
+// into the payload. Progress, partial and log records are sent to `records` while it runs.
+// `limits` bounds its runtime, memory and output.
+pub async fn execute_job(
+    pool: &PgPool,
+    org_id: Option<i64>,
+    actor_id: Option<i64>,
+    payload: &JsonValue,
+    limits: &Limits,
+    records: &RecordSender,
+) -> Result<Option<JsonValue>, VmError> {
+    let (label, trust) = plugin_trust(pool, org_id, payload).await?;
+    if trust == Trust::Invalid {
+        return Err(VmError::Untrusted(label));
+    }
+    let package = package_dir(pool, org_id, &label).await?;
+    let secrets = job_secrets(pool, org_id, actor_id, &label, payload).await?;
+    let confined = trust.confined() || package.is_some();
+    let executor = if confined {
+        confined_executor().await
     } else {
-        // Production path: placeholder until Firecracker integration
-        tokio::time::sleep(Duration::from_millis(250)).await;
-        Ok(None)
+        let cfg = common::config::CFG.get_or_init(common::config::cfg).await;
+        EXECUTOR
+            .get_or_init(|| async {
+                let executor = executor::from_config(cfg);
+                info!("executing jobs via the {} executor", executor.name());
+                executor
+            })
+            .await
+            .as_ref()
+    };
+    if confined {
+        info!(
+            "running {trust} plugin {label} on the {} executor",
+            executor.name()
+        );
     }
+    let command = ObCommand::run(payload)?.in_package(package);
+    let out = OutputStream::new(records.clone(), limits.max_output_bytes, secrets.clone());
+    executor.execute(&command, &secrets, limits, &out).await?;
+    Ok(out.finish())
 }